@@ -0,0 +1,229 @@
+/// query ACLs restricting zeronsd to answering the ZeroTier network it serves.
+use std::{net::IpAddr, str::FromStr, sync::Arc};
+
+use anyhow::anyhow;
+use arc_swap::ArcSwap;
+use ipnetwork::IpNetwork;
+use serde::{Deserialize, Serialize};
+use zerotier_central_api::types::Member;
+
+use crate::addresses::Calculator;
+
+// AclAction is what is done with requests from clients outside the network.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum AclAction {
+    // answer with REFUSED.
+    Refuse,
+    // send no answer at all, so that the client cannot even tell zeronsd is there.
+    Drop,
+}
+
+impl ToString for AclAction {
+    fn to_string(&self) -> String {
+        match self {
+            AclAction::Refuse => "refuse",
+            AclAction::Drop => "drop",
+        }
+        .to_string()
+    }
+}
+
+impl FromStr for AclAction {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "refuse" => Ok(Self::Refuse),
+            "drop" => Ok(Self::Drop),
+            _ => Err(anyhow!("invalid action: allowed values: [refuse, drop]")),
+        }
+    }
+}
+
+// QueryAcl lists the networks requests are answered for, and the addresses of de-authorized
+// members within them which are not.
+#[derive(Debug, Clone)]
+pub struct QueryAcl {
+    action: AclAction,
+    networks: Arc<Vec<IpNetwork>>,
+    deauthorized: Arc<ArcSwap<Vec<IpNetwork>>>,
+}
+
+impl QueryAcl {
+    // new answers requests from the given networks, typically those the member zeronsd runs on
+    // has addresses in, and applies the action to every other request.
+    pub fn new(action: AclAction, networks: Vec<IpNetwork>) -> Self {
+        Self {
+            action,
+            networks: Arc::new(networks),
+            deauthorized: Arc::new(ArcSwap::from_pointee(Vec::new())),
+        }
+    }
+
+    pub fn action(&self) -> AclAction {
+        self.action
+    }
+
+    // allowed tells whether requests from the address are answered. Requests from this host are,
+    // for health checks and local forwarders.
+    pub fn allowed(&self, ip: IpAddr) -> bool {
+        ip.is_loopback()
+            || (self.networks.iter().any(|network| network.contains(ip))
+                && !self
+                    .deauthorized
+                    .load()
+                    .iter()
+                    .any(|network| network.contains(ip)))
+    }
+
+    // sync_members replaces the addresses of de-authorized members with those of the members
+    // fetched from Central. Addresses also assigned to an authorized member stay allowed.
+    pub fn sync_members(&self, members: &[Member]) {
+        let (mut authorized, mut deauthorized) = (Vec::new(), Vec::new());
+
+        for member in members {
+            let addresses = member_addresses(member);
+
+            if member
+                .config
+                .as_ref()
+                .and_then(|c| c.authorized)
+                .unwrap_or(false)
+            {
+                authorized.extend(addresses);
+            } else {
+                deauthorized.extend(addresses);
+            }
+        }
+
+        deauthorized.retain(|network| {
+            !authorized
+                .iter()
+                .any(|authorized| network.contains(authorized.ip()))
+        });
+
+        if deauthorized != **self.deauthorized.load() {
+            tracing::info!(
+                "Refusing requests from {} addresses of de-authorized members",
+                deauthorized.len()
+            );
+            self.deauthorized.store(Arc::new(deauthorized));
+        }
+    }
+}
+
+// member_addresses are the addresses a member can send requests from: its managed addresses and
+// its 6PLANE range and RFC4193 address, whether or not the network assigns them.
+pub fn member_addresses(member: &Member) -> Vec<IpNetwork> {
+    let mut addresses: Vec<IpNetwork> = member
+        .config
+        .as_ref()
+        .and_then(|c| c.ip_assignments.as_ref())
+        .map_or(Vec::new(), |v| {
+            v.iter()
+                .filter_map(|ip| IpAddr::from_str(ip).ok())
+                .map(IpNetwork::from)
+                .collect()
+        });
+
+    // the calculated addresses are meaningless without both IDs.
+    if member.network_id.is_some() && member.node_id.is_some() {
+        addresses.extend(member.clone().sixplane());
+        addresses.extend(member.clone().rfc4193());
+    }
+
+    addresses
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{AclAction, QueryAcl};
+    use ipnetwork::IpNetwork;
+    use std::{net::IpAddr, str::FromStr};
+    use zerotier_central_api::types::Member;
+
+    fn member(node_id: &str, authorized: bool, ip: &str) -> Member {
+        serde_json::from_value(serde_json::json!({
+            "networkId": "8056c2e21c000001",
+            "nodeId": node_id,
+            "config": { "authorized": authorized, "ipAssignments": [ip] },
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn test_acl_action() {
+        assert_eq!(AclAction::from_str("drop").unwrap(), AclAction::Drop);
+        assert_eq!(AclAction::Refuse.to_string(), "refuse");
+        assert!(AclAction::from_str("allow").is_err());
+    }
+
+    #[test]
+    fn test_query_acl() {
+        let acl = QueryAcl::new(
+            AclAction::Refuse,
+            vec![
+                IpNetwork::from_str("10.147.20.0/24").unwrap(),
+                IpNetwork::from_str("fd00:1234::/88").unwrap(),
+            ],
+        );
+
+        for (ip, allowed) in [
+            ("10.147.20.9", true),
+            ("fd00:1234::1", true),
+            ("127.0.0.1", true),
+            ("::1", true),
+            ("10.147.21.9", false),
+            ("192.168.1.10", false),
+        ] {
+            assert_eq!(
+                acl.allowed(IpAddr::from_str(ip).unwrap()),
+                allowed,
+                "{}",
+                ip
+            );
+        }
+    }
+
+    #[test]
+    fn test_deauthorized_members() {
+        let acl = QueryAcl::new(
+            AclAction::Drop,
+            vec![
+                IpNetwork::from_str("10.147.20.0/24").unwrap(),
+                IpNetwork::from_str("fc9c:56c2:e300::/40").unwrap(),
+            ],
+        );
+
+        acl.sync_members(&[
+            member("efcc1b0947", false, "10.147.20.9"),
+            member("efcc1b0948", true, "10.147.20.10"),
+            // the address of a de-authorized member was given to another one.
+            member("efcc1b0949", false, "10.147.20.11"),
+            member("efcc1b0950", true, "10.147.20.11"),
+        ]);
+
+        for (ip, allowed) in [
+            ("10.147.20.9", false),
+            ("fc9c:56c2:e3ef:cc1b:947::1", false),
+            ("fc9c:56c2:e3ef:cc1b:947::2", false),
+            ("10.147.20.10", true),
+            ("fc9c:56c2:e3ef:cc1b:948::1", true),
+            ("10.147.20.11", true),
+            ("10.147.20.12", true),
+            ("127.0.0.1", true),
+        ] {
+            assert_eq!(
+                acl.allowed(IpAddr::from_str(ip).unwrap()),
+                allowed,
+                "{}",
+                ip
+            );
+        }
+
+        // authorized again at the next poll.
+        acl.sync_members(&[member("efcc1b0947", true, "10.147.20.9")]);
+        assert!(acl.allowed(IpAddr::from_str("10.147.20.9").unwrap()));
+    }
+}
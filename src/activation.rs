@@ -0,0 +1,127 @@
+/// systemd socket activation, picking up the sockets of `zeronsd supervise --socket`.
+use std::{
+    net::{SocketAddr, TcpListener, UdpSocket},
+    ops::Range,
+    sync::Mutex,
+};
+
+use lazy_static::lazy_static;
+
+// the first descriptor systemd passes; the others follow it.
+const LISTEN_FDS_START: i32 = 3;
+
+enum Passed {
+    Tcp(TcpListener),
+    Udp(UdpSocket),
+}
+
+// the sockets systemd passed, kept for as long as zeronsd runs, so that a listener stopped when
+// its address goes away and started again when it comes back gets the same socket.
+lazy_static! {
+    static ref PASSED: Mutex<Vec<(SocketAddr, Passed)>> = Mutex::new(Vec::new());
+}
+
+// listen_fds is the range of descriptors systemd passed, given the LISTEN_PID and LISTEN_FDS
+// variables: none if they are missing, or meant for another process.
+fn listen_fds(pid: Option<&str>, fds: Option<&str>, own_pid: u32) -> Range<i32> {
+    let passed = match (pid, fds) {
+        (Some(pid), Some(fds)) if pid.parse::<u32>().ok() == Some(own_pid) => {
+            fds.parse().unwrap_or(0)
+        }
+        _ => 0,
+    };
+
+    LISTEN_FDS_START..LISTEN_FDS_START + passed
+}
+
+// receive takes the sockets systemd passed, if any, and returns how many there were. It is called
+// once, before anything is bound; the variables are removed so that they do not leak to children.
+#[cfg(unix)]
+pub fn receive() -> Result<usize, anyhow::Error> {
+    use nix::{
+        fcntl::{fcntl, FcntlArg, FdFlag},
+        sys::socket::{getsockopt, sockopt, SockType},
+    };
+    use std::os::unix::io::FromRawFd;
+
+    let fds = listen_fds(
+        std::env::var("LISTEN_PID").ok().as_deref(),
+        std::env::var("LISTEN_FDS").ok().as_deref(),
+        std::process::id(),
+    );
+
+    for name in ["LISTEN_PID", "LISTEN_FDS", "LISTEN_FDNAMES"] {
+        std::env::remove_var(name);
+    }
+
+    let mut passed = PASSED.lock().unwrap();
+
+    for fd in fds {
+        fcntl(fd, FcntlArg::F_SETFD(FdFlag::FD_CLOEXEC))?;
+
+        // the descriptors are ours from now on; systemd holds its own copies.
+        let (addr, socket) = match getsockopt(fd, sockopt::SockType)? {
+            SockType::Stream => {
+                let tcp = unsafe { TcpListener::from_raw_fd(fd) };
+                (tcp.local_addr()?, Passed::Tcp(tcp))
+            }
+            SockType::Datagram => {
+                let udp = unsafe { UdpSocket::from_raw_fd(fd) };
+                (udp.local_addr()?, Passed::Udp(udp))
+            }
+            other => {
+                tracing::warn!(
+                    "Ignoring socket {} of type {:?} passed by systemd",
+                    fd,
+                    other
+                );
+                continue;
+            }
+        };
+
+        tracing::info!("Using the socket systemd listens on for {}", addr);
+        passed.push((addr, socket));
+    }
+
+    Ok(passed.len())
+}
+
+#[cfg(not(unix))]
+pub fn receive() -> Result<usize, anyhow::Error> {
+    Ok(0)
+}
+
+// take returns copies of the sockets systemd passed for the address, TCP and UDP, if it did.
+pub fn take(addr: SocketAddr) -> Result<(Option<TcpListener>, Option<UdpSocket>), anyhow::Error> {
+    let passed = PASSED.lock().unwrap();
+    let (mut tcp, mut udp) = (None, None);
+
+    for (passed_addr, socket) in passed.iter() {
+        if *passed_addr != addr {
+            continue;
+        }
+
+        match socket {
+            Passed::Tcp(listener) if tcp.is_none() => tcp = Some(listener.try_clone()?),
+            Passed::Udp(socket) if udp.is_none() => udp = Some(socket.try_clone()?),
+            _ => {}
+        }
+    }
+
+    Ok((tcp, udp))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::listen_fds;
+
+    #[test]
+    fn test_listen_fds() {
+        assert_eq!(listen_fds(Some("42"), Some("2"), 42), 3..5);
+        assert_eq!(listen_fds(Some("42"), Some("0"), 42), 3..3);
+        assert!(listen_fds(Some("41"), Some("2"), 42).is_empty());
+        assert!(listen_fds(None, Some("2"), 42).is_empty());
+        assert!(listen_fds(Some("42"), None, 42).is_empty());
+        assert!(listen_fds(Some("42"), Some("two"), 42).is_empty());
+    }
+}
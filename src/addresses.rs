@@ -101,3 +101,67 @@ impl Calculator for Member {
         )?)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+
+    use super::Calculator;
+    use ipnetwork::IpNetwork;
+    use zerotier_central_api::types::{Member, Network};
+
+    fn member(network_id: &str, node_id: &str) -> Member {
+        Member {
+            supports_rules_engine: None,
+            protocol_version: None,
+            physical_address: None,
+            node_id: Some(node_id.to_string()),
+            network_id: Some(network_id.to_string()),
+            name: None,
+            last_online: None,
+            id: None,
+            hidden: None,
+            description: None,
+            controller_id: None,
+            config: None,
+            client_version: None,
+            clock: None,
+        }
+    }
+
+    fn network(network_id: &str) -> Network {
+        serde_json::from_value(serde_json::json!({ "id": network_id })).unwrap()
+    }
+
+    #[test]
+    fn test_rfc4193() {
+        assert_eq!(
+            network("8056c2e21c000001").rfc4193().unwrap(),
+            IpNetwork::from_str("fd80:56c2:e21c:0:199:9300::/88").unwrap()
+        );
+
+        assert_eq!(
+            member("8056c2e21c000001", "efcc1b0947").rfc4193().unwrap(),
+            IpNetwork::from_str("fd80:56c2:e21c:0:199:93ef:cc1b:947/128").unwrap()
+        );
+    }
+
+    #[test]
+    fn test_sixplane() {
+        assert_eq!(
+            network("8056c2e21c000001").sixplane().unwrap(),
+            IpNetwork::from_str("fc9c:56c2:e300::1/40").unwrap()
+        );
+
+        assert_eq!(
+            member("8056c2e21c000001", "efcc1b0947").sixplane().unwrap(),
+            IpNetwork::from_str("fc9c:56c2:e3ef:cc1b:947::1/80").unwrap()
+        );
+    }
+
+    #[test]
+    fn test_bad_ids() {
+        assert!(member("not hex", "efcc1b0947").rfc4193().is_err());
+        assert!(member("8056c2e21c000001", "not hex").sixplane().is_err());
+    }
+}
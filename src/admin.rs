@@ -0,0 +1,409 @@
+/// the admin API, JSON over HTTP on `--admin-socket` or `--admin-addr`.
+use std::{
+    collections::BTreeMap,
+    net::SocketAddr,
+    path::PathBuf,
+    sync::{Arc, Mutex},
+};
+
+use anyhow::anyhow;
+use serde::{Deserialize, Serialize};
+use tokio::{
+    net::{TcpListener, TcpStream},
+    sync::Notify,
+    task::JoinHandle,
+};
+use trust_dns_server::{
+    authority::AuthorityObject,
+    client::rr::{Name, Record},
+};
+
+use crate::{
+    authority::ZTAuthority,
+    health::NetworkStatus,
+    http::{self, Request, Response},
+    records::{parse_records, Records},
+};
+
+// Control carries what the admin API asks of the refresh loop of a network.
+#[derive(Clone, Default)]
+pub struct Control {
+    refresh: Arc<Notify>,
+    records: Arc<Mutex<Records>>,
+}
+
+impl Control {
+    // refresh asks for the network to be refreshed right away.
+    pub fn refresh(&self) {
+        self.refresh.notify_one();
+    }
+
+    // refresh_requested resolves once a refresh is asked for.
+    pub async fn refresh_requested(&self) {
+        self.refresh.notified().await
+    }
+
+    // records are the temporary records, by name.
+    pub fn records(&self) -> Records {
+        self.records.lock().unwrap().clone()
+    }
+
+    // add_record adds a temporary record for the name, once it parses under the domain.
+    pub fn add_record(&self, name: &str, record: &str, domain: &Name) -> Result<(), anyhow::Error> {
+        let mut added = Records::new();
+        added.insert(name.to_string(), vec![record.to_string()]);
+        parse_records(&added, domain)?;
+
+        let mut records = self.records.lock().unwrap();
+        let values = records.entry(name.to_string()).or_default();
+
+        if !values.iter().any(|value| value == record) {
+            values.push(record.to_string());
+        }
+
+        Ok(())
+    }
+
+    // remove_records removes the temporary records of the name, telling whether it had any.
+    pub fn remove_records(&self, name: &str) -> bool {
+        self.records.lock().unwrap().remove(name).is_some()
+    }
+}
+
+// Networks holds the networks served by the process, by network ID.
+#[derive(Clone, Default)]
+pub struct Networks(Arc<Mutex<BTreeMap<String, ZTAuthority>>>);
+
+impl Networks {
+    // register adds a network, in place of any with the same ID, as when it is started again
+    // under a new domain.
+    pub fn register(&self, zt: ZTAuthority) {
+        self.0.lock().unwrap().insert(zt.network_id.clone(), zt);
+    }
+
+    pub(crate) fn get(&self, network_id: &str) -> Option<ZTAuthority> {
+        self.0.lock().unwrap().get(network_id).cloned()
+    }
+
+    pub(crate) fn all(&self) -> Vec<ZTAuthority> {
+        self.0.lock().unwrap().values().cloned().collect()
+    }
+}
+
+// RecordEntry is a record served, as listed by the API.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RecordEntry {
+    pub zone: String,
+    pub name: String,
+    #[serde(rename = "type")]
+    pub record_type: String,
+    pub ttl: u32,
+    pub data: String,
+}
+
+impl RecordEntry {
+    pub fn new(zone: &impl std::fmt::Display, record: &Record) -> Self {
+        Self {
+            zone: zone.to_string(),
+            name: record.name().to_string(),
+            record_type: record.record_type().to_string(),
+            ttl: record.ttl(),
+            data: record
+                .data()
+                .map(|rdata| rdata.to_string())
+                .unwrap_or_default(),
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct TemporaryRecord {
+    name: String,
+    record: String,
+}
+
+// listen_tcp binds the loopback address and answers the API on it in the background.
+pub async fn listen_tcp(
+    addr: SocketAddr,
+    networks: Networks,
+) -> Result<JoinHandle<()>, anyhow::Error> {
+    if !addr.ip().is_loopback() {
+        return Err(anyhow!(
+            "the admin API is only served on loopback addresses, not {}",
+            addr
+        ));
+    }
+
+    let listener = TcpListener::bind(addr)
+        .await
+        .map_err(|e| anyhow!("could not listen for the admin API on {}: {}", addr, e))?;
+
+    tracing::info!("Serving the admin API on http://{}", addr);
+
+    Ok(tokio::spawn(async move {
+        loop {
+            match listener.accept().await {
+                Ok((stream, _)) => serve(stream, networks.clone()),
+                Err(e) => tracing::warn!("Could not accept admin API connection: {}", e),
+            }
+        }
+    }))
+}
+
+// listen_unix binds the unix socket, replacing any left over, and answers the API on it in the
+// background. Only the user zeronsd runs as can connect to it.
+#[cfg(unix)]
+pub fn listen_unix(path: PathBuf, networks: Networks) -> Result<JoinHandle<()>, anyhow::Error> {
+    use std::os::unix::fs::PermissionsExt;
+
+    let _ = std::fs::remove_file(&path);
+    let listener = tokio::net::UnixListener::bind(&path).map_err(|e| {
+        anyhow!(
+            "could not listen for the admin API on {}: {}",
+            path.display(),
+            e
+        )
+    })?;
+    std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o600))?;
+
+    tracing::info!("Serving the admin API on {}", path.display());
+
+    Ok(tokio::spawn(async move {
+        loop {
+            match listener.accept().await {
+                Ok((stream, _)) => serve(stream, networks.clone()),
+                Err(e) => tracing::warn!("Could not accept admin API connection: {}", e),
+            }
+        }
+    }))
+}
+
+#[cfg(not(unix))]
+pub fn listen_unix(_: PathBuf, _: Networks) -> Result<JoinHandle<()>, anyhow::Error> {
+    Err(anyhow!(
+        "the admin socket is only supported on unix platforms; use --admin-addr"
+    ))
+}
+
+// Endpoint is where the admin API of a running zeronsd is reached.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Endpoint {
+    Socket(PathBuf),
+    Addr(SocketAddr),
+}
+
+// statuses asks the admin API at the endpoint for the status of the networks served.
+pub async fn statuses(endpoint: &Endpoint) -> Result<Vec<NetworkStatus>, anyhow::Error> {
+    get(endpoint, "/networks").await
+}
+
+// records asks the admin API at the endpoint for the records served for a network, by zone.
+pub async fn records(
+    endpoint: &Endpoint,
+    network_id: &str,
+) -> Result<Vec<RecordEntry>, anyhow::Error> {
+    get(endpoint, &format!("/networks/{}/records", network_id)).await
+}
+
+// get asks the admin API at the endpoint for the path, and reads the JSON answer.
+async fn get<T: serde::de::DeserializeOwned>(
+    endpoint: &Endpoint,
+    path: &str,
+) -> Result<T, anyhow::Error> {
+    let response = match endpoint {
+        #[cfg(unix)]
+        Endpoint::Socket(path) => {
+            let stream = tokio::net::UnixStream::connect(path).await.map_err(|e| {
+                anyhow!(
+                    "could not connect to the admin API on {}: {}",
+                    path.display(),
+                    e
+                )
+            })?;
+            http::get(stream, path).await?
+        }
+        #[cfg(not(unix))]
+        Endpoint::Socket(_) => {
+            return Err(anyhow!(
+                "the admin socket is only supported on unix platforms; use --admin-addr"
+            ))
+        }
+        Endpoint::Addr(addr) => {
+            let stream = TcpStream::connect(addr)
+                .await
+                .map_err(|e| anyhow!("could not connect to the admin API on {}: {}", addr, e))?;
+            http::get(stream, path).await?
+        }
+    };
+
+    if response.status != 200 {
+        return Err(anyhow!(
+            "the admin API answered {}: {}",
+            response.status,
+            response.body
+        ));
+    }
+
+    Ok(serde_json::from_str(&response.body)?)
+}
+
+// serve answers the request of a connection in the background.
+fn serve<S>(stream: S, networks: Networks)
+where
+    S: tokio::io::AsyncRead + tokio::io::AsyncWrite + Unpin + Send + 'static,
+{
+    tokio::spawn(async move {
+        let answer = move |request| async move { respond(request, &networks).await };
+
+        if let Err(e) = http::handle(stream, answer).await {
+            tracing::debug!("Admin API request failed: {}", e);
+        }
+    });
+}
+
+// respond routes a request of the API.
+async fn respond(request: Request, networks: &Networks) -> Response {
+    let segments = request.segments();
+
+    let network_id = match segments.as_slice() {
+        ["networks"] if request.method == "GET" => {
+            let statuses: Vec<_> = networks.all().iter().map(|zt| zt.health.status()).collect();
+            return Response::json(200, &statuses);
+        }
+        ["networks", network_id, ..] => *network_id,
+        _ => return Response::error(404, "not found"),
+    };
+
+    let zt = match networks.get(network_id) {
+        Some(zt) => zt,
+        None => return Response::error(404, format!("network {} is not served", network_id)),
+    };
+
+    match (request.method.as_str(), &segments[2..]) {
+        ("GET", []) => Response::json(200, &zt.health.status()),
+        ("GET", ["records"]) => {
+            let mut records = Vec::new();
+
+            for (zone, served) in zt.records().await {
+                records.extend(served.iter().map(|record| RecordEntry::new(&zone, record)));
+            }
+
+            Response::json(200, &records)
+        }
+        ("GET", ["statistics"]) => match zt.statistics() {
+            Ok(statistics) => Response::json(200, &statistics),
+            Err(e) => Response::error(500, format!("could not read the statistics: {}", e)),
+        },
+        ("POST", ["refresh"]) => {
+            zt.control.refresh();
+            Response::json(202, &serde_json::json!({ "status": "refreshing" }))
+        }
+        ("GET", ["temporary"]) => Response::json(200, &zt.control.records()),
+        ("POST", ["temporary"]) => {
+            let added: TemporaryRecord = match serde_json::from_slice(&request.body) {
+                Ok(added) => added,
+                Err(e) => return Response::error(400, format!("invalid record: {}", e)),
+            };
+
+            let domain: Name = zt.forward_authority.origin().clone().into();
+
+            match zt.control.add_record(&added.name, &added.record, &domain) {
+                Ok(_) => {
+                    tracing::info!(
+                        "Adding temporary record {} {} to network {}",
+                        added.name,
+                        added.record,
+                        network_id
+                    );
+                    zt.control.refresh();
+                    Response::json(201, &zt.control.records())
+                }
+                Err(e) => Response::error(400, e),
+            }
+        }
+        ("DELETE", ["temporary", name]) => {
+            if zt.control.remove_records(name) {
+                tracing::info!(
+                    "Removing temporary records of {} from network {}",
+                    name,
+                    network_id
+                );
+                zt.control.refresh();
+                Response::json(200, &zt.control.records())
+            } else {
+                Response::error(404, format!("{} has no temporary records", name))
+            }
+        }
+        (_, ["refresh"] | ["temporary", ..] | ["records"] | ["statistics"] | []) => {
+            Response::error(405, "method not allowed")
+        }
+        _ => Response::error(404, "not found"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{listen_tcp, statuses, Control, Endpoint, Networks};
+    use std::str::FromStr;
+    use trust_dns_server::client::rr::Name;
+
+    #[tokio::test]
+    async fn test_control() {
+        let control = Control::default();
+        let domain = Name::from_str("home.arpa.").unwrap();
+
+        control
+            .add_record("build", "A 10.147.20.9", &domain)
+            .unwrap();
+        control
+            .add_record("build", "A 10.147.20.9", &domain)
+            .unwrap();
+        control
+            .add_record("build", "TXT \"ci runner\"", &domain)
+            .unwrap();
+        assert!(control.add_record("bad", "A not-an-ip", &domain).is_err());
+        assert!(control.add_record("bad", "NS ns1", &domain).is_err());
+
+        let records = control.records();
+        assert_eq!(records.len(), 1);
+        assert_eq!(
+            records["build"],
+            vec!["A 10.147.20.9".to_string(), "TXT \"ci runner\"".to_string()]
+        );
+
+        assert!(control.remove_records("build"));
+        assert!(!control.remove_records("build"));
+        assert!(control.records().is_empty());
+
+        // a refresh asked for before the refresh loop waits is not lost.
+        control.refresh();
+        tokio::time::timeout(
+            std::time::Duration::from_secs(1),
+            control.refresh_requested(),
+        )
+        .await
+        .unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_statuses() {
+        // there is no way to learn the port picked for 0 from listen_tcp, so pick one first.
+        let addr = std::net::TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap();
+        let endpoint = Endpoint::Addr(addr);
+
+        assert!(statuses(&endpoint).await.is_err());
+
+        let server = listen_tcp(addr, Networks::default()).await.unwrap();
+        assert_eq!(statuses(&endpoint).await.unwrap(), vec![]);
+        server.abort();
+
+        assert!(
+            listen_tcp("0.0.0.0:0".parse().unwrap(), Networks::default())
+                .await
+                .is_err()
+        );
+    }
+}
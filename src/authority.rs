@@ -1,28 +1,75 @@
 use std::{
-    collections::{BTreeMap, HashMap},
-    net::IpAddr,
+    collections::{hash_map::DefaultHasher, BTreeMap, HashMap, HashSet},
+    hash::{Hash, Hasher},
+    net::{IpAddr, SocketAddr},
     path::PathBuf,
     str::FromStr,
     sync::Arc,
-    time::Duration,
+    time::{Duration, SystemTime, UNIX_EPOCH},
 };
 
 use crate::{
+    acl::QueryAcl,
     addresses::Calculator,
-    hosts::{parse_hosts, HostsFile},
-    traits::{ToHostname, ToPointerSOA, ToWildcard},
-    utils::parse_member_name,
+    admin::Control,
+    blocklist::{BlockAction, Blocklist},
+    cache::ForwardCache,
+    central::{fingerprint, is_transient, is_unauthorized, CircuitBreaker, RetryPolicy},
+    classless::Classless,
+    collisions::{resolve_collisions, CollisionPolicy},
+    controller::{CentralConfig, Controller},
+    dnssec::ZoneKey,
+    dnstap::Dnstap,
+    edns::EdnsConfig,
+    filter::MemberFilter,
+    health::Health,
+    hooks::{RecordChange, RecordHooks},
+    hosts::{
+        hosts_modified, log_hosts_diff, parse_hosts_files, rebase_hosts, wait_for_hosts_change,
+        HostsFile,
+    },
+    limits::{limit, MemberRecords, RecordEviction},
+    order::AnswerOrdering,
+    querylog::QueryLog,
+    records::{parse_name, parse_records, Records},
+    replication::Replica,
+    rpz::{Rpz, RpzConfig},
+    services::{member_services, member_tag, Service},
+    snapshot::Snapshot,
+    store::{Saved, Store},
+    traits::{ToHostname, ToWildcard},
+    transfer::{diff, ixfr_answers, notify, soa_serial, History, ZoneChange},
+    tsig::Key,
+    update::DynamicZone,
+    upstream::{resolver_config, Upstreams},
+    utils::{
+        network_from_cidr, notify_watchdog, parse_member_aliases, parse_member_name,
+        watchdog_interval, NamePolicy, DEFAULT_SOA_EXPIRE, DEFAULT_SOA_REFRESH, DEFAULT_SOA_RETRY,
+    },
+    views::{ViewZones, Views},
 };
 
+use anyhow::anyhow;
+use arc_swap::ArcSwap;
 use async_trait::async_trait;
 use ipnetwork::IpNetwork;
+use serde::{Deserialize, Serialize};
+use tokio::sync::watch;
 use trust_dns_resolver::{
     config::NameServerConfigGroup,
-    proto::rr::{dnssec::SupportedAlgorithms, rdata::SOA, RData, Record, RecordSet, RecordType},
+    proto::op::ResponseCode,
+    proto::rr::{
+        dnssec::rdata::DNSSECRData,
+        rdata::{HINFO, SOA, TXT},
+        RData, Record, RecordSet, RecordType,
+    },
     IntoName, Name,
 };
 use trust_dns_server::{
-    authority::{AuthorityObject, Catalog},
+    authority::{
+        AuthLookup, AuthorityObject, Catalog, DnssecAuthority, LookupObject, LookupOptions,
+        LookupRecords,
+    },
     client::rr::{LowerName, RrKey},
     store::{
         forwarder::{ForwardAuthority, ForwardConfig},
@@ -32,33 +79,168 @@ use trust_dns_server::{
 
 pub async fn find_members(mut zt: ZTAuthority) {
     let mut timer = tokio::time::interval(zt.update_interval);
+    let policy = RetryPolicy::default();
+    let mut breaker = CircuitBreaker::default();
+    // the members saved by the last run are served at once, rather than after the first poll.
+    let mut last_fingerprint = zt.restore_snapshot().await;
+    let mut watchdog = watchdog_interval().map(tokio::time::interval);
 
     loop {
+        let hosts_mtime = hosts_modified(&zt.hosts_files);
+
         match zt.configure_hosts().await {
             Ok(_) => {}
             Err(e) => tracing::error!("error refreshing hosts file: {}", e),
         }
 
-        match zt.get_members().await {
-            Ok((network, members)) => match zt.configure_members(network, members).await {
-                Ok(_) => {}
-                Err(e) => {
-                    tracing::error!("error configuring authority: {}", e)
+        let members = if breaker.allow() {
+            Some(zt.get_members(&policy).await)
+        } else {
+            tracing::debug!("Central is unavailable; serving the last known records");
+            None
+        };
+
+        match members {
+            None => {}
+            Some(Ok((network, members))) => {
+                breaker.success();
+                zt.health.polled();
+                zt.record_poll();
+
+                let fp = zt.fingerprint(&network, &members);
+
+                if last_fingerprint == Some(fp) {
+                    tracing::debug!("No changes to members or hosts; skipping update");
+                } else {
+                    zt.save_snapshot(&network, &members);
+                    zt.replica.set(&network, &members);
+
+                    match zt.configure_members(network, members).await {
+                        Ok(_) => last_fingerprint = Some(fp),
+                        Err(e) => {
+                            tracing::error!("error configuring authority: {}", e)
+                        }
+                    }
+                }
+            }
+            Some(Err(e)) => {
+                if is_transient(&e) {
+                    breaker.failure();
+                }
+
+                tracing::error!("error syncing members: {:#}", e);
+                zt.health.failed(&e);
+                zt.count("poll_failures");
+
+                // the token may have been rotated; the next poll uses the one in the token file.
+                if is_unauthorized(&e) {
+                    tracing::warn!("Central rejected the token; reading it again");
+                    zt.controller.reload_credentials();
                 }
-            },
-            Err(e) => {
-                tracing::error!("error syncing members: {}", e)
+
+                // nothing is being served yet, e.g. when starting during an outage.
+                if last_fingerprint.is_none() {
+                    last_fingerprint = zt.restore_snapshot().await;
+                }
+            }
+        }
+
+        zt.resign().await;
+        notify_watchdog();
+
+        zt.health.refreshed(
+            zt.update_interval,
+            zt.record_count().await,
+            last_fingerprint.is_some(),
+        );
+
+        // wait for the next poll, but refresh early if the hosts file is edited or a reload is
+        // requested. The systemd watchdog is pinged in the meantime.
+        loop {
+            tokio::select! {
+                _ = timer.tick() => break,
+                _ = wait_for_hosts_change(zt.hosts_files.clone(), hosts_mtime.clone()) => {
+                    tracing::info!("Hosts file changed; reloading");
+                    break;
+                }
+                reload = wait_for_reload(&mut zt.reload) => {
+                    tracing::info!("Reload requested; refreshing");
+
+                    if reload.update_interval != zt.update_interval {
+                        timer = tokio::time::interval(reload.update_interval);
+                        timer.tick().await;
+                    }
+
+                    zt.apply(reload);
+                    last_fingerprint = None;
+                    break;
+                }
+                _ = zt.control.refresh_requested() => {
+                    tracing::info!("Refresh requested through the admin API");
+                    last_fingerprint = None;
+                    break;
+                }
+                _ = wait_for_watchdog(&mut watchdog) => notify_watchdog(),
             }
         }
+    }
+}
+
+// wait_for_watchdog resolves when the systemd watchdog is due a ping, and never resolves if the
+// watchdog is not enabled.
+async fn wait_for_watchdog(watchdog: &mut Option<tokio::time::Interval>) {
+    match watchdog {
+        Some(watchdog) => {
+            watchdog.tick().await;
+        }
+        None => std::future::pending().await,
+    }
+}
+
+// the statistic holding when Central was last polled successfully, in seconds since the epoch.
+pub const LAST_POLL: &str = "last_poll";
 
-        timer.tick().await;
+// the tag of Central giving members wildcard names; see member_wildcard.
+const WILDCARD_TAG: &str = "wildcard";
+
+// Reload carries the settings which can be changed without restarting zeronsd. Sending one to
+// find_members also forces an immediate refresh.
+#[derive(Debug, Clone)]
+pub struct Reload {
+    pub hosts_files: Vec<PathBuf>,
+    pub blocklists: Vec<PathBuf>,
+    pub block_action: BlockAction,
+    pub rpz: Vec<RpzConfig>,
+    pub wildcard: bool,
+    pub wildcard_members: Vec<String>,
+    pub name_policy: NamePolicy,
+    pub name_collisions: CollisionPolicy,
+    pub member_filter: MemberFilter,
+    pub node_id_names: bool,
+    pub all_ptr_names: bool,
+    pub member_txt: bool,
+    pub update_interval: Duration,
+    pub central: Option<CentralConfig>,
+    pub records: Records,
+}
+
+// wait_for_reload resolves when a reload is sent, and never resolves if reloading is not
+// configured or no longer possible.
+async fn wait_for_reload(reload: &mut Option<watch::Receiver<Reload>>) -> Reload {
+    if let Some(rx) = reload {
+        if rx.changed().await.is_ok() {
+            return rx.borrow().clone();
+        }
     }
+
+    *reload = None;
+    std::future::pending().await
 }
 
 pub async fn init_catalog(zt: ZTAuthority) -> Result<Catalog, anyhow::Error> {
     let mut catalog = Catalog::default();
 
-    let resolv = trust_dns_resolver::system_conf::read_system_conf()?;
+    let resolv = resolver_config(zt.upstreams.as_ref().map(Upstreams::name_servers))?;
     let mut nsconfig = NameServerConfigGroup::new();
 
     for server in resolv.0.name_servers() {
@@ -81,13 +263,51 @@ pub async fn init_catalog(zt: ZTAuthority) -> Result<Catalog, anyhow::Error> {
 
     catalog.upsert(Name::root().into(), Box::new(Arc::new(forwarder)));
 
+    // stub zones are forwarded to their own set of upstreams instead of the system resolvers.
+    for (zone, servers) in zt.forward_zones.clone() {
+        let mut ports: BTreeMap<u16, Vec<IpAddr>> = BTreeMap::new();
+        for server in servers {
+            ports.entry(server.port()).or_default().push(server.ip());
+        }
+
+        let mut name_servers = NameServerConfigGroup::new();
+        for (port, ips) in ports {
+            name_servers.merge(NameServerConfigGroup::from_ips_clear(&ips, port, true));
+        }
+
+        let config = &ForwardConfig {
+            name_servers,
+            options: None,
+        };
+
+        let forwarder = ForwardAuthority::try_from_config(
+            zone.clone(),
+            trust_dns_server::authority::ZoneType::Primary,
+            config,
+        )
+        .await
+        .map_err(|e| anyhow::anyhow!("Could not initialize forwarder for {}: {}", zone, e))?;
+
+        tracing::info!("Forwarding {} to its own upstreams", zone);
+        catalog.upsert(zone.into(), Box::new(Arc::new(forwarder)));
+    }
+
     catalog.upsert(
         zt.forward_authority.domain_name.clone(),
         zt.forward_authority.box_clone(),
     );
 
-    for (network, authority) in zt.reverse_authority_map {
-        catalog.upsert(network.to_ptr_soa_name()?, authority.box_clone())
+    for alias in zt.alias_authorities {
+        catalog.upsert(alias.domain_name.clone(), alias.box_clone());
+    }
+
+    for authority in zt.reverse_authority_map.values() {
+        catalog.upsert(authority.domain_name.clone(), authority.box_clone())
+    }
+
+    if let Some(dynamic) = zt.dynamic_zone {
+        let authority = dynamic.authority();
+        catalog.upsert(authority.domain_name.clone(), authority.box_clone());
     }
 
     Ok(catalog)
@@ -96,84 +316,356 @@ pub async fn init_catalog(zt: ZTAuthority) -> Result<Catalog, anyhow::Error> {
 #[derive(Clone)]
 pub struct ZTAuthority {
     pub network_id: String,
-    pub hosts_file: Option<PathBuf>,
-    pub client: zerotier_central_api::Client,
+    pub hosts_files: Vec<PathBuf>,
+    pub controller: Controller,
     pub reverse_authority_map: HashMap<IpNetwork, RecordAuthority>,
     pub forward_authority: RecordAuthority,
+    // the same records as forward_authority, served under other domains.
+    pub alias_authorities: Vec<RecordAuthority>,
+    pub forward_zones: HashMap<Name, Vec<SocketAddr>>,
+    // the resolvers forwarded queries are sent to instead of the system's; see crate::upstream.
+    pub upstreams: Option<Upstreams>,
     pub wildcard: bool,
+    // the members given wildcard names without wildcard mode, by member ID or name.
+    pub wildcard_members: Vec<String>,
+    // how member names which are not valid hostnames are turned into ones.
+    pub name_policy: NamePolicy,
+    // what is served for names members share; see crate::collisions.
+    pub name_collisions: CollisionPolicy,
+    // the members which get records; see crate::filter.
+    pub member_filter: MemberFilter,
+    // whether members are also served as <member id>.<domain>, without the zt- prefix.
+    pub node_id_names: bool,
+    // whether the PTR records of members' addresses point at all of their names, not just one.
+    pub all_ptr_names: bool,
+    // whether members get a TXT record with their metadata; see member_txt.
+    pub member_txt: bool,
+    // the most records members get, and what is left out beyond it; see crate::limits.
+    pub max_records: Option<usize>,
+    pub record_eviction: RecordEviction,
     pub update_interval: Duration,
     pub hosts: Option<Box<HostsFile>>,
+    pub reload: Option<watch::Receiver<Reload>>,
+    // where the last members fetched from Central are saved; see crate::snapshot.
+    pub snapshot: Option<Saved>,
+    // where the statistics of the network are kept across restarts; see crate::store.
+    pub store: Option<Store>,
+    // the static records from the configuration file; see crate::records.
+    pub records: Records,
+    // the zone members register names in with dynamic updates; see crate::update.
+    pub dynamic_zone: Option<DynamicZone>,
+    // whether the answers to forwarded queries are validated with DNSSEC; see server::Handler.
+    pub dnssec_validation: bool,
+    // whether forwarded queries are resolved a label at a time; see crate::upstream.
+    pub qname_minimization: bool,
+    // whether names such as ip-10-147-17-5.<domain> are answered; see crate::ipnames.
+    pub ip_names: bool,
+    // the EDNS options of answers; see crate::edns.
+    pub edns: EdnsConfig,
+    // how long a query may take to answer before SERVFAIL is sent instead; see server::Handler.
+    pub query_timeout: Option<Duration>,
+    // the clients requests are answered for, if restricted; see crate::acl.
+    pub query_acl: Option<QueryAcl>,
+    // where requests are logged, if anywhere; see crate::querylog.
+    pub query_log: Option<QueryLog>,
+    // where dnstap messages of requests are sent, if anywhere; see crate::dnstap.
+    pub dnstap: Option<Dnstap>,
+    // what the health endpoint reports of the network; see crate::health.
+    pub health: Health,
+    // refreshes and temporary records requested through the admin API; see crate::admin.
+    pub control: Control,
+    // the members last fetched, handed to secondaries; see crate::replication.
+    pub replica: Replica,
+    // the answers to forwarded queries, if they are cached; see crate::cache.
+    pub cache: Option<ForwardCache>,
+    // the names forwarded queries are not answered for; see crate::blocklist.
+    pub blocklist: Blocklist,
+    // the response policy zones applied to queries; see crate::rpz.
+    pub rpz: Rpz,
+    // the views clients are answered in, and the zones of those with names of their own; see
+    // crate::views.
+    pub views: Views,
+    pub view_zones: Vec<ViewZones>,
 }
 
 impl ZTAuthority {
+    fn apply(&mut self, reload: Reload) {
+        self.controller.reload(&reload);
+        self.hosts_files = reload.hosts_files;
+        self.wildcard = reload.wildcard;
+        self.wildcard_members = reload.wildcard_members;
+        self.name_policy = reload.name_policy;
+        self.name_collisions = reload.name_collisions;
+        self.member_filter = reload.member_filter;
+        self.node_id_names = reload.node_id_names;
+        self.all_ptr_names = reload.all_ptr_names;
+        self.member_txt = reload.member_txt;
+        self.update_interval = reload.update_interval;
+        self.records = reload.records;
+
+        if let Err(e) = self.blocklist.load(&reload.blocklists, reload.block_action) {
+            tracing::error!("{}; keeping the names blocked so far", e);
+        }
+
+        if let Err(e) = self.rpz.load(&reload.rpz) {
+            tracing::error!("{}; keeping the policy zones loaded so far", e);
+        }
+    }
+
+    // wildcard_for tells whether the member gets wildcard names: all do in wildcard mode.
+    fn wildcard_for(
+        &self,
+        network: &zerotier_central_api::types::Network,
+        member: &zerotier_central_api::types::Member,
+    ) -> bool {
+        self.wildcard || member_wildcard(&self.wildcard_members, network, member)
+    }
+
+    // resign renews the signatures of the zones served which are due, including those of zones
+    // which have not changed in a while.
+    async fn resign(&self) {
+        let zones = std::iter::once(&self.forward_authority)
+            .chain(self.alias_authorities.iter())
+            .chain(self.reverse_authority_map.values())
+            .chain(self.view_zones.iter().flat_map(|zones| zones.zones()));
+
+        for zone in zones {
+            if let Err(e) = zone.resign().await {
+                tracing::error!("Could not sign {}: {}", zone.domain_name, e);
+            }
+        }
+
+        if let Some(dynamic) = &self.dynamic_zone {
+            if let Err(e) = dynamic.resign().await {
+                tracing::error!("Could not sign {}: {}", dynamic.authority().domain_name, e);
+            }
+        }
+    }
+
+    // zones are the zones served for the network, the dynamic zone included.
+    fn zones(&self) -> impl Iterator<Item = &RecordAuthority> {
+        std::iter::once(&self.forward_authority)
+            .chain(self.alias_authorities.iter())
+            .chain(self.reverse_authority_map.values())
+            .chain(self.dynamic_zone.iter().map(DynamicZone::authority))
+    }
+
+    // record_count is the number of records served in the zones of the network, signatures aside.
+    async fn record_count(&self) -> usize {
+        let mut count = 0;
+
+        for zone in self.zones() {
+            count += zone.record_count().await;
+        }
+
+        count
+    }
+
+    // records are the records served in each zone of the network, signatures aside, with the SOA
+    // record of the zone first.
+    pub async fn records(&self) -> Vec<(Name, Vec<Record>)> {
+        let mut zones = Vec::new();
+
+        for zone in self.zones() {
+            let mut records = zone.records().await;
+            records.sort_by_key(|record| record.record_type() != RecordType::SOA);
+            zones.push((zone.origin().clone().into(), records));
+        }
+
+        zones
+    }
+
+    // static_records are the static records of the configuration file, with the temporary ones
+    // added through the admin API.
+    fn static_records(&self) -> Records {
+        let mut records = self.records.clone();
+
+        for (name, values) in self.control.records() {
+            records.entry(name).or_default().extend(values);
+        }
+
+        records
+    }
+
     pub async fn configure_hosts(&mut self) -> Result<(), anyhow::Error> {
-        self.hosts = Some(Box::new(parse_hosts(
-            self.hosts_file.clone(),
+        let hosts = parse_hosts_files(
+            &self.hosts_files,
             self.forward_authority.domain_name.clone().into(),
-        )?));
+        )?;
 
-        for (ip, hostnames) in self.hosts.clone().unwrap().iter() {
-            for hostname in hostnames {
-                self.forward_authority
-                    .match_or_insert(hostname.clone(), &[*ip])
-                    .await;
-            }
+        if let Some(old) = &self.hosts {
+            log_hosts_diff(old, &hosts);
         }
 
+        self.hosts = Some(Box::new(hosts));
+
         Ok(())
     }
 
+    // save_snapshot saves what Central returned, so that restore_snapshot can serve it later.
+    fn save_snapshot(
+        &self,
+        network: &zerotier_central_api::types::Network,
+        members: &[zerotier_central_api::types::Member],
+    ) {
+        if let Some(saved) = &self.snapshot {
+            let snapshot = Snapshot {
+                network: network.clone(),
+                members: members.to_vec(),
+            };
+
+            if let Err(e) = snapshot.save(saved) {
+                tracing::warn!("Could not save members to {}: {}", saved, e);
+            }
+        }
+    }
+
+    // count adds one to a counter of the network in the state database, if there is one.
+    fn count(&self, name: &str) {
+        if let Some(store) = &self.store {
+            if let Err(e) = store.count(&self.network_id, name, 1) {
+                tracing::warn!(
+                    "Could not update the statistics of {}: {}",
+                    self.network_id,
+                    e
+                );
+            }
+        }
+    }
+
+    // record_poll counts a successful poll of Central, and saves when it happened so that the
+    // health endpoint can report it after a restart.
+    fn record_poll(&self) {
+        self.count("polls");
+
+        if let Some(store) = &self.store {
+            let now = SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map_or(0, |d| d.as_secs());
+
+            if let Err(e) = store.set(&self.network_id, LAST_POLL, now) {
+                tracing::warn!(
+                    "Could not update the statistics of {}: {}",
+                    self.network_id,
+                    e
+                );
+            }
+        }
+    }
+
+    // statistics returns the statistics kept of the network in the state database, by name; there
+    // are none without one.
+    pub fn statistics(&self) -> Result<BTreeMap<String, u64>, anyhow::Error> {
+        match &self.store {
+            Some(store) => store.statistics(&self.network_id),
+            None => Ok(BTreeMap::new()),
+        }
+    }
+
+    // restore_snapshot serves the members last saved by save_snapshot, returning their
+    // fingerprint if there were any.
+    async fn restore_snapshot(&self) -> Option<u64> {
+        let saved = self.snapshot.as_ref()?;
+
+        let snapshot = match Snapshot::load(saved) {
+            Ok(snapshot) => snapshot?,
+            Err(e) => {
+                tracing::error!("Could not read members from {}: {}", saved, e);
+                return None;
+            }
+        };
+
+        tracing::warn!(
+            "Serving the last known members of {} from {}",
+            self.network_id,
+            saved
+        );
+        self.count("restores");
+
+        let fp = self.fingerprint(&snapshot.network, &snapshot.members);
+        self.replica.set(&snapshot.network, &snapshot.members);
+
+        match self
+            .configure_members(snapshot.network, snapshot.members)
+            .await
+        {
+            Ok(_) => Some(fp),
+            Err(e) => {
+                tracing::error!("error configuring authority: {}", e);
+                None
+            }
+        }
+    }
+
+    // configure_members builds fresh copies of the forward and reverse authorities from the
+    // static records, hosts file and member list, then swaps them in. Lookups continue to be
+    // answered from the previous copies while this happens.
     pub async fn configure_members(
         &self,
         network: zerotier_central_api::types::Network,
         members: Vec<zerotier_central_api::types::Member>,
     ) -> Result<(), anyhow::Error> {
-        let mut forward_records = vec![self.forward_authority.domain_name.clone()];
-        let mut reverse_records = HashMap::new();
+        // members de-authorized since the last poll are refused before their records are gone.
+        if let Some(acl) = &self.query_acl {
+            acl.sync_members(&members);
+        }
 
-        self.reverse_authority_map
-            .iter()
-            .for_each(|(network, authority)| {
-                reverse_records.insert(network, vec![authority.domain_name.clone()]);
-            });
+        self.views.sync_members(&network, &members);
 
-        if let Some(hosts) = self.hosts.clone() {
-            self.forward_authority.prune_hosts(hosts.clone()).await?;
-            forward_records.append(&mut hosts.values().flatten().map(|v| v.into()).collect());
+        let (sixplane, rfc4193) = ipv6_networks(&network)?;
+
+        // members filtered out get no records at all, not even zt-<member id>.
+        let served = self.served_members(&network, &members, &self.member_filter);
+
+        if served.len() < members.len() {
+            tracing::debug!(
+                "Serving {} of {} members; the others are filtered out",
+                served.len(),
+                members.len()
+            );
         }
 
-        let (mut sixplane, mut rfc4193) = (None, None);
+        let (served, left_out, wildcards) =
+            self.limit_members(&network, served, sixplane, rfc4193)?;
 
-        let v6assign = network.config.clone().unwrap().v6_assign_mode;
-        if let Some(v6assign) = v6assign {
-            if v6assign._6plane.unwrap_or(false) {
-                let s = network.clone().sixplane()?;
-                sixplane = Some(s);
-            }
+        let (forward_authority, alias_authorities) = self
+            .stage_names(
+                &self.forward_authority,
+                &self.alias_authorities,
+                &network,
+                &served,
+                &self.static_records(),
+                sixplane,
+                rfc4193,
+                wildcards,
+            )
+            .await?;
 
-            if v6assign.rfc4193.unwrap_or(false) {
-                let s = network.clone().rfc4193()?;
-                rfc4193 = Some(s);
-                reverse_records
-                    .get_mut(&s)
-                    .unwrap()
-                    .push(s.to_ptr_soa_name()?)
-            }
+        let mut reverse_authority_map = HashMap::new();
+
+        for (network, authority) in self.reverse_authority_map.iter() {
+            reverse_authority_map.insert(*network, authority.stage().await?);
         }
 
-        for member in members {
+        // the names requests are attributed to in the query log.
+        let mut names = HashMap::new();
+
+        for member in served {
             let record = ZTRecord::new(
                 &member,
                 sixplane,
                 rfc4193,
                 self.forward_authority.domain_name.clone().into(),
-                self.wildcard,
+                self.wildcard_for(&network, &member),
+                self.name_policy,
+                self.node_id_names,
             )?;
 
-            self.forward_authority
-                .insert_member(&mut forward_records, record.clone())
-                .await?;
+            for ip in record.ips.iter() {
+                names.insert(*ip, record.ptr_name.clone());
+            }
 
+            // PTR records only point at names in the primary domain.
             if let Some(ips) = member.clone().config.and_then(|c| {
                 c.ip_assignments.map(|v| {
                     v.iter()
@@ -181,48 +673,340 @@ impl ZTAuthority {
                         .collect::<Vec<IpAddr>>()
                 })
             }) {
-                for (network, authority) in self.reverse_authority_map.clone() {
+                for (network, authority) in reverse_authority_map.iter() {
                     for ip in ips.clone() {
                         if network.contains(ip) {
                             authority
-                                .insert_member_ptr(
-                                    reverse_records.get_mut(&network).unwrap(),
-                                    record.clone(),
-                                )
+                                .insert_member_ptr(record.clone(), self.all_ptr_names)
                                 .await?;
                         }
                     }
                 }
             }
 
-            if let Some(ptr) = rfc4193 {
-                if let Some(authority) = self.reverse_authority_map.get(&ptr) {
-                    if let Some(records) = reverse_records.get_mut(&ptr) {
-                        let ptr = member.rfc4193()?.ip().into_name()?;
+            // the calculated v6 addresses live in their own ip6.arpa zones.
+            for (network, ip) in [
+                (sixplane, member.clone().sixplane()),
+                (rfc4193, member.clone().rfc4193()),
+            ] {
+                if let Some(network) = network {
+                    if let Some(authority) = reverse_authority_map.get(&network) {
                         authority
-                            .configure_ptr(ptr.clone(), record.ptr_name.clone())
+                            .configure_ptr(
+                                ip?.ip().into_name()?,
+                                record.ptr_names(self.all_ptr_names),
+                            )
                             .await?;
-                        records.push(ptr.into());
                     }
                 }
             }
         }
 
-        self.forward_authority
-            .prune_records(forward_records.clone())
-            .await?;
+        if let Some(log) = &self.query_log {
+            log.sync_members(names);
+        }
 
-        for (network, authority) in self.reverse_authority_map.clone() {
-            authority
-                .prune_records(reverse_records.get(&network).unwrap().clone())
+        self.forward_authority.commit(&forward_authority).await;
+
+        for (alias, staged) in self.alias_authorities.iter().zip(alias_authorities.iter()) {
+            alias.commit(staged).await;
+        }
+
+        for (network, authority) in self.reverse_authority_map.iter() {
+            if let Some(staged) = reverse_authority_map.get(network) {
+                authority.commit(staged).await;
+            }
+        }
+
+        // views with names of their own get their members and records in their own zones, short
+        // of those left out by the record limit.
+        for zones in &self.view_zones {
+            let view = &self.views.views()[zones.view];
+            let mut served = self.served_members(
+                &network,
+                &members,
+                &view.member_filter(self.member_filter.authorized_only),
+            );
+            served.retain(|member| !left_out.contains(&member.node_id));
+
+            tracing::debug!(
+                "Serving {} of {} members to {}",
+                served.len(),
+                members.len(),
+                view.label(zones.view)
+            );
+
+            let mut records = self.static_records();
+            for (name, values) in view.records.clone() {
+                records.entry(name).or_default().extend(values);
+            }
+
+            let (forward_authority, alias_authorities) = self
+                .stage_names(
+                    &zones.forward_authority,
+                    &zones.alias_authorities,
+                    &network,
+                    &served,
+                    &records,
+                    sixplane,
+                    rfc4193,
+                    wildcards,
+                )
                 .await?;
+
+            zones.forward_authority.commit(&forward_authority).await;
+
+            for (alias, staged) in zones.alias_authorities.iter().zip(alias_authorities.iter()) {
+                alias.commit(staged).await;
+            }
         }
 
         Ok(())
     }
 
+    // limit_members leaves members out, or their wildcard names, when they would get more records
+    // than max_records, returning those served, the IDs of those left out and whether they get
+    // their wildcard names. The records of a member are counted in every zone of the domain, and
+    // its PTR records too.
+    fn limit_members(
+        &self,
+        network: &zerotier_central_api::types::Network,
+        members: Vec<zerotier_central_api::types::Member>,
+        sixplane: Option<IpNetwork>,
+        rfc4193: Option<IpNetwork>,
+    ) -> Result<
+        (
+            Vec<zerotier_central_api::types::Member>,
+            HashSet<Option<String>>,
+            bool,
+        ),
+        anyhow::Error,
+    > {
+        let max = match self.max_records {
+            Some(max) => max,
+            None => return Ok((members, HashSet::new(), true)),
+        };
+
+        let counts = self.member_records(network, &members, sixplane, rfc4193)?;
+        let limited = limit(&counts, max, self.record_eviction);
+        let members_left_out = members.len() - limited.served.len();
+        let wildcards_dropped =
+            !limited.wildcards && counts.iter().any(|c| c.records > c.without_wildcards);
+
+        // what is left out is only logged when it changes, not at every poll.
+        let previous = self.health.status();
+        if (previous.members_left_out, previous.wildcards_dropped)
+            != (members_left_out, wildcards_dropped)
+        {
+            if members_left_out > 0 || wildcards_dropped {
+                tracing::warn!(
+                    "The members would get more than the {} records max_records allows; serving {} of {} members{}",
+                    max,
+                    limited.served.len(),
+                    members.len(),
+                    if wildcards_dropped { ", without wildcards" } else { "" }
+                );
+            } else {
+                tracing::info!("The records of all members fit within max_records again");
+            }
+        }
+
+        self.health
+            .limited(max, members_left_out, wildcards_dropped);
+
+        let mut served = Vec::with_capacity(limited.served.len());
+        let mut left_out = HashSet::new();
+        let mut kept = limited.served.into_iter().peekable();
+
+        for (i, member) in members.into_iter().enumerate() {
+            if kept.next_if_eq(&i).is_some() {
+                served.push(member);
+            } else {
+                left_out.insert(member.node_id);
+            }
+        }
+
+        Ok((served, left_out, limited.wildcards))
+    }
+
+    // member_records counts the records of each member, in every zone of the domain and its PTR
+    // records too, with and without its wildcard names.
+    fn member_records(
+        &self,
+        network: &zerotier_central_api::types::Network,
+        members: &[zerotier_central_api::types::Member],
+        sixplane: Option<IpNetwork>,
+        rfc4193: Option<IpNetwork>,
+    ) -> Result<Vec<MemberRecords>, anyhow::Error> {
+        let zones = 1 + self.alias_authorities.len();
+
+        members
+            .iter()
+            .map(|member| {
+                let record = ZTRecord::new(
+                    member,
+                    sixplane,
+                    rfc4193,
+                    self.forward_authority.domain_name.clone().into(),
+                    self.wildcard_for(network, member),
+                    self.name_policy,
+                    self.node_id_names,
+                )?;
+
+                Ok(MemberRecords {
+                    records: record.record_count(true) * zones + record.ips.len(),
+                    without_wildcards: record.record_count(false) * zones + record.ips.len(),
+                    last_online: member.last_online,
+                })
+            })
+            .collect()
+    }
+
+    // fingerprint tells whether the records served would change; see central::fingerprint. Which
+    // members max_records leaves out depends on when they were last online, which that ignores as
+    // it changes at every poll, so the members the limit would serve are hashed in instead.
+    pub fn fingerprint(
+        &self,
+        network: &zerotier_central_api::types::Network,
+        members: &[zerotier_central_api::types::Member],
+    ) -> u64 {
+        let fp = fingerprint(network, members, self.hosts.as_deref());
+
+        let max = match self.max_records {
+            Some(max) => max,
+            None => return fp,
+        };
+
+        let served = self.served_members(network, members, &self.member_filter);
+        let limited = match ipv6_networks(network).and_then(|(sixplane, rfc4193)| {
+            self.member_records(network, &served, sixplane, rfc4193)
+        }) {
+            Ok(counts) => limit(&counts, max, self.record_eviction),
+            // configuring the members fails the same way, and is tried again at the next poll.
+            Err(_) => return fp,
+        };
+
+        let mut hasher = DefaultHasher::new();
+        fp.hash(&mut hasher);
+        for i in limited.served {
+            served[i].node_id.hash(&mut hasher);
+        }
+        limited.wildcards.hash(&mut hasher);
+        hasher.finish()
+    }
+
+    // served_members are the members the filter lets through, renamed where their names collide.
+    fn served_members(
+        &self,
+        network: &zerotier_central_api::types::Network,
+        members: &[zerotier_central_api::types::Member],
+        filter: &MemberFilter,
+    ) -> Vec<zerotier_central_api::types::Member> {
+        let members = members
+            .iter()
+            .filter(|member| filter.allows(network, member))
+            .cloned()
+            .collect();
+
+        resolve_collisions(
+            members,
+            self.hosts.as_deref(),
+            &self.forward_authority.domain_name.clone().into(),
+            self.name_policy,
+            self.name_collisions,
+        )
+    }
+
+    // stage_names stages the domain and its aliases with the static records, the hosts files and
+    // the names of the members given, returning the staged zones for them to be committed.
+    // Without wildcards, no member gets wildcard names.
+    #[allow(clippy::too_many_arguments)]
+    async fn stage_names(
+        &self,
+        forward: &RecordAuthority,
+        aliases: &[RecordAuthority],
+        network: &zerotier_central_api::types::Network,
+        members: &[zerotier_central_api::types::Member],
+        records: &Records,
+        sixplane: Option<IpNetwork>,
+        rfc4193: Option<IpNetwork>,
+        wildcards: bool,
+    ) -> Result<(RecordAuthority, Vec<RecordAuthority>), anyhow::Error> {
+        let forward_authority = forward.stage().await?;
+        let mut alias_authorities = Vec::new();
+
+        for alias in aliases {
+            alias_authorities.push(alias.stage().await?);
+        }
+
+        // the dynamic zone is delegated to zeronsd itself, so that secondaries of the domain find
+        // it too. Signed, it is vouched for with its DS record.
+        if let Some(dynamic) = &self.dynamic_zone {
+            let origin: Name = dynamic.authority().domain_name.clone().into();
+            let domain_name: Name = forward_authority.domain_name.clone().into();
+
+            if domain_name.zone_of(&origin) {
+                let mut delegation = vec![(
+                    origin.clone(),
+                    RData::NS(forward_authority.member_name.clone().into()),
+                )];
+                delegation.extend(dynamic.authority().ds().map(|ds| (origin, ds)));
+
+                forward_authority.insert_records(delegation).await;
+            }
+        }
+
+        for staged in std::iter::once(&forward_authority).chain(alias_authorities.iter()) {
+            staged
+                .insert_records(parse_records(records, &staged.domain_name.clone().into())?)
+                .await;
+        }
+
+        if let Some(hosts) = self.hosts.clone() {
+            forward_authority.insert_hosts(&hosts).await;
+
+            let domain_name: Name = forward.domain_name.clone().into();
+            for alias in alias_authorities.iter() {
+                alias
+                    .insert_hosts(&rebase_hosts(
+                        &hosts,
+                        &domain_name,
+                        &alias.domain_name.clone().into(),
+                    ))
+                    .await;
+            }
+        }
+
+        for member in members {
+            let wildcard = wildcards && self.wildcard_for(network, member);
+            let services = member_services(network, member);
+            let txt = self.member_txt.then(|| member_txt(member));
+
+            for staged in std::iter::once(&forward_authority).chain(alias_authorities.iter()) {
+                let record = ZTRecord::new(
+                    member,
+                    sixplane,
+                    rfc4193,
+                    staged.domain_name.clone().into(),
+                    wildcard,
+                    self.name_policy,
+                    self.node_id_names,
+                )?;
+
+                staged.insert_member(record.clone()).await?;
+                staged.insert_txt(&record, txt.clone()).await;
+                staged.insert_services(&services, record.ptr_name).await?;
+            }
+        }
+
+        Ok((forward_authority, alias_authorities))
+    }
+
+    // get_members fetches the network and its members from their source, retrying transient
+    // failures.
     pub async fn get_members(
         &self,
+        policy: &RetryPolicy,
     ) -> Result<
         (
             zerotier_central_api::types::Network,
@@ -230,174 +1014,660 @@ impl ZTAuthority {
         ),
         anyhow::Error,
     > {
-        let client = self.client.clone();
+        let controller = &self.controller;
         let network_id = self.network_id.clone();
 
-        let members = client.get_network_member_list(&network_id).await?;
-        let network = client.get_network_by_id(&network_id).await?;
+        let members = policy
+            .run("Fetching members", || controller.members(&network_id))
+            .await?;
+        let network = policy
+            .run("Fetching the network", || controller.network(&network_id))
+            .await?;
 
-        Ok((network.to_owned(), members.to_owned()))
+        Ok((network, members))
     }
 }
 
+// RecordAuthority is a zone served by zeronsd. The records live in an InMemoryAuthority which is
+// never edited while it is being served: updates are made to a staged copy (see `stage`), which
+// then atomically replaces the served one (see `commit`).
 #[derive(Clone)]
 pub struct RecordAuthority {
     domain_name: LowerName,
-    authority: Arc<InMemoryAuthority>,
+    member_name: LowerName,
+    authority: Arc<ArcSwap<InMemoryAuthority>>,
+    ttl: u32,
+    soa: SoaConfig,
+    // clients allowed to transfer the zone with AXFR.
+    transfers: Arc<Vec<IpNetwork>>,
+    // keys transfers must be signed with, if any.
+    keys: Arc<Vec<Key>>,
+    // secondaries sent a NOTIFY when the zone changes.
+    notify: Arc<Vec<SocketAddr>>,
+    // the last changes of the zone, for incremental transfers.
+    history: History,
+    // the key the zone is signed with, if any; see crate::dnssec.
+    signing: Option<ZoneKey>,
+    // whether ANY queries get a minimal answer; see `minimal_any`.
+    minimal_any: bool,
+    // how the addresses of answers are ordered; see crate::order.
+    ordering: AnswerOrdering,
+    // the addresses of answers clients are given by network; see crate::views.
+    views: Views,
+    // the delegated zone a reverse zone is, if it is classless; see crate::classless.
+    classless: Option<Classless>,
+    // where changes to the records are sent; see crate::hooks.
+    hooks: RecordHooks,
+}
+
+// SoaConfig overrides parts of the SOA record of the zones served. Names are relative to the zone
+// unless they end in a period, and rname may also be given as an email address.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SoaConfig {
+    pub mname: Option<String>,
+    pub rname: Option<String>,
+    pub refresh: Option<i32>,
+    pub retry: Option<i32>,
+    pub expire: Option<i32>,
+    pub minimum: Option<u32>,
+}
+
+impl SoaConfig {
+    // soa builds the SOA of a zone. The primary nameserver defaults to the name of the member
+    // zeronsd runs on, and the responsible person to `administrator` in the zone.
+    pub fn soa(
+        &self,
+        domain_name: &Name,
+        member_name: &Name,
+        serial: u32,
+        ttl: u32,
+    ) -> Result<SOA, anyhow::Error> {
+        let mname = match &self.mname {
+            Some(mname) => parse_name(mname, domain_name)?,
+            None => member_name.clone(),
+        };
+
+        let rname = match &self.rname {
+            Some(rname) => match rname.split_once('@') {
+                Some((local, _)) if local.contains('.') => {
+                    return Err(anyhow!(
+                        "SOA rname {} cannot be written as a name; its local part contains a '.'",
+                        rname
+                    ))
+                }
+                Some((local, domain)) => {
+                    Name::from_str(&format!("{}.{}.", local, domain.trim_end_matches('.')))?
+                }
+                None => parse_name(rname, domain_name)?,
+            },
+            None => Name::from_str("administrator")?.append_domain(domain_name)?,
+        };
+
+        Ok(SOA::new(
+            mname,
+            rname,
+            serial,
+            self.refresh.unwrap_or(DEFAULT_SOA_REFRESH),
+            self.retry.unwrap_or(DEFAULT_SOA_RETRY),
+            self.expire.unwrap_or(DEFAULT_SOA_EXPIRE),
+            self.minimum.unwrap_or(ttl),
+        ))
+    }
 }
 
 impl RecordAuthority {
+    // ttl is the TTL, in seconds, of every record served by the authority.
     pub async fn new(
         domain_name: LowerName,
         member_name: LowerName,
+        ttl: u32,
+    ) -> Result<Self, anyhow::Error> {
+        Self::with_soa(domain_name, member_name, ttl, SoaConfig::default()).await
+    }
+
+    // with_soa is like new, with the SOA record customized.
+    pub async fn with_soa(
+        domain_name: LowerName,
+        member_name: LowerName,
+        ttl: u32,
+        soa: SoaConfig,
     ) -> Result<Self, anyhow::Error> {
         Ok(Self {
-            authority: Arc::new(
-                Self::configure_authority(domain_name.clone().into(), member_name.into()).await?,
-            ),
+            authority: Arc::new(ArcSwap::from_pointee(
+                Self::configure_authority(
+                    domain_name.clone().into(),
+                    member_name.clone().into(),
+                    ttl,
+                    &soa,
+                    initial_serial(),
+                )
+                .await?,
+            )),
             domain_name,
+            member_name,
+            ttl,
+            soa,
+            transfers: Arc::new(Vec::new()),
+            keys: Arc::new(Vec::new()),
+            notify: Arc::new(Vec::new()),
+            history: History::default(),
+            signing: None,
+            minimal_any: false,
+            ordering: AnswerOrdering::default(),
+            views: Views::default(),
+            classless: None,
+            hooks: RecordHooks::default(),
         })
     }
 
+    // with_transfers allows the given clients to transfer the zone with AXFR.
+    pub fn with_transfers(mut self, clients: Vec<IpNetwork>) -> Self {
+        self.transfers = Arc::new(clients);
+        self
+    }
+
+    // with_keys requires transfers of the zone to be signed with one of the given TSIG keys.
+    pub fn with_keys(mut self, keys: Vec<Key>) -> Self {
+        self.keys = Arc::new(keys);
+        self
+    }
+
+    // ttl returns the TTL, in seconds, of the records of the zone.
+    pub fn ttl(&self) -> u32 {
+        self.ttl
+    }
+
+    // keys returns the TSIG keys transfers must be signed with.
+    pub fn keys(&self) -> &[Key] {
+        &self.keys
+    }
+
+    // with_notify sends a NOTIFY to the given secondaries whenever the zone changes.
+    pub fn with_notify(mut self, secondaries: Vec<SocketAddr>) -> Self {
+        self.notify = Arc::new(secondaries);
+        self
+    }
+
+    // with_hooks sends the changes of the zone to the record-change webhooks.
+    pub fn with_hooks(mut self, hooks: RecordHooks) -> Self {
+        self.hooks = hooks;
+        self
+    }
+
+    // with_signing signs the zone with the given DNSSEC key whenever it changes.
+    pub fn with_signing(mut self, key: ZoneKey) -> Self {
+        self.signing = Some(key);
+        self
+    }
+
+    // with_minimal_any answers ANY queries with a single record instead of every record of the
+    // name (RFC 8482).
+    pub fn with_minimal_any(mut self, minimal_any: bool) -> Self {
+        self.minimal_any = minimal_any;
+        self
+    }
+
+    // with_answer_order orders the addresses of A and AAAA answers; see crate::order.
+    pub fn with_answer_order(mut self, ordering: AnswerOrdering) -> Self {
+        self.ordering = ordering;
+        self
+    }
+
+    // with_views answers clients with the addresses their view prefers; see crate::views.
+    pub fn with_views(mut self, views: Views) -> Self {
+        self.views = views;
+        self
+    }
+
+    // with_classless serves the PTR records of IPv4 addresses under the delegated zone of a network
+    // smaller than a /24; see crate::classless.
+    pub fn with_classless(mut self, classless: Option<Classless>) -> Self {
+        self.classless = classless;
+        self
+    }
+
+    pub fn classless(&self) -> Option<&Classless> {
+        self.classless.as_ref()
+    }
+
+    // ds returns the DS record of the zone, if it is signed.
+    pub fn ds(&self) -> Option<RData> {
+        let key = self.signing.as_ref()?;
+
+        match key.ds(&self.domain_name.clone().into()) {
+            Ok(ds) => Some(RData::DNSSEC(DNSSECRData::DS(ds))),
+            Err(e) => {
+                tracing::error!(
+                    "Could not compute the DS record of {}: {}",
+                    self.domain_name,
+                    e
+                );
+                None
+            }
+        }
+    }
+
+    // transfer_allowed tells whether the client may transfer the zone. Without a list of clients,
+    // transfers are allowed from anywhere as long as they are signed; see `with_keys`.
+    pub fn transfer_allowed(&self, client: IpAddr) -> bool {
+        if self.transfers.is_empty() {
+            return !self.keys.is_empty();
+        }
+
+        self.transfers
+            .iter()
+            .any(|network| network.contains(client))
+    }
+
     async fn configure_authority(
         domain_name: Name,
         member_name: Name,
+        ttl: u32,
+        soa_config: &SoaConfig,
+        serial: u32,
     ) -> Result<InMemoryAuthority, anyhow::Error> {
         let mut map = BTreeMap::new();
-        let mut soa = Record::with(domain_name.clone(), RecordType::SOA, 30);
-
-        soa.set_data(Some(RData::SOA(SOA::new(
-            domain_name.clone(),
-            Name::from_str("administrator")?.append_domain(&domain_name)?,
-            1,
-            30,
-            0,
-            -1,
-            0,
-        ))));
-
-        let mut soa_rs = RecordSet::new(&domain_name, RecordType::SOA, 1);
-        soa_rs.insert(soa, 1);
+        let mut soa = Record::with(domain_name.clone(), RecordType::SOA, ttl);
+
+        soa.set_data(Some(RData::SOA(soa_config.soa(
+            &domain_name,
+            &member_name,
+            serial,
+            ttl,
+        )?)));
+
+        let mut soa_rs = RecordSet::new(&domain_name, RecordType::SOA, serial);
+        soa_rs.insert(soa, serial);
         map.insert(
             RrKey::new(domain_name.clone().into(), RecordType::SOA),
             soa_rs,
         );
 
-        let mut ns = Record::with(domain_name.clone(), RecordType::NS, 30);
+        let mut ns = Record::with(domain_name.clone(), RecordType::NS, ttl);
         ns.set_data(Some(RData::NS(member_name)));
-        let mut ns_rs = RecordSet::new(&domain_name, RecordType::NS, 1);
-        ns_rs.insert(ns, 1);
+        let mut ns_rs = RecordSet::new(&domain_name, RecordType::NS, serial);
+        ns_rs.insert(ns, serial);
 
         map.insert(
             RrKey::new(domain_name.clone().into(), RecordType::NS),
             ns_rs,
         );
 
+        // transfers are allowed here and refused in `search` for clients not allowed to make them.
         let authority = InMemoryAuthority::new(
             domain_name,
             map,
             trust_dns_server::authority::ZoneType::Primary,
-            false,
+            true,
         )
         .expect("Could not initialize authority");
 
         Ok(authority)
     }
 
-    async fn replace_ip_record(&self, fqdn: Name, rdatas: Vec<RData>) {
-        let serial = self.authority.serial().await;
-        for rdata in rdatas {
-            let mut address = Record::with(fqdn.clone(), rdata.to_record_type(), 60);
-            address.set_data(Some(rdata.clone()));
-            tracing::info!("Adding new record {}: ({})", fqdn.clone(), rdata);
-            self.authority.upsert(address, serial).await;
+    // current returns the authority being served right now.
+    fn current(&self) -> Arc<InMemoryAuthority> {
+        self.authority.load_full()
+    }
+
+    // stage returns a copy of this authority containing only the SOA and NS records, with the
+    // serial bumped. It is not served until passed to `commit`.
+    pub async fn stage(&self) -> Result<Self, anyhow::Error> {
+        let serial = self.current().serial().await;
+
+        Ok(Self {
+            authority: Arc::new(ArcSwap::from_pointee(
+                Self::configure_authority(
+                    self.domain_name.clone().into(),
+                    self.member_name.clone().into(),
+                    self.ttl,
+                    &self.soa,
+                    serial.wrapping_add(1),
+                )
+                .await?,
+            )),
+            domain_name: self.domain_name.clone(),
+            member_name: self.member_name.clone(),
+            ttl: self.ttl,
+            soa: self.soa.clone(),
+            transfers: self.transfers.clone(),
+            keys: self.keys.clone(),
+            notify: self.notify.clone(),
+            history: self.history.clone(),
+            signing: self.signing.clone(),
+            minimal_any: self.minimal_any,
+            ordering: self.ordering.clone(),
+            views: self.views.clone(),
+            classless: self.classless.clone(),
+            hooks: self.hooks.clone(),
+        })
+    }
+
+    // minimal_any answers an ANY query for the name as RFC 8482 suggests: with a synthesized HINFO
+    // record, or, in signed zones, where that could not be validated, with the first RRset of the
+    // name. None if the name has no records, so that it gets the usual NXDOMAIN or NODATA answer.
+    async fn minimal_any(
+        &self,
+        name: &LowerName,
+        lookup_options: LookupOptions,
+    ) -> Option<Box<dyn LookupObject>> {
+        let current = self.current();
+        let any = current
+            .lookup(name, RecordType::ANY, lookup_options)
+            .await
+            .ok()?;
+
+        let record_type = any
+            .iter()
+            .map(Record::record_type)
+            .find(|record_type| *record_type != RecordType::RRSIG)?;
+
+        if self.signing.is_some() {
+            return current.lookup(name, record_type, lookup_options).await.ok();
         }
+
+        let hinfo = Record::from_rdata(
+            name.clone().into(),
+            self.ttl,
+            RData::HINFO(HINFO::new("RFC8482".to_string(), String::new())),
+        );
+
+        Some(Box::new(AuthLookup::answers(
+            LookupRecords::new(lookup_options, Arc::new(RecordSet::from(hinfo))),
+            None,
+        )))
     }
 
-    async fn prune_hosts(&self, hosts: Box<HostsFile>) -> Result<(), anyhow::Error> {
-        let serial = self.authority.serial().await;
-        let mut rr = self.authority.records_mut().await;
+    // ordered keeps the addresses of an A or AAAA answer the client's view prefers, and puts them
+    // in order. Answers with anything else, such as the CNAME of the name queried, are left as
+    // they are.
+    fn ordered(
+        &self,
+        lookup: Box<dyn LookupObject>,
+        record_type: RecordType,
+        client: IpAddr,
+        lookup_options: LookupOptions,
+    ) -> Box<dyn LookupObject> {
+        let (mut addresses, rrsigs): (Vec<Record>, Vec<Record>) = lookup
+            .iter()
+            .cloned()
+            .partition(|record| record.record_type() != RecordType::RRSIG);
 
-        let mut hosts_map = HashMap::new();
+        if addresses.len() < 2
+            || addresses
+                .iter()
+                .any(|record| record.record_type() != record_type)
+        {
+            return lookup;
+        }
 
-        for (ip, hosts) in hosts.into_iter() {
-            for host in hosts {
-                if !hosts_map.contains_key(&host) {
-                    hosts_map.insert(host.clone(), vec![]);
-                }
+        // the signatures of signed zones cover every address of the name.
+        if self.signing.is_none() {
+            self.views.select(&mut addresses, client);
+        }
+        self.ordering.apply(&mut addresses, client);
+
+        let mut rset =
+            RecordSet::with_ttl(addresses[0].name().clone(), record_type, addresses[0].ttl());
+
+        for record in addresses {
+            rset.insert(record, 0);
+        }
+
+        for rrsig in rrsigs {
+            rset.insert_rrsig(rrsig);
+        }
+
+        Box::new(AuthLookup::answers(
+            LookupRecords::new(lookup_options, Arc::new(rset)),
+            None,
+        ))
+    }
+
+    // record_count is the number of records being served, signatures aside.
+    pub async fn record_count(&self) -> usize {
+        self.current()
+            .records()
+            .await
+            .values()
+            .map(|rset| rset.records_without_rrsigs().count())
+            .sum()
+    }
+
+    // records are the records being served, signatures aside.
+    pub async fn records(&self) -> Vec<Record> {
+        self.current()
+            .records()
+            .await
+            .values()
+            .flat_map(|rset| rset.records_without_rrsigs().cloned())
+            .collect()
+    }
+
+    // serial is the serial of the SOA record being served.
+    pub async fn serial(&self) -> u32 {
+        self.current().serial().await
+    }
 
-                hosts_map.get_mut(&host).unwrap().push(ip);
+    // commit starts serving the records of a staged authority, logging what changed. If the
+    // records are the same as those being served, the staged copy is dropped instead, so that
+    // the serial only increases when the zone actually changes or its signatures are renewed.
+    pub async fn commit(&self, staged: &RecordAuthority) {
+        let new = staged.current();
+
+        if digest(&self.current().records().await) == digest(&new.records().await)
+            && !self.needs_signing().await
+        {
+            tracing::debug!(
+                "No changes to {}; keeping serial {}",
+                self.domain_name,
+                self.serial().await
+            );
+            return;
+        }
+
+        // a zone which cannot be signed would not validate, so the signed one is kept instead.
+        if let Err(e) = staged.sign().await {
+            tracing::error!("Could not sign {}: {}", self.domain_name, e);
+            return;
+        }
+
+        let new_records = new.records().await.clone();
+        let old = self.authority.swap(new.clone());
+        let old_records = old.records().await.clone();
+
+        if let (Some(from), Some(to)) = (
+            soa_record(&old_records, &self.domain_name),
+            soa_record(&new_records, &self.domain_name),
+        ) {
+            let (removed, added) = diff(&old_records, &new_records);
+
+            if let (Some(previous_serial), Some(serial)) = (soa_serial(&from), soa_serial(&to)) {
+                let reported = |records: &[Record]| {
+                    records
+                        .iter()
+                        .filter(|record| !generated(record.record_type()))
+                        .cloned()
+                        .collect::<Vec<_>>()
+                };
+
+                self.hooks.send(RecordChange::new(
+                    &self.domain_name,
+                    previous_serial,
+                    serial,
+                    &reported(&added),
+                    &reported(&removed),
+                ));
             }
+
+            self.history.push(ZoneChange {
+                from,
+                to,
+                removed,
+                added,
+            });
         }
 
-        for (host, ips) in hosts_map.into_iter() {
-            for (rrkey, rset) in rr.clone() {
-                let key = &rrkey.name().into_name().expect("could not parse name");
-                let records = rset.records(false, SupportedAlgorithms::all());
-
-                let rt = rset.record_type();
-                let rdatas: Vec<RData> = ips
-                    .clone()
-                    .into_iter()
-                    .filter_map(|i| match i {
-                        IpAddr::V4(ip) => {
-                            if rt == RecordType::A {
-                                Some(RData::A(ip))
-                            } else {
-                                None
-                            }
-                        }
-                        IpAddr::V6(ip) => {
-                            if rt == RecordType::AAAA {
-                                Some(RData::AAAA(ip))
-                            } else {
-                                None
-                            }
-                        }
-                    })
-                    .collect();
-
-                if key.eq(&host)
-                    && (records.is_empty()
-                        || !records
-                            .map(|r| r.data().unwrap())
-                            .all(|rd| rdatas.contains(rd)))
-                {
-                    let mut new_rset = RecordSet::new(key, rt, serial);
-                    for rdata in rdatas.clone() {
-                        new_rset.add_rdata(rdata);
-                    }
+        if !self.notify.is_empty() {
+            tokio::spawn(notify(self.domain_name.clone().into(), self.notify.clone()));
+        }
 
-                    tracing::warn!("Replacing host record for {} with {:?}", key, ips);
-                    rr.remove(&rrkey);
-                    rr.insert(rrkey.clone(), Arc::new(new_rset));
-                }
+        for (rrkey, rset) in new_records.iter() {
+            if generated(rrkey.record_type) {
+                continue;
+            }
+
+            let data = rdatas(rset);
+            if old_records.get(rrkey).map(|r| rdatas(r)) != Some(data.clone()) {
+                tracing::info!(
+                    "Adding new record {} {}: ({:?})",
+                    rrkey.name(),
+                    rrkey.record_type,
+                    data
+                );
             }
         }
 
-        Ok(())
+        for rrkey in old_records.keys() {
+            if !generated(rrkey.record_type) && !new_records.contains_key(rrkey) {
+                tracing::warn!(
+                    "Removing expired record {} {}",
+                    rrkey.name(),
+                    rrkey.record_type
+                );
+            }
+        }
+    }
+
+    // ixfr returns the answer to an incremental transfer of the zone for a secondary at the given
+    // serial, or to a full transfer (AXFR) without one; see crate::transfer. Signed zones are
+    // always transferred in full, as the history does not keep their signatures.
+    pub async fn ixfr(&self, client_serial: Option<u32>) -> Option<Vec<Record>> {
+        let records = self.current().records().await;
+        let soa = soa_record(&records, &self.domain_name)?;
+        let changes = client_serial
+            .filter(|_| self.signing.is_none())
+            .and_then(|serial| self.history.since(serial));
+
+        Some(ixfr_answers(&soa, client_serial, changes, &records))
+    }
+
+    // sign signs the staged zone with the DNSSEC key, if there is one, adding its DNSKEY and NSEC
+    // records. This bumps the serial once more.
+    async fn sign(&self) -> Result<(), anyhow::Error> {
+        let key = match &self.signing {
+            Some(key) => key,
+            None => return Ok(()),
+        };
+
+        let authority = self.current();
+        authority
+            .add_zone_signing_key(key.signer(&self.domain_name.clone().into())?)
+            .await
+            .map_err(|e| anyhow!("{}", e))?;
+        authority.secure_zone().await.map_err(|e| anyhow!("{}", e))
     }
 
-    async fn prune_records(&self, written: Vec<LowerName>) -> Result<(), anyhow::Error> {
-        let mut rrkey_list = Vec::new();
+    // needs_signing tells whether the signatures of the zone being served are due to be renewed.
+    async fn needs_signing(&self) -> bool {
+        let key = match &self.signing {
+            Some(key) => key,
+            None => return false,
+        };
 
-        let mut rr = self.authority.records_mut().await;
+        let records = self.current().records().await;
+        let rrsigs = records
+            .get(&RrKey::new(self.domain_name.clone(), RecordType::SOA))
+            .map_or(&[][..], |rset| rset.rrsigs());
 
-        for (rrkey, rs) in rr.clone() {
-            let key = &rrkey.name().into_name()?.into();
-            if !written.contains(key) && rs.record_type() != RecordType::SOA {
-                rrkey_list.push(rrkey);
+        key.needs_signing(rrsigs)
+    }
+
+    // resign renews the signatures of the zone once they are due, even if nothing else changed.
+    pub async fn resign(&self) -> Result<(), anyhow::Error> {
+        if !self.needs_signing().await {
+            return Ok(());
+        }
+
+        let staged = self.stage().await?;
+        let serial = staged.serial().await;
+
+        for (rrkey, rset) in self.current().records().await.iter() {
+            if !generated(rrkey.record_type) {
+                for record in rset.records_without_rrsigs() {
+                    staged.current().upsert(record.clone(), serial).await;
+                }
             }
         }
 
-        for rrkey in rrkey_list {
-            tracing::warn!("Removing expired record {}", rrkey.name());
-            rr.remove(&rrkey);
+        self.commit(&staged).await;
+        Ok(())
+    }
+
+    async fn replace_ip_record(&self, fqdn: Name, rdatas: Vec<RData>) {
+        let authority = self.current();
+        let serial = authority.serial().await;
+        for rdata in rdatas {
+            let mut address = Record::with(fqdn.clone(), rdata.to_record_type(), self.ttl);
+            address.set_data(Some(rdata.clone()));
+            authority.upsert(address, serial).await;
+        }
+    }
+
+    // insert_records adds the static records from the configuration file.
+    pub async fn insert_records(&self, records: Vec<(Name, RData)>) {
+        let authority = self.current();
+        let serial = authority.serial().await;
+        for (name, rdata) in records {
+            let mut record = Record::with(name, rdata.to_record_type(), self.ttl);
+            record.set_data(Some(rdata));
+            authority.upsert(record, serial).await;
         }
+    }
+
+    // insert_txt adds the member's TXT record, if any, under each of its names.
+    async fn insert_txt(&self, record: &ZTRecord, txt: Option<RData>) {
+        if let Some(txt) = txt {
+            let names = std::iter::once(record.fqdn.clone())
+                .chain(record.custom_name.clone())
+                .chain(record.node_name.clone());
+            self.insert_records(names.map(|name| (name, txt.clone())).collect())
+                .await;
+        }
+    }
 
+    // insert_services adds SRV records for the services a member advertises; see crate::services.
+    async fn insert_services(
+        &self,
+        services: &[Service],
+        target: Name,
+    ) -> Result<(), anyhow::Error> {
+        let domain_name: Name = self.domain_name.clone().into();
+        let records = services
+            .iter()
+            .map(|service| service.srv(&domain_name, target.clone()))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        self.insert_records(records).await;
         Ok(())
     }
 
+    // insert_hosts adds the hosts file entries; all addresses of a name are inserted together.
+    async fn insert_hosts(&self, hosts: &HostsFile) {
+        let mut hosts_map: HashMap<Name, Vec<IpAddr>> = HashMap::new();
+
+        for (ip, hosts) in hosts.iter() {
+            for host in hosts {
+                hosts_map.entry(host.clone()).or_default().push(*ip);
+            }
+        }
+
+        for (host, ips) in hosts_map.into_iter() {
+            self.match_or_insert(host, &ips).await;
+        }
+    }
+
     pub async fn match_or_insert(&self, name: Name, ips: &[IpAddr]) {
         let rdatas: Vec<RData> = ips
             .iter()
@@ -408,7 +1678,7 @@ impl RecordAuthority {
             .collect();
 
         for rt in [RecordType::A, RecordType::AAAA] {
-            let type_records = self.authority.records().await.clone();
+            let type_records = self.current().records().await.clone();
             let name_records = type_records.get(&RrKey::new(name.clone().into(), rt));
 
             let type_ips: Vec<IpAddr> = ips
@@ -442,72 +1712,84 @@ impl RecordAuthority {
         }
     }
 
-    async fn insert_member(
-        &self,
-        records: &mut Vec<LowerName>,
-        record: ZTRecord,
-    ) -> Result<(), anyhow::Error> {
+    async fn insert_member(&self, record: ZTRecord) -> Result<(), anyhow::Error> {
         self.match_or_insert(record.fqdn.clone(), &record.ips).await;
-        records.push(record.fqdn.clone().into());
 
         if record.wildcard {
             self.match_or_insert(record.fqdn.clone().to_wildcard(), &record.ips)
                 .await;
-            records.push(record.fqdn.clone().to_wildcard().into());
+        }
+
+        if let Some(name) = &record.node_name {
+            self.match_or_insert(name.clone(), &record.ips).await;
+
+            if record.wildcard {
+                self.match_or_insert(name.to_wildcard(), &record.ips).await;
+            }
         }
 
         if let Some(name) = &record.custom_name {
             self.match_or_insert(name.clone(), &record.ips).await;
-            records.push(name.clone().into());
 
             if record.wildcard {
                 self.match_or_insert(record.get_custom_wildcard().unwrap(), &record.ips)
                     .await;
-                records.push(record.get_custom_wildcard().unwrap().into());
             }
         }
 
+        let mut aliases = Vec::new();
+        for alias in &record.aliases {
+            aliases.push((alias.clone(), RData::CNAME(record.ptr_name.clone())));
+
+            if record.wildcard {
+                aliases.push((alias.to_wildcard(), RData::CNAME(record.ptr_name.clone())));
+            }
+        }
+        self.insert_records(aliases).await;
+
         Ok(())
     }
 
     // insert_member_ptr is a lot like insert_authority, but for PTRs.
     async fn insert_member_ptr(
         &self,
-        records: &mut Vec<LowerName>,
         record: ZTRecord,
+        all_ptr_names: bool,
     ) -> Result<(), anyhow::Error> {
         for ip in record.ips.clone() {
-            let ip = ip.into_name()?;
-            self.configure_ptr(ip.clone(), record.ptr_name.clone())
+            let ptr = match (&self.classless, ip) {
+                (Some(classless), IpAddr::V4(ip)) if classless.contains(ip) => {
+                    classless.ptr_name(ip)?
+                }
+                // the zone only has the names of its own network's addresses.
+                (Some(_), _) => continue,
+                (None, _) => ip.into_name()?,
+            };
+
+            self.configure_ptr(ptr, record.ptr_names(all_ptr_names))
                 .await?;
-            records.push(ip.into());
         }
 
         Ok(())
     }
 
-    async fn configure_ptr(&self, ptr: Name, fqdn: Name) -> Result<(), anyhow::Error> {
-        let records = self.authority.records().await.clone();
+    // configure_ptr points the PTR records of an address at the names given, in their order.
+    async fn configure_ptr(&self, ptr: Name, names: Vec<Name>) -> Result<(), anyhow::Error> {
+        let records = self.current().records().await.clone();
+        let rdata: Vec<RData> = names.iter().cloned().map(RData::PTR).collect();
 
         match records.get(&RrKey::new(ptr.clone().into(), RecordType::PTR)) {
-            Some(records) => {
-                if !records
-                    .records_without_rrsigs()
-                    .any(|rec| rec.data().unwrap().eq(&RData::PTR(fqdn.clone())))
-                {
-                    self.set_ptr_record(ptr.clone(), fqdn.clone()).await;
-                }
-            }
-            None => self.set_ptr_record(ptr.clone(), fqdn.clone()).await,
+            Some(records) if rdatas(records) == rdata => {}
+            _ => self.set_ptr_records(ptr, names).await,
         }
 
         Ok(())
     }
 
-    async fn set_ptr_record(&self, ptr: Name, fqdn: Name) {
-        tracing::info!("Adding/Replacing record {}: ({})", ptr, fqdn);
+    async fn set_ptr_records(&self, ptr: Name, names: Vec<Name>) {
+        let authority = self.current();
 
-        let mut records = self.authority.records_mut().await;
+        let mut records = authority.records_mut().await;
         records.remove(&RrKey::new(
             ptr.clone()
                 .into_name()
@@ -517,18 +1799,97 @@ impl RecordAuthority {
         ));
         drop(records);
 
-        let serial = self.authority.serial().await;
-        let mut address = Record::with(ptr.clone(), RecordType::PTR, 60);
-        address.set_data(Some(RData::PTR(fqdn.clone())));
+        let serial = authority.serial().await;
+        for name in names {
+            let mut address = Record::with(ptr.clone(), RecordType::PTR, self.ttl);
+            address.set_data(Some(RData::PTR(name)));
+
+            authority.upsert(address, serial).await;
+        }
+    }
+}
+
+// soa_record returns the SOA record of a zone.
+fn soa_record(
+    records: &BTreeMap<RrKey, Arc<RecordSet>>,
+    domain_name: &LowerName,
+) -> Option<Record> {
+    records
+        .get(&RrKey::new(domain_name.clone(), RecordType::SOA))
+        .and_then(|rset| rset.records_without_rrsigs().next().cloned())
+}
+
+// initial_serial is the serial a zone starts with: the current time, so that serials keep
+// increasing across restarts for as long as zones change less than once a second on average.
+fn initial_serial() -> u32 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(1, |d| d.as_secs() as u32)
+}
+
+// ipv6_networks are the 6PLANE and RFC4193 networks the members of the network are assigned
+// addresses in, if it assigns them.
+fn ipv6_networks(
+    network: &zerotier_central_api::types::Network,
+) -> Result<(Option<IpNetwork>, Option<IpNetwork>), anyhow::Error> {
+    let (mut sixplane, mut rfc4193) = (None, None);
+
+    let v6assign = network.config.clone().unwrap().v6_assign_mode;
+    if let Some(v6assign) = v6assign {
+        if v6assign._6plane.unwrap_or(false) {
+            sixplane = Some(network_from_cidr(network.clone().sixplane()?)?);
+        }
+
+        if v6assign.rfc4193.unwrap_or(false) {
+            rfc4193 = Some(network_from_cidr(network.clone().rfc4193()?)?);
+        }
+    }
+
+    Ok((sixplane, rfc4193))
+}
+
+// digest hashes the records of a zone apart from those zeronsd generates, regardless of the
+// order the records were inserted in.
+fn digest(records: &BTreeMap<RrKey, Arc<RecordSet>>) -> u64 {
+    let mut hasher = DefaultHasher::new();
+
+    for (rrkey, rset) in records {
+        if generated(rrkey.record_type) {
+            continue;
+        }
+
+        let mut data: Vec<String> = rdatas(rset).iter().map(|r| format!("{:?}", r)).collect();
+        data.sort();
 
-        self.authority.upsert(address, serial).await;
+        rrkey.name().to_string().hash(&mut hasher);
+        u16::from(rrkey.record_type).hash(&mut hasher);
+        data.hash(&mut hasher);
     }
+
+    hasher.finish()
+}
+
+// generated tells whether records of the type are made by zeronsd itself for every zone, rather
+// than taken from Central or the configuration: the SOA, and the DNSKEY and NSEC records of signed
+// zones.
+fn generated(record_type: RecordType) -> bool {
+    matches!(
+        record_type,
+        RecordType::SOA | RecordType::NSEC | RecordType::DNSKEY
+    )
+}
+
+// rdatas returns the data of a record set, for comparison.
+fn rdatas(rset: &RecordSet) -> Vec<RData> {
+    rset.records_without_rrsigs()
+        .filter_map(|r| r.data().cloned())
+        .collect()
 }
 
 #[async_trait]
 impl AuthorityObject for RecordAuthority {
     fn box_clone(&self) -> Box<dyn AuthorityObject> {
-        Box::new(self.authority.clone())
+        Box::new(self.clone())
     }
 
     fn zone_type(&self) -> trust_dns_server::authority::ZoneType {
@@ -536,14 +1897,14 @@ impl AuthorityObject for RecordAuthority {
     }
 
     fn is_axfr_allowed(&self) -> bool {
-        false
+        !self.transfers.is_empty() || !self.keys.is_empty()
     }
 
     async fn update(
         &self,
         update: &trust_dns_server::authority::MessageRequest,
     ) -> trust_dns_server::authority::UpdateResult<bool> {
-        self.authority.update(update).await
+        self.current().update(update).await
     }
 
     fn origin(&self) -> &trust_dns_server::client::rr::LowerName {
@@ -559,7 +1920,7 @@ impl AuthorityObject for RecordAuthority {
         Box<dyn trust_dns_server::authority::LookupObject>,
         trust_dns_server::authority::LookupError,
     > {
-        self.authority.lookup(name, rtype, lookup_options).await
+        self.current().lookup(name, rtype, lookup_options).await
     }
 
     async fn search(
@@ -570,7 +1931,39 @@ impl AuthorityObject for RecordAuthority {
         Box<dyn trust_dns_server::authority::LookupObject>,
         trust_dns_server::authority::LookupError,
     > {
-        self.authority.search(request_info, lookup_options).await
+        // transfers of the zone are answered by server::Handler, which checks their signatures;
+        // the catalog only sees those of names within it.
+        if request_info.query.query_type() == RecordType::AXFR
+            && (!self.transfer_allowed(request_info.src.ip()) || !self.keys.is_empty())
+        {
+            tracing::warn!(
+                "Refusing transfer of {} to {}",
+                self.domain_name,
+                request_info.src
+            );
+            return Err(ResponseCode::Refused.into());
+        }
+
+        if request_info.query.query_type() == RecordType::ANY && self.minimal_any {
+            if let Some(lookup) = self
+                .minimal_any(request_info.query.name(), lookup_options)
+                .await
+            {
+                return Ok(lookup);
+            }
+        }
+
+        let query_type = request_info.query.query_type();
+        let client = request_info.src.ip();
+        let lookup = self.current().search(request_info, lookup_options).await?;
+
+        if matches!(query_type, RecordType::A | RecordType::AAAA)
+            && (!self.ordering.is_fixed() || !self.views.is_empty())
+        {
+            return Ok(self.ordered(lookup, query_type, client, lookup_options));
+        }
+
+        Ok(lookup)
     }
 
     async fn get_nsec_records(
@@ -581,15 +1974,75 @@ impl AuthorityObject for RecordAuthority {
         Box<dyn trust_dns_server::authority::LookupObject>,
         trust_dns_server::authority::LookupError,
     > {
-        self.authority.get_nsec_records(name, lookup_options).await
+        self.current().get_nsec_records(name, lookup_options).await
     }
 }
 
+// member_txt describes the member for inventory tooling, as `key=value` strings: its node ID, its
+// description and when it was last authorized, in seconds since the epoch. Empty values are
+// left out.
+// member_wildcard tells whether the member gets wildcard names without wildcard mode: if it is
+// listed by member ID or name, or tagged `wildcard` in Central with a value other than 0.
+pub fn member_wildcard(
+    listed: &[String],
+    network: &zerotier_central_api::types::Network,
+    member: &zerotier_central_api::types::Member,
+) -> bool {
+    let listed = listed.iter().any(|entry| {
+        [member.node_id.as_deref(), member.name.as_deref()]
+            .iter()
+            .flatten()
+            .any(|id| id.eq_ignore_ascii_case(entry))
+    });
+
+    listed || member_tag(network, member, WILDCARD_TAG).map_or(false, |value| value != 0)
+}
+
+pub fn member_txt(member: &zerotier_central_api::types::Member) -> RData {
+    let mut strings = Vec::new();
+
+    if let Some(node_id) = &member.node_id {
+        strings.push(format!("node={}", node_id));
+    }
+
+    if let Some(description) = member.description.as_deref().filter(|d| !d.is_empty()) {
+        strings.push(truncate_txt(format!("description={}", description)));
+    }
+
+    if let Some(authorized) = member
+        .config
+        .as_ref()
+        .and_then(|c| c.last_authorized_time)
+        .filter(|t| *t > 0)
+    {
+        strings.push(format!("authorized={}", authorized / 1000));
+    }
+
+    RData::TXT(TXT::new(strings))
+}
+
+// truncate_txt cuts a string down to the 255 bytes a TXT string can hold.
+fn truncate_txt(mut s: String) -> String {
+    if s.len() > 255 {
+        let mut end = 255;
+        while !s.is_char_boundary(end) {
+            end -= 1;
+        }
+        s.truncate(end);
+    }
+
+    s
+}
+
 #[derive(Debug, Clone)]
 struct ZTRecord {
     fqdn: Name,
     custom_name: Option<Name>,
+    // <member id>.<domain>, with --node-id-names.
+    node_name: Option<Name>,
     ptr_name: Name,
+    // names from the member's description which are CNAMEs to ptr_name.
+    aliases: Vec<Name>,
     ips: Vec<IpAddr>,
     wildcard: bool,
 }
@@ -601,6 +2054,8 @@ impl ZTRecord {
         rfc4193: Option<IpNetwork>,
         domain_name: Name,
         wildcard: bool,
+        name_policy: NamePolicy,
+        node_id_names: bool,
     ) -> Result<Self, anyhow::Error> {
         let member_name = format!(
             "zt-{}",
@@ -612,16 +2067,36 @@ impl ZTRecord {
 
         let fqdn = member_name.to_fqdn(domain_name.clone())?;
 
+        let node_name = if node_id_names {
+            Some(
+                member_name
+                    .trim_start_matches("zt-")
+                    .to_fqdn(domain_name.clone())?,
+            )
+        } else {
+            None
+        };
+
         // this is default the zt-<member id> but can switch to a named name if
         // tweaked in central. see below.
         let mut custom_name = None;
         let mut ptr_name = fqdn.clone();
 
-        if let Some(name) = parse_member_name(member.name.clone(), domain_name) {
+        if let Some(name) = parse_member_name(member.name.clone(), domain_name.clone(), name_policy)
+        {
             custom_name = Some(name.clone());
             ptr_name = name;
         }
 
+        let mut aliases =
+            parse_member_aliases(member.description.clone(), domain_name, name_policy);
+        aliases.retain(|alias| {
+            alias != &fqdn
+                && Some(alias) != custom_name.as_ref()
+                && Some(alias) != node_name.as_ref()
+        });
+        aliases.dedup();
+
         let mut ips = member
             .clone()
             .config
@@ -633,24 +2108,72 @@ impl ZTRecord {
                     .collect()
             });
 
+        // 6PLANE and RFC4193 addresses are calculated from the network and node IDs; they yield
+        // AAAA records alongside any managed addresses.
+        let mut v6 = Vec::new();
+
         if sixplane.is_some() {
-            ips.push(member.clone().sixplane()?.ip());
+            v6.push(member.clone().sixplane()?.ip());
         }
 
         if rfc4193.is_some() {
-            ips.push(member.clone().rfc4193()?.ip());
+            v6.push(member.clone().rfc4193()?.ip());
+        }
+
+        for ip in v6 {
+            if !ips.contains(&ip) {
+                ips.push(ip);
+            }
         }
 
         Ok(Self {
             wildcard,
             fqdn,
             custom_name,
+            node_name,
             ptr_name,
+            aliases,
             ips,
         })
     }
 
+    // record_count is how many address records and CNAMEs the member gets in a zone of the
+    // domain: one for each of its addresses under each of its names, one for each alias, and as
+    // many again for their wildcards, if it gets them.
+    pub fn record_count(&self, wildcards: bool) -> usize {
+        let names = 1 + self.custom_name.iter().count() + self.node_name.iter().count();
+        let count = names * self.ips.len() + self.aliases.len();
+
+        if wildcards && self.wildcard {
+            count * 2
+        } else {
+            count
+        }
+    }
+
     pub fn get_custom_wildcard(&self) -> Option<Name> {
         self.custom_name.as_ref().map(ToWildcard::to_wildcard)
     }
+
+    // ptr_names are the names the PTR records of the member's addresses point at: ptr_name, and
+    // with `all`, the member's other names after it, sorted so answers do not change between
+    // refreshes. Wildcards are not names a PTR record can point at.
+    pub fn ptr_names(&self, all: bool) -> Vec<Name> {
+        let mut others = Vec::new();
+
+        if all {
+            others = std::iter::once(&self.fqdn)
+                .chain(self.node_name.iter())
+                .chain(self.aliases.iter())
+                .filter(|name| **name != self.ptr_name)
+                .cloned()
+                .collect();
+            others.sort();
+            others.dedup();
+        }
+
+        std::iter::once(self.ptr_name.clone())
+            .chain(others)
+            .collect()
+    }
 }
@@ -0,0 +1,392 @@
+/// code for `zeronsd bench`, which measures how fast a local zeronsd answers.
+use std::{
+    collections::HashMap,
+    net::{Ipv4Addr, SocketAddr, UdpSocket},
+    path::PathBuf,
+    str::FromStr,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use anyhow::anyhow;
+use async_trait::async_trait;
+use serde_json::json;
+use tokio::time::MissedTickBehavior;
+use trust_dns_server::{
+    client::rr::Name,
+    proto::{op::ResponseCode, rr::RecordType},
+};
+use zerotier_central_api::types::{Member, Network};
+
+use crate::{
+    authority::{RecordAuthority, ZTAuthority},
+    controller::{local_member, local_network, Controller, MemberSource, MembersFile},
+    query::query,
+    server::Server,
+    utils::{domain_or_default, DEFAULT_POLL_INTERVAL, DEFAULT_TCP_TIMEOUT, DEFAULT_TTL},
+};
+
+// the network the members are served as; nothing is asked of ZeroTier about it.
+const NETWORK_ID: &str = "8056c2e21c000001";
+
+// the label queried under the wildcard names of members.
+const WILDCARD_LABEL: &str = "bench";
+
+// the percentiles of the latencies reported.
+const PERCENTILES: [f64; 4] = [50.0, 90.0, 99.0, 99.9];
+
+// BenchOptions are what is served, and how it is queried.
+#[derive(Debug, Clone)]
+pub struct BenchOptions {
+    // the members served; made-up ones without a file.
+    pub members_file: Option<PathBuf>,
+    pub records: usize,
+    pub domain: Option<String>,
+    pub wildcard: bool,
+    pub workers: usize,
+    // queries sent per second, in total; as many as are answered if None.
+    pub qps: Option<u32>,
+    // queries sent at once.
+    pub concurrency: usize,
+    pub duration: Duration,
+    pub tcp: bool,
+}
+
+// Report is the outcome of a run.
+#[derive(Debug, Clone)]
+pub struct Report {
+    pub records: usize,
+    pub answered: usize,
+    pub failed: usize,
+    pub elapsed: Duration,
+    // how long each answered query took, shortest first.
+    pub latencies: Vec<Duration>,
+}
+
+impl Report {
+    pub fn qps(&self) -> f64 {
+        self.answered as f64 / self.elapsed.as_secs_f64()
+    }
+
+    // percentile is the latency under which p percent of the answered queries were answered.
+    pub fn percentile(&self, p: f64) -> Option<Duration> {
+        if self.latencies.is_empty() {
+            return None;
+        }
+
+        let rank = (p / 100.0 * self.latencies.len() as f64).ceil() as usize;
+        Some(self.latencies[rank.clamp(1, self.latencies.len()) - 1])
+    }
+}
+
+// Synthetic is a network of made-up members, member-1 to member-N, with addresses in 10.0.0.0/8.
+struct Synthetic(usize);
+
+#[async_trait]
+impl MemberSource for Synthetic {
+    async fn network(&self, network_id: &str) -> Result<Network, anyhow::Error> {
+        local_network(network_id, &json!({ "name": "bench" }))
+    }
+
+    async fn members(&self, network_id: &str) -> Result<Vec<Member>, anyhow::Error> {
+        (1..=self.0)
+            .map(|i| {
+                let ip = Ipv4Addr::from(0x0a00_0000 | i as u32);
+
+                local_member(
+                    network_id,
+                    &json!({
+                        "id": format!("{:010x}", i),
+                        "name": format!("member-{}", i),
+                        "authorized": true,
+                        "ipAssignments": [ip.to_string()],
+                    }),
+                )
+            })
+            .collect()
+    }
+}
+
+// run serves the members and queries them for the duration.
+pub async fn run(options: BenchOptions) -> Result<Report, anyhow::Error> {
+    if options.records == 0 || options.records >= 1 << 24 {
+        return Err(anyhow!("records must be between 1 and {}", (1 << 24) - 1));
+    }
+
+    if options.concurrency == 0 || options.workers == 0 || options.qps == Some(0) {
+        return Err(anyhow!("concurrency, workers and qps must be at least 1"));
+    }
+
+    let source: Controller = match &options.members_file {
+        Some(path) => Arc::new(MembersFile(path.clone())),
+        None => Arc::new(Synthetic(options.records)),
+    };
+
+    let zt = authority(source.clone(), &options).await?;
+    zt.configure_members(
+        source.network(NETWORK_ID).await?,
+        source.members(NETWORK_ID).await?,
+    )
+    .await?;
+
+    let zones = zt.records().await;
+    let records = zones.iter().map(|(_, records)| records.len()).sum();
+    let queries = Arc::new(queries(&zones[0].1)?);
+
+    if queries.is_empty() {
+        return Err(anyhow!("no members with addresses to query"));
+    }
+
+    let server = SocketAddr::new(Ipv4Addr::LOCALHOST.into(), free_port()?);
+    let serving = Server::new(zt)
+        .with_workers(options.workers)
+        .bind(
+            server,
+            Duration::from_millis(DEFAULT_TCP_TIMEOUT),
+            None,
+            None,
+            None,
+            None,
+        )
+        .await?;
+    let serving = tokio::spawn(serving.block_until_done());
+
+    // every task sends its share of the rate.
+    let interval = options
+        .qps
+        .map(|qps| Duration::from_secs_f64(options.concurrency as f64 / qps as f64));
+
+    let start = Instant::now();
+    let deadline = start + options.duration;
+
+    let tasks: Vec<_> = (0..options.concurrency)
+        .map(|_| {
+            let queries = queries.clone();
+            let tcp = options.tcp;
+
+            tokio::spawn(async move {
+                let mut ticker = interval.map(tokio::time::interval);
+                if let Some(ticker) = &mut ticker {
+                    ticker.set_missed_tick_behavior(MissedTickBehavior::Delay);
+                }
+
+                let (mut latencies, mut failed) = (Vec::new(), 0);
+
+                while Instant::now() < deadline {
+                    if let Some(ticker) = &mut ticker {
+                        ticker.tick().await;
+                    }
+
+                    let (name, record_type) = &queries[rand::random::<usize>() % queries.len()];
+
+                    match query(server, name, *record_type, tcp).await {
+                        Ok(answer)
+                            if answer.message.response_code() == ResponseCode::NoError
+                                && !answer.message.answers().is_empty() =>
+                        {
+                            latencies.push(answer.elapsed)
+                        }
+                        _ => failed += 1,
+                    }
+                }
+
+                (latencies, failed)
+            })
+        })
+        .collect();
+
+    let mut report = Report {
+        records,
+        answered: 0,
+        failed: 0,
+        elapsed: Duration::default(),
+        latencies: Vec::new(),
+    };
+
+    for task in tasks {
+        let (latencies, failed) = task.await?;
+        report.latencies.extend(latencies);
+        report.failed += failed;
+    }
+
+    report.elapsed = start.elapsed();
+    report.answered = report.latencies.len();
+    report.latencies.sort();
+
+    serving.abort();
+    Ok(report)
+}
+
+// authority is a ZTAuthority of the network with nothing but the forward zone, whose members are
+// configured by the caller.
+async fn authority(
+    controller: Controller,
+    options: &BenchOptions,
+) -> Result<ZTAuthority, anyhow::Error> {
+    let domain = domain_or_default(options.domain.as_deref())?;
+    let member_name = Name::from_str("bench")?.append_domain(&domain)?;
+
+    Ok(ZTAuthority {
+        controller,
+        network_id: NETWORK_ID.to_string(),
+        hosts: None,
+        hosts_files: Vec::new(),
+        reverse_authority_map: HashMap::new(),
+        forward_authority: RecordAuthority::new(domain.into(), member_name.into(), DEFAULT_TTL)
+            .await?,
+        alias_authorities: Vec::new(),
+        forward_zones: HashMap::new(),
+        upstreams: None,
+        wildcard: options.wildcard,
+        wildcard_members: Vec::new(),
+        name_policy: Default::default(),
+        name_collisions: Default::default(),
+        member_filter: Default::default(),
+        node_id_names: false,
+        all_ptr_names: false,
+        member_txt: false,
+        max_records: None,
+        record_eviction: Default::default(),
+        update_interval: Duration::new(DEFAULT_POLL_INTERVAL, 0),
+        reload: None,
+        snapshot: None,
+        store: None,
+        records: Default::default(),
+        dynamic_zone: None,
+        dnssec_validation: false,
+        qname_minimization: false,
+        ip_names: false,
+        edns: Default::default(),
+        query_timeout: None,
+        query_acl: None,
+        query_log: None,
+        dnstap: None,
+        health: Default::default(),
+        control: Default::default(),
+        replica: Default::default(),
+        cache: None,
+        blocklist: Default::default(),
+        rpz: Default::default(),
+        views: Default::default(),
+        view_zones: Vec::new(),
+    })
+}
+
+// queries are the names of the address records of the zone, with their types; wildcards are
+// queried under a label of their own.
+fn queries(
+    records: &[trust_dns_server::client::rr::Record],
+) -> Result<Vec<(Name, RecordType)>, anyhow::Error> {
+    let mut queries = Vec::new();
+
+    for record in records {
+        if !matches!(record.record_type(), RecordType::A | RecordType::AAAA) {
+            continue;
+        }
+
+        let name = if record.name().is_wildcard() {
+            Name::from_str(WILDCARD_LABEL)?.append_domain(&record.name().base_name())?
+        } else {
+            record.name().clone()
+        };
+
+        let query = (name, record.record_type());
+        if !queries.contains(&query) {
+            queries.push(query);
+        }
+    }
+
+    Ok(queries)
+}
+
+// free_port is a port nothing listens on over UDP on the loopback address, for both the UDP and
+// TCP listeners: with port 0, each would be given a different one.
+fn free_port() -> Result<u16, anyhow::Error> {
+    Ok(UdpSocket::bind((Ipv4Addr::LOCALHOST, 0))?
+        .local_addr()?
+        .port())
+}
+
+// format_report lays out the report for `zeronsd bench`.
+pub fn format_report(report: &Report) -> String {
+    let mut out = format!(
+        "Records served: {}\nAnswered: {} in {:.1}s ({:.0} queries/s)\nFailed: {}\n",
+        report.records,
+        report.answered,
+        report.elapsed.as_secs_f64(),
+        report.qps(),
+        report.failed,
+    );
+
+    if let Some(max) = report.latencies.last() {
+        let percentiles = PERCENTILES
+            .iter()
+            .filter_map(|p| Some(format!("p{} {}", p, millis(report.percentile(*p)?))))
+            .collect::<Vec<_>>();
+
+        out.push_str(&format!(
+            "Latency: {}, max {}\n",
+            percentiles.join(", "),
+            millis(*max)
+        ));
+    }
+
+    out
+}
+
+fn millis(d: Duration) -> String {
+    format!("{:.3}ms", d.as_secs_f64() * 1000.0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{format_report, run, BenchOptions, Report};
+    use std::time::Duration;
+
+    #[test]
+    fn test_percentile() {
+        let report = Report {
+            records: 10,
+            answered: 10,
+            failed: 0,
+            elapsed: Duration::from_secs(2),
+            latencies: (1..=10).map(Duration::from_millis).collect(),
+        };
+
+        assert_eq!(report.qps(), 5.0);
+        assert_eq!(report.percentile(50.0), Some(Duration::from_millis(5)));
+        assert_eq!(report.percentile(90.0), Some(Duration::from_millis(9)));
+        assert_eq!(report.percentile(99.9), Some(Duration::from_millis(10)));
+        assert_eq!(report.percentile(0.0), Some(Duration::from_millis(1)));
+        assert!(format_report(&report).contains("p50 5.000ms"));
+
+        let empty = Report {
+            latencies: Vec::new(),
+            ..report
+        };
+        assert_eq!(empty.percentile(50.0), None);
+        assert!(!format_report(&empty).contains("Latency"));
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_bench() {
+        let report = run(BenchOptions {
+            members_file: None,
+            records: 20,
+            domain: None,
+            wildcard: true,
+            workers: 1,
+            qps: Some(100),
+            concurrency: 2,
+            duration: Duration::from_millis(500),
+            tcp: false,
+        })
+        .await
+        .unwrap();
+
+        // every member has a name, an ID name and a wildcard, with the SOA and NS records.
+        assert!(report.records > 20);
+        assert!(report.answered > 0);
+        assert_eq!(report.failed, 0);
+    }
+}
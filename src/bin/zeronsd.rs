@@ -1,6 +1,5 @@
 use zeronsd::cli::init;
 
-#[tokio::main]
-async fn main() -> Result<(), anyhow::Error> {
-    init().await
+fn main() -> Result<(), anyhow::Error> {
+    init()
 }
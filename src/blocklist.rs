@@ -0,0 +1,207 @@
+/// blocklists of names not to forward, in hosts, domain list or Adblock format.
+use std::{collections::HashSet, net::IpAddr, path::PathBuf, str::FromStr, sync::Arc};
+
+use anyhow::anyhow;
+use arc_swap::ArcSwap;
+use serde::{Deserialize, Serialize};
+use trust_dns_server::client::rr::{LowerName, Name};
+
+// TTL of the answers for blocked names, in seconds.
+pub const BLOCKED_TTL: u32 = 60;
+
+// BlockAction is how queries for blocked names are answered.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum BlockAction {
+    // answer that the name does not exist.
+    Nxdomain,
+    // answer A and AAAA queries with 0.0.0.0 and ::, and other queries with no records.
+    Null,
+}
+
+impl Default for BlockAction {
+    fn default() -> Self {
+        BlockAction::Nxdomain
+    }
+}
+
+impl ToString for BlockAction {
+    fn to_string(&self) -> String {
+        match self {
+            BlockAction::Nxdomain => "nxdomain",
+            BlockAction::Null => "null",
+        }
+        .to_string()
+    }
+}
+
+impl FromStr for BlockAction {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "nxdomain" => Ok(Self::Nxdomain),
+            "null" => Ok(Self::Null),
+            _ => Err(anyhow!("invalid action: allowed values: [nxdomain, null]")),
+        }
+    }
+}
+
+#[derive(Default)]
+struct Lists {
+    action: BlockAction,
+    names: HashSet<String>,
+}
+
+// Blocklist holds the names blocked. It is shared by the listeners of a network, and replaced as
+// a whole when the lists are read again.
+#[derive(Clone, Default)]
+pub struct Blocklist(Arc<ArcSwap<Lists>>);
+
+impl Blocklist {
+    // load reads the lists. The names blocked so far are kept if any of them cannot be read.
+    pub fn load(&self, files: &[PathBuf], action: BlockAction) -> Result<(), anyhow::Error> {
+        let mut names = HashSet::new();
+
+        for file in files {
+            let contents = std::fs::read_to_string(file)
+                .map_err(|e| anyhow!("Could not read blocklist {}: {}", file.display(), e))?;
+            names.extend(parse_blocklist(&contents));
+        }
+
+        if !files.is_empty() {
+            tracing::info!(
+                "Blocking {} names from {} blocklists",
+                names.len(),
+                files.len()
+            );
+        }
+
+        self.0.store(Arc::new(Lists { action, names }));
+        Ok(())
+    }
+
+    // blocked returns the action to take for the name, if it or a domain it is under is blocked.
+    pub fn blocked(&self, name: &LowerName) -> Option<BlockAction> {
+        let lists = self.0.load();
+
+        if lists.names.is_empty() {
+            return None;
+        }
+
+        // lists name internationalized domains in their punycode form.
+        let name = Name::from(name.clone()).to_ascii();
+        let name = name.trim_end_matches('.');
+
+        let blocked = std::iter::once(name)
+            .chain(name.match_indices('.').map(|(i, _)| &name[i + 1..]))
+            .any(|domain| lists.names.contains(domain));
+
+        if blocked {
+            Some(lists.action)
+        } else {
+            None
+        }
+    }
+}
+
+// parse_blocklist returns the names of a list, lowercased and without a trailing period. Lines
+// starting with `#` or `!` are comments, as is anything after a `#`. Single-label names such as
+// `localhost`, found in most hosts files, are skipped.
+pub fn parse_blocklist(contents: &str) -> impl Iterator<Item = String> + '_ {
+    contents
+        .lines()
+        .map(|line| line.split('#').next().unwrap_or_default().trim())
+        .filter(|line| !line.is_empty() && !line.starts_with('!'))
+        .flat_map(|line| {
+            let mut fields = line.split_whitespace().peekable();
+
+            // hosts files name the address first.
+            if fields
+                .peek()
+                .map_or(false, |field| IpAddr::from_str(field).is_ok())
+            {
+                fields.next();
+            }
+
+            fields
+        })
+        .map(|name| {
+            name.trim_start_matches("||")
+                .trim_end_matches('^')
+                .trim_end_matches('.')
+                .to_lowercase()
+        })
+        .filter(|name| name.contains('.') && IpAddr::from_str(name).is_err())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{parse_blocklist, BlockAction, Blocklist};
+    use std::str::FromStr;
+    use trust_dns_server::client::rr::{LowerName, Name};
+
+    #[test]
+    fn test_parse_blocklist() {
+        let names: Vec<String> = parse_blocklist(
+            "# StevenBlack-style hosts\n\
+             127.0.0.1 localhost\n\
+             0.0.0.0 0.0.0.0\n\
+             0.0.0.0 ads.example.com tracker.example.com # inline comment\n\
+             ::1 ip6-localhost\n\
+             \n\
+             Malware.Example.NET.\n\
+             ! Adblock comment\n\
+             ||doubleclick.example.org^\n",
+        )
+        .collect();
+
+        assert_eq!(
+            names,
+            vec![
+                "ads.example.com",
+                "tracker.example.com",
+                "malware.example.net",
+                "doubleclick.example.org",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_blocklist() {
+        let dir = std::env::temp_dir().join("zeronsd-test-blocklist");
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+
+        let path = dir.join("blocklist.txt");
+        std::fs::write(&path, "0.0.0.0 ads.example.com\n").unwrap();
+
+        let name = |s: &str| LowerName::from(Name::from_str(s).unwrap());
+
+        let blocklist = Blocklist::default();
+        assert_eq!(blocklist.blocked(&name("ads.example.com.")), None);
+
+        blocklist.load(&[path.clone()], BlockAction::Null).unwrap();
+
+        for (query, action) in [
+            ("ads.example.com.", Some(BlockAction::Null)),
+            ("ADS.example.com.", Some(BlockAction::Null)),
+            ("banner.ads.example.com.", Some(BlockAction::Null)),
+            ("example.com.", None),
+            ("badads.example.com.", None),
+        ] {
+            assert_eq!(blocklist.blocked(&name(query)), action, "{}", query);
+        }
+
+        // lists which cannot be read leave the names blocked so far in place.
+        assert!(blocklist
+            .load(&[dir.join("missing.txt")], BlockAction::Nxdomain)
+            .is_err());
+        assert_eq!(
+            blocklist.blocked(&name("ads.example.com.")),
+            Some(BlockAction::Null)
+        );
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
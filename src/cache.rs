@@ -0,0 +1,359 @@
+/// a cache of the answers to forwarded queries, with serve-stale (RFC 8767).
+use std::{
+    collections::{BTreeMap, HashMap},
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use serde::Serialize;
+use trust_dns_server::{
+    client::rr::{LowerName, Record, RecordType},
+    proto::op::ResponseCode,
+};
+
+// answers kept unless configured otherwise.
+pub const DEFAULT_CACHE_SIZE: usize = 10000;
+
+// longest an answer is kept, in seconds, whatever its TTL.
+pub const MAX_TTL: u32 = 86400;
+
+// longest a negative answer is kept, in seconds, whatever its SOA says.
+pub const MAX_NEGATIVE_TTL: u32 = 3600;
+
+// TTL of the records of stale answers, in seconds, as RFC 8767 recommends.
+pub const STALE_TTL: u32 = 30;
+
+// how long an answer can be served stale for after it expires, in seconds.
+pub const MAX_STALE: u32 = 86400;
+
+// how often the statistics of the cache are logged, while it is in use.
+const REPORT_INTERVAL: Duration = Duration::from_secs(300);
+
+// Answer is a cached answer: its records, with the TTLs left, and its response code.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Answer {
+    pub records: Vec<Record>,
+    pub response_code: ResponseCode,
+}
+
+// CacheStats counts what the cache was used for since startup.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize)]
+pub struct CacheStats {
+    pub entries: usize,
+    // the most entries the cache holds.
+    pub capacity: usize,
+    pub hits: u64,
+    // hits on negative answers, which are counted in hits as well.
+    pub negative_hits: u64,
+    pub misses: u64,
+    // answers served stale, after a miss the upstream resolvers could not answer.
+    pub stale_hits: u64,
+    pub evictions: u64,
+}
+
+impl CacheStats {
+    // hit_rate is the share of lookups answered from the cache.
+    pub fn hit_rate(&self) -> f64 {
+        match self.hits + self.misses {
+            0 => 0.0,
+            lookups => self.hits as f64 / lookups as f64,
+        }
+    }
+}
+
+type Key = (LowerName, RecordType);
+
+struct Entry {
+    answer: Answer,
+    inserted: Instant,
+    expires: Instant,
+    // when the entry was last used, as a tick of Inner::clock.
+    used: u64,
+}
+
+struct Inner {
+    capacity: usize,
+    serve_stale: bool,
+    entries: HashMap<Key, Entry>,
+    // the keys of the entries, least recently used first.
+    recency: BTreeMap<u64, Key>,
+    clock: u64,
+    stats: CacheStats,
+    reported: Instant,
+}
+
+// ForwardCache is the cache of forwarded answers. It is shared by the listeners of a network.
+#[derive(Clone)]
+pub struct ForwardCache(Arc<Mutex<Inner>>);
+
+impl ForwardCache {
+    // new holds up to capacity answers, and keeps them for MAX_STALE seconds after they expire if
+    // serve_stale is set.
+    pub fn new(capacity: usize, serve_stale: bool) -> Self {
+        Self(Arc::new(Mutex::new(Inner {
+            capacity,
+            serve_stale,
+            entries: HashMap::new(),
+            recency: BTreeMap::new(),
+            clock: 0,
+            stats: CacheStats::default(),
+            reported: Instant::now(),
+        })))
+    }
+
+    // get returns the answer for the name and type, if one is cached and has not expired, with
+    // the TTLs of its records lowered by the time it has been cached for.
+    pub fn get(&self, name: &LowerName, record_type: RecordType) -> Option<Answer> {
+        let mut inner = self.0.lock().unwrap();
+        let now = Instant::now();
+        let key = (name.clone(), record_type);
+
+        let answer = match inner.entries.get(&key) {
+            Some(entry) if entry.expires > now => {
+                let elapsed = now.duration_since(entry.inserted).as_secs() as u32;
+                let mut answer = entry.answer.clone();
+
+                for record in answer.records.iter_mut() {
+                    record.set_ttl(record.ttl().saturating_sub(elapsed));
+                }
+
+                Some(answer)
+            }
+            _ => None,
+        };
+
+        match &answer {
+            Some(answer) => {
+                inner.touch(&key);
+                inner.stats.hits += 1;
+
+                if answer.records.is_empty() {
+                    inner.stats.negative_hits += 1;
+                }
+            }
+            None => inner.stats.misses += 1,
+        }
+
+        inner.report(now);
+        answer
+    }
+
+    // stale returns the answer for the name and type if it expired less than MAX_STALE seconds
+    // ago, with the TTLs of its records set to STALE_TTL, when serve-stale is enabled.
+    pub fn stale(&self, name: &LowerName, record_type: RecordType) -> Option<Answer> {
+        let mut inner = self.0.lock().unwrap();
+
+        if !inner.serve_stale {
+            return None;
+        }
+
+        let now = Instant::now();
+        let key = (name.clone(), record_type);
+
+        let answer = match inner.entries.get(&key) {
+            Some(entry) if entry.expires + Duration::from_secs(MAX_STALE.into()) > now => {
+                let mut answer = entry.answer.clone();
+
+                for record in answer.records.iter_mut() {
+                    record.set_ttl(STALE_TTL);
+                }
+
+                Some(answer)
+            }
+            _ => None,
+        };
+
+        if answer.is_some() {
+            inner.touch(&key);
+            inner.stats.stale_hits += 1;
+        }
+
+        answer
+    }
+
+    // insert caches the answer for the name and type for ttl seconds, capped at MAX_TTL, or at
+    // MAX_NEGATIVE_TTL for answers without records.
+    pub fn insert(&self, name: &LowerName, record_type: RecordType, answer: Answer, ttl: u32) {
+        let mut inner = self.0.lock().unwrap();
+
+        if inner.capacity == 0 {
+            return;
+        }
+
+        let ttl = if answer.records.is_empty() {
+            ttl.min(MAX_NEGATIVE_TTL)
+        } else {
+            ttl.min(MAX_TTL)
+        };
+
+        let key = (name.clone(), record_type);
+        let now = Instant::now();
+
+        if let Some(old) = inner.entries.remove(&key) {
+            inner.recency.remove(&old.used);
+        }
+
+        while inner.entries.len() >= inner.capacity {
+            inner.evict();
+        }
+
+        inner.clock += 1;
+        let used = inner.clock;
+
+        inner.recency.insert(used, key.clone());
+        inner.entries.insert(
+            key,
+            Entry {
+                answer,
+                inserted: now,
+                expires: now + Duration::from_secs(ttl.into()),
+                used,
+            },
+        );
+    }
+
+    pub fn stats(&self) -> CacheStats {
+        let inner = self.0.lock().unwrap();
+
+        CacheStats {
+            entries: inner.entries.len(),
+            capacity: inner.capacity,
+            ..inner.stats
+        }
+    }
+}
+
+impl Inner {
+    // touch marks the entry for the key as the most recently used.
+    fn touch(&mut self, key: &Key) {
+        self.clock += 1;
+        let clock = self.clock;
+
+        if let Some(entry) = self.entries.get_mut(key) {
+            self.recency.remove(&entry.used);
+            entry.used = clock;
+            self.recency.insert(clock, key.clone());
+        }
+    }
+
+    // evict drops the least recently used entry.
+    fn evict(&mut self) {
+        if let Some(used) = self.recency.keys().next().copied() {
+            let key = self.recency.remove(&used).unwrap();
+            self.entries.remove(&key);
+            self.stats.evictions += 1;
+        }
+    }
+
+    fn report(&mut self, now: Instant) {
+        if now.duration_since(self.reported) < REPORT_INTERVAL {
+            return;
+        }
+
+        self.reported = now;
+
+        tracing::info!(
+            "Forwarder cache: {} answers, {} hits ({} negative), {} misses, {:.1}% hit rate, {} served stale, {} evictions",
+            self.entries.len(),
+            self.stats.hits,
+            self.stats.negative_hits,
+            self.stats.misses,
+            self.stats.hit_rate() * 100.0,
+            self.stats.stale_hits,
+            self.stats.evictions
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Answer, ForwardCache, MAX_NEGATIVE_TTL, STALE_TTL};
+    use std::{net::Ipv4Addr, str::FromStr};
+    use trust_dns_server::{
+        client::rr::{LowerName, Name, RData, Record, RecordType},
+        proto::op::ResponseCode,
+    };
+
+    fn name(s: &str) -> LowerName {
+        LowerName::from(Name::from_str(s).unwrap())
+    }
+
+    fn answer(s: &str, ttl: u32) -> Answer {
+        Answer {
+            records: vec![Record::from_rdata(
+                Name::from_str(s).unwrap(),
+                ttl,
+                RData::A(Ipv4Addr::new(192, 0, 2, 1)),
+            )],
+            response_code: ResponseCode::NoError,
+        }
+    }
+
+    #[test]
+    fn test_forward_cache() {
+        let cache = ForwardCache::new(2, false);
+        let (a, b, c) = (
+            name("a.example.com."),
+            name("b.example.com."),
+            name("c.example.com."),
+        );
+
+        assert_eq!(cache.get(&a, RecordType::A), None);
+
+        cache.insert(&a, RecordType::A, answer("a.example.com.", 300), 300);
+        assert_eq!(
+            cache.get(&a, RecordType::A),
+            Some(answer("a.example.com.", 300))
+        );
+        assert_eq!(cache.get(&a, RecordType::AAAA), None);
+
+        // negative answers are cached too.
+        let nxdomain = Answer {
+            records: Vec::new(),
+            response_code: ResponseCode::NXDomain,
+        };
+        cache.insert(&b, RecordType::A, nxdomain.clone(), 10 * MAX_NEGATIVE_TTL);
+        assert_eq!(cache.get(&b, RecordType::A), Some(nxdomain));
+
+        // a was used less recently than b, so it makes room for c.
+        cache.insert(&c, RecordType::A, answer("c.example.com.", 300), 300);
+        assert_eq!(cache.get(&a, RecordType::A), None);
+        assert!(cache.get(&b, RecordType::A).is_some());
+        assert!(cache.get(&c, RecordType::A).is_some());
+
+        // answers are not served once they expire.
+        cache.insert(&c, RecordType::A, answer("c.example.com.", 0), 0);
+        assert_eq!(cache.get(&c, RecordType::A), None);
+
+        let stats = cache.stats();
+        assert_eq!(stats.entries, 2);
+        assert_eq!(stats.capacity, 2);
+        assert_eq!(stats.hits, 4);
+        assert_eq!(stats.negative_hits, 2);
+        assert_eq!(stats.misses, 4);
+        assert_eq!(stats.evictions, 1);
+        assert_eq!(stats.hit_rate(), 0.5);
+
+        // expired answers are only served stale when asked to.
+        assert_eq!(cache.stale(&c, RecordType::A), None);
+    }
+
+    #[test]
+    fn test_serve_stale() {
+        let cache = ForwardCache::new(10, true);
+        let a = name("a.example.com.");
+
+        assert_eq!(cache.stale(&a, RecordType::A), None);
+
+        cache.insert(&a, RecordType::A, answer("a.example.com.", 0), 0);
+        assert_eq!(cache.get(&a, RecordType::A), None);
+        assert_eq!(
+            cache.stale(&a, RecordType::A),
+            Some(answer("a.example.com.", STALE_TTL))
+        );
+
+        let stats = cache.stats();
+        assert_eq!(stats.misses, 1);
+        assert_eq!(stats.stale_hits, 1);
+    }
+}
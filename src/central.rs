@@ -0,0 +1,395 @@
+/// conditional polling of the ZeroTier Central API, and retrying it when it fails.
+use std::{
+    collections::hash_map::DefaultHasher,
+    future::Future,
+    hash::{Hash, Hasher},
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+use anyhow::anyhow;
+use rand::Rng;
+use reqwest::{
+    header::{ETAG, IF_NONE_MATCH},
+    StatusCode,
+};
+use zerotier_central_api::types::{Member, Network};
+
+use crate::hosts::HostsFile;
+
+/// MemberPoller remembers the ETag of the last member list Central returned, so that subsequent
+/// polls can be made conditional with If-None-Match. When Central answers 304 Not Modified the
+/// previously fetched list is reused.
+#[derive(Default)]
+pub struct MemberPoller(Mutex<LastPoll>);
+
+#[derive(Default, Clone)]
+struct LastPoll {
+    etag: Option<String>,
+    members: Vec<Member>,
+}
+
+impl MemberPoller {
+    pub async fn members(
+        &self,
+        client: &zerotier_central_api::Client,
+        network_id: &str,
+    ) -> Result<Vec<Member>, anyhow::Error> {
+        let last = self.0.lock().unwrap().clone();
+
+        let url = format!("{}/network/{}/member", client.baseurl(), network_id);
+        let mut req = client.client().get(url);
+
+        if let Some(etag) = &last.etag {
+            req = req.header(IF_NONE_MATCH, etag);
+        }
+
+        let res = req.send().await?;
+
+        if res.status() == StatusCode::NOT_MODIFIED && last.etag.is_some() {
+            tracing::debug!("Member list for {} is unchanged", network_id);
+            return Ok(last.members);
+        }
+
+        // keeps the status code available to is_transient.
+        if let Err(e) = res.error_for_status_ref() {
+            return Err(anyhow::Error::new(e).context(format!(
+                "Central returned {} fetching members of {}",
+                res.status(),
+                network_id
+            )));
+        }
+
+        let etag = res
+            .headers()
+            .get(ETAG)
+            .and_then(|v| v.to_str().ok())
+            .map(ToString::to_string);
+
+        let members: Vec<Member> = res.json().await?;
+
+        *self.0.lock().unwrap() = LastPoll {
+            etag,
+            members: members.clone(),
+        };
+
+        Ok(members)
+    }
+}
+
+/// RetryPolicy retries calls to Central which failed in a way that may go away on its own: server
+/// errors, rate limiting, timeouts and connection failures. Every retry waits twice as long as
+/// the previous one, up to `max_delay`, plus up to half of that again at random so that instances
+/// started together do not retry in lockstep.
+#[derive(Debug, Clone)]
+pub struct RetryPolicy {
+    pub attempts: u32,
+    pub initial_delay: Duration,
+    pub max_delay: Duration,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            attempts: 4,
+            initial_delay: Duration::new(1, 0),
+            max_delay: Duration::new(30, 0),
+        }
+    }
+}
+
+impl RetryPolicy {
+    // delay is how long to wait before the given retry, counting from zero.
+    pub fn delay(&self, retry: u32) -> Duration {
+        let base = self
+            .initial_delay
+            .saturating_mul(2u32.saturating_pow(retry))
+            .min(self.max_delay);
+
+        base + base.mul_f64(rand::thread_rng().gen_range(0.0..0.5))
+    }
+
+    // run calls f until it succeeds, fails with an error which is not transient, or runs out of
+    // attempts. `what` describes the call in logs.
+    pub async fn run<T, F, Fut>(&self, what: &str, mut f: F) -> Result<T, anyhow::Error>
+    where
+        F: FnMut() -> Fut,
+        Fut: Future<Output = Result<T, anyhow::Error>>,
+    {
+        let mut retry = 0;
+
+        loop {
+            match f().await {
+                Ok(res) => return Ok(res),
+                Err(e) if retry + 1 < self.attempts && is_transient(&e) => {
+                    let delay = self.delay(retry);
+                    tracing::warn!(
+                        attempt = retry + 1,
+                        "{} failed: {:#}; retrying in {:.1}s",
+                        what,
+                        e,
+                        delay.as_secs_f32()
+                    );
+                    tokio::time::sleep(delay).await;
+                    retry += 1;
+                }
+                Err(e) => return Err(e),
+            }
+        }
+    }
+}
+
+// is_transient decides whether a failed call to Central is worth retrying.
+pub fn is_transient(e: &anyhow::Error) -> bool {
+    fn transient_status(status: StatusCode) -> bool {
+        status.is_server_error() || status == StatusCode::TOO_MANY_REQUESTS
+    }
+
+    if let Some(e) = e.downcast_ref::<reqwest::Error>() {
+        return e.is_timeout() || e.is_connect() || e.status().map_or(false, transient_status);
+    }
+
+    if let Some(e) = e.downcast_ref::<zerotier_central_api::Error>() {
+        return match e {
+            zerotier_central_api::Error::CommunicationError(_) => true,
+            e => e.status().map_or(false, transient_status),
+        };
+    }
+
+    false
+}
+
+// is_unauthorized decides whether Central rejected the token, e.g. because it was rotated.
+pub fn is_unauthorized(e: &anyhow::Error) -> bool {
+    if let Some(e) = e.downcast_ref::<reqwest::Error>() {
+        return e.status() == Some(StatusCode::UNAUTHORIZED);
+    }
+
+    if let Some(e) = e.downcast_ref::<zerotier_central_api::Error>() {
+        return e.status() == Some(StatusCode::UNAUTHORIZED);
+    }
+
+    false
+}
+
+/// CircuitBreaker stops polling Central after `threshold` refreshes in a row have failed
+/// transiently, for `cooldown`, after which one refresh is let through to find out whether Central
+/// has recovered. The records already being served are kept meanwhile.
+#[derive(Debug)]
+pub struct CircuitBreaker {
+    threshold: u32,
+    cooldown: Duration,
+    failures: u32,
+    open_until: Option<Instant>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum CircuitState {
+    // Central is being polled normally.
+    Closed,
+    // Central is not being polled until the cooldown passes.
+    Open,
+    // the cooldown has passed; the next refresh decides whether polling resumes.
+    HalfOpen,
+}
+
+impl Default for CircuitBreaker {
+    fn default() -> Self {
+        Self::new(5, Duration::new(300, 0))
+    }
+}
+
+impl CircuitBreaker {
+    pub fn new(threshold: u32, cooldown: Duration) -> Self {
+        Self {
+            threshold,
+            cooldown,
+            failures: 0,
+            open_until: None,
+        }
+    }
+
+    pub fn state(&self) -> CircuitState {
+        match self.open_until {
+            None => CircuitState::Closed,
+            Some(until) if Instant::now() < until => CircuitState::Open,
+            Some(_) => CircuitState::HalfOpen,
+        }
+    }
+
+    // allow returns whether Central may be called now.
+    pub fn allow(&self) -> bool {
+        self.state() != CircuitState::Open
+    }
+
+    pub fn success(&mut self) {
+        if self.failures > 0 {
+            tracing::info!(
+                failures = self.failures,
+                "Central is reachable again after {} failed refreshes",
+                self.failures
+            );
+        }
+
+        self.failures = 0;
+        self.open_until = None;
+    }
+
+    // failure records a failed refresh. Only failures which are not caused by zeronsd's own
+    // configuration, see is_transient, should be recorded.
+    pub fn failure(&mut self) {
+        self.failures += 1;
+
+        if self.state() == CircuitState::HalfOpen || self.failures >= self.threshold {
+            self.open_until = Some(Instant::now() + self.cooldown);
+            tracing::error!(
+                failures = self.failures,
+                state = "open",
+                "Central failed {} refreshes in a row; pausing polls for {}s and serving the last known records",
+                self.failures,
+                self.cooldown.as_secs()
+            );
+        } else {
+            tracing::warn!(
+                failures = self.failures,
+                state = "closed",
+                "Central failed {} refreshes in a row",
+                self.failures
+            );
+        }
+    }
+}
+
+/// Computes a fingerprint over the parts of the network, its members and the hosts file that
+/// affect the records served. Fields which change on every poll, such as the last time a member
+/// was online, are ignored; when two fingerprints match the authority does not need rebuilding.
+pub fn fingerprint(network: &Network, members: &[Member], hosts: Option<&HostsFile>) -> u64 {
+    let mut hasher = DefaultHasher::new();
+
+    serde_json::to_string(&network.config)
+        .unwrap_or_default()
+        .hash(&mut hasher);
+    serde_json::to_string(&network.tags_by_name)
+        .unwrap_or_default()
+        .hash(&mut hasher);
+
+    for member in members {
+        member.node_id.hash(&mut hasher);
+        member.name.hash(&mut hasher);
+        member.description.hash(&mut hasher);
+        serde_json::to_string(&member.config)
+            .unwrap_or_default()
+            .hash(&mut hasher);
+    }
+
+    if let Some(hosts) = hosts {
+        let mut entries = hosts
+            .iter()
+            .map(|(ip, names)| {
+                (
+                    *ip,
+                    names
+                        .iter()
+                        .map(ToString::to_string)
+                        .collect::<Vec<String>>(),
+                )
+            })
+            .collect::<Vec<_>>();
+        entries.sort();
+        entries.hash(&mut hasher);
+    }
+
+    hasher.finish()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{fingerprint, CircuitBreaker, CircuitState, RetryPolicy};
+    use std::time::Duration;
+    use zerotier_central_api::types::{Member, Network};
+
+    fn member(node_id: &str, name: Option<&str>, last_online: Option<i64>) -> Member {
+        serde_json::from_value(serde_json::json!({
+            "nodeId": node_id,
+            "name": name,
+            "lastOnline": last_online,
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn test_fingerprint() {
+        let network: Network = serde_json::from_value(serde_json::json!({})).unwrap();
+
+        let a = fingerprint(&network, &[member("abcdef0123", None, Some(1))], None);
+        let b = fingerprint(&network, &[member("abcdef0123", None, Some(2))], None);
+        assert_eq!(a, b, "last online should not change the fingerprint");
+
+        let c = fingerprint(
+            &network,
+            &[member("abcdef0123", Some("islay"), Some(2))],
+            None,
+        );
+        assert_ne!(a, c, "renames should change the fingerprint");
+
+        let d = fingerprint(&network, &[], None);
+        assert_ne!(a, d, "removals should change the fingerprint");
+    }
+
+    #[test]
+    fn test_retry_delay() {
+        let policy = RetryPolicy::default();
+
+        for retry in 0..10 {
+            let base = Duration::new(1 << retry.min(5), 0).min(policy.max_delay);
+            let delay = policy.delay(retry);
+            assert!(delay >= base, "{}: {:?}", retry, delay);
+            assert!(delay < base.mul_f64(1.5), "{}: {:?}", retry, delay);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_retry_run() {
+        let policy = RetryPolicy {
+            attempts: 3,
+            initial_delay: Duration::from_millis(1),
+            max_delay: Duration::from_millis(1),
+        };
+
+        // errors which are not transient are returned right away.
+        let mut calls = 0;
+        let res: Result<(), _> = policy
+            .run("testing", || {
+                calls += 1;
+                async { Err(anyhow::anyhow!("not found")) }
+            })
+            .await;
+        assert!(res.is_err());
+        assert_eq!(calls, 1);
+    }
+
+    #[test]
+    fn test_circuit_breaker() {
+        let mut breaker = CircuitBreaker::new(2, Duration::new(3600, 0));
+        breaker.failure();
+        assert_eq!(breaker.state(), CircuitState::Closed);
+        assert!(breaker.allow());
+
+        breaker.failure();
+        assert_eq!(breaker.state(), CircuitState::Open);
+        assert!(!breaker.allow());
+
+        breaker.success();
+        assert_eq!(breaker.state(), CircuitState::Closed);
+
+        // once the cooldown has passed a single failure opens it again.
+        let mut breaker = CircuitBreaker::new(2, Duration::new(0, 0));
+        breaker.failure();
+        breaker.failure();
+        assert_eq!(breaker.state(), CircuitState::HalfOpen);
+        assert!(breaker.allow());
+        breaker.failure();
+        assert_eq!(breaker.state(), CircuitState::HalfOpen);
+        assert_eq!(breaker.failures, 3);
+    }
+}
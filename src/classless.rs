@@ -0,0 +1,185 @@
+/// classless reverse delegation (RFC 2317) of networks smaller than a /24.
+use std::{net::Ipv4Addr, str::FromStr};
+
+use anyhow::anyhow;
+use ipnetwork::{IpNetwork, Ipv4Network};
+use serde::{Deserialize, Serialize};
+use trust_dns_server::client::rr::{LowerName, Name};
+
+// ReverseZone is the name of the zone a network's addresses are delegated to.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ReverseZone {
+    pub network: IpNetwork,
+    pub zone: String,
+}
+
+impl FromStr for ReverseZone {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.split_once('=') {
+            Some((network, zone)) if !zone.is_empty() => Ok(Self {
+                network: IpNetwork::from_str(network)?,
+                zone: zone.to_string(),
+            }),
+            _ => Err(anyhow!(
+                "expected CIDR=ZONE, e.g. 10.147.17.16/28=16-31.17.147.10.in-addr.arpa"
+            )),
+        }
+    }
+}
+
+// Classless is the delegated reverse zone of a network smaller than a /24.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Classless {
+    network: Ipv4Network,
+    zone: Name,
+}
+
+impl Classless {
+    // for_network is the delegated reverse zone of the network, if it has one: the zone configured
+    // for it, or with `enabled`, the one RFC 2317 names after its first address and prefix.
+    pub fn for_network(
+        network: IpNetwork,
+        enabled: bool,
+        zones: &[ReverseZone],
+    ) -> Result<Option<Self>, anyhow::Error> {
+        let configured = zones.iter().find(|zone| {
+            IpNetwork::new(zone.network.network(), zone.network.prefix()).ok() == Some(network)
+        });
+
+        let network = match network {
+            IpNetwork::V4(network) if network.prefix() > 24 => network,
+            _ => match configured {
+                Some(zone) => {
+                    return Err(anyhow!(
+                        "cannot delegate {} to {}: only IPv4 networks smaller than a /24 have classless reverse zones",
+                        zone.network,
+                        zone.zone
+                    ))
+                }
+                None => return Ok(None),
+            },
+        };
+
+        let zone = match configured {
+            Some(zone) => parse_zone(&zone.zone)?,
+            None if enabled => {
+                let octets = network.network().octets();
+                parse_zone(&format!(
+                    "{}/{}.{}.{}.{}.in-addr.arpa",
+                    octets[3],
+                    network.prefix(),
+                    octets[2],
+                    octets[1],
+                    octets[0]
+                ))?
+            }
+            None => return Ok(None),
+        };
+
+        Ok(Some(Self { network, zone }))
+    }
+
+    pub fn zone(&self) -> &Name {
+        &self.zone
+    }
+
+    pub fn contains(&self, ip: Ipv4Addr) -> bool {
+        self.network.contains(ip)
+    }
+
+    // ptr_name is the name of the PTR record of an address in the delegated zone.
+    pub fn ptr_name(&self, ip: Ipv4Addr) -> Result<Name, anyhow::Error> {
+        Ok(
+            Name::from_labels(vec![ip.octets()[3].to_string().into_bytes()])?
+                .append_domain(&self.zone)?,
+        )
+    }
+
+    // target is the name in the delegated zone the CNAME of a reverse name of the /24 points at,
+    // if its address is in the network.
+    pub fn target(&self, name: &LowerName) -> Option<Name> {
+        let name = Name::from(name);
+        let labels: Vec<&[u8]> = name.iter().collect();
+
+        match labels.as_slice() {
+            [d, c, b, a, in_addr, arpa]
+                if in_addr.eq_ignore_ascii_case(b"in-addr")
+                    && arpa.eq_ignore_ascii_case(b"arpa") =>
+            {
+                let octet = |label: &[u8]| std::str::from_utf8(label).ok()?.parse::<u8>().ok();
+                let ip = Ipv4Addr::new(octet(a)?, octet(b)?, octet(c)?, octet(d)?);
+
+                self.contains(ip).then(|| self.ptr_name(ip).ok()).flatten()
+            }
+            _ => None,
+        }
+    }
+}
+
+// parse_zone reads a zone name, whose labels may have characters such as `/` which are not
+// allowed in host names.
+fn parse_zone(zone: &str) -> Result<Name, anyhow::Error> {
+    let labels = zone.trim_end_matches('.').split('.').map(str::as_bytes);
+
+    Name::from_labels(labels)
+        .map(|name| name.to_lowercase())
+        .map_err(|e| anyhow!("invalid reverse zone {}: {}", zone, e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{parse_zone, Classless, ReverseZone};
+    use ipnetwork::IpNetwork;
+    use std::str::FromStr;
+    use trust_dns_server::client::rr::LowerName;
+
+    #[test]
+    fn test_classless() {
+        let network = IpNetwork::from_str("10.147.17.16/28").unwrap();
+        let name = |s: &str| LowerName::from(parse_zone(s).unwrap());
+
+        let classless = Classless::for_network(network, true, &[]).unwrap().unwrap();
+        assert_eq!(
+            classless.zone(),
+            &parse_zone("16/28.17.147.10.in-addr.arpa.").unwrap()
+        );
+        assert_eq!(
+            classless.ptr_name("10.147.17.20".parse().unwrap()).unwrap(),
+            parse_zone("20.16/28.17.147.10.in-addr.arpa.").unwrap()
+        );
+        assert_eq!(
+            classless.target(&name("20.17.147.10.in-addr.arpa.")),
+            Some(parse_zone("20.16/28.17.147.10.in-addr.arpa.").unwrap())
+        );
+
+        // addresses of the /24 outside the network, and other names, have no CNAME.
+        assert_eq!(classless.target(&name("40.17.147.10.in-addr.arpa.")), None);
+        assert_eq!(classless.target(&name("17.147.10.in-addr.arpa.")), None);
+        assert_eq!(classless.target(&name("20.17.147.10.example.com.")), None);
+
+        let zones =
+            vec![ReverseZone::from_str("10.147.17.20/28=16-31.17.147.10.in-addr.arpa").unwrap()];
+        let configured = Classless::for_network(network, false, &zones)
+            .unwrap()
+            .unwrap();
+        assert_eq!(
+            configured.target(&name("20.17.147.10.in-addr.arpa.")),
+            Some(parse_zone("20.16-31.17.147.10.in-addr.arpa.").unwrap())
+        );
+
+        // networks of a /24 or more are served as they are, and cannot be delegated.
+        let wide = IpNetwork::from_str("10.147.17.0/24").unwrap();
+        assert_eq!(Classless::for_network(wide, true, &[]).unwrap(), None);
+        assert_eq!(Classless::for_network(network, false, &[]).unwrap(), None);
+        assert!(Classless::for_network(
+            wide,
+            false,
+            &[ReverseZone::from_str("10.147.17.0/24=0-255.17.147.10.in-addr.arpa").unwrap()]
+        )
+        .is_err());
+
+        assert!(ReverseZone::from_str("10.147.17.16/28").is_err());
+    }
+}
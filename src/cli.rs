@@ -1,11 +1,41 @@
 use crate::{
-    init::{ConfigFormat, Launcher},
-    supervise::Properties,
-    utils::ZEROTIER_LOCAL_URL,
+    acl::AclAction,
+    activation,
+    admin::{self, Endpoint, Networks, RecordEntry},
+    authority::Reload,
+    blocklist::BlockAction,
+    classless::ReverseZone,
+    collisions::CollisionPolicy,
+    dnssec::DnssecConfig,
+    health::{self, NetworkStatus, Registry},
+    init::{ConfigFormat, Instance, Launcher},
+    limits::RecordEviction,
+    order::AnswerOrder,
+    query::parse_server,
+    replication,
+    rpz::RpzConfig,
+    supervise::{InitStyle, Properties},
+    utils::{
+        authtoken_path, drop_privileges, init_runtime, load_config, local_client_from_file,
+        notify_ready, wait_for_reload_signal, wait_for_shutdown, NamePolicy, DEFAULT_POLL_INTERVAL,
+        SHUTDOWN_GRACE_PERIOD, ZEROTIER_LOCAL_URL,
+    },
+    webhook,
+};
+use std::{
+    future::Future,
+    net::{IpAddr, SocketAddr},
+    path::PathBuf,
+    str::FromStr,
+    time::Duration,
 };
-use std::{path::PathBuf, time::Duration};
 
 use clap::{Args, Parser, Subcommand};
+use clap_complete::Shell;
+use ipnetwork::IpNetwork;
+use tokio::sync::watch;
+use tracing::{error, info, warn};
+use trust_dns_server::{client::rr::Name, proto::rr::RecordType};
 
 /// zerotier central nameserver
 #[derive(Parser)]
@@ -24,25 +54,63 @@ pub enum Command {
     /// Start the nameserver
     Start(StartArgs),
 
-    /// Configure supervision of the nameserver for a single network
-    Supervise(StartArgs),
+    /// Configure supervision of the nameserver; one service is installed per network
+    Supervise(SuperviseArgs),
 
     /// Remove supervision of the nameserver for a network
     Unsupervise(UnsuperviseArgs),
+
+    /// Print the DS record to publish in the parent zone of a zone signed with DNSSEC
+    Ds(DsArgs),
+
+    /// Show the networks a running nameserver serves, through its admin API
+    Status(StatusArgs),
+
+    /// Export the zones a running nameserver serves as zone files or JSON, through its admin API
+    Dump(DumpArgs),
+
+    /// Look a name up on a running nameserver and print the answer, as dig does
+    Query(QueryArgs),
+
+    /// Check a configuration file and hosts files, exiting with an error if anything is wrong
+    Validate(ValidateArgs),
+
+    /// Serve made-up members, or those of a members file, and measure how fast queries are answered
+    Bench(BenchArgs),
+
+    /// Route the domain of a network to its nameservers on this machine: /etc/resolver on macOS, systemd-resolved on Linux, NRPT on Windows
+    ClientSetup(ClientSetupArgs),
+
+    /// Print the completion script of a shell [bash, zsh, fish, powershell, elvish]
+    Completions(CompletionsArgs),
+
+    /// Run the nameserver under the Windows service control manager
+    #[cfg(target_os = "windows")]
+    #[clap(hide = true)]
+    Service(StartArgs),
 }
 
 #[derive(Args, Clone)]
 pub struct StartArgs {
-    /// Network ID to query
-    pub network_id: String,
+    /// Network ID(s) to query; each network is served with its own authority and listeners
+    #[clap(required_unless_present = "network-domains")]
+    pub network_ids: Vec<String>,
 
-    /// TLD to use for hostnames
+    /// TLD to use for hostnames; repeat it to serve the same records under other TLDs as well
     #[clap(short, long)]
-    pub domain: Option<String>,
+    pub domain: Vec<String>,
 
-    /// An additional list of hosts in /etc/hosts format
+    /// Use the network's name in Central as its TLD, following renames; --domain is the fallback
+    #[clap(long = "auto-domain")]
+    pub auto_domain: bool,
+
+    /// Network to query with its own TLD, taking precedence over --domain (repeatable)
+    #[clap(long = "network", value_name = "ID=DOMAIN", parse(try_from_str = parse_network_domain))]
+    pub network_domains: Vec<(String, String)>,
+
+    /// An additional list of hosts in /etc/hosts format, or a directory of them (repeatable)
     #[clap(short = 'f', long = "file", value_name = "PATH")]
-    pub hosts: Option<PathBuf>,
+    pub hosts: Vec<PathBuf>,
 
     /// Path to authtoken.secret (usually detected)
     #[clap(short, long, value_name = "PATH")]
@@ -52,11 +120,283 @@ pub struct StartArgs {
     #[clap(short, long, value_name = "PATH")]
     pub token: Option<PathBuf>,
 
+    /// Read the ZeroTier Central token from the first line of standard input
+    #[clap(long = "token-stdin", conflicts_with_all = &["token", "token-keychain"])]
+    pub token_stdin: bool,
+
+    /// Read the ZeroTier Central token from the OS credential store (service "zeronsd", account "central-token")
+    #[clap(long = "token-keychain", conflicts_with = "token")]
+    pub token_keychain: bool,
+
+    /// Only warn, instead of refusing to start, when the token file is accessible by other users
+    #[clap(long = "insecure-token-permissions")]
+    pub insecure_token_permissions: bool,
+
     /// Wildcard all names in Central to point at the respective member's IP address(es)
     #[clap(short, long)]
     pub wildcard: bool,
 
-    /// Configuration file containing these arguments (overrides most CLI options)
+    /// Wildcard the names of this member only, given by member ID or name in Central; can be given more than once
+    #[clap(long = "wildcard-member", value_name = "MEMBER")]
+    pub wildcard_members: Vec<String>,
+
+    /// How member names which are not valid hostnames are turned into ones: idna (or punycode), dash, strip or reject [default: idna]
+    #[clap(long = "name-policy", value_name = "POLICY")]
+    pub name_policy: Option<NamePolicy>,
+
+    /// What is served for a name members share, with each other or with the hosts files: merge, hosts, newest or suffix [default: merge]
+    #[clap(long = "name-collisions", value_name = "POLICY")]
+    pub name_collisions: Option<CollisionPolicy>,
+
+    /// Only serve the members matching this pattern: a member ID, a glob on member names, or tag:<name>[=<value>]; can be given more than once
+    #[clap(long = "include-member", value_name = "PATTERN")]
+    pub include_members: Vec<String>,
+
+    /// Do not serve the members matching this pattern, given as with --include-member; can be given more than once
+    #[clap(long = "exclude-member", value_name = "PATTERN")]
+    pub exclude_members: Vec<String>,
+
+    /// Only serve the members authorized on the network
+    #[clap(long = "authorized-only")]
+    pub authorized_only: bool,
+
+    /// Also serve each member as <member id>.<domain>, a name which survives renames
+    #[clap(long = "node-id-names")]
+    pub node_id_names: bool,
+
+    /// Point the PTR records of members' addresses at all of their names, not just the canonical one
+    #[clap(long = "all-ptr-names")]
+    pub all_ptr_names: bool,
+
+    /// Answer names such as ip-10-147-17-5.<domain> with the address they spell, if it is in the network's ranges
+    #[clap(long = "ip-names")]
+    pub ip_names: bool,
+
+    /// Serve the PTR records of networks smaller than a /24 in RFC 2317 classless zones, such as 16/28.17.147.10.in-addr.arpa
+    #[clap(long = "classless-reverse")]
+    pub classless_reverse: bool,
+
+    /// Serve the PTR records of a network smaller than a /24 in the classless zone delegated to it (repeatable)
+    #[clap(long = "reverse-zone", value_name = "CIDR=ZONE")]
+    pub reverse_zones: Vec<ReverseZone>,
+
+    /// Publish a TXT record for each member with its node ID, description and authorization time
+    #[clap(long = "member-txt")]
+    pub member_txt: bool,
+
+    /// Serve no more than this many records for members, leaving out what --record-eviction says beyond it
+    #[clap(long = "max-records", value_name = "N")]
+    pub max_records: Option<usize>,
+
+    /// What is left out first beyond --max-records: offline (members offline the longest) or wildcards (wildcard names, then members) [default: offline]
+    #[clap(long = "record-eviction", value_name = "POLICY")]
+    pub record_eviction: Option<RecordEviction>,
+
+    /// How often to poll ZeroTier Central for member changes, in seconds [default: 30]
+    #[clap(
+        long = "poll-interval",
+        value_name = "SECONDS",
+        value_parser = clap::value_parser!(u64).range(1..)
+    )]
+    pub poll_interval: Option<u64>,
+
+    /// TTL of the records served, in seconds [default: 60]
+    #[clap(long = "ttl", value_name = "SECONDS")]
+    pub ttl: Option<u32>,
+
+    /// Remove the DNS settings pushed to ZeroTier Central when shutting down
+    #[clap(long = "clear-dns-on-exit")]
+    pub clear_dns_on_exit: bool,
+
+    /// Leave the DNS settings of the network in ZeroTier Central alone instead of pointing them at this instance
+    #[clap(long = "no-update-central", conflicts_with = "central-dns-servers")]
+    pub no_update_central: bool,
+
+    /// Push these DNS servers to ZeroTier Central instead of this member's addresses, such as the address of a load balancer
+    #[clap(
+        long = "central-dns-servers",
+        value_name = "ADDRS",
+        use_value_delimiter = true
+    )]
+    pub central_dns_servers: Vec<IpAddr>,
+
+    /// Another zeronsd serving the network, published to ZeroTier Central alongside this one while it answers (repeatable)
+    #[clap(long = "peer", value_name = "ADDR")]
+    pub peers: Vec<String>,
+
+    /// Wait up to this long for zerotier-one to assign an address on the network, instead of exiting right away
+    #[clap(long = "wait-for-network", value_name = "SECONDS")]
+    pub wait_for_network: Option<u64>,
+
+    /// Directory to save the last members fetched from Central in, to serve them while Central is unreachable
+    #[clap(long = "state-dir", value_name = "PATH")]
+    pub state_dir: Option<PathBuf>,
+
+    /// SQLite database to keep the last members fetched from Central, dynamic updates and statistics in, instead of the state directory
+    #[clap(long = "state-db", value_name = "PATH")]
+    pub state_db: Option<PathBuf>,
+
+    /// Port to serve DNS on [default: 53]
+    #[clap(long = "listen-port", value_name = "PORT")]
+    pub listen_port: Option<u16>,
+
+    /// Address to listen on instead of this member's ZeroTier addresses (repeatable)
+    #[clap(long = "listen-addr", value_name = "ADDR")]
+    pub listen_addrs: Vec<IpAddr>,
+
+    /// UDP sockets, each with its own receive loop, to serve every address with; they share it with SO_REUSEPORT [default: the number of cores] (unix only)
+    #[clap(long = "workers", value_name = "N")]
+    pub workers: Option<usize>,
+
+    /// Threads to answer queries with; 1 runs zeronsd on a single thread, for small routers [default: the number of cores]
+    #[clap(long = "worker-threads", value_name = "N")]
+    pub worker_threads: Option<usize>,
+
+    /// Most threads to run blocking work such as file reads on [default: 512]
+    #[clap(long = "max-blocking-threads", value_name = "N")]
+    pub max_blocking_threads: Option<usize>,
+
+    /// Answer SERVFAIL to queries which take longer than this to answer, e.g. on slow upstreams
+    #[clap(long = "query-timeout", value_name = "MS")]
+    pub query_timeout: Option<u64>,
+
+    /// Close TCP, DNS-over-TLS and DNS-over-HTTPS connections no request has come in on for this long [default: 1000]
+    #[clap(long = "tcp-timeout", value_name = "MS")]
+    pub tcp_timeout: Option<u64>,
+
+    /// Address or network allowed to transfer the zones with AXFR (repeatable)
+    #[clap(long = "allow-transfer", value_name = "ADDR")]
+    pub allow_transfer: Vec<IpNetwork>,
+
+    /// Secondary nameserver to send a NOTIFY to when a zone changes, as ip or ip:port (repeatable)
+    #[clap(long = "notify", value_name = "ADDR")]
+    pub notify: Vec<String>,
+
+    /// Upstream resolver to forward queries to instead of the system's: ip[:port], tcp://, tls:// or https:// (repeatable)
+    #[clap(long = "upstream", value_name = "URL")]
+    pub upstreams: Vec<String>,
+
+    /// Validate the answers to forwarded queries with DNSSEC, answering SERVFAIL when they are bogus
+    #[clap(long = "dnssec-validation")]
+    pub dnssec_validation: bool,
+
+    /// Resolve forwarded queries a label at a time, so that the upstreams only see as much of a name as they need (RFC 7816)
+    #[clap(long = "qname-minimization")]
+    pub qname_minimization: bool,
+
+    /// Cache the answers to forwarded queries, including negative answers, for as long as their TTL
+    #[clap(long = "cache")]
+    pub cache: bool,
+
+    /// Number of answers the forwarder cache holds, dropping the least recently used [default: 10000]
+    #[clap(long = "cache-size", value_name = "ENTRIES")]
+    pub cache_size: Option<usize>,
+
+    /// Answer from expired cache entries when the upstream resolvers cannot be reached (RFC 8767); implies --cache
+    #[clap(long = "serve-stale")]
+    pub serve_stale: bool,
+
+    /// UDP payload size, in bytes, advertised to clients in EDNS answers [default: the client's]
+    #[clap(long = "edns-buffer-size", value_name = "BYTES")]
+    pub edns_buffer_size: Option<u16>,
+
+    /// Answer NSID requests (RFC 5001) with this identifier, to tell instances apart
+    #[clap(long = "nsid", value_name = "ID")]
+    pub nsid: Option<String>,
+
+    /// Send DNS cookies (RFC 7873) to clients which support them, so they can detect spoofed answers
+    #[clap(long = "dns-cookies")]
+    pub dns_cookies: bool,
+
+    /// Answer ANY queries for the zones served with a single HINFO record (RFC 8482)
+    #[clap(long = "minimal-any")]
+    pub minimal_any: bool,
+
+    /// Order of the addresses of names with several of them: fixed, rotate, random, or subnet to put those in the client's network first [default: fixed]
+    #[clap(long = "answer-order", value_name = "ORDER")]
+    pub answer_order: Option<AnswerOrder>,
+
+    /// Refuse or drop requests from outside the network's address ranges [refuse, drop]
+    #[clap(long = "query-acl", value_name = "ACTION")]
+    pub query_acl: Option<AclAction>,
+
+    /// Log every request as a line of JSON to this file, or to stdout with -
+    #[clap(long = "query-log", value_name = "PATH")]
+    pub query_log: Option<PathBuf>,
+
+    /// Size the query log file is rotated at, in MiB [default: 10]
+    #[clap(long = "query-log-size", value_name = "MIB")]
+    pub query_log_size: Option<u64>,
+
+    /// Send dnstap messages of every request to the collector listening on this unix socket (unix only)
+    #[clap(long = "dnstap", value_name = "PATH")]
+    pub dnstap: Option<PathBuf>,
+
+    /// Answer health checks over HTTP on this address, at /healthz and /readyz, e.g. 127.0.0.1:8053
+    #[clap(long = "health-addr", value_name = "ADDR:PORT")]
+    pub health_addr: Option<SocketAddr>,
+
+    /// Serve the admin API on this unix socket, for `zeronsd status` and automation (unix only)
+    #[clap(long = "admin-socket", value_name = "PATH")]
+    pub admin_socket: Option<PathBuf>,
+
+    /// Serve the admin API on this loopback address instead of a unix socket, e.g. 127.0.0.1:8054
+    #[clap(long = "admin-addr", value_name = "ADDR:PORT")]
+    pub admin_addr: Option<SocketAddr>,
+
+    /// Receive webhooks on this address, refreshing the networks right away when Central or automation reports that members changed, e.g. 0.0.0.0:8055
+    #[clap(long = "webhook-addr", value_name = "ADDR:PORT")]
+    pub webhook_addr: Option<SocketAddr>,
+
+    /// File holding the secret webhooks are signed with
+    #[clap(long = "webhook-secret", value_name = "PATH")]
+    pub webhook_secret: Option<PathBuf>,
+
+    /// POST the records added to and removed from the zones to this URL whenever they change; can be given more than once
+    #[clap(long = "record-webhook", value_name = "URL")]
+    pub record_webhooks: Vec<String>,
+
+    /// File holding the secret record-change webhooks are signed with
+    #[clap(long = "record-webhook-secret", value_name = "PATH")]
+    pub record_webhook_secret: Option<PathBuf>,
+
+    /// Serve the members fetched from Central to secondaries started with --primary on this address, e.g. 10.147.17.2:8056
+    #[clap(long = "replication-addr", value_name = "ADDR:PORT")]
+    pub replication_addr: Option<SocketAddr>,
+
+    /// File holding the secret shared by a primary and its secondaries, or by the members of a cluster
+    #[clap(long = "replication-secret", value_name = "PATH")]
+    pub replication_secret: Option<PathBuf>,
+
+    /// URL of the replication listener of another member of the cluster; Central is only polled by the leader (repeatable)
+    #[clap(
+        long = "cluster-member",
+        value_name = "URL",
+        conflicts_with = "primary"
+    )]
+    pub cluster: Vec<String>,
+
+    /// Blocklist in hosts or domain-list format; forwarded queries for its names are not answered (repeatable)
+    #[clap(long = "blocklist", value_name = "PATH")]
+    pub blocklists: Vec<PathBuf>,
+
+    /// How queries for blocked names are answered [nxdomain, null] [default: nxdomain]
+    #[clap(long = "block-action", value_name = "ACTION")]
+    pub block_action: Option<BlockAction>,
+
+    /// Response policy zone file, with the zone it is the origin of; its policies apply to every query (repeatable)
+    #[clap(long = "rpz", value_name = "ZONE=PATH")]
+    pub rpz: Vec<RpzConfig>,
+
+    /// Switch to this user once the listeners are bound (unix only)
+    #[clap(long = "user", value_name = "USER")]
+    pub user: Option<String>,
+
+    /// Switch to this group once the listeners are bound; defaults to the user's primary group (unix only)
+    #[clap(long = "group", value_name = "GROUP")]
+    pub group: Option<String>,
+
+    /// Configuration file containing these arguments (flags given on the command line take precedence)
     #[clap(short = 'c', long = "config", value_name = "PATH")]
     pub config: Option<PathBuf>,
 
@@ -64,97 +404,1352 @@ pub struct StartArgs {
     #[clap(long = "config-type", default_value = "yaml")]
     pub config_type: ConfigFormat,
 
+    /// Certificate (PEM) for the DNS-over-TLS listener on port 853; requires --tls-key
     #[clap(long = "tls-cert", value_name = "PATH")]
     pub tls_cert: Option<PathBuf>,
 
+    /// Intermediate certificate chain (PEM) presented by the DNS-over-TLS listener
     #[clap(long = "chain-cert", value_name = "PATH")]
     pub chain_cert: Option<PathBuf>,
 
+    /// Private key (PEM) for the DNS-over-TLS listener; requires --tls-cert
     #[clap(long = "tls-key", value_name = "PATH")]
     pub tls_key: Option<PathBuf>,
 
+    /// Serve DNS-over-HTTPS (RFC 8484) on this port using --tls-cert and --tls-key
+    #[clap(long = "doh-port", value_name = "PORT")]
+    pub doh_port: Option<u16>,
+
+    /// Hostname clients use to reach the DNS-over-HTTPS listener (defaults to this member's name)
+    #[clap(long = "doh-hostname", value_name = "HOSTNAME")]
+    pub doh_hostname: Option<String>,
+
     /// Provide a different URL for contacting the local zerotier-one service. Default:
     #[clap(long = "local-url", value_name = "LOCAL_URL", default_value = ZEROTIER_LOCAL_URL)]
     pub local_url: String,
 
+    /// Read members from the network controller embedded in the local zerotier-one instead of Central; no token is needed
+    #[clap(long = "local-controller")]
+    pub local_controller: bool,
+
+    /// Read members from a YAML or JSON file listing them, instead of Central; no token is needed
+    #[clap(
+        long = "members-file",
+        value_name = "PATH",
+        conflicts_with = "local-controller"
+    )]
+    pub members_file: Option<PathBuf>,
+
+    /// Read members from the zeronsd serving them on this URL with --replication-addr, instead of Central; no token is needed
+    #[clap(
+        long = "primary",
+        value_name = "URL",
+        conflicts_with_all = &["local-controller", "members-file"]
+    )]
+    pub primary: Option<String>,
+
+    /// Serve the members of a members file through a mock of the Central API, for tests and demos; no token or access to Central is needed
+    #[clap(
+        long = "mock",
+        value_name = "MEMBERS",
+        conflicts_with_all = &["local-controller", "members-file", "primary"]
+    )]
+    pub mock: Option<PathBuf>,
+
+    /// Base URL of the Central API, for self-hosted controllers such as ZTNET [default: https://my.zerotier.com/api/v1]
+    #[clap(long = "central-url", value_name = "URL")]
+    pub central_url: Option<String>,
+
+    /// Seconds a request to Central may take before it is abandoned [default: 30]
+    #[clap(long = "central-timeout", value_name = "SECONDS")]
+    pub central_timeout: Option<u64>,
+
+    /// Seconds to wait for a connection to Central [default: 10]
+    #[clap(long = "central-connect-timeout", value_name = "SECONDS")]
+    pub central_connect_timeout: Option<u64>,
+
+    /// Proxy for requests to Central, e.g. http://proxy.example.com:3128 (HTTP_PROXY and HTTPS_PROXY are used otherwise)
+    #[clap(long = "central-proxy", value_name = "URL")]
+    pub central_proxy: Option<String>,
+
     /// Log Level to print [off, trace, debug, error, warn, info]
     #[clap(short = 'l', long = "log-level", value_name = "LEVEL")]
     pub log_level: Option<crate::log::LevelFilter>,
+
+    /// Log output format [text, json]
+    #[clap(long = "log-format", value_name = "FORMAT")]
+    pub log_format: Option<crate::log::LogFormat>,
 }
 
 impl Into<Launcher> for StartArgs {
     fn into(self) -> Launcher {
-        if let Some(config) = self.config {
-            let res = Launcher::new_from_config(config.to_str().unwrap(), self.config_type);
-            match res {
-                Ok(mut res) => {
-                    res.network_id = Some(self.network_id.clone());
-                    res
-                }
-                Err(e) => {
-                    eprintln!("{}", e);
-                    std::process::exit(1);
-                }
-            }
-        } else {
-            Launcher {
-                domain: self.domain,
-                hosts: self.hosts,
-                secret: self.secret,
-                token: self.token,
-                wildcard: self.wildcard,
-                chain_cert: self.chain_cert,
-                tls_cert: self.tls_cert,
-                tls_key: self.tls_key,
-                log_level: self.log_level,
-                network_id: Some(self.network_id),
-                local_url: self.local_url,
+        match self.launcher() {
+            Ok(res) => res,
+            Err(e) => {
+                eprintln!("{}", e);
+                std::process::exit(1);
             }
         }
     }
 }
 
-#[derive(Args)]
-pub struct UnsuperviseArgs {
-    /// Network ID to remove
-    pub network_id: String,
-}
+impl StartArgs {
+    // launcher combines the configuration file with the flags: values from the configuration
+    // file are loaded first, and any flags supplied on the command line are layered on top.
+    pub fn launcher(self) -> Result<Launcher, anyhow::Error> {
+        let mut launcher = if let Some(config) = self.config {
+            load_config(&config, self.config_type)?
+        } else {
+            Launcher::default()
+        };
 
-pub async fn init() -> Result<(), anyhow::Error> {
-    let cli = Cli::parse();
+        // the first domain is the one pushed to Central; the others are aliases of it.
+        let mut domains = self.domain.into_iter();
+        if let Some(domain) = domains.next() {
+            launcher.domain = Some(domain);
+            launcher.domain_aliases = domains.collect();
+        }
 
-    let result = match cli.command {
-        Command::Start(args) => {
-            start(args).await?;
+        if self.auto_domain {
+            launcher.auto_domain = true;
+        }
 
-            loop {
-                tokio::time::sleep(Duration::MAX).await
-            }
+        launcher.network_domains.extend(self.network_domains);
+
+        if !self.hosts.is_empty() {
+            launcher.hosts = self.hosts;
         }
-        Command::Supervise(args) => supervise(args),
-        Command::Unsupervise(args) => unsupervise(args),
-    };
 
-    if result.is_err() {
-        eprintln!("{}", result.unwrap_err())
-    }
+        if self.secret.is_some() {
+            launcher.secret = self.secret;
+        }
 
-    Ok(())
-}
+        if self.token.is_some() {
+            launcher.token = self.token;
+        }
 
-async fn start(args: StartArgs) -> Result<(), anyhow::Error> {
-    let launcher: Launcher = args.into();
+        if self.token_stdin {
+            launcher.token_stdin = true;
+        }
 
-    launcher.start().await?;
-    Ok(())
-}
+        if self.token_keychain {
+            launcher.token_keychain = true;
+        }
 
-fn unsupervise(args: UnsuperviseArgs) -> Result<(), anyhow::Error> {
-    crate::utils::init_logger(Some(tracing::Level::INFO));
-    Properties::from(args).uninstall_supervisor()
-}
+        if self.insecure_token_permissions {
+            launcher.insecure_token_permissions = true;
+        }
 
-fn supervise(args: StartArgs) -> Result<(), anyhow::Error> {
-    crate::utils::init_logger(Some(tracing::Level::INFO));
-    Properties::from(args).install_supervisor()
+        if self.wildcard {
+            launcher.wildcard = true;
+        }
+
+        if !self.wildcard_members.is_empty() {
+            launcher.wildcard_members = self.wildcard_members;
+        }
+
+        if self.name_policy.is_some() {
+            launcher.name_policy = self.name_policy;
+        }
+
+        if self.name_collisions.is_some() {
+            launcher.name_collisions = self.name_collisions;
+        }
+
+        if !self.include_members.is_empty() {
+            launcher.include_members = self.include_members;
+        }
+
+        if !self.exclude_members.is_empty() {
+            launcher.exclude_members = self.exclude_members;
+        }
+
+        if self.authorized_only {
+            launcher.authorized_only = true;
+        }
+
+        if self.node_id_names {
+            launcher.node_id_names = true;
+        }
+
+        if self.all_ptr_names {
+            launcher.all_ptr_names = true;
+        }
+
+        if self.ip_names {
+            launcher.ip_names = true;
+        }
+
+        if self.classless_reverse {
+            launcher.classless_reverse = true;
+        }
+
+        if !self.reverse_zones.is_empty() {
+            launcher.reverse_zones = self.reverse_zones;
+        }
+
+        if self.member_txt {
+            launcher.member_txt = true;
+        }
+
+        if self.max_records.is_some() {
+            launcher.max_records = self.max_records;
+        }
+
+        if self.record_eviction.is_some() {
+            launcher.record_eviction = self.record_eviction;
+        }
+
+        if self.poll_interval.is_some() {
+            launcher.poll_interval = self.poll_interval;
+        }
+
+        if self.ttl.is_some() {
+            launcher.ttl = self.ttl;
+        }
+
+        if self.clear_dns_on_exit {
+            launcher.clear_dns_on_exit = true;
+        }
+
+        if self.no_update_central {
+            launcher.no_update_central = true;
+        }
+
+        if !self.central_dns_servers.is_empty() {
+            launcher.central_dns_servers = self.central_dns_servers;
+        }
+
+        if !self.peers.is_empty() {
+            launcher.peers = self.peers;
+        }
+
+        if self.wait_for_network.is_some() {
+            launcher.wait_for_network = self.wait_for_network;
+        }
+
+        if self.state_dir.is_some() {
+            launcher.state_dir = self.state_dir;
+        }
+
+        if self.state_db.is_some() {
+            launcher.state_db = self.state_db;
+        }
+
+        if self.listen_port.is_some() {
+            launcher.listen_port = self.listen_port;
+        }
+
+        if !self.listen_addrs.is_empty() {
+            launcher.listen_addrs = self.listen_addrs;
+        }
+
+        if self.workers.is_some() {
+            launcher.workers = self.workers;
+        }
+
+        if self.worker_threads.is_some() {
+            launcher.worker_threads = self.worker_threads;
+        }
+
+        if self.max_blocking_threads.is_some() {
+            launcher.max_blocking_threads = self.max_blocking_threads;
+        }
+
+        if self.query_timeout.is_some() {
+            launcher.query_timeout = self.query_timeout;
+        }
+
+        if self.tcp_timeout.is_some() {
+            launcher.tcp_timeout = self.tcp_timeout;
+        }
+
+        if !self.allow_transfer.is_empty() {
+            launcher.allow_transfer = self.allow_transfer;
+        }
+
+        if !self.notify.is_empty() {
+            launcher.notify = self.notify;
+        }
+
+        if !self.upstreams.is_empty() {
+            launcher.upstreams = self.upstreams;
+        }
+
+        if self.dnssec_validation {
+            launcher.dnssec_validation = true;
+        }
+
+        if self.qname_minimization {
+            launcher.qname_minimization = true;
+        }
+
+        if self.cache {
+            launcher.cache = true;
+        }
+
+        if self.cache_size.is_some() {
+            launcher.cache_size = self.cache_size;
+        }
+
+        if self.serve_stale {
+            launcher.serve_stale = true;
+        }
+
+        if self.edns_buffer_size.is_some() {
+            launcher.edns_buffer_size = self.edns_buffer_size;
+        }
+
+        if self.nsid.is_some() {
+            launcher.nsid = self.nsid;
+        }
+
+        if self.dns_cookies {
+            launcher.dns_cookies = true;
+        }
+
+        if self.minimal_any {
+            launcher.minimal_any = true;
+        }
+
+        if self.answer_order.is_some() {
+            launcher.answer_order = self.answer_order;
+        }
+
+        if self.query_acl.is_some() {
+            launcher.query_acl = self.query_acl;
+        }
+
+        if self.query_log.is_some() {
+            launcher.query_log = self.query_log;
+        }
+
+        if self.query_log_size.is_some() {
+            launcher.query_log_size = self.query_log_size;
+        }
+
+        if self.dnstap.is_some() {
+            launcher.dnstap = self.dnstap;
+        }
+
+        if self.health_addr.is_some() {
+            launcher.health_addr = self.health_addr;
+        }
+
+        if self.admin_socket.is_some() {
+            launcher.admin_socket = self.admin_socket;
+        }
+
+        if self.admin_addr.is_some() {
+            launcher.admin_addr = self.admin_addr;
+        }
+
+        if self.webhook_addr.is_some() {
+            launcher.webhook_addr = self.webhook_addr;
+        }
+
+        if self.webhook_secret.is_some() {
+            launcher.webhook_secret = self.webhook_secret;
+        }
+
+        if !self.record_webhooks.is_empty() {
+            launcher.record_webhooks = self.record_webhooks;
+        }
+
+        if self.record_webhook_secret.is_some() {
+            launcher.record_webhook_secret = self.record_webhook_secret;
+        }
+
+        if self.replication_addr.is_some() {
+            launcher.replication_addr = self.replication_addr;
+        }
+
+        if self.replication_secret.is_some() {
+            launcher.replication_secret = self.replication_secret;
+        }
+
+        if !self.cluster.is_empty() {
+            launcher.cluster = self.cluster;
+        }
+
+        if !self.blocklists.is_empty() {
+            launcher.blocklists = self.blocklists;
+        }
+
+        if self.block_action.is_some() {
+            launcher.block_action = self.block_action;
+        }
+
+        if !self.rpz.is_empty() {
+            launcher.rpz = self.rpz;
+        }
+
+        if self.user.is_some() {
+            launcher.user = self.user;
+        }
+
+        if self.group.is_some() {
+            launcher.group = self.group;
+        }
+
+        if self.chain_cert.is_some() {
+            launcher.chain_cert = self.chain_cert;
+        }
+
+        if self.tls_cert.is_some() {
+            launcher.tls_cert = self.tls_cert;
+        }
+
+        if self.tls_key.is_some() {
+            launcher.tls_key = self.tls_key;
+        }
+
+        if self.doh_port.is_some() {
+            launcher.doh_port = self.doh_port;
+        }
+
+        if self.doh_hostname.is_some() {
+            launcher.doh_hostname = self.doh_hostname;
+        }
+
+        if self.log_level.is_some() {
+            launcher.log_level = self.log_level;
+        }
+
+        if let Some(log_format) = self.log_format {
+            launcher.log_format = log_format;
+        }
+
+        if self.local_url != ZEROTIER_LOCAL_URL {
+            launcher.local_url = self.local_url;
+        }
+
+        if self.local_controller {
+            launcher.local_controller = true;
+        }
+
+        if self.members_file.is_some() {
+            launcher.members_file = self.members_file;
+        }
+
+        if self.primary.is_some() {
+            launcher.primary = self.primary;
+        }
+
+        if self.mock.is_some() {
+            launcher.mock = self.mock;
+        }
+
+        if self.central_url.is_some() {
+            launcher.central_url = self.central_url;
+        }
+
+        if self.central_timeout.is_some() {
+            launcher.central_timeout = self.central_timeout;
+        }
+
+        if self.central_connect_timeout.is_some() {
+            launcher.central_connect_timeout = self.central_connect_timeout;
+        }
+
+        if self.central_proxy.is_some() {
+            launcher.central_proxy = self.central_proxy;
+        }
+
+        Ok(launcher)
+    }
+
+    // all_network_ids lists the networks given on the command line, with or without a domain of
+    // their own.
+    pub fn all_network_ids(&self) -> Vec<String> {
+        let mut network_ids = self.network_ids.clone();
+
+        for (network_id, _) in &self.network_domains {
+            if !network_ids.contains(network_id) {
+                network_ids.push(network_id.clone());
+            }
+        }
+
+        network_ids
+    }
+
+    // runtime builds the runtime zeronsd is served on. Every network is served by the same
+    // process, so the first one's settings are used.
+    pub fn runtime(&self) -> Result<tokio::runtime::Runtime, anyhow::Error> {
+        let launcher = self.clone().launcher()?;
+        init_runtime(launcher.worker_threads, launcher.max_blocking_threads)
+    }
+
+    // launchers yields one launcher for each network ID provided on the command line.
+    pub fn launchers(self) -> Vec<Launcher> {
+        let network_ids = self.all_network_ids();
+        let launcher: Launcher = self.into();
+
+        network_ids
+            .into_iter()
+            .map(|network_id| launcher.for_network(network_id))
+            .collect()
+    }
+}
+
+#[derive(Args, Clone)]
+pub struct SuperviseArgs {
+    #[clap(flatten)]
+    pub start: StartArgs,
+
+    /// Init system to install the service for [systemd, openrc, runit] (Linux only; detected by default)
+    #[clap(long = "init-style", value_name = "STYLE")]
+    pub init_style: Option<InitStyle>,
+
+    /// Print the service definition to stdout instead of installing it
+    #[clap(long = "print")]
+    pub print: bool,
+
+    /// Serve all networks from one service named `zeronsd` instead of one service per network
+    #[clap(long = "single-unit")]
+    pub single_unit: bool,
+
+    /// Have systemd listen on this address and pass the sockets to zeronsd, which then needs no privileges to bind them (systemd only; repeatable)
+    #[clap(long = "socket", value_name = "ADDR:PORT")]
+    pub sockets: Vec<SocketAddr>,
+}
+
+#[derive(Args)]
+pub struct UnsuperviseArgs {
+    /// Network ID to remove
+    #[clap(required_unless_present = "single-unit")]
+    pub network_id: Option<String>,
+
+    /// Remove the service installed with `supervise --single-unit`
+    #[clap(long = "single-unit", conflicts_with = "network-id")]
+    pub single_unit: bool,
+
+    /// Init system the service was installed for [systemd, openrc, runit] (Linux only; detected by default)
+    #[clap(long = "init-style", value_name = "STYLE")]
+    pub init_style: Option<InitStyle>,
+}
+
+#[derive(Args)]
+pub struct DsArgs {
+    /// Zone to print the DS record of, e.g. home.arpa
+    pub domain: String,
+
+    /// DNSSEC key the zone is signed with, generated if it does not exist; defaults to the `dnssec` key of the configuration file
+    #[clap(long = "key", value_name = "PATH")]
+    pub key: Option<PathBuf>,
+
+    /// Algorithm of the key [RSASHA256, RSASHA512, ECDSAP256SHA256, ECDSAP384SHA384, ED25519]
+    #[clap(long = "algorithm", value_name = "ALGORITHM")]
+    pub algorithm: Option<String>,
+
+    /// Configuration file containing the `dnssec` section
+    #[clap(short = 'c', long = "config", value_name = "PATH")]
+    pub config: Option<PathBuf>,
+
+    /// Configuration file format [yaml, json, toml]
+    #[clap(long = "config-type", default_value = "yaml")]
+    pub config_type: ConfigFormat,
+}
+
+#[derive(Args)]
+pub struct StatusArgs {
+    #[clap(flatten)]
+    pub admin: AdminArgs,
+}
+
+#[derive(Args)]
+pub struct DumpArgs {
+    /// Network ID to export the zones of; every network served by default
+    pub network_id: Option<String>,
+
+    /// Output format [zone, json]
+    #[clap(long = "format", default_value = "zone")]
+    pub format: DumpFormat,
+
+    #[clap(flatten)]
+    pub admin: AdminArgs,
+}
+
+// DumpFormat is what `zeronsd dump` writes the records in.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum DumpFormat {
+    // a BIND zone file per zone.
+    Zone,
+    JSON,
+}
+
+impl FromStr for DumpFormat {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "zone" => Ok(DumpFormat::Zone),
+            "json" | "JSON" => Ok(DumpFormat::JSON),
+            _ => Err(anyhow::anyhow!(
+                "invalid format: allowed values: [zone, json]"
+            )),
+        }
+    }
+}
+
+#[derive(Args)]
+pub struct QueryArgs {
+    /// Name to look up, e.g. db.home.arpa
+    pub name: String,
+
+    /// Record type to look up
+    #[clap(default_value = "A")]
+    pub record_type: RecordType,
+
+    /// Nameserver to ask, with port 53 unless given; defaults to the first address the nameserver listens on, found through its admin API
+    #[clap(long = "server", value_name = "ADDR[:PORT]", parse(try_from_str = parse_server))]
+    pub server: Option<SocketAddr>,
+
+    /// Ask over TCP instead of UDP
+    #[clap(long = "tcp")]
+    pub tcp: bool,
+
+    #[clap(flatten)]
+    pub admin: AdminArgs,
+}
+
+#[derive(Args)]
+pub struct BenchArgs {
+    /// Members to serve, in the format of --members-file; made-up members are served without one
+    #[clap(long = "members-file", value_name = "PATH")]
+    pub members_file: Option<PathBuf>,
+
+    /// Made-up members to serve without a members file, each with a name and an address
+    #[clap(long = "records", value_name = "N", default_value = "1000")]
+    pub records: usize,
+
+    /// TLD to serve the members under
+    #[clap(short, long)]
+    pub domain: Option<String>,
+
+    /// Serve wildcard names for every member, and query them as well
+    #[clap(short, long)]
+    pub wildcard: bool,
+
+    /// Queries to send per second, in total [default: as many as are answered]
+    #[clap(long = "qps", value_name = "N")]
+    pub qps: Option<u32>,
+
+    /// Queries to send at once
+    #[clap(long = "concurrency", value_name = "N", default_value = "16")]
+    pub concurrency: usize,
+
+    /// How long to send queries for
+    #[clap(long = "duration", value_name = "SECONDS", default_value = "10")]
+    pub duration: u64,
+
+    /// UDP sockets to serve with, as with start --workers
+    #[clap(long = "workers", value_name = "N", default_value = "1")]
+    pub workers: usize,
+
+    /// Ask over TCP instead of UDP
+    #[clap(long = "tcp")]
+    pub tcp: bool,
+}
+
+#[derive(Args)]
+pub struct ClientSetupArgs {
+    /// Network ID to route the domain of
+    pub network_id: String,
+
+    /// Domain to route; defaults to the one pushed to the network
+    #[clap(short, long)]
+    pub domain: Option<String>,
+
+    /// Nameserver to route the domain to (repeatable); defaults to those pushed to the network
+    #[clap(long = "server", value_name = "IP")]
+    pub servers: Vec<IpAddr>,
+
+    /// Undo what an earlier client-setup did for the network
+    #[clap(long = "remove")]
+    pub remove: bool,
+
+    /// Print what would be changed, changing nothing
+    #[clap(long = "dry-run")]
+    pub dry_run: bool,
+
+    /// Path to authtoken.secret (usually detected)
+    #[clap(short, long, value_name = "PATH")]
+    pub secret: Option<PathBuf>,
+
+    /// Provide a different URL for contacting the local zerotier-one service. Default:
+    #[clap(long = "local-url", value_name = "LOCAL_URL", default_value = ZEROTIER_LOCAL_URL)]
+    pub local_url: String,
+}
+
+#[derive(Args)]
+pub struct ValidateArgs {
+    /// Configuration file to check, along with the hosts files it names
+    #[clap(short = 'c', long = "config", value_name = "PATH")]
+    pub config: Option<PathBuf>,
+
+    /// Configuration file format [yaml, json, toml]
+    #[clap(long = "config-type", default_value = "yaml")]
+    pub config_type: ConfigFormat,
+
+    /// Hosts file, or directory of them, to check (repeatable)
+    #[clap(
+        short = 'f',
+        long = "file",
+        value_name = "PATH",
+        required_unless_present = "config"
+    )]
+    pub hosts: Vec<PathBuf>,
+
+    /// TLD the hosts files are served under; defaults to the domain of the configuration file
+    #[clap(short, long)]
+    pub domain: Option<String>,
+}
+
+#[derive(Args)]
+pub struct CompletionsArgs {
+    /// Shell to complete for
+    #[clap(required_unless_present = "list-networks")]
+    pub shell: Option<Shell>,
+
+    /// List the networks joined by the local ZeroTier service, for the completion scripts
+    #[clap(long = "list-networks", hide = true)]
+    pub list_networks: bool,
+}
+
+// AdminArgs are how the subcommands talking to a running nameserver find its admin API.
+#[derive(Args)]
+pub struct AdminArgs {
+    /// Admin socket of the nameserver; defaults to the `admin_socket` of the configuration file
+    #[clap(
+        long = "admin-socket",
+        value_name = "PATH",
+        conflicts_with = "admin-addr"
+    )]
+    pub admin_socket: Option<PathBuf>,
+
+    /// Loopback address of the admin API of the nameserver; defaults to the `admin_addr` of the configuration file
+    #[clap(long = "admin-addr", value_name = "ADDR:PORT")]
+    pub admin_addr: Option<SocketAddr>,
+
+    /// Configuration file the nameserver was started with
+    #[clap(short = 'c', long = "config", value_name = "PATH")]
+    pub config: Option<PathBuf>,
+
+    /// Configuration file format [yaml, json, toml]
+    #[clap(long = "config-type", default_value = "yaml")]
+    pub config_type: ConfigFormat,
+}
+
+// init runs the command given on the command line.
+pub fn init() -> Result<(), anyhow::Error> {
+    let cli = Cli::parse();
+
+    let runtime = match &cli.command {
+        Command::Start(args) => args.runtime()?,
+        _ => init_runtime(None, None)?,
+    };
+
+    runtime.block_on(run(cli))
+}
+
+async fn run(cli: Cli) -> Result<(), anyhow::Error> {
+    let result = match cli.command {
+        Command::Start(mut args) => {
+            if args.log_level.is_none() {
+                args.log_level = crate::log::verbosity(cli.verbose);
+            }
+
+            start(args).await?;
+            Ok(())
+        }
+        Command::Supervise(args) => supervise(args),
+        Command::Unsupervise(args) => unsupervise(args),
+        Command::Ds(args) => ds(args),
+        Command::Status(args) => status(args).await,
+        Command::Dump(args) => dump(args).await,
+        Command::Query(args) => query(args).await,
+        Command::Validate(args) => validate(args),
+        Command::Bench(args) => bench(args).await,
+        Command::ClientSetup(args) => client_setup(args).await,
+        Command::Completions(args) => completions(args).await,
+        #[cfg(target_os = "windows")]
+        Command::Service(mut args) => {
+            if args.log_level.is_none() {
+                args.log_level = crate::log::verbosity(cli.verbose);
+            }
+
+            // the service control manager blocks this thread until the service stops.
+            tokio::task::spawn_blocking(move || crate::service::dispatch(args)).await?
+        }
+    };
+
+    if result.is_err() {
+        eprintln!("{}", result.unwrap_err())
+    }
+
+    Ok(())
+}
+
+// start serves every network until SIGINT or SIGTERM is received.
+async fn start(args: StartArgs) -> Result<(), anyhow::Error> {
+    serve(args, wait_for_shutdown()).await
+}
+
+// serve serves every network until `shutdown` resolves. SIGHUP and SIGUSR1 re-read the
+// configuration and refresh every network immediately. Networks served with --auto-domain are
+// restarted under their new domain when they are renamed.
+pub async fn serve(
+    args: StartArgs,
+    shutdown: impl Future<Output = Result<(), anyhow::Error>>,
+) -> Result<(), anyhow::Error> {
+    tokio::pin!(shutdown);
+
+    // the sockets systemd listens on for us, if it does, are taken before anything is bound.
+    activation::receive()?;
+
+    let mut running = Vec::new();
+    let registry = Registry::default();
+    let networks = Networks::default();
+
+    for launcher in args.clone().launchers() {
+        let (reload, rx) = watch::channel(launcher.reload());
+        let instance = launcher.start(Some(rx)).await?;
+        registry.register(instance.zt.health.clone());
+        networks.register(instance.zt.clone());
+        running.push((launcher, instance, reload));
+    }
+
+    // health checks are answered for the whole process, on the first network's address.
+    let health_checks = match running
+        .first()
+        .and_then(|(launcher, _, _)| launcher.health_addr)
+    {
+        Some(addr) => Some(health::listen(addr, registry.clone()).await?),
+        None => None,
+    };
+
+    // the secret may only be readable before privileges are dropped.
+    let webhooks = match running.first().map(|(launcher, _, _)| launcher) {
+        Some(Launcher {
+            webhook_addr: Some(addr),
+            webhook_secret,
+            ..
+        }) => {
+            let path = webhook_secret
+                .as_deref()
+                .ok_or_else(|| anyhow::anyhow!("webhook_addr requires webhook_secret"))?;
+            Some(webhook::listen(*addr, webhook::read_secret(path)?, networks.clone()).await?)
+        }
+        _ => None,
+    };
+
+    let replication = match running.first().map(|(launcher, _, _)| launcher) {
+        Some(launcher) => match launcher.replication_addr {
+            Some(addr) => Some(
+                replication::listen(
+                    addr,
+                    launcher.read_replication_secret()?,
+                    networks.clone(),
+                    launcher.node_id().await?,
+                )
+                .await?,
+            ),
+            None => None,
+        },
+        None => None,
+    };
+
+    let mut rename_timer = tokio::time::interval(Duration::new(DEFAULT_POLL_INTERVAL, 0));
+
+    // every network is served by the same process, so they all share the first one's account.
+    if let Some((launcher, _, _)) = running.first() {
+        drop_privileges(launcher.user.as_deref(), launcher.group.as_deref())?;
+    }
+
+    // the admin socket is made once privileges are dropped, so that it belongs to that account.
+    let mut admin = Vec::new();
+
+    if let Some((launcher, _, _)) = running.first() {
+        if let Some(path) = &launcher.admin_socket {
+            admin.push(admin::listen_unix(path.clone(), networks.clone())?);
+        }
+
+        if let Some(addr) = launcher.admin_addr {
+            admin.push(admin::listen_tcp(addr, networks.clone()).await?);
+        }
+    }
+
+    notify_ready();
+
+    loop {
+        tokio::select! {
+            res = &mut shutdown => {
+                res?;
+                break;
+            }
+            res = wait_for_reload_signal() => {
+                res?;
+                info!("Reloading configuration");
+
+                let config = match args.clone().launcher() {
+                    Ok(config) => Some(config),
+                    Err(e) => {
+                        error!(
+                            "Could not reload configuration; refreshing with the current one: {}",
+                            e
+                        );
+                        None
+                    }
+                };
+
+                for (launcher, instance, reload) in running.iter_mut() {
+                    if let Some(config) = &config {
+                        *launcher = config.for_network(instance.zt.network_id.clone());
+                    }
+
+                    // nothing is listening only if the refresh task has exited.
+                    let _ = reload.send(launcher.reload());
+                }
+            }
+            _ = rename_timer.tick() => {
+                for entry in running.iter_mut().filter(|entry| entry.0.auto_domain) {
+                    follow_rename(entry).await?;
+                    registry.register(entry.1.zt.health.clone());
+                    networks.register(entry.1.zt.clone());
+                }
+            }
+        }
+    }
+
+    info!("Shutting down");
+
+    if let Some(health_checks) = health_checks {
+        health_checks.abort();
+    }
+
+    for admin in admin {
+        admin.abort();
+    }
+
+    if let Some(webhooks) = webhooks {
+        webhooks.abort();
+    }
+
+    if let Some(replication) = replication {
+        replication.abort();
+    }
+
+    // the listeners are closed first, so that no more queries come in while the DNS settings are
+    // cleared and the queries already being answered finish.
+    for (launcher, mut instance, _) in running {
+        instance.stop().await;
+        launcher.shutdown(&instance.zt).await;
+    }
+
+    if !crate::server::drain(Duration::new(SHUTDOWN_GRACE_PERIOD, 0)).await {
+        warn!(
+            "Queries still being answered after {}s were dropped",
+            SHUTDOWN_GRACE_PERIOD
+        );
+    }
+
+    Ok(())
+}
+
+// parse_network_domain parses the NETWORK_ID=DOMAIN form of --network.
+fn parse_network_domain(s: &str) -> Result<(String, String), anyhow::Error> {
+    match s.split_once('=') {
+        Some((network_id, domain)) if !network_id.is_empty() && !domain.is_empty() => {
+            Ok((network_id.to_string(), domain.to_string()))
+        }
+        _ => Err(anyhow::anyhow!(
+            "expected NETWORK_ID=DOMAIN, e.g. 8056c2e21c000001=home.arpa"
+        )),
+    }
+}
+
+// follow_rename restarts a network served with --auto-domain under a new domain once the network
+// has been renamed. Failing to check is not fatal; failing to start it again is.
+async fn follow_rename(
+    (launcher, instance, reload): &mut (Launcher, Instance, watch::Sender<Reload>),
+) -> Result<(), anyhow::Error> {
+    let network_id = instance.zt.network_id.clone();
+
+    let domain_name = match instance.zt.controller.network(&network_id).await {
+        Ok(network) => launcher.domain_name(&network)?,
+        Err(e) => {
+            warn!(
+                "Could not check network {} for a new name: {}",
+                network_id, e
+            );
+            return Ok(());
+        }
+    };
+
+    if domain_name == instance.domain_name {
+        return Ok(());
+    }
+
+    info!(
+        "Network {} was renamed; serving it under {} instead of {}",
+        network_id, domain_name, instance.domain_name
+    );
+
+    // the listeners have to be closed before the new ones can bind the same addresses. Their
+    // servers' own tasks only wind down once they are next polled, so give them a moment.
+    instance.stop().await;
+    tokio::time::sleep(Duration::new(1, 0)).await;
+
+    let (new_reload, rx) = watch::channel(launcher.reload());
+    *instance = launcher.start(Some(rx)).await?;
+    *reload = new_reload;
+
+    Ok(())
+}
+
+// ds prints the DS record of a zone, for its parent zone to delegate to the key it is signed with.
+fn ds(args: DsArgs) -> Result<(), anyhow::Error> {
+    crate::utils::init_logger(Some(tracing::Level::INFO));
+
+    let config = match args.config {
+        Some(config) => load_config(&config, args.config_type)?.dnssec,
+        None => None,
+    };
+
+    let mut dnssec = match (config, args.key) {
+        (Some(config), Some(key)) => DnssecConfig { key, ..config },
+        (Some(config), None) => config,
+        (None, Some(key)) => DnssecConfig::new(key),
+        (None, None) => {
+            return Err(anyhow::anyhow!(
+                "no DNSSEC key: pass --key or a configuration file with a `dnssec` section"
+            ))
+        }
+    };
+
+    if let Some(algorithm) = args.algorithm {
+        dnssec.algorithm = algorithm;
+    }
+
+    let domain = Name::from_str(&format!("{}.", args.domain.trim_end_matches('.')))?;
+    println!("{} IN DS {}", domain, dnssec.zone_key()?.ds(&domain)?);
+
+    Ok(())
+}
+
+impl AdminArgs {
+    // is_empty tells whether no admin API was given, directly or through a configuration file.
+    fn is_empty(&self) -> bool {
+        self.admin_socket.is_none() && self.admin_addr.is_none() && self.config.is_none()
+    }
+
+    // endpoint is the admin API given, or else the one of the configuration file.
+    fn endpoint(self) -> Result<Endpoint, anyhow::Error> {
+        Ok(match (self.admin_socket, self.admin_addr, self.config) {
+            (Some(path), _, _) => Endpoint::Socket(path),
+            (None, Some(addr), _) => Endpoint::Addr(addr),
+            (None, None, Some(config)) => {
+                let launcher = load_config(&config, self.config_type)?;
+
+                match (launcher.admin_socket, launcher.admin_addr) {
+                    (Some(path), _) => Endpoint::Socket(path),
+                    (None, Some(addr)) => Endpoint::Addr(addr),
+                    (None, None) => {
+                        return Err(anyhow::anyhow!(
+                            "the configuration file sets neither admin_socket nor admin_addr"
+                        ))
+                    }
+                }
+            }
+            (None, None, None) => {
+                return Err(anyhow::anyhow!(
+                    "no admin API to ask: pass --admin-socket, --admin-addr or --config"
+                ))
+            }
+        })
+    }
+}
+
+async fn status(args: StatusArgs) -> Result<(), anyhow::Error> {
+    let statuses = admin::statuses(&args.admin.endpoint()?).await?;
+
+    if statuses.is_empty() {
+        println!("No networks are served");
+    }
+
+    for (i, status) in statuses.iter().enumerate() {
+        if i > 0 {
+            println!();
+        }
+
+        print!("{}", format_status(status));
+    }
+
+    Ok(())
+}
+
+// format_status lays out the status of a network for `zeronsd status`.
+fn format_status(status: &NetworkStatus) -> String {
+    let state = match (status.live, status.ready) {
+        (true, true) => "ready",
+        (true, false) => "not ready",
+        (false, _) => "not refreshing",
+    };
+
+    let listening = if status.listening.is_empty() {
+        "nothing".to_string()
+    } else {
+        status.listening.join(", ")
+    };
+
+    let last_poll = match status.last_poll_seconds {
+        Some(seconds) => format!("{}s ago", seconds),
+        None => "never".to_string(),
+    };
+
+    let central = match &status.central_error {
+        Some(error) => format!("failing: {}", error),
+        None if status.last_poll_seconds.is_some() => "ok".to_string(),
+        None => "unknown".to_string(),
+    };
+
+    format!(
+        "{} ({}): {}\n  Listening: {}\n  Records:   {}\n  Last poll: {}\n  Central:   {}\n",
+        status.network_id, status.domain, state, listening, status.records, last_poll, central
+    )
+}
+
+async fn dump(args: DumpArgs) -> Result<(), anyhow::Error> {
+    let endpoint = args.admin.endpoint()?;
+
+    let network_ids = match args.network_id {
+        Some(network_id) => vec![network_id],
+        None => admin::statuses(&endpoint)
+            .await?
+            .into_iter()
+            .map(|status| status.network_id)
+            .collect(),
+    };
+
+    let mut records = Vec::new();
+
+    for network_id in network_ids {
+        records.extend(admin::records(&endpoint, &network_id).await?);
+    }
+
+    match args.format {
+        DumpFormat::JSON => println!("{}", serde_json::to_string_pretty(&records)?),
+        DumpFormat::Zone => print!("{}", format_zones(&records)),
+    }
+
+    Ok(())
+}
+
+async fn query(args: QueryArgs) -> Result<(), anyhow::Error> {
+    let name = Name::from_str(&format!("{}.", args.name.trim_end_matches('.')))?;
+
+    let server = match args.server {
+        Some(server) => server,
+        None if args.admin.is_empty() => {
+            return Err(anyhow::anyhow!(
+                "no nameserver to ask: pass --server, or --admin-socket, --admin-addr or --config to find it through the admin API"
+            ))
+        }
+        None => listening_addr(&args.admin.endpoint()?, &name).await?,
+    };
+
+    let answer = crate::query::query(server, &name, args.record_type, args.tcp).await?;
+    print!("{}", crate::query::format_answer(&answer));
+
+    Ok(())
+}
+
+// listening_addr is the first address listened on for the network whose domain the name is in,
+// or else for the first network served.
+async fn listening_addr(endpoint: &Endpoint, name: &Name) -> Result<SocketAddr, anyhow::Error> {
+    let statuses = admin::statuses(endpoint).await?;
+
+    let status = statuses
+        .iter()
+        .find(|status| Name::from_str(&status.domain).map_or(false, |domain| domain.zone_of(name)))
+        .or_else(|| statuses.first())
+        .ok_or_else(|| anyhow::anyhow!("no networks are served"))?;
+
+    match status.listening.first() {
+        Some(addr) => Ok(SocketAddr::from_str(addr)?),
+        None => Err(anyhow::anyhow!(
+            "network {} is not listening on any address",
+            status.network_id
+        )),
+    }
+}
+
+async fn bench(args: BenchArgs) -> Result<(), anyhow::Error> {
+    let report = crate::bench::run(crate::bench::BenchOptions {
+        members_file: args.members_file,
+        records: args.records,
+        domain: args.domain,
+        wildcard: args.wildcard,
+        workers: args.workers,
+        qps: args.qps,
+        concurrency: args.concurrency,
+        duration: Duration::new(args.duration, 0),
+        tcp: args.tcp,
+    })
+    .await?;
+
+    print!("{}", crate::bench::format_report(&report));
+
+    Ok(())
+}
+
+async fn client_setup(args: ClientSetupArgs) -> Result<(), anyhow::Error> {
+    use crate::clientsetup::{apply, check_network_id, plan, Platform, Setup};
+
+    check_network_id(&args.network_id)?;
+    let platform = Platform::current()?;
+
+    // the local zerotier-one is only asked for what was not given; systemd-resolved needs the
+    // interface of the network from it either way.
+    let network = if args.domain.is_none()
+        || (args.servers.is_empty() && !args.remove)
+        || platform == Platform::Resolved
+    {
+        let client = local_client_from_file(
+            authtoken_path(args.secret.as_deref()),
+            args.local_url.clone(),
+        )?;
+
+        Some(
+            crate::controller::get(&client, &format!("network/{}", args.network_id))
+                .await
+                .map_err(|e| e.context("has zerotier-one joined the network?"))?,
+        )
+    } else {
+        None
+    };
+
+    let setup = Setup::from_network(
+        &args.network_id,
+        network.as_ref(),
+        args.domain.as_deref(),
+        args.servers,
+    )?;
+
+    apply(&plan(platform, &setup, args.remove)?, args.dry_run)
+}
+
+fn validate(args: ValidateArgs) -> Result<(), anyhow::Error> {
+    let problems = crate::validate::validate(
+        args.config.as_deref().map(|path| (path, args.config_type)),
+        &args.hosts,
+        args.domain.as_deref(),
+    );
+
+    if problems.is_empty() {
+        println!("No problems found");
+        return Ok(());
+    }
+
+    for problem in &problems {
+        println!("{}", problem);
+    }
+
+    // the exit status is what CI and configuration management look at.
+    eprintln!("{} problem(s) found", problems.len());
+    std::process::exit(1)
+}
+
+async fn completions(args: CompletionsArgs) -> Result<(), anyhow::Error> {
+    if args.list_networks {
+        for network_id in crate::completions::network_ids().await? {
+            println!("{}", network_id);
+        }
+
+        return Ok(());
+    }
+
+    if let Some(shell) = args.shell {
+        crate::completions::generate(shell, &mut std::io::stdout())?;
+    }
+
+    Ok(())
+}
+
+// format_zones writes the records as zone files, one after the other, each starting with an
+// $ORIGIN line.
+pub(crate) fn format_zones(records: &[RecordEntry]) -> String {
+    let mut out = String::new();
+    let mut zone = None;
+
+    for record in records {
+        if zone != Some(&record.zone) {
+            if zone.is_some() {
+                out.push('\n');
+            }
+
+            out.push_str(&format!("$ORIGIN {}\n", record.zone));
+            zone = Some(&record.zone);
+        }
+
+        out.push_str(&format!(
+            "{}\t{}\tIN\t{}\t{}\n",
+            record.name, record.ttl, record.record_type, record.data
+        ));
+    }
+
+    out
+}
+
+fn unsupervise(args: UnsuperviseArgs) -> Result<(), anyhow::Error> {
+    crate::utils::init_logger(Some(tracing::Level::INFO));
+    Properties::try_from(args)?.uninstall_supervisor()
+}
+
+fn supervise(args: SuperviseArgs) -> Result<(), anyhow::Error> {
+    crate::utils::init_logger(Some(tracing::Level::INFO));
+
+    let start = args.start;
+
+    let mut all = Vec::new();
+
+    if args.single_unit {
+        let mut props = Properties::new(
+            start.clone().launcher()?,
+            start.config.as_deref(),
+            start.config_type.clone(),
+            args.init_style.clone(),
+        )?;
+        props.networks = start.all_network_ids();
+        all.push(props);
+    } else {
+        // the services of different networks cannot pass each other's sockets.
+        if !args.sockets.is_empty() && start.all_network_ids().len() > 1 {
+            return Err(anyhow::anyhow!(
+                "--socket takes --single-unit when serving several networks"
+            ));
+        }
+
+        for launcher in start.clone().launchers() {
+            all.push(Properties::new(
+                launcher,
+                start.config.as_deref(),
+                start.config_type.clone(),
+                args.init_style.clone(),
+            )?);
+        }
+    }
+
+    for mut props in all {
+        props.sockets = args.sockets.clone();
+
+        if args.print {
+            props.print_supervisor()?;
+        } else {
+            props.install_supervisor()?;
+        }
+    }
+
+    Ok(())
 }
@@ -0,0 +1,403 @@
+/// code for `zeronsd client-setup`, routing the domain of a network to its nameservers.
+use std::{
+    fmt,
+    io::ErrorKind,
+    net::IpAddr,
+    path::{Path, PathBuf},
+    process::Command,
+};
+
+use anyhow::anyhow;
+use serde_json::Value;
+use trust_dns_server::client::rr::Name;
+
+// where macOS looks for the nameservers of a domain, in a file named after it.
+const RESOLVER_DIR: &str = "/etc/resolver";
+
+// Platform is how the resolvers of the machine are configured.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Platform {
+    MacOs,
+    // systemd-resolved, with resolvectl.
+    Resolved,
+    // the Name Resolution Policy Table, with PowerShell.
+    Windows,
+}
+
+impl Platform {
+    pub fn current() -> Result<Self, anyhow::Error> {
+        if cfg!(target_os = "macos") {
+            Ok(Self::MacOs)
+        } else if cfg!(target_os = "linux") {
+            Ok(Self::Resolved)
+        } else if cfg!(target_os = "windows") {
+            Ok(Self::Windows)
+        } else {
+            Err(anyhow!(
+                "client-setup supports macOS, Linux with systemd-resolved and Windows"
+            ))
+        }
+    }
+}
+
+// Setup is what is routed where.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Setup {
+    pub network_id: String,
+    // the domain, in ASCII and without the trailing dot; not needed to remove an NRPT rule.
+    pub domain: Option<String>,
+    pub servers: Vec<IpAddr>,
+    // the ZeroTier interface of the network, which systemd-resolved routes the domain through.
+    pub interface: Option<String>,
+}
+
+impl Setup {
+    // from_network fills in what was not given from the network as the local zerotier-one reports
+    // it: the DNS settings pushed to it, and its interface.
+    pub fn from_network(
+        network_id: &str,
+        network: Option<&Value>,
+        domain: Option<&str>,
+        servers: Vec<IpAddr>,
+    ) -> Result<Self, anyhow::Error> {
+        check_network_id(network_id)?;
+
+        let dns = network.map(|network| &network["dns"]);
+
+        let domain = domain
+            .or_else(|| dns?["domain"].as_str())
+            .filter(|domain| !domain.is_empty())
+            .map(ascii_domain)
+            .transpose()?;
+
+        let servers = if servers.is_empty() {
+            dns.and_then(|dns| dns["servers"].as_array())
+                .into_iter()
+                .flatten()
+                .filter_map(|server| server.as_str()?.parse().ok())
+                .collect()
+        } else {
+            servers
+        };
+
+        Ok(Self {
+            network_id: network_id.to_string(),
+            domain,
+            servers,
+            interface: network
+                .and_then(|network| network["portDeviceName"].as_str())
+                .map(str::to_string),
+        })
+    }
+
+    fn domain(&self) -> Result<&str, anyhow::Error> {
+        self.domain.as_deref().ok_or_else(|| {
+            anyhow!(
+                "network {} has no DNS domain; is zeronsd serving it? Pass --domain otherwise",
+                self.network_id
+            )
+        })
+    }
+
+    fn servers(&self) -> Result<Vec<String>, anyhow::Error> {
+        if self.servers.is_empty() {
+            return Err(anyhow!(
+                "network {} has no nameservers; is zeronsd serving it? Pass --server otherwise",
+                self.network_id
+            ));
+        }
+
+        Ok(self.servers.iter().map(IpAddr::to_string).collect())
+    }
+
+    fn interface(&self) -> Result<&str, anyhow::Error> {
+        self.interface.as_deref().ok_or_else(|| {
+            anyhow!(
+                "the interface of network {} is not known; has zerotier-one joined it?",
+                self.network_id
+            )
+        })
+    }
+
+    // nrpt_comment marks the NRPT rule of the network, so that it can be found again.
+    fn nrpt_comment(&self) -> String {
+        format!("zeronsd {}", self.network_id)
+    }
+}
+
+// check_network_id makes sure the network ID is one, as it ends up in requests and commands, such
+// as the comment of the NRPT rule.
+pub fn check_network_id(network_id: &str) -> Result<(), anyhow::Error> {
+    if network_id.len() != 16 || !network_id.chars().all(|c| c.is_ascii_hexdigit()) {
+        return Err(anyhow!(
+            "invalid network ID {}: it must be 16 hexadecimal digits",
+            network_id
+        ));
+    }
+
+    Ok(())
+}
+
+// ascii_domain is the domain as it is queried, with its labels outside ASCII in punycode. It ends
+// up in paths and commands, so nothing but the characters of hostnames is allowed in it.
+fn ascii_domain(domain: &str) -> Result<String, anyhow::Error> {
+    let ascii = Name::from_utf8(domain)?.to_ascii();
+    let ascii = ascii.trim_end_matches('.').to_lowercase();
+
+    if ascii.is_empty()
+        || !ascii
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_' || c == '.')
+    {
+        return Err(anyhow!("invalid domain {}", domain));
+    }
+
+    Ok(ascii)
+}
+
+// Step is a change made to the machine.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Step {
+    Write(PathBuf, String),
+    Remove(PathBuf),
+    Run(Vec<String>),
+}
+
+impl fmt::Display for Step {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Write(path, _) => write!(f, "Writing {}", path.display()),
+            Self::Remove(path) => write!(f, "Removing {}", path.display()),
+            Self::Run(command) => write!(f, "Running {}", command.join(" ")),
+        }
+    }
+}
+
+// plan is what sets up the machine, or undoes it.
+pub fn plan(platform: Platform, setup: &Setup, remove: bool) -> Result<Vec<Step>, anyhow::Error> {
+    let run = |args: &[&str]| Step::Run(args.iter().map(|arg| arg.to_string()).collect());
+
+    Ok(match platform {
+        Platform::MacOs => {
+            let path = Path::new(RESOLVER_DIR).join(setup.domain()?);
+
+            if remove {
+                vec![Step::Remove(path)]
+            } else {
+                let mut contents = format!(
+                    "# Written by zeronsd client-setup for network {}.\n",
+                    setup.network_id
+                );
+                for server in setup.servers()? {
+                    contents.push_str(&format!("nameserver {}\n", server));
+                }
+
+                vec![Step::Write(path, contents)]
+            }
+        }
+        Platform::Resolved => {
+            let interface = setup.interface()?;
+
+            if remove {
+                vec![run(&["resolvectl", "revert", interface])]
+            } else {
+                // the link only gets the queries for the domain, not those for every name.
+                let routing = format!("~{}", setup.domain()?);
+                let mut dns = vec!["resolvectl".to_string(), "dns".into(), interface.into()];
+                dns.extend(setup.servers()?);
+
+                vec![
+                    Step::Run(dns),
+                    run(&["resolvectl", "domain", interface, &routing]),
+                    run(&["resolvectl", "default-route", interface, "false"]),
+                ]
+            }
+        }
+        Platform::Windows => {
+            // the rule the network had is replaced, so that running it again changes nothing.
+            let mut script = format!(
+                "Get-DnsClientNrptRule | Where-Object Comment -eq '{}' | Remove-DnsClientNrptRule -Force",
+                setup.nrpt_comment()
+            );
+
+            if !remove {
+                let servers = setup
+                    .servers()?
+                    .iter()
+                    .map(|server| format!("'{}'", server))
+                    .collect::<Vec<_>>();
+
+                script.push_str(&format!(
+                    "; Add-DnsClientNrptRule -Namespace '.{}' -NameServers {} -Comment '{}'",
+                    setup.domain()?,
+                    servers.join(","),
+                    setup.nrpt_comment()
+                ));
+            }
+
+            vec![run(&[
+                "powershell",
+                "-NoProfile",
+                "-NonInteractive",
+                "-Command",
+                &script,
+            ])]
+        }
+    })
+}
+
+// apply makes the changes, or only prints them with dry_run.
+pub fn apply(steps: &[Step], dry_run: bool) -> Result<(), anyhow::Error> {
+    for step in steps {
+        println!("{}", step);
+
+        if dry_run {
+            if let Step::Write(_, contents) = step {
+                print!("{}", contents);
+            }
+
+            continue;
+        }
+
+        match step {
+            Step::Write(path, contents) => {
+                if let Some(dir) = path.parent() {
+                    std::fs::create_dir_all(dir)?;
+                }
+
+                std::fs::write(path, contents).map_err(|e| {
+                    anyhow!(
+                        "Could not write {}: {}; is zeronsd run as root?",
+                        path.display(),
+                        e
+                    )
+                })?;
+            }
+            Step::Remove(path) => match std::fs::remove_file(path) {
+                Err(e) if e.kind() != ErrorKind::NotFound => {
+                    return Err(anyhow!("Could not remove {}: {}", path.display(), e))
+                }
+                _ => {}
+            },
+            Step::Run(command) => {
+                let status = Command::new(&command[0])
+                    .args(&command[1..])
+                    .status()
+                    .map_err(|e| anyhow!("Could not run {}: {}", command[0], e))?;
+
+                if !status.success() {
+                    return Err(anyhow!("{} failed: {}", command[0], status));
+                }
+            }
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{plan, Platform, Setup, Step};
+    use serde_json::json;
+    use std::path::PathBuf;
+
+    fn setup() -> Setup {
+        Setup::from_network(
+            "1234567891011121",
+            Some(&json!({
+                "id": "1234567891011121",
+                "portDeviceName": "ztrta7jzpc",
+                "dns": { "domain": "Home.Arpa", "servers": ["10.147.20.1", "10.147.20.2"] },
+            })),
+            None,
+            Vec::new(),
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn test_setup() {
+        let setup = setup();
+        assert_eq!(setup.domain.as_deref(), Some("home.arpa"));
+        assert_eq!(setup.servers.len(), 2);
+        assert_eq!(setup.interface.as_deref(), Some("ztrta7jzpc"));
+
+        // what is given wins over what the network has.
+        let given = Setup::from_network(
+            "1234567891011121",
+            Some(&json!({ "dns": { "domain": "home.arpa", "servers": ["10.147.20.1"] } })),
+            Some("büro.example"),
+            vec!["10.147.20.9".parse().unwrap()],
+        )
+        .unwrap();
+        assert_eq!(given.domain.as_deref(), Some("xn--bro-hoa.example"));
+        assert_eq!(
+            given.servers,
+            vec!["10.147.20.9".parse::<std::net::IpAddr>().unwrap()]
+        );
+        assert_eq!(given.interface, None);
+
+        let empty = Setup::from_network(
+            "1234567891011121",
+            Some(&json!({ "dns": { "domain": "", "servers": [] } })),
+            None,
+            Vec::new(),
+        )
+        .unwrap();
+        assert_eq!(empty.domain, None);
+        assert!(plan(Platform::MacOs, &empty, false).is_err());
+
+        assert!(Setup::from_network("1234567891011121", None, Some("a'b"), Vec::new()).is_err());
+        assert!(Setup::from_network("123456789101112'", None, None, Vec::new()).is_err());
+        assert!(Setup::from_network("12345678910111", None, None, Vec::new()).is_err());
+    }
+
+    #[test]
+    fn test_plan() {
+        let setup = setup();
+
+        assert_eq!(
+            plan(Platform::MacOs, &setup, false).unwrap(),
+            vec![Step::Write(
+                PathBuf::from("/etc/resolver/home.arpa"),
+                "# Written by zeronsd client-setup for network 1234567891011121.\n\
+                 nameserver 10.147.20.1\n\
+                 nameserver 10.147.20.2\n"
+                    .to_string()
+            )]
+        );
+        assert_eq!(
+            plan(Platform::MacOs, &setup, true).unwrap(),
+            vec![Step::Remove(PathBuf::from("/etc/resolver/home.arpa"))]
+        );
+
+        let resolved = plan(Platform::Resolved, &setup, false).unwrap();
+        assert_eq!(
+            resolved[0].to_string(),
+            "Running resolvectl dns ztrta7jzpc 10.147.20.1 10.147.20.2"
+        );
+        assert_eq!(
+            resolved[1].to_string(),
+            "Running resolvectl domain ztrta7jzpc ~home.arpa"
+        );
+        assert_eq!(
+            plan(Platform::Resolved, &setup, true).unwrap()[0].to_string(),
+            "Running resolvectl revert ztrta7jzpc"
+        );
+
+        let windows = plan(Platform::Windows, &setup, false).unwrap()[0].to_string();
+        assert!(windows.contains("Remove-DnsClientNrptRule"));
+        assert!(windows.contains(
+            "Add-DnsClientNrptRule -Namespace '.home.arpa' -NameServers '10.147.20.1','10.147.20.2' -Comment 'zeronsd 1234567891011121'"
+        ));
+        let windows = plan(Platform::Windows, &setup, true).unwrap()[0].to_string();
+        assert!(!windows.contains("Add-DnsClientNrptRule"));
+
+        // systemd-resolved routes through the interface of the network.
+        let unjoined = Setup {
+            interface: None,
+            ..setup
+        };
+        assert!(plan(Platform::Resolved, &unjoined, false).is_err());
+    }
+}
@@ -0,0 +1,226 @@
+/// cluster mode (`--cluster-member`), electing the instance which polls Central.
+use std::{
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use tracing::{debug, info};
+use zerotier_central_api::types::{Member, Network};
+
+use crate::{
+    authority::Reload,
+    controller::{Controller, MemberSource},
+    health::NetworkStatus,
+    replication::Primary,
+};
+use trust_dns_server::client::rr::Name;
+
+// how long the outcome of an election is used before the members are asked again.
+const ELECTION_INTERVAL: Duration = Duration::from_secs(10);
+
+// how long a member has to answer during an election.
+const STATUS_TIMEOUT: Duration = Duration::from_secs(2);
+
+// ClusterStatus is what an instance tells the other members of the cluster about itself.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ClusterStatus {
+    // the ZeroTier address of the member zeronsd runs on.
+    pub node_id: String,
+    pub networks: Vec<NetworkStatus>,
+}
+
+impl ClusterStatus {
+    // eligible tells whether the instance can lead: the refresh loops of its networks are running.
+    fn eligible(&self) -> bool {
+        self.networks.iter().all(|network| network.live)
+    }
+}
+
+// Leader is the instance which polls the source of members.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Leader {
+    Local,
+    // a member of the cluster, by its position in the members given.
+    Member(usize),
+}
+
+// Cluster is the source of members of an instance in a cluster: the configured source when it
+// leads, and the leader otherwise.
+pub struct Cluster {
+    node_id: String,
+    source: Controller,
+    members: Vec<Primary>,
+    elected: Mutex<Option<(Leader, Instant)>>,
+}
+
+impl Cluster {
+    pub fn new(node_id: String, source: Controller, members: Vec<Primary>) -> Self {
+        Self {
+            node_id,
+            source,
+            members,
+            elected: Mutex::new(None),
+        }
+    }
+
+    // leader is the outcome of the last election, which is held again once it is too old.
+    async fn leader(&self) -> Leader {
+        let last = *self.elected.lock().unwrap();
+        if let Some((leader, at)) = last {
+            if at.elapsed() < ELECTION_INTERVAL {
+                return leader;
+            }
+        }
+
+        let mut statuses = Vec::new();
+        for (i, member) in self.members.iter().enumerate() {
+            match tokio::time::timeout(STATUS_TIMEOUT, member.cluster_status()).await {
+                Ok(Ok(status)) => statuses.push((i, status)),
+                Ok(Err(e)) => debug!("Cluster member {} did not answer: {:#}", member.url(), e),
+                Err(_) => debug!("Cluster member {} did not answer in time", member.url()),
+            }
+        }
+
+        let leader = elect(&self.node_id, &statuses);
+
+        let mut elected = self.elected.lock().unwrap();
+        if elected.map(|(previous, _)| previous) != Some(leader) {
+            match leader {
+                Leader::Local => info!("This instance ({}) leads the cluster", self.node_id),
+                Leader::Member(i) => info!(
+                    "Cluster member {} leads the cluster; reading members from it",
+                    self.members[i].url()
+                ),
+            }
+        }
+
+        *elected = Some((leader, Instant::now()));
+        leader
+    }
+
+    async fn is_leader(&self) -> bool {
+        self.leader().await == Leader::Local
+    }
+}
+
+// elect picks the eligible instance with the lowest ZeroTier address, this one included.
+fn elect(node_id: &str, statuses: &[(usize, ClusterStatus)]) -> Leader {
+    statuses
+        .iter()
+        .filter(|(_, status)| status.eligible() && status.node_id.as_str() < node_id)
+        .min_by(|(_, a), (_, b)| a.node_id.cmp(&b.node_id))
+        .map_or(Leader::Local, |(i, _)| Leader::Member(*i))
+}
+
+#[async_trait]
+impl MemberSource for Cluster {
+    async fn network(&self, network_id: &str) -> Result<Network, anyhow::Error> {
+        match self.leader().await {
+            Leader::Local => self.source.network(network_id).await,
+            Leader::Member(i) => self.members[i].network(network_id).await,
+        }
+    }
+
+    async fn members(&self, network_id: &str) -> Result<Vec<Member>, anyhow::Error> {
+        match self.leader().await {
+            Leader::Local => self.source.members(network_id).await,
+            Leader::Member(i) => self.members[i].members(network_id).await,
+        }
+    }
+
+    // only the leader pushes the DNS settings, so that the members do not take turns.
+    async fn update_dns(
+        &self,
+        network_id: &str,
+        domain_name: Name,
+        ips: Vec<String>,
+    ) -> Result<(), anyhow::Error> {
+        if self.is_leader().await {
+            self.source.update_dns(network_id, domain_name, ips).await
+        } else {
+            Ok(())
+        }
+    }
+
+    async fn clear_dns(&self, network_id: &str) -> Result<(), anyhow::Error> {
+        if self.is_leader().await {
+            self.source.clear_dns(network_id).await
+        } else {
+            Ok(())
+        }
+    }
+
+    fn reload(&self, reload: &Reload) {
+        self.source.reload(reload);
+    }
+
+    fn reload_credentials(&self) {
+        self.source.reload_credentials();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{elect, ClusterStatus, Leader};
+    use crate::health::NetworkStatus;
+
+    fn status(node_id: &str, live: bool) -> ClusterStatus {
+        ClusterStatus {
+            node_id: node_id.to_string(),
+            networks: vec![NetworkStatus {
+                network_id: "8056c2e21c000001".to_string(),
+                domain: "home.arpa.".to_string(),
+                listening: Vec::new(),
+                records: 0,
+                last_poll_seconds: None,
+                central_error: None,
+                record_limit: None,
+                members_left_out: 0,
+                wildcards_dropped: false,
+                live,
+                ready: live,
+            }],
+        }
+    }
+
+    #[test]
+    fn test_elect() {
+        assert_eq!(elect("5555555555", &[]), Leader::Local);
+
+        assert_eq!(
+            elect(
+                "5555555555",
+                &[
+                    (0, status("7777777777", true)),
+                    (1, status("3333333333", true))
+                ]
+            ),
+            Leader::Member(1)
+        );
+
+        assert_eq!(
+            elect(
+                "5555555555",
+                &[
+                    (0, status("1111111111", true)),
+                    (1, status("3333333333", true))
+                ]
+            ),
+            Leader::Member(0)
+        );
+
+        // members whose refresh loops are stuck are passed over.
+        assert_eq!(
+            elect(
+                "5555555555",
+                &[
+                    (0, status("1111111111", false)),
+                    (1, status("7777777777", true))
+                ]
+            ),
+            Leader::Local
+        );
+    }
+}
@@ -0,0 +1,251 @@
+/// what to serve for names several members, or hosts entries, have.
+use std::{
+    collections::{HashMap, HashSet},
+    str::FromStr,
+};
+
+use anyhow::anyhow;
+use serde::{Deserialize, Serialize};
+use tracing::warn;
+use trust_dns_server::client::rr::{LowerName, Name};
+use zerotier_central_api::types::Member;
+
+use crate::{hosts::HostsFile, utils::NamePolicy};
+
+// CollisionPolicy is what is served for a name several members, or a member and a hosts file
+// entry, have.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum CollisionPolicy {
+    // the addresses of all of them, which clients pick from in turn.
+    Merge,
+    // the hosts file entry's only; members named alike are merged with each other.
+    Hosts,
+    // the addresses of the member authorized last; hosts file entries win over members.
+    Newest,
+    // the addresses of the member authorized first, the others being served as
+    // `<name>-<member id>`, as are members named like a hosts file entry.
+    Suffix,
+}
+
+impl Default for CollisionPolicy {
+    fn default() -> Self {
+        CollisionPolicy::Merge
+    }
+}
+
+impl ToString for CollisionPolicy {
+    fn to_string(&self) -> String {
+        match self {
+            CollisionPolicy::Merge => "merge",
+            CollisionPolicy::Hosts => "hosts",
+            CollisionPolicy::Newest => "newest",
+            CollisionPolicy::Suffix => "suffix",
+        }
+        .to_string()
+    }
+}
+
+impl FromStr for CollisionPolicy {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "merge" => Ok(Self::Merge),
+            "hosts" => Ok(Self::Hosts),
+            "newest" => Ok(Self::Newest),
+            "suffix" => Ok(Self::Suffix),
+            _ => Err(anyhow!(
+                "invalid policy: allowed values: [merge, hosts, newest, suffix]"
+            )),
+        }
+    }
+}
+
+// resolve_collisions renames the members which lose a name they collide on, under the policy:
+// they are given no name, or the suffixed one. Every collision is logged.
+pub fn resolve_collisions(
+    mut members: Vec<Member>,
+    hosts: Option<&HostsFile>,
+    domain_name: &Name,
+    name_policy: NamePolicy,
+    policy: CollisionPolicy,
+) -> Vec<Member> {
+    let hosts: HashSet<LowerName> = hosts
+        .into_iter()
+        .flat_map(|hosts| hosts.values().flatten())
+        .map(LowerName::from)
+        .collect();
+
+    // the members with each name, as hostnames and as names under the domain.
+    let mut named: HashMap<LowerName, Vec<(usize, Name)>> = HashMap::new();
+
+    for (i, member) in members.iter().enumerate() {
+        let hostname = member
+            .name
+            .as_deref()
+            .map(str::trim)
+            .filter(|name| !name.is_empty())
+            .and_then(|name| name_policy.to_hostname(name).ok());
+
+        if let Some(hostname) = hostname {
+            if let Ok(fqdn) = hostname.clone().append_domain(domain_name) {
+                named.entry(fqdn.into()).or_default().push((i, hostname));
+            }
+        }
+    }
+
+    let mut collisions: Vec<_> = named
+        .into_iter()
+        .filter(|(name, owners)| owners.len() > 1 || hosts.contains(name))
+        .collect();
+    collisions.sort_by(|a, b| a.0.cmp(&b.0));
+
+    for (name, mut owners) in collisions {
+        owners.sort_by_key(|(i, _)| (authorized(&members[*i]), members[*i].node_id.clone()));
+
+        let in_hosts = hosts.contains(&name);
+        let mut sharing: Vec<String> = owners
+            .iter()
+            .map(|(i, _)| format!("member {}", node_id(&members[*i])))
+            .collect();
+
+        if in_hosts {
+            sharing.push("a hosts file entry".to_string());
+        }
+
+        let sharing = sharing.join(", ");
+
+        let losers = match (policy, in_hosts) {
+            (CollisionPolicy::Merge, _) | (CollisionPolicy::Hosts, false) => &owners[..0],
+            (_, true) => &owners[..],
+            (CollisionPolicy::Newest, false) => &owners[..owners.len() - 1],
+            (CollisionPolicy::Suffix, false) => &owners[1..],
+        };
+
+        if losers.is_empty() {
+            warn!(
+                "{} is the name of {}; serving the addresses of all of them",
+                name, sharing
+            );
+        }
+
+        for (i, hostname) in losers {
+            let member = &mut members[*i];
+            let id = node_id(member);
+
+            if policy == CollisionPolicy::Suffix {
+                let suffixed = suffix(hostname, &id);
+                warn!(
+                    "{} is the name of {}; serving member {} as {}",
+                    name, sharing, id, suffixed
+                );
+                member.name = Some(suffixed);
+            } else {
+                warn!(
+                    "{} is the name of {}; serving member {} as zt-{} only",
+                    name, sharing, id, id
+                );
+                member.name = None;
+            }
+        }
+    }
+
+    members
+}
+
+// suffix adds the member ID to the first label of the hostname: web becomes web-<member id>.
+fn suffix(hostname: &Name, node_id: &str) -> String {
+    let hostname = hostname.to_utf8();
+
+    match hostname.split_once('.') {
+        Some((first, rest)) => format!("{}-{}.{}", first, node_id, rest),
+        None => format!("{}-{}", hostname, node_id),
+    }
+}
+
+fn node_id(member: &Member) -> String {
+    member.node_id.clone().unwrap_or_default()
+}
+
+fn authorized(member: &Member) -> i64 {
+    member
+        .config
+        .as_ref()
+        .and_then(|config| config.last_authorized_time)
+        .unwrap_or(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{resolve_collisions, CollisionPolicy};
+    use crate::{hosts::HostsFile, utils::NamePolicy};
+    use std::{net::IpAddr, str::FromStr};
+    use trust_dns_server::client::rr::Name;
+    use zerotier_central_api::types::Member;
+
+    fn member(node_id: &str, name: &str, authorized: i64) -> Member {
+        serde_json::from_value(serde_json::json!({
+            "nodeId": node_id,
+            "name": name,
+            "config": { "lastAuthorizedTime": authorized },
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn test_resolve_collisions() {
+        let domain = Name::from_str("home.arpa.").unwrap();
+
+        let mut hosts = HostsFile::new();
+        hosts.insert(
+            IpAddr::from_str("10.147.20.1").unwrap(),
+            vec![Name::from_str("gateway.home.arpa.").unwrap()],
+        );
+
+        let members = vec![
+            member("abcdef0123", "web", 2000),
+            member("abcdef0124", "Web", 1000),
+            member("abcdef0125", "gateway", 1000),
+            member("abcdef0126", "db", 1000),
+        ];
+
+        let names = |policy| {
+            resolve_collisions(
+                members.clone(),
+                Some(&hosts),
+                &domain,
+                NamePolicy::default(),
+                policy,
+            )
+            .into_iter()
+            .map(|member| member.name)
+            .collect::<Vec<_>>()
+        };
+        let named = |names: [Option<&str>; 4]| names.map(|name| name.map(ToString::to_string));
+
+        assert_eq!(
+            names(CollisionPolicy::Merge),
+            named([Some("web"), Some("Web"), Some("gateway"), Some("db")])
+        );
+        assert_eq!(
+            names(CollisionPolicy::Hosts),
+            named([Some("web"), Some("Web"), None, Some("db")])
+        );
+        assert_eq!(
+            names(CollisionPolicy::Newest),
+            named([Some("web"), None, None, Some("db")])
+        );
+        assert_eq!(
+            names(CollisionPolicy::Suffix),
+            named([
+                Some("web-abcdef0123"),
+                Some("Web"),
+                Some("gateway-abcdef0125"),
+                Some("db"),
+            ])
+        );
+
+        assert!("oldest".parse::<CollisionPolicy>().is_err());
+    }
+}
@@ -0,0 +1,95 @@
+/// shell completions for `zeronsd completions <shell>`.
+use std::io::Write;
+
+use clap::CommandFactory;
+use clap_complete::Shell;
+
+use crate::{
+    cli::Cli,
+    utils::{authtoken_path, local_client_from_file, ZEROTIER_LOCAL_URL},
+};
+
+// the subcommands taking network IDs.
+const NETWORK_SUBCOMMANDS: &str = "start supervise unsupervise client-setup";
+
+// completes network IDs after clap's own completions, without replacing them.
+const BASH_NETWORKS: &str = r#"
+_zeronsd_networks() {
+    _zeronsd "$@"
+
+    local cur="${COMP_WORDS[COMP_CWORD]}"
+    case " NETWORK_SUBCOMMANDS " in
+        *" ${COMP_WORDS[1]} "*)
+            if [[ "${cur}" != -* ]]; then
+                COMPREPLY+=( $(compgen -W "$(zeronsd completions --list-networks 2>/dev/null)" -- "${cur}") )
+            fi
+            ;;
+    esac
+}
+
+complete -F _zeronsd_networks -o bashdefault -o default zeronsd
+"#;
+
+const FISH_NETWORKS: &str = r#"
+complete -c zeronsd -n "__fish_seen_subcommand_from NETWORK_SUBCOMMANDS" -f -a "(zeronsd completions --list-networks 2>/dev/null)"
+"#;
+
+// generate writes the completion script of the shell.
+pub fn generate(shell: Shell, out: &mut impl Write) -> Result<(), anyhow::Error> {
+    clap_complete::generate(shell, &mut Cli::command(), "zeronsd", out);
+
+    let networks = match shell {
+        Shell::Bash => BASH_NETWORKS,
+        Shell::Fish => FISH_NETWORKS,
+        _ => return Ok(()),
+    };
+
+    out.write_all(
+        networks
+            .replace("NETWORK_SUBCOMMANDS", NETWORK_SUBCOMMANDS)
+            .as_bytes(),
+    )?;
+
+    Ok(())
+}
+
+// network_ids lists the networks the local ZeroTier service has joined, for the completion
+// scripts to offer.
+pub async fn network_ids() -> Result<Vec<String>, anyhow::Error> {
+    let client = local_client_from_file(authtoken_path(None), ZEROTIER_LOCAL_URL.into())?;
+
+    Ok(client
+        .get_networks()
+        .await?
+        .iter()
+        .filter_map(|network| network.subtype_1.id.clone())
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::generate;
+    use clap_complete::Shell;
+
+    #[test]
+    fn test_generate() {
+        let mut bash = Vec::new();
+        generate(Shell::Bash, &mut bash).unwrap();
+        let bash = String::from_utf8(bash).unwrap();
+        assert!(bash.contains("--allow-transfer"));
+        assert!(bash.contains(r#"case " start supervise unsupervise client-setup " in"#));
+        assert!(bash.ends_with("complete -F _zeronsd_networks -o bashdefault -o default zeronsd\n"));
+
+        let mut fish = Vec::new();
+        generate(Shell::Fish, &mut fish).unwrap();
+        let fish = String::from_utf8(fish).unwrap();
+        assert!(
+            fish.contains("__fish_seen_subcommand_from start supervise unsupervise client-setup")
+        );
+
+        let mut zsh = Vec::new();
+        generate(Shell::Zsh, &mut zsh).unwrap();
+        let zsh = String::from_utf8(zsh).unwrap();
+        assert!(zsh.contains("#compdef zeronsd"));
+    }
+}
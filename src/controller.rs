@@ -0,0 +1,846 @@
+/// where the members of a network come from and its DNS settings are pushed to.
+use std::{
+    net::IpAddr,
+    path::PathBuf,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+};
+
+use anyhow::anyhow;
+use arc_swap::ArcSwap;
+use async_trait::async_trait;
+use serde::Deserialize;
+use serde_json::{json, Value};
+use zerotier_central_api::types::{Member, Network};
+
+use crate::{
+    addresses::Calculator,
+    authority::Reload,
+    central::{is_unauthorized, MemberPoller},
+    utils::{
+        central_client_with_options, clear_central_dns, update_central_dns, CentralOptions,
+        TokenSource,
+    },
+};
+use trust_dns_server::client::rr::Name;
+
+// Controller is the source of members of a network being served, shared by everything serving it.
+pub type Controller = Arc<dyn MemberSource>;
+
+/// Where the networks served and their members come from: ZeroTier Central, the local
+/// controller, a members file, or a source given by a program embedding zeronsd (see
+/// `Launcher::start_with_source`). Members and networks are in Central's representation, of which
+/// zeronsd only reads the IDs, names, authorization and assigned addresses.
+#[async_trait]
+pub trait MemberSource: Send + Sync {
+    /// The network, whose name is used as the domain with `auto_domain`.
+    async fn network(&self, network_id: &str) -> Result<Network, anyhow::Error>;
+
+    /// The members of the network. It is called on every refresh.
+    async fn members(&self, network_id: &str) -> Result<Vec<Member>, anyhow::Error>;
+
+    /// Points the members of the network at the given nameservers for the domain. By default,
+    /// nothing is done.
+    async fn update_dns(
+        &self,
+        _network_id: &str,
+        _domain_name: Name,
+        _ips: Vec<String>,
+    ) -> Result<(), anyhow::Error> {
+        Ok(())
+    }
+
+    /// Removes what update_dns set, with `clear_dns_on_exit`. By default, nothing is done.
+    async fn clear_dns(&self, _network_id: &str) -> Result<(), anyhow::Error> {
+        Ok(())
+    }
+
+    /// Applies the settings of a reload, which also forces credentials to be read again. By
+    /// default, nothing is done.
+    fn reload(&self, _reload: &Reload) {}
+
+    /// Reads the credentials again, as when they were rejected because they were rotated. By
+    /// default, nothing is done.
+    fn reload_credentials(&self) {}
+}
+
+// CentralConfig is what a Central client is built from. The token is read every time a client
+// is built, so a rotated token is picked up without restarting.
+#[derive(Debug, Clone)]
+pub struct CentralConfig {
+    pub token: TokenSource,
+    pub options: CentralOptions,
+}
+
+impl CentralConfig {
+    pub fn client(&self) -> Result<zerotier_central_api::Client, anyhow::Error> {
+        central_client_with_options(self.token.read()?, &self.options)
+    }
+}
+
+// Central is ZeroTier Central, or a self-hosted controller speaking its API.
+pub struct Central {
+    client: ArcSwap<zerotier_central_api::Client>,
+    // how to build a new client when the token changes; None if the client is all there is.
+    config: ArcSwap<Option<CentralConfig>>,
+    poller: MemberPoller,
+}
+
+impl Central {
+    pub fn new(client: zerotier_central_api::Client, config: Option<CentralConfig>) -> Self {
+        Self {
+            client: ArcSwap::from_pointee(client),
+            config: ArcSwap::from_pointee(config),
+            poller: MemberPoller::default(),
+        }
+    }
+
+    // from_config builds the client from the configuration, reading the token.
+    pub fn from_config(config: CentralConfig) -> Result<Self, anyhow::Error> {
+        Ok(Self::new(config.client()?, Some(config)))
+    }
+
+    fn client(&self) -> Arc<zerotier_central_api::Client> {
+        self.client.load_full()
+    }
+}
+
+#[async_trait]
+impl MemberSource for Central {
+    async fn network(&self, network_id: &str) -> Result<Network, anyhow::Error> {
+        Ok(self
+            .client()
+            .get_network_by_id(network_id)
+            .await?
+            .into_inner())
+    }
+
+    // the poller makes requests to Central conditional.
+    async fn members(&self, network_id: &str) -> Result<Vec<Member>, anyhow::Error> {
+        self.poller.members(&self.client(), network_id).await
+    }
+
+    async fn update_dns(
+        &self,
+        network_id: &str,
+        domain_name: Name,
+        ips: Vec<String>,
+    ) -> Result<(), anyhow::Error> {
+        let client = (*self.client()).clone();
+        update_central_dns(domain_name, ips, client, network_id.to_string()).await
+    }
+
+    async fn clear_dns(&self, network_id: &str) -> Result<(), anyhow::Error> {
+        let client = (*self.client()).clone();
+        clear_central_dns(client, network_id.to_string()).await
+    }
+
+    fn reload(&self, reload: &Reload) {
+        if reload.central.is_some() {
+            self.config.store(Arc::new(reload.central.clone()));
+        }
+
+        self.reload_credentials();
+    }
+
+    // the current client is kept if the token cannot be read.
+    fn reload_credentials(&self) {
+        if let Some(config) = &*self.config.load() {
+            match config.client() {
+                Ok(client) => self.client.store(Arc::new(client)),
+                Err(e) => tracing::error!("Could not reload the Central token: {}", e),
+            }
+        }
+    }
+}
+
+// CentralFallback is Central, in a degraded mode while the token cannot be read or Central rejects
+// it: the members are then the peers of the local zerotier-one (see PeerList), and no DNS settings
+// are pushed. Central is tried again on every refresh, with the token read again, so that fixing
+// the token brings the members back.
+pub struct CentralFallback {
+    config: CentralConfig,
+    // None until a token could be read.
+    central: ArcSwap<Option<Arc<Central>>>,
+    peers: PeerList,
+    degraded: AtomicBool,
+}
+
+impl CentralFallback {
+    pub fn new(config: CentralConfig, local: zerotier_one_api::Client) -> Self {
+        Self {
+            config,
+            central: ArcSwap::from_pointee(None),
+            peers: PeerList(local),
+            degraded: AtomicBool::new(false),
+        }
+    }
+
+    // central is the Central source, built once a token can be read.
+    fn central(&self) -> Result<Arc<Central>, anyhow::Error> {
+        if let Some(central) = &*self.central.load_full() {
+            return Ok(central.clone());
+        }
+
+        let central = Arc::new(Central::from_config(self.config.clone())?);
+        self.central.store(Arc::new(Some(central.clone())));
+        Ok(central)
+    }
+
+    // fall_back decides whether the failure is one the peers stand in for, noting the change of
+    // mode, and reads the token again if Central rejected it.
+    fn fall_back(&self, e: &anyhow::Error, unreadable: bool) -> bool {
+        if !unreadable && !is_unauthorized(e) {
+            return false;
+        }
+
+        if !unreadable {
+            if let Some(central) = &*self.central.load_full() {
+                central.reload_credentials();
+            }
+        }
+
+        if !self.degraded.swap(true, Ordering::SeqCst) {
+            tracing::warn!(
+                "Central cannot be used ({:#}); serving the peers of zerotier-one by their member IDs until it can",
+                e
+            );
+        }
+
+        true
+    }
+
+    fn recovered(&self) {
+        if self.degraded.swap(false, Ordering::SeqCst) {
+            tracing::info!("Central can be used again; serving its members");
+        }
+    }
+}
+
+#[async_trait]
+impl MemberSource for CentralFallback {
+    async fn network(&self, network_id: &str) -> Result<Network, anyhow::Error> {
+        let result = match self.central() {
+            Ok(central) => central.network(network_id).await.map_err(|e| (e, false)),
+            Err(e) => Err((e, true)),
+        };
+
+        match result {
+            Ok(network) => Ok(network),
+            Err((e, unreadable)) if self.fall_back(&e, unreadable) => {
+                self.peers.network(network_id).await
+            }
+            Err((e, _)) => Err(e),
+        }
+    }
+
+    async fn members(&self, network_id: &str) -> Result<Vec<Member>, anyhow::Error> {
+        let result = match self.central() {
+            Ok(central) => central.members(network_id).await.map_err(|e| (e, false)),
+            Err(e) => Err((e, true)),
+        };
+
+        match result {
+            Ok(members) => {
+                self.recovered();
+                Ok(members)
+            }
+            Err((e, unreadable)) if self.fall_back(&e, unreadable) => {
+                self.peers.members(network_id).await
+            }
+            Err((e, _)) => Err(e),
+        }
+    }
+
+    // the settings are pushed once Central can be used again, when the listeners are restarted or
+    // the peers change.
+    async fn update_dns(
+        &self,
+        network_id: &str,
+        domain_name: Name,
+        ips: Vec<String>,
+    ) -> Result<(), anyhow::Error> {
+        if self.degraded.load(Ordering::SeqCst) {
+            tracing::warn!("Not pushing DNS settings to Central, which cannot be used");
+            return Ok(());
+        }
+
+        self.central()?
+            .update_dns(network_id, domain_name, ips)
+            .await
+    }
+
+    async fn clear_dns(&self, network_id: &str) -> Result<(), anyhow::Error> {
+        self.central()?.clear_dns(network_id).await
+    }
+
+    fn reload(&self, reload: &Reload) {
+        if let Some(central) = &*self.central.load_full() {
+            central.reload(reload);
+        }
+    }
+
+    fn reload_credentials(&self) {
+        if let Some(central) = &*self.central.load_full() {
+            central.reload_credentials();
+        }
+    }
+}
+
+// PeerList is what the local zerotier-one knows of a network without Central: this node, with the
+// addresses it was assigned, and the leaf peers it talks to, which get the 6plane and RFC 4193
+// addresses they have on the network if it assigns them. Peers are not known to be members of the
+// network, and their names and IPv4 addresses are not known at all, so they are served by their
+// member IDs only.
+pub struct PeerList(pub zerotier_one_api::Client);
+
+#[async_trait]
+impl MemberSource for PeerList {
+    async fn network(&self, network_id: &str) -> Result<Network, anyhow::Error> {
+        let network = get(&self.0, &format!("network/{}", network_id)).await?;
+        peer_network(network_id, &network)
+    }
+
+    async fn members(&self, network_id: &str) -> Result<Vec<Member>, anyhow::Error> {
+        let status = get(&self.0, "status").await?;
+        let network = get(&self.0, &format!("network/{}", network_id)).await?;
+        let peers = get(&self.0, "peer").await?;
+
+        peer_members(network_id, &status, &network, &peers)
+    }
+}
+
+// peer_network is the network as the local zerotier-one reports it, with the IPv6 modes told by
+// the addresses this node was assigned, as zerotier-one does not report them.
+fn peer_network(network_id: &str, network: &Value) -> Result<Network, anyhow::Error> {
+    let bare = local_network(network_id, &json!({}))?;
+    let (sixplane, rfc4193) = (bare.clone().sixplane()?, bare.rfc4193()?);
+    let assigned = assigned_addresses(network);
+
+    local_network(
+        network_id,
+        &json!({
+            "name": network["name"],
+            "v6AssignMode": {
+                "6plane": assigned.iter().any(|ip| sixplane.contains(*ip)),
+                "rfc4193": assigned.iter().any(|ip| rfc4193.contains(*ip)),
+            },
+        }),
+    )
+}
+
+// peer_members are this node and the leaf peers of the local zerotier-one, as members of the
+// network. Roots and moons are not members of any network.
+fn peer_members(
+    network_id: &str,
+    status: &Value,
+    network: &Value,
+    peers: &Value,
+) -> Result<Vec<Member>, anyhow::Error> {
+    let served = peer_network(network_id, network)?;
+    let (sixplane, rfc4193) = (served.clone().sixplane()?, served.rfc4193()?);
+
+    // the 6plane and RFC 4193 addresses are worked out from the member ID with the others.
+    let ips: Vec<String> = assigned_addresses(network)
+        .into_iter()
+        .filter(|ip| !sixplane.contains(*ip) && !rfc4193.contains(*ip))
+        .map(|ip| ip.to_string())
+        .collect();
+
+    let mut members = vec![local_member(
+        network_id,
+        &json!({ "id": status["address"], "authorized": true, "ipAssignments": ips }),
+    )?];
+
+    for peer in peers.as_array().into_iter().flatten() {
+        if peer["role"] != "LEAF" || peer["address"] == status["address"] {
+            continue;
+        }
+
+        members.push(local_member(
+            network_id,
+            &json!({ "id": peer["address"], "authorized": true, "ipAssignments": [] }),
+        )?);
+    }
+
+    Ok(members)
+}
+
+// assigned_addresses are the addresses of this node on the network, without their prefix length.
+fn assigned_addresses(network: &Value) -> Vec<IpAddr> {
+    network["assignedAddresses"]
+        .as_array()
+        .into_iter()
+        .flatten()
+        .filter_map(|cidr| cidr.as_str()?.split('/').next()?.parse().ok())
+        .collect()
+}
+
+// LocalController is the network controller embedded in the local zerotier-one service,
+// authenticated with authtoken.secret, for networks it manages itself.
+pub struct LocalController(pub zerotier_one_api::Client);
+
+#[async_trait]
+impl MemberSource for LocalController {
+    async fn network(&self, network_id: &str) -> Result<Network, anyhow::Error> {
+        let network = get(&self.0, &format!("controller/network/{}", network_id)).await?;
+        local_network(network_id, &network)
+    }
+
+    async fn members(&self, network_id: &str) -> Result<Vec<Member>, anyhow::Error> {
+        // the list only maps member IDs to their revision.
+        let list = get(
+            &self.0,
+            &format!("controller/network/{}/member", network_id),
+        )
+        .await?;
+        let ids = list
+            .as_object()
+            .ok_or_else(|| anyhow!("Unexpected member list for {}", network_id))?
+            .keys();
+
+        let mut members = Vec::new();
+        for id in ids {
+            let member = get(
+                &self.0,
+                &format!("controller/network/{}/member/{}", network_id, id),
+            )
+            .await?;
+            members.push(local_member(network_id, &member)?);
+        }
+
+        Ok(members)
+    }
+
+    async fn update_dns(
+        &self,
+        network_id: &str,
+        domain_name: Name,
+        ips: Vec<String>,
+    ) -> Result<(), anyhow::Error> {
+        let mut domain_name = domain_name;
+        domain_name.set_fqdn(false);
+
+        post(
+            &self.0,
+            &format!("controller/network/{}", network_id),
+            json!({ "dns": { "domain": domain_name.to_string(), "servers": ips } }),
+        )
+        .await
+    }
+
+    async fn clear_dns(&self, network_id: &str) -> Result<(), anyhow::Error> {
+        post(
+            &self.0,
+            &format!("controller/network/{}", network_id),
+            json!({ "dns": { "domain": "", "servers": [] } }),
+        )
+        .await
+    }
+}
+
+// MembersFile is a YAML or JSON file listing the members of the network, for networks whose
+// members are not known to Central or a controller zeronsd can reach. It is read again on every
+// refresh, so edits are picked up without reloading.
+pub struct MembersFile(pub PathBuf);
+
+#[derive(Debug, Clone, Default, Deserialize)]
+struct MembersFileContent {
+    // the name of the network, used as the domain with auto_domain.
+    #[serde(default)]
+    name: Option<String>,
+    #[serde(default)]
+    members: Vec<FileMember>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+struct FileMember {
+    // the ZeroTier address of the member.
+    id: String,
+    #[serde(default)]
+    name: Option<String>,
+    #[serde(default)]
+    ips: Vec<String>,
+    #[serde(default = "authorized")]
+    authorized: bool,
+}
+
+fn authorized() -> bool {
+    true
+}
+
+impl MembersFile {
+    fn read(&self) -> Result<MembersFileContent, anyhow::Error> {
+        let content = std::fs::read_to_string(&self.0).map_err(|e| {
+            anyhow!(
+                "Could not read the members file {}: {}",
+                self.0.display(),
+                e
+            )
+        })?;
+
+        // YAML parses JSON as well.
+        serde_yaml::from_str(&content)
+            .map_err(|e| anyhow!("Invalid members file {}: {}", self.0.display(), e))
+    }
+}
+
+#[async_trait]
+impl MemberSource for MembersFile {
+    async fn network(&self, network_id: &str) -> Result<Network, anyhow::Error> {
+        let content = self.read()?;
+        local_network(network_id, &json!({ "name": content.name }))
+    }
+
+    async fn members(&self, network_id: &str) -> Result<Vec<Member>, anyhow::Error> {
+        self.read()?
+            .members
+            .iter()
+            .map(|member| {
+                local_member(
+                    network_id,
+                    &json!({
+                        "id": member.id,
+                        "name": member.name,
+                        "authorized": member.authorized,
+                        "ipAssignments": member.ips,
+                    }),
+                )
+            })
+            .collect()
+    }
+}
+
+pub(crate) async fn get(
+    client: &zerotier_one_api::Client,
+    path: &str,
+) -> Result<Value, anyhow::Error> {
+    let res = client
+        .client()
+        .get(format!("{}/{}", client.baseurl(), path))
+        .send()
+        .await?;
+
+    if let Err(e) = res.error_for_status_ref() {
+        let hint = if path.starts_with("controller/") {
+            "; is this network managed by it?"
+        } else {
+            ""
+        };
+
+        return Err(anyhow::Error::new(e).context(format!(
+            "zerotier-one returned {} for /{}{}",
+            res.status(),
+            path,
+            hint
+        )));
+    }
+
+    Ok(res.json().await?)
+}
+
+async fn post(
+    client: &zerotier_one_api::Client,
+    path: &str,
+    body: Value,
+) -> Result<(), anyhow::Error> {
+    client
+        .client()
+        .post(format!("{}/{}", client.baseurl(), path))
+        .json(&body)
+        .send()
+        .await?
+        .error_for_status()?;
+
+    Ok(())
+}
+
+// local_network converts a network of the local controller into Central's representation. Only
+// the settings zeronsd uses are carried over.
+pub fn local_network(network_id: &str, network: &Value) -> Result<Network, anyhow::Error> {
+    Ok(serde_json::from_value(json!({
+        "id": network_id,
+        "config": {
+            "id": network_id,
+            "name": network["name"],
+            "v6AssignMode": network["v6AssignMode"],
+        },
+    }))?)
+}
+
+// local_member converts a member of the local controller into Central's representation. The
+// controller does not keep names for members unless it is told to, so most are only reachable
+// by their member ID.
+pub fn local_member(network_id: &str, member: &Value) -> Result<Member, anyhow::Error> {
+    Ok(serde_json::from_value(json!({
+        "id": format!("{}-{}", network_id, member["id"].as_str().unwrap_or_default()),
+        "networkId": network_id,
+        "nodeId": member["id"],
+        "name": member["name"],
+        "config": {
+            "authorized": member["authorized"],
+            "ipAssignments": member["ipAssignments"],
+        },
+    }))?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        local_member, local_network, peer_members, Central, CentralConfig, CentralFallback,
+        Controller, MemberSource, MembersFile,
+    };
+    use crate::{
+        http::{self, Response},
+        utils::{central_client_with_url, local_client, CentralOptions, TokenSource},
+    };
+    use async_trait::async_trait;
+    use serde_json::json;
+    use std::{str::FromStr, sync::Arc};
+    use trust_dns_server::client::rr::Name;
+    use zerotier_central_api::types::{Member, Network};
+
+    struct Static;
+
+    #[async_trait]
+    impl MemberSource for Static {
+        async fn network(&self, network_id: &str) -> Result<Network, anyhow::Error> {
+            local_network(network_id, &json!({ "name": "lab" }))
+        }
+
+        async fn members(&self, network_id: &str) -> Result<Vec<Member>, anyhow::Error> {
+            Ok(vec![local_member(
+                network_id,
+                &json!({ "id": "abcdef0123", "authorized": true, "ipAssignments": ["10.147.20.5"] }),
+            )?])
+        }
+    }
+
+    #[tokio::test]
+    async fn test_member_source() {
+        let controller: Controller = Arc::new(Static);
+
+        let network = controller.network("1234567891011121").await.unwrap();
+        assert_eq!(network.config.unwrap().name, Some("lab".to_string()));
+
+        let members = controller.members("1234567891011121").await.unwrap();
+        assert_eq!(members.len(), 1);
+        assert_eq!(members[0].node_id, Some("abcdef0123".to_string()));
+
+        // sources which do not push DNS settings anywhere need not implement it.
+        controller
+            .update_dns(
+                "1234567891011121",
+                Name::from_str("lab.").unwrap(),
+                vec!["10.147.20.1".to_string()],
+            )
+            .await
+            .unwrap();
+        controller.clear_dns("1234567891011121").await.unwrap();
+        controller.reload_credentials();
+    }
+
+    #[test]
+    fn test_reload_credentials() {
+        let token = std::env::temp_dir().join("zeronsd-test-reload-credentials");
+        std::fs::write(&token, "token\n").unwrap();
+
+        let config = |url: &str| CentralConfig {
+            token: TokenSource::File(token.clone()),
+            options: CentralOptions {
+                url: Some(url.to_string()),
+                ..Default::default()
+            },
+        };
+
+        let central = Central::new(
+            central_client_with_url("token".to_string(), Some("https://one.example.com/api/v1"))
+                .unwrap(),
+            Some(config("https://two.example.com/api/v1")),
+        );
+        assert_eq!(central.client().baseurl(), "https://one.example.com/api/v1");
+
+        central.reload_credentials();
+        assert_eq!(central.client().baseurl(), "https://two.example.com/api/v1");
+
+        // a client which cannot be built leaves the current one in place.
+        std::fs::remove_file(&token).unwrap();
+        central.reload_credentials();
+        assert_eq!(central.client().baseurl(), "https://two.example.com/api/v1");
+    }
+
+    // the local API of a zerotier-one which is on 1234567891011121, talking to one other member.
+    fn local_api(path: &str) -> Option<serde_json::Value> {
+        match path {
+            "/status" => Some(json!({ "address": "abcdef0123", "online": true })),
+            "/network/1234567891011121" => Some(json!({
+                "id": "1234567891011121",
+                "name": "lab",
+                "assignedAddresses": [
+                    "10.147.20.5/24",
+                    "fd12:3456:7891:111:2199:93ab:cdef:123/88",
+                ],
+            })),
+            "/peer" => Some(json!([
+                { "address": "0123abcdef", "role": "LEAF", "latency": 12 },
+                { "address": "778cde7190", "role": "PLANET", "latency": 80 },
+            ])),
+            _ => None,
+        }
+    }
+
+    #[test]
+    fn test_peer_members() {
+        let members = peer_members(
+            "1234567891011121",
+            &local_api("/status").unwrap(),
+            &local_api("/network/1234567891011121").unwrap(),
+            &local_api("/peer").unwrap(),
+        )
+        .unwrap();
+
+        // the RFC 4193 address is worked out from the member ID, and roots are left out.
+        assert_eq!(members.len(), 2);
+        assert_eq!(members[0].node_id, Some("abcdef0123".to_string()));
+        assert_eq!(
+            members[0].config.clone().unwrap().ip_assignments,
+            Some(vec!["10.147.20.5".to_string()])
+        );
+        assert_eq!(members[1].node_id, Some("0123abcdef".to_string()));
+        assert_eq!(
+            members[1].config.clone().unwrap().ip_assignments,
+            Some(Vec::new())
+        );
+    }
+
+    #[tokio::test]
+    async fn test_central_fallback() {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let server = tokio::spawn(async move {
+            loop {
+                let (stream, _) = listener.accept().await.unwrap();
+                tokio::spawn(http::handle(stream, |request| async move {
+                    match local_api(&request.path) {
+                        Some(body) => Response::json(200, &body),
+                        None => Response::error(404, "not found"),
+                    }
+                }));
+            }
+        });
+
+        let source = CentralFallback::new(
+            CentralConfig {
+                token: TokenSource::File(std::env::temp_dir().join("zeronsd-test-no-token")),
+                options: CentralOptions::default(),
+            },
+            local_client("secret".to_string(), format!("http://{}", addr)).unwrap(),
+        );
+
+        // without a token, the peers are served, and no DNS settings are pushed.
+        let network = source.network("1234567891011121").await.unwrap();
+        let config = network.config.unwrap();
+        assert_eq!(config.name, Some("lab".to_string()));
+        let v6 = config.v6_assign_mode.unwrap();
+        assert_eq!(v6.rfc4193, Some(true));
+        assert_eq!(v6._6plane, Some(false));
+
+        let members = source.members("1234567891011121").await.unwrap();
+        assert_eq!(members.len(), 2);
+
+        source
+            .update_dns(
+                "1234567891011121",
+                Name::from_str("lab.").unwrap(),
+                vec!["10.147.20.5".to_string()],
+            )
+            .await
+            .unwrap();
+
+        // networks zerotier-one is not on are errors still.
+        assert!(source.members("8056c2e21c000001").await.is_err());
+
+        server.abort();
+    }
+
+    #[tokio::test]
+    async fn test_members_file() {
+        let path = std::env::temp_dir().join("zeronsd-test-members-file.yaml");
+        std::fs::write(
+            &path,
+            "name: lab\n\
+             members:\n\
+             \x20 - id: abcdef0123\n\
+             \x20   name: db\n\
+             \x20   ips: [10.147.20.5]\n\
+             \x20 - id: 0123abcdef\n\
+             \x20   authorized: false\n",
+        )
+        .unwrap();
+
+        let source = MembersFile(path.clone());
+
+        let network = source.network("1234567891011121").await.unwrap();
+        assert_eq!(network.config.unwrap().name, Some("lab".to_string()));
+
+        let members = source.members("1234567891011121").await.unwrap();
+        assert_eq!(members.len(), 2);
+        assert_eq!(members[0].name, Some("db".to_string()));
+        assert_eq!(
+            members[0].config.clone().unwrap().ip_assignments,
+            Some(vec!["10.147.20.5".to_string()])
+        );
+        assert_eq!(members[1].config.clone().unwrap().authorized, Some(false));
+
+        std::fs::write(&path, "members: {").unwrap();
+        assert!(source.members("1234567891011121").await.is_err());
+
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_local_member() {
+        let member = local_member(
+            "1234567891011121",
+            &json!({
+                "id": "abcdef0123",
+                "address": "abcdef0123",
+                "nwid": "1234567891011121",
+                "authorized": true,
+                "ipAssignments": ["10.147.20.5"],
+                "revision": 3,
+            }),
+        )
+        .unwrap();
+
+        assert_eq!(member.node_id, Some("abcdef0123".to_string()));
+        assert_eq!(member.network_id, Some("1234567891011121".to_string()));
+        assert_eq!(member.name, None);
+        assert_eq!(
+            member.config.unwrap().ip_assignments,
+            Some(vec!["10.147.20.5".to_string()])
+        );
+    }
+
+    #[test]
+    fn test_local_network() {
+        let network = local_network(
+            "1234567891011121",
+            &json!({
+                "id": "1234567891011121",
+                "name": "lab",
+                "v6AssignMode": { "6plane": true, "rfc4193": false, "zt": false },
+                "rules": [{ "type": "ACTION_ACCEPT" }],
+            }),
+        )
+        .unwrap();
+
+        assert_eq!(network.id, Some("1234567891011121".to_string()));
+
+        let v6 = network.config.unwrap().v6_assign_mode.unwrap();
+        assert_eq!(v6._6plane, Some(true));
+        assert_eq!(v6.rfc4193, Some(false));
+    }
+}
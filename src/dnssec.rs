@@ -0,0 +1,241 @@
+/// DNSSEC signing (RFC 4033) of the zones served.
+use std::{
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use anyhow::anyhow;
+use serde::{Deserialize, Serialize};
+use trust_dns_server::{
+    client::rr::{
+        dnssec::{Algorithm, DigestType, KeyFormat, KeyPair, Private, SigSigner},
+        Name,
+    },
+    proto::rr::{
+        dnssec::rdata::{DNSSECRData, DNSKEY, DS},
+        RData, Record,
+    },
+};
+
+use crate::snapshot::write_private;
+
+// days signatures are valid for, unless configured otherwise.
+pub const DEFAULT_VALIDITY: u64 = 30;
+
+// DnssecConfig is the `dnssec` section of the configuration file: the key the zones are signed
+// with, its algorithm, and how many days signatures are valid for.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct DnssecConfig {
+    pub key: PathBuf,
+    #[serde(default = "default_algorithm")]
+    pub algorithm: String,
+    #[serde(default = "default_validity")]
+    pub validity: u64,
+}
+
+fn default_algorithm() -> String {
+    "ECDSAP256SHA256".to_string()
+}
+
+fn default_validity() -> u64 {
+    DEFAULT_VALIDITY
+}
+
+impl DnssecConfig {
+    // new signs with a key at the path, with the default algorithm and validity.
+    pub fn new(key: PathBuf) -> Self {
+        Self {
+            key,
+            algorithm: default_algorithm(),
+            validity: default_validity(),
+        }
+    }
+
+    // zone_key reads the key. Keys in PKCS#8 form are generated if the file does not exist yet;
+    // PEM and DER keys, told apart by their extension, have to be made with openssl.
+    pub fn zone_key(&self) -> Result<ZoneKey, anyhow::Error> {
+        let algorithm = parse_algorithm(&self.algorithm)?;
+        let format = key_format(&self.key);
+
+        if self.validity == 0 {
+            return Err(anyhow!(
+                "DNSSEC signatures must be valid for at least a day"
+            ));
+        }
+
+        if !self.key.exists() {
+            if format != KeyFormat::Pkcs8 {
+                return Err(anyhow!(
+                    "DNSSEC key {} does not exist; only PKCS#8 keys are generated",
+                    self.key.display()
+                ));
+            }
+
+            let key = KeyPair::generate_pkcs8(algorithm)
+                .map_err(|e| anyhow!("Could not generate a DNSSEC key: {}", e))?;
+            write_private(&self.key, &key)?;
+            tracing::info!(
+                "Generated DNSSEC key {}; publish its DS record with `zeronsd ds`",
+                self.key.display()
+            );
+        }
+
+        let key = ZoneKey {
+            algorithm,
+            format,
+            bytes: Arc::new(std::fs::read(&self.key)?),
+            validity: Duration::from_secs(self.validity * 24 * 60 * 60),
+        };
+
+        key.key_pair()
+            .map_err(|e| anyhow!("Invalid DNSSEC key {}: {}", self.key.display(), e))?;
+
+        Ok(key)
+    }
+}
+
+// parse_algorithm accepts the algorithms keys can be used with, by their mnemonic.
+pub fn parse_algorithm(s: &str) -> Result<Algorithm, anyhow::Error> {
+    match s.to_uppercase().as_str() {
+        "RSASHA256" => Ok(Algorithm::RSASHA256),
+        "RSASHA512" => Ok(Algorithm::RSASHA512),
+        "ECDSAP256SHA256" => Ok(Algorithm::ECDSAP256SHA256),
+        "ECDSAP384SHA384" => Ok(Algorithm::ECDSAP384SHA384),
+        "ED25519" => Ok(Algorithm::ED25519),
+        _ => Err(anyhow!(
+            "invalid DNSSEC algorithm {}: allowed values: [RSASHA256, RSASHA512, ECDSAP256SHA256, ECDSAP384SHA384, ED25519]",
+            s
+        )),
+    }
+}
+
+fn key_format(path: &Path) -> KeyFormat {
+    match path.extension().and_then(|ext| ext.to_str()) {
+        Some("pem") => KeyFormat::Pem,
+        Some("der") => KeyFormat::Der,
+        _ => KeyFormat::Pkcs8,
+    }
+}
+
+// ZoneKey is the key zones are signed with. It is kept encoded, as the signers made from it
+// cannot be cloned.
+#[derive(Clone)]
+pub struct ZoneKey {
+    algorithm: Algorithm,
+    format: KeyFormat,
+    bytes: Arc<Vec<u8>>,
+    validity: Duration,
+}
+
+impl ZoneKey {
+    fn key_pair(&self) -> Result<KeyPair<Private>, anyhow::Error> {
+        self.format
+            .decode_key(&self.bytes, None, self.algorithm)
+            .map_err(|e| anyhow!("{}", e))
+    }
+
+    // dnskey is the DNSKEY record published at the apex of the zones.
+    pub fn dnskey(&self) -> Result<DNSKEY, anyhow::Error> {
+        self.key_pair()?
+            .to_dnskey(self.algorithm)
+            .map_err(|e| anyhow!("{}", e))
+    }
+
+    // signer signs the records of the zone.
+    pub fn signer(&self, zone: &Name) -> Result<SigSigner, anyhow::Error> {
+        Ok(SigSigner::dnssec(
+            self.dnskey()?,
+            self.key_pair()?,
+            zone.clone(),
+            self.validity,
+        ))
+    }
+
+    // ds is the DS record of the zone, with a SHA-256 digest of its DNSKEY.
+    pub fn ds(&self, zone: &Name) -> Result<DS, anyhow::Error> {
+        let dnskey = self.dnskey()?;
+        let digest = dnskey.to_digest(zone, DigestType::SHA256)?;
+
+        Ok(DS::new(
+            dnskey.calculate_key_tag()?,
+            self.algorithm,
+            DigestType::SHA256,
+            digest.as_ref().to_vec(),
+        ))
+    }
+
+    // needs_signing tells whether signatures have to be made again: when there are none, or once
+    // half of their validity has passed.
+    pub fn needs_signing(&self, rrsigs: &[Record]) -> bool {
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |d| d.as_secs());
+
+        let expiration = rrsigs
+            .iter()
+            .filter_map(|rrsig| match rrsig.data() {
+                Some(RData::DNSSEC(DNSSECRData::SIG(sig))) => Some(sig.sig_expiration() as u64),
+                _ => None,
+            })
+            .min();
+
+        match expiration {
+            Some(expiration) => now + self.validity.as_secs() / 2 >= expiration,
+            None => true,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{parse_algorithm, DnssecConfig};
+    use std::str::FromStr;
+    use trust_dns_server::client::rr::{
+        dnssec::{Algorithm, DigestType},
+        Name,
+    };
+
+    #[test]
+    fn test_parse_algorithm() {
+        assert_eq!(
+            parse_algorithm("ecdsap256sha256").unwrap(),
+            Algorithm::ECDSAP256SHA256
+        );
+        assert_eq!(parse_algorithm("ED25519").unwrap(), Algorithm::ED25519);
+        assert!(parse_algorithm("RSASHA1").is_err());
+    }
+
+    #[test]
+    fn test_zone_key() {
+        let dir = std::env::temp_dir().join("zeronsd-test-dnssec");
+        let _ = std::fs::remove_dir_all(&dir);
+
+        let config = DnssecConfig::new(dir.join("zone.pk8"));
+
+        let zone = Name::from_str("home.arpa.").unwrap();
+        let ds = config.zone_key().unwrap().ds(&zone).unwrap();
+
+        assert_eq!(ds.algorithm(), Algorithm::ECDSAP256SHA256);
+        assert_eq!(ds.digest_type(), DigestType::SHA256);
+        assert_eq!(ds.digest().len(), 32);
+
+        // the key generated the first time is used from then on.
+        let key = config.zone_key().unwrap();
+        assert_eq!(key.ds(&zone).unwrap(), ds);
+
+        let signer = key.signer(&zone).unwrap();
+        assert_eq!(signer.signer_name(), &zone);
+
+        assert!(key.needs_signing(&[]));
+
+        assert!(DnssecConfig {
+            key: dir.join("zone.pem"),
+            ..config
+        }
+        .zone_key()
+        .is_err());
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,367 @@
+/// dnstap export of requests and responses over Frame Streams.
+use std::{
+    net::SocketAddr,
+    path::PathBuf,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use tokio::sync::mpsc;
+use trust_dns_server::{
+    proto::{op::Message, serialize::binary::BinEncodable},
+    server::{Protocol, Request, ResponseInfo},
+};
+
+// the content type of the frames, agreed on with the collector.
+const CONTENT_TYPE: &str = "protobuf:dnstap.Dnstap";
+
+// Frame Streams control frame types and fields.
+const CONTROL_ACCEPT: u32 = 0x01;
+const CONTROL_START: u32 = 0x02;
+const CONTROL_STOP: u32 = 0x03;
+const CONTROL_READY: u32 = 0x04;
+const FIELD_CONTENT_TYPE: u32 = 0x01;
+
+// dnstap Message.Type values.
+const CLIENT_QUERY: u64 = 5;
+const CLIENT_RESPONSE: u64 = 6;
+
+// how many frames wait for the collector before new ones are dropped.
+const QUEUE_SIZE: usize = 1024;
+
+// how long to wait before connecting to the collector again.
+const RECONNECT_INTERVAL: Duration = Duration::from_secs(10);
+
+// Dnstap sends messages to the collector listening on a unix socket. It is shared by the
+// listeners of a network.
+#[derive(Clone)]
+pub struct Dnstap {
+    frames: mpsc::Sender<Vec<u8>>,
+}
+
+impl Dnstap {
+    // new connects to the collector listening on the socket at the path, in the background.
+    pub fn new(path: PathBuf) -> Result<Self, anyhow::Error> {
+        let (frames, rx) = mpsc::channel(QUEUE_SIZE);
+        spawn_writer(path, rx)?;
+
+        Ok(Self { frames })
+    }
+
+    // record sends the request, received at query_time, and the response sent for it.
+    pub fn record(&self, request: &Request, query_time: SystemTime, info: &ResponseInfo) {
+        let response_time = SystemTime::now();
+
+        if let Ok(query) = request.to_bytes() {
+            self.send(encode(
+                CLIENT_QUERY,
+                request.src(),
+                request.protocol(),
+                query_time,
+                &query,
+            ));
+        }
+
+        let mut response = Message::new();
+        response.set_header(**info);
+        response.add_query(request.query().original().clone());
+
+        if let Ok(response) = response.to_vec() {
+            self.send(encode(
+                CLIENT_RESPONSE,
+                request.src(),
+                request.protocol(),
+                response_time,
+                &response,
+            ));
+        }
+    }
+
+    fn send(&self, payload: Vec<u8>) {
+        let mut frame = (payload.len() as u32).to_be_bytes().to_vec();
+        frame.extend(payload);
+
+        if self.frames.try_send(frame).is_err() {
+            tracing::trace!("dnstap queue is full; dropping message");
+        }
+    }
+}
+
+// encode builds a Dnstap protobuf message. Queries and responses carry their time and DNS message
+// in different fields; the client's address is the query address of both.
+fn encode(
+    kind: u64,
+    client: SocketAddr,
+    protocol: Protocol,
+    time: SystemTime,
+    dns: &[u8],
+) -> Vec<u8> {
+    let time = time.duration_since(UNIX_EPOCH).unwrap_or_default();
+
+    let mut message = Vec::new();
+    varint_field(&mut message, 1, kind);
+    varint_field(&mut message, 2, if client.is_ipv4() { 1 } else { 2 });
+
+    // dnstap has no value for DTLS.
+    if let Some(protocol) = match protocol {
+        Protocol::Udp => Some(1),
+        Protocol::Tcp => Some(2),
+        Protocol::Tls => Some(3),
+        Protocol::Https => Some(4),
+        Protocol::Dtls => None,
+    } {
+        varint_field(&mut message, 3, protocol);
+    }
+
+    match client.ip() {
+        std::net::IpAddr::V4(ip) => bytes_field(&mut message, 4, &ip.octets()),
+        std::net::IpAddr::V6(ip) => bytes_field(&mut message, 4, &ip.octets()),
+    }
+    varint_field(&mut message, 6, client.port() as u64);
+
+    let (sec, nsec, dns_field) = if kind == CLIENT_QUERY {
+        (8, 9, 10)
+    } else {
+        (12, 13, 14)
+    };
+    varint_field(&mut message, sec, time.as_secs());
+    fixed32_field(&mut message, nsec, time.subsec_nanos());
+    bytes_field(&mut message, dns_field, dns);
+
+    let mut dnstap = Vec::new();
+    bytes_field(
+        &mut dnstap,
+        2,
+        format!("zeronsd {}", env!("CARGO_PKG_VERSION")).as_bytes(),
+    );
+    bytes_field(&mut dnstap, 14, &message);
+    // Dnstap.Type MESSAGE
+    varint_field(&mut dnstap, 15, 1);
+
+    dnstap
+}
+
+fn varint(buf: &mut Vec<u8>, mut value: u64) {
+    while value >= 0x80 {
+        buf.push(value as u8 | 0x80);
+        value >>= 7;
+    }
+
+    buf.push(value as u8);
+}
+
+fn varint_field(buf: &mut Vec<u8>, field: u64, value: u64) {
+    varint(buf, field << 3);
+    varint(buf, value);
+}
+
+fn fixed32_field(buf: &mut Vec<u8>, field: u64, value: u32) {
+    varint(buf, field << 3 | 5);
+    buf.extend(value.to_le_bytes());
+}
+
+fn bytes_field(buf: &mut Vec<u8>, field: u64, value: &[u8]) {
+    varint(buf, field << 3 | 2);
+    varint(buf, value.len() as u64);
+    buf.extend(value);
+}
+
+// control builds a Frame Streams control frame; all but STOP name the content type.
+fn control(kind: u32) -> Vec<u8> {
+    let mut body = kind.to_be_bytes().to_vec();
+
+    if kind != CONTROL_STOP {
+        body.extend(FIELD_CONTENT_TYPE.to_be_bytes());
+        body.extend((CONTENT_TYPE.len() as u32).to_be_bytes());
+        body.extend(CONTENT_TYPE.as_bytes());
+    }
+
+    // control frames are escaped with a zero length.
+    let mut frame = 0u32.to_be_bytes().to_vec();
+    frame.extend((body.len() as u32).to_be_bytes());
+    frame.extend(body);
+    frame
+}
+
+// connect opens the socket and goes through the handshake: READY, answered with ACCEPT, then
+// START, after which data frames follow.
+#[cfg(unix)]
+async fn connect(path: &std::path::Path) -> Result<tokio::net::UnixStream, anyhow::Error> {
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    let mut stream = tokio::net::UnixStream::connect(path).await?;
+    stream.write_all(&control(CONTROL_READY)).await?;
+
+    let mut header = [0u8; 8];
+    stream.read_exact(&mut header).await?;
+
+    let escape = u32::from_be_bytes([header[0], header[1], header[2], header[3]]);
+    let length = u32::from_be_bytes([header[4], header[5], header[6], header[7]]) as usize;
+
+    if escape != 0 || !(4..=512).contains(&length) {
+        return Err(anyhow::anyhow!("invalid control frame from collector"));
+    }
+
+    let mut body = vec![0u8; length];
+    stream.read_exact(&mut body).await?;
+
+    if u32::from_be_bytes([body[0], body[1], body[2], body[3]]) != CONTROL_ACCEPT {
+        return Err(anyhow::anyhow!("collector did not accept {}", CONTENT_TYPE));
+    }
+
+    stream.write_all(&control(CONTROL_START)).await?;
+    Ok(stream)
+}
+
+#[cfg(unix)]
+fn spawn_writer(path: PathBuf, frames: mpsc::Receiver<Vec<u8>>) -> Result<(), anyhow::Error> {
+    tokio::spawn(write_frames(path, frames));
+    Ok(())
+}
+
+#[cfg(not(unix))]
+fn spawn_writer(_: PathBuf, _: mpsc::Receiver<Vec<u8>>) -> Result<(), anyhow::Error> {
+    Err(anyhow::anyhow!(
+        "dnstap is only supported on unix platforms"
+    ))
+}
+
+// write_frames sends the frames to the collector until zeronsd stops, connecting again whenever
+// the connection is lost.
+#[cfg(unix)]
+async fn write_frames(path: PathBuf, mut frames: mpsc::Receiver<Vec<u8>>) {
+    use tokio::io::AsyncWriteExt;
+
+    loop {
+        match connect(&path).await {
+            Ok(mut stream) => {
+                tracing::info!("Sending dnstap messages to {}", path.display());
+
+                loop {
+                    match frames.recv().await {
+                        Some(frame) => {
+                            if let Err(e) = stream.write_all(&frame).await {
+                                tracing::warn!("Lost dnstap collector {}: {}", path.display(), e);
+                                break;
+                            }
+                        }
+                        None => {
+                            let _ = stream.write_all(&control(CONTROL_STOP)).await;
+                            return;
+                        }
+                    }
+                }
+            }
+            Err(e) => tracing::warn!(
+                "Could not connect to dnstap collector {}: {}",
+                path.display(),
+                e
+            ),
+        }
+
+        tokio::time::sleep(RECONNECT_INTERVAL).await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{varint, Dnstap, CLIENT_QUERY, CONTENT_TYPE};
+
+    #[test]
+    fn test_varint() {
+        for (value, encoded) in [
+            (0, vec![0x00]),
+            (1, vec![0x01]),
+            (300, vec![0xac, 0x02]),
+            (1 << 32, vec![0x80, 0x80, 0x80, 0x80, 0x10]),
+        ] {
+            let mut buf = Vec::new();
+            varint(&mut buf, value);
+            assert_eq!(buf, encoded, "{}", value);
+        }
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_dnstap() {
+        use std::{str::FromStr, time::SystemTime};
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+        use trust_dns_server::{
+            authority::MessageRequest,
+            client::rr::{Name, RecordType},
+            proto::{
+                op::{Header, Message, Query, ResponseCode},
+                serialize::binary::{BinDecodable, BinEncodable},
+            },
+            server::{Protocol, Request},
+        };
+
+        let path = std::env::temp_dir().join("zeronsd-test-dnstap.sock");
+        let _ = std::fs::remove_file(&path);
+        let listener = tokio::net::UnixListener::bind(&path).unwrap();
+
+        let dnstap = Dnstap::new(path.clone()).unwrap();
+        let (mut stream, _) = listener.accept().await.unwrap();
+
+        // READY, naming the content type.
+        let mut ready = [0u8; 12 + CONTENT_TYPE.len() + 8];
+        stream.read_exact(&mut ready).await.unwrap();
+        assert_eq!(&ready[0..4], &[0, 0, 0, 0]);
+        assert_eq!(&ready[8..12], &[0, 0, 0, 4]);
+        assert!(ready.ends_with(CONTENT_TYPE.as_bytes()));
+
+        let mut accept = ready;
+        accept[11] = 1;
+        stream.write_all(&accept).await.unwrap();
+
+        // START, then the data frames.
+        let mut start = [0u8; 12 + CONTENT_TYPE.len() + 8];
+        stream.read_exact(&mut start).await.unwrap();
+        assert_eq!(&start[8..12], &[0, 0, 0, 2]);
+
+        let mut query = Message::new();
+        query.add_query(Query::query(
+            Name::from_str("islay.home.arpa.").unwrap(),
+            RecordType::A,
+        ));
+        let query = query.to_vec().unwrap();
+
+        let request = Request::new(
+            MessageRequest::from_bytes(&query).unwrap(),
+            "10.147.20.9:5353".parse().unwrap(),
+            Protocol::Udp,
+        );
+
+        let mut header = Header::response_from_request(request.header());
+        header.set_response_code(ResponseCode::NXDomain);
+        dnstap.record(&request, SystemTime::now(), &header.into());
+
+        let mut frames = Vec::new();
+        for _ in 0..2 {
+            let mut length = [0u8; 4];
+            stream.read_exact(&mut length).await.unwrap();
+
+            let mut frame = vec![0u8; u32::from_be_bytes(length) as usize];
+            stream.read_exact(&mut frame).await.unwrap();
+            frames.push(frame);
+        }
+
+        // the query is sent as received, with the client's address.
+        let request_bytes = request.to_bytes().unwrap();
+        assert!(frames[0]
+            .windows(request_bytes.len())
+            .any(|w| w == request_bytes));
+        assert!(frames[0].windows(4).any(|w| w == [10, 147, 20, 9]));
+        assert!(frames[0]
+            .windows(2)
+            .any(|w| w == [0x08, CLIENT_QUERY as u8]));
+
+        // the response carries its header and question.
+        let mut response = Message::new();
+        response.set_header(header);
+        response.add_query(request.query().original().clone());
+        let response = response.to_vec().unwrap();
+        assert!(frames[1].windows(response.len()).any(|w| w == response));
+
+        std::fs::remove_file(path).unwrap();
+    }
+}
@@ -0,0 +1,437 @@
+/// EDNS (RFC 6891) options of the answers: payload size, DNS cookies and NSID.
+use std::{
+    net::IpAddr,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use anyhow::anyhow;
+use openssl::{hash::MessageDigest, pkey::PKey, sign::Signer};
+use trust_dns_server::{
+    authority::{MessageRequest, MessageResponse, MessageResponseBuilder},
+    client::rr::Record,
+    proto::{
+        op::{Edns, ResponseCode},
+        rr::rdata::opt::{EdnsCode, EdnsOption},
+        serialize::binary::{BinDecodable, BinEncoder},
+    },
+    server::{ResponseHandler, ResponseInfo},
+};
+
+// the smallest payload size a client can be told, as RFC 6891 treats smaller ones as 512.
+pub const MIN_BUFFER_SIZE: u16 = 512;
+
+// the lengths of the client cookie, and of the server cookie zeronsd makes.
+const CLIENT_COOKIE_LEN: usize = 8;
+const SERVER_COOKIE_LEN: usize = 16;
+
+// the version of the server cookie layout of RFC 9018.
+const COOKIE_VERSION: u8 = 1;
+
+// server cookies are made again once they are this old, in seconds, and are not accepted once
+// they are older than COOKIE_LIFETIME, or more than COOKIE_SKEW ahead of the clock.
+const COOKIE_REFRESH: u32 = 1800;
+const COOKIE_LIFETIME: u32 = 3600;
+const COOKIE_SKEW: u32 = 300;
+
+// EdnsConfig is what is put in the EDNS options of answers. Without any of it, answers have the
+// EDNS options trust-dns gives them.
+#[derive(Clone, Default)]
+pub struct EdnsConfig {
+    buffer_size: Option<u16>,
+    nsid: Option<Vec<u8>>,
+    cookie_secret: Option<[u8; 16]>,
+}
+
+impl EdnsConfig {
+    pub fn new(
+        buffer_size: Option<u16>,
+        nsid: Option<String>,
+        cookies: bool,
+    ) -> Result<Self, anyhow::Error> {
+        if let Some(size) = buffer_size.filter(|size| *size < MIN_BUFFER_SIZE) {
+            return Err(anyhow!(
+                "EDNS buffer size {} is below the minimum of {}",
+                size,
+                MIN_BUFFER_SIZE
+            ));
+        }
+
+        if nsid.as_ref().map_or(false, |nsid| nsid.is_empty()) {
+            return Err(anyhow!("NSID cannot be empty"));
+        }
+
+        Ok(Self {
+            buffer_size,
+            nsid: nsid.map(String::into_bytes),
+            cookie_secret: if cookies { Some(rand::random()) } else { None },
+        })
+    }
+
+    // response_edns is the EDNS of the answer to a request with the given EDNS, from the client
+    // address; requests without EDNS are answered without it. None leaves the answer's EDNS as it
+    // is, as when nothing is configured.
+    pub fn response_edns(&self, request: Option<&Edns>, client: IpAddr) -> Option<Edns> {
+        if self.buffer_size.is_none() && self.nsid.is_none() && self.cookie_secret.is_none() {
+            return None;
+        }
+
+        let request = request?;
+        let mut edns = Edns::new();
+
+        edns.set_version(0);
+        edns.set_dnssec_ok(request.dnssec_ok());
+        edns.set_max_payload(self.max_size(Some(request)));
+
+        if let (Some(nsid), Some(_)) = (&self.nsid, request.option(EdnsCode::NSID)) {
+            edns.set_option(EdnsOption::Unknown(EdnsCode::NSID.into(), nsid.clone()));
+        }
+
+        if let (Some(secret), Some(option)) =
+            (&self.cookie_secret, request.option(EdnsCode::Cookie))
+        {
+            let cookie = Vec::<u8>::from(option);
+
+            match cookie_for(secret, &cookie, client, now()) {
+                Some(cookie) => {
+                    edns.set_option(EdnsOption::Unknown(EdnsCode::Cookie.into(), cookie))
+                }
+                None => tracing::debug!("Ignoring malformed DNS cookie from {}", client),
+            }
+        }
+
+        Some(edns)
+    }
+
+    // max_size is the size answers over UDP are truncated to, given the EDNS of the request: what
+    // the client can take, up to the configured buffer size, or 512 bytes without EDNS.
+    pub fn max_size(&self, request: Option<&Edns>) -> u16 {
+        match request {
+            Some(request) => {
+                let client_size = request.max_payload().max(MIN_BUFFER_SIZE);
+                self.buffer_size
+                    .map_or(client_size, |size| size.min(client_size))
+            }
+            None => MIN_BUFFER_SIZE,
+        }
+    }
+}
+
+fn now() -> u32 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_secs() as u32)
+}
+
+// cookie_for is the cookie option of the answer to a request with the given cookie option: the
+// client cookie, followed by the server cookie the client sent if it is still fresh, or else a
+// new one. Options of the wrong length are malformed, and get no cookie back.
+fn cookie_for(secret: &[u8], cookie: &[u8], client: IpAddr, now: u32) -> Option<Vec<u8>> {
+    match cookie.len() {
+        CLIENT_COOKIE_LEN | 16..=40 => {}
+        _ => return None,
+    }
+
+    let (client_cookie, server_cookie) = cookie.split_at(CLIENT_COOKIE_LEN);
+
+    let reused = valid_cookie(secret, cookie, client, now)
+        && cookie_time(server_cookie)
+            .map_or(false, |time| now.saturating_sub(time) < COOKIE_REFRESH);
+
+    let mut answer = client_cookie.to_vec();
+
+    if reused {
+        answer.extend_from_slice(server_cookie);
+    } else {
+        answer.extend(server_cookie_at(secret, client_cookie, client, now));
+    }
+
+    Some(answer)
+}
+
+// valid_cookie tells whether the cookie option carries a server cookie zeronsd made for the
+// client, which has not expired.
+fn valid_cookie(secret: &[u8], cookie: &[u8], client: IpAddr, now: u32) -> bool {
+    if cookie.len() != CLIENT_COOKIE_LEN + SERVER_COOKIE_LEN {
+        return false;
+    }
+
+    let (client_cookie, server_cookie) = cookie.split_at(CLIENT_COOKIE_LEN);
+
+    match cookie_time(server_cookie) {
+        Some(time)
+            if time <= now.saturating_add(COOKIE_SKEW)
+                && now.saturating_sub(time) < COOKIE_LIFETIME =>
+        {
+            server_cookie == server_cookie_at(secret, client_cookie, client, time).as_slice()
+        }
+        _ => false,
+    }
+}
+
+// cookie_time is the time a server cookie was made at.
+fn cookie_time(server_cookie: &[u8]) -> Option<u32> {
+    Some(u32::from_be_bytes(
+        server_cookie.get(4..8)?.try_into().ok()?,
+    ))
+}
+
+// server_cookie_at makes the server cookie for the client cookie and address at the given time:
+// the version, three reserved bytes, the time, and the first eight bytes of the HMAC of them all
+// with the client cookie and address.
+fn server_cookie_at(secret: &[u8], client_cookie: &[u8], client: IpAddr, time: u32) -> Vec<u8> {
+    let mut cookie = vec![COOKIE_VERSION, 0, 0, 0];
+    cookie.extend(time.to_be_bytes());
+
+    let mut input = client_cookie.to_vec();
+    input.extend(&cookie);
+    match client {
+        IpAddr::V4(ip) => input.extend(ip.octets()),
+        IpAddr::V6(ip) => input.extend(ip.octets()),
+    }
+
+    let hash = PKey::hmac(secret)
+        .and_then(|key| Signer::new(MessageDigest::sha256(), &key)?.sign_oneshot_to_vec(&input))
+        .expect("HMAC-SHA256 is available");
+
+    cookie.extend(&hash[..8]);
+    cookie
+}
+
+// EdnsResponse sends answers with the EDNS options of the configuration, in place of those they
+// were made with.
+#[derive(Clone)]
+pub struct EdnsResponse<R> {
+    inner: R,
+    edns: Option<Edns>,
+}
+
+impl<R> EdnsResponse<R> {
+    pub fn new(inner: R, edns: Option<Edns>) -> Self {
+        Self { inner, edns }
+    }
+}
+
+#[async_trait::async_trait]
+impl<R: ResponseHandler> ResponseHandler for EdnsResponse<R> {
+    async fn send_response<'a>(
+        &mut self,
+        mut response: MessageResponse<
+            '_,
+            'a,
+            impl Iterator<Item = &'a Record> + Send + 'a,
+            impl Iterator<Item = &'a Record> + Send + 'a,
+            impl Iterator<Item = &'a Record> + Send + 'a,
+            impl Iterator<Item = &'a Record> + Send + 'a,
+        >,
+    ) -> std::io::Result<ResponseInfo> {
+        // answers to requests of EDNS versions not spoken keep the OPT record saying so.
+        if let Some(edns) = self
+            .edns
+            .clone()
+            .filter(|_| response.header().response_code() != ResponseCode::BADVERS)
+        {
+            response.set_edns(edns);
+        }
+
+        self.inner.send_response(response).await
+    }
+}
+
+// UdpResponse truncates answers sent over UDP to the size the client can take, as trust-dns sends
+// them whole. Answers which do not fit are sent with the TC bit and no records, so that the client
+// asks again over TCP.
+#[derive(Clone)]
+pub struct UdpResponse<R> {
+    inner: R,
+    // None for answers over other transports, which are sent as they are.
+    max_size: Option<u16>,
+}
+
+impl<R> UdpResponse<R> {
+    pub fn new(inner: R, max_size: Option<u16>) -> Self {
+        Self { inner, max_size }
+    }
+}
+
+#[async_trait::async_trait]
+impl<R: ResponseHandler> ResponseHandler for UdpResponse<R> {
+    async fn send_response<'a>(
+        &mut self,
+        response: MessageResponse<
+            '_,
+            'a,
+            impl Iterator<Item = &'a Record> + Send + 'a,
+            impl Iterator<Item = &'a Record> + Send + 'a,
+            impl Iterator<Item = &'a Record> + Send + 'a,
+            impl Iterator<Item = &'a Record> + Send + 'a,
+        >,
+    ) -> std::io::Result<ResponseInfo> {
+        let max_size = match self.max_size {
+            Some(max_size) => max_size,
+            None => return self.inner.send_response(response).await,
+        };
+
+        let encode_error = |e| {
+            std::io::Error::new(
+                std::io::ErrorKind::Other,
+                format!("error encoding message: {}", e),
+            )
+        };
+
+        let mut buffer = Vec::with_capacity(max_size as usize);
+        {
+            let mut encoder = BinEncoder::new(&mut buffer);
+            encoder.set_max_size(max_size);
+            response
+                .destructive_emit(&mut encoder)
+                .map_err(encode_error)?;
+        }
+
+        // a response can only be encoded once, so the one sent is made again from what was
+        // encoded within the size.
+        let encoded = MessageRequest::from_bytes(&buffer).map_err(encode_error)?;
+        let header = *encoded.header();
+
+        let mut builder = MessageResponseBuilder::from_message_request(&encoded);
+        if let Some(edns) = encoded.edns() {
+            builder.edns(edns.clone());
+        }
+
+        if header.truncated() {
+            self.inner
+                .send_response(builder.build_no_records(header))
+                .await
+        } else {
+            self.inner
+                .send_response(builder.build(
+                    header,
+                    encoded.answers(),
+                    encoded.name_servers(),
+                    std::iter::empty(),
+                    encoded.additionals(),
+                ))
+                .await
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{cookie_for, valid_cookie, EdnsConfig};
+    use std::net::{IpAddr, Ipv4Addr};
+    use trust_dns_server::proto::{
+        op::Edns,
+        rr::rdata::opt::{EdnsCode, EdnsOption},
+    };
+
+    const SECRET: &[u8] = b"0123456789abcdef";
+    const CLIENT: IpAddr = IpAddr::V4(Ipv4Addr::new(10, 147, 20, 2));
+
+    #[test]
+    fn test_cookies() {
+        let now = 1_700_000_000;
+        let client_cookie = [1, 2, 3, 4, 5, 6, 7, 8];
+
+        let cookie = cookie_for(SECRET, &client_cookie, CLIENT, now).unwrap();
+        assert_eq!(cookie.len(), 24);
+        assert_eq!(&cookie[..8], &client_cookie);
+        assert!(valid_cookie(SECRET, &cookie, CLIENT, now));
+
+        // cookies are bound to the client, the secret and the client cookie.
+        assert!(!valid_cookie(
+            SECRET,
+            &cookie,
+            IpAddr::V4(Ipv4Addr::new(10, 147, 20, 3)),
+            now
+        ));
+        assert!(!valid_cookie(b"another secret!!", &cookie, CLIENT, now));
+        let mut other = cookie.clone();
+        other[0] ^= 1;
+        assert!(!valid_cookie(SECRET, &other, CLIENT, now));
+
+        // a fresh cookie is sent back as it is, an old one is made again, and an expired one is
+        // no longer valid.
+        assert_eq!(
+            cookie_for(SECRET, &cookie, CLIENT, now + 60),
+            Some(cookie.clone())
+        );
+        let refreshed = cookie_for(SECRET, &cookie, CLIENT, now + 2000).unwrap();
+        assert_ne!(refreshed, cookie);
+        assert!(valid_cookie(SECRET, &refreshed, CLIENT, now + 2000));
+        assert!(!valid_cookie(SECRET, &cookie, CLIENT, now + 4000));
+
+        // cookies the client made up are replaced.
+        let mut forged = client_cookie.to_vec();
+        forged.extend([1, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0]);
+        assert_eq!(cookie_for(SECRET, &forged, CLIENT, now), Some(cookie));
+
+        // malformed cookies get nothing back.
+        assert_eq!(cookie_for(SECRET, &[1, 2, 3], CLIENT, now), None);
+        assert_eq!(cookie_for(SECRET, &[0; 12], CLIENT, now), None);
+    }
+
+    #[test]
+    fn test_response_edns() {
+        assert!(EdnsConfig::new(Some(256), None, false).is_err());
+        assert!(EdnsConfig::new(None, Some(String::new()), false).is_err());
+
+        let config = EdnsConfig::new(Some(1232), Some("ns1".to_string()), true).unwrap();
+
+        // requests without EDNS are answered without it.
+        assert!(config.response_edns(None, CLIENT).is_none());
+
+        let mut request = Edns::new();
+        request.set_max_payload(4096);
+        request.set_dnssec_ok(true);
+
+        // without configuration, answers keep the EDNS trust-dns gives them.
+        assert!(EdnsConfig::default()
+            .response_edns(Some(&request), CLIENT)
+            .is_none());
+
+        let edns = config.response_edns(Some(&request), CLIENT).unwrap();
+        assert_eq!(edns.max_payload(), 1232);
+        assert!(edns.dnssec_ok());
+        // NSID and cookies are only sent to clients which ask for them.
+        assert!(edns.option(EdnsCode::NSID).is_none());
+        assert!(edns.option(EdnsCode::Cookie).is_none());
+
+        request.set_option(EdnsOption::Unknown(EdnsCode::NSID.into(), Vec::new()));
+        request.set_option(EdnsOption::Unknown(
+            EdnsCode::Cookie.into(),
+            vec![1, 2, 3, 4, 5, 6, 7, 8],
+        ));
+
+        let edns = config.response_edns(Some(&request), CLIENT).unwrap();
+        assert_eq!(
+            edns.option(EdnsCode::NSID),
+            Some(&EdnsOption::Unknown(EdnsCode::NSID.into(), b"ns1".to_vec()))
+        );
+        match edns.option(EdnsCode::Cookie) {
+            Some(EdnsOption::Unknown(10, cookie)) => {
+                assert_eq!(cookie.len(), 24);
+                assert_eq!(&cookie[..8], &[1, 2, 3, 4, 5, 6, 7, 8]);
+            }
+            option => panic!("unexpected cookie {:?}", option),
+        }
+
+        // answers are no larger than what the client can take.
+        request.set_max_payload(1024);
+        let edns = config.response_edns(Some(&request), CLIENT).unwrap();
+        assert_eq!(edns.max_payload(), 1024);
+        request.set_max_payload(4096);
+
+        // without a size of its own, the client's is advertised back, as trust-dns does.
+        let edns = EdnsConfig::new(None, Some("ns1".to_string()), false)
+            .unwrap()
+            .response_edns(Some(&request), CLIENT)
+            .unwrap();
+        assert_eq!(edns.max_payload(), 4096);
+        assert!(edns.option(EdnsCode::Cookie).is_none());
+
+        // answers over UDP are truncated to the same size, or to 512 bytes without EDNS.
+        assert_eq!(config.max_size(Some(&request)), 1232);
+        assert_eq!(EdnsConfig::default().max_size(Some(&request)), 4096);
+        assert_eq!(config.max_size(None), 512);
+    }
+}
@@ -0,0 +1,158 @@
+/// member filters choosing which members of the network get records.
+use regex::Regex;
+use zerotier_central_api::types::{Member, Network};
+
+use crate::services::{member_tag, tag_enum};
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct MemberFilter {
+    pub include: Vec<String>,
+    pub exclude: Vec<String>,
+    pub authorized_only: bool,
+}
+
+impl MemberFilter {
+    pub fn is_empty(&self) -> bool {
+        self.include.is_empty() && self.exclude.is_empty() && !self.authorized_only
+    }
+
+    // allows tells whether the member gets records.
+    pub fn allows(&self, network: &Network, member: &Member) -> bool {
+        let authorized = member
+            .config
+            .as_ref()
+            .and_then(|config| config.authorized)
+            .unwrap_or(false);
+
+        if self.authorized_only && !authorized {
+            return false;
+        }
+
+        let matches = |pattern: &String| matches(pattern, network, member);
+
+        (self.include.is_empty() || self.include.iter().any(matches))
+            && !self.exclude.iter().any(matches)
+    }
+}
+
+// matches tells whether the member matches the pattern of a filter.
+pub fn matches(pattern: &str, network: &Network, member: &Member) -> bool {
+    if let Some(tag) = pattern.strip_prefix("tag:") {
+        let (name, value) = match tag.split_once('=') {
+            Some((name, value)) => (name, Some(value)),
+            None => (tag, None),
+        };
+
+        return match (member_tag(network, member, name), value) {
+            (Some(tagged), Some(value)) => {
+                value
+                    .parse::<u64>()
+                    .ok()
+                    .or_else(|| tag_enum(network, name, value))
+                    == Some(tagged)
+            }
+            (Some(tagged), None) => tagged != 0,
+            (None, _) => false,
+        };
+    }
+
+    if member
+        .node_id
+        .as_deref()
+        .map_or(false, |node_id| node_id.eq_ignore_ascii_case(pattern))
+    {
+        return true;
+    }
+
+    member
+        .name
+        .as_deref()
+        .map_or(false, |name| glob(pattern).is_match(name.trim()))
+}
+
+// glob turns a pattern with `*` and `?` into a regex matching whole names, regardless of case.
+fn glob(pattern: &str) -> Regex {
+    let pattern = regex::escape(pattern)
+        .replace(r"\*", ".*")
+        .replace(r"\?", ".");
+
+    Regex::new(&format!("(?i)^{}$", pattern)).unwrap()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::MemberFilter;
+    use serde_json::json;
+    use zerotier_central_api::types::{Member, Network};
+
+    #[test]
+    fn test_member_filter() {
+        let network: Network = serde_json::from_value(json!({
+            "id": "1234567891011121",
+            "tagsByName": {
+                "role": { "id": 3000, "enums": { "server": 1, "iot": 2 } },
+            },
+        }))
+        .unwrap();
+
+        let member = |node_id: &str, name: &str, authorized: bool, tags: serde_json::Value| {
+            serde_json::from_value::<Member>(json!({
+                "nodeId": node_id,
+                "name": name,
+                "config": { "authorized": authorized, "tags": tags },
+            }))
+            .unwrap()
+        };
+
+        let web = member("abcdef0123", "web", true, json!([[3000, 1]]));
+        let staging = member("abcdef0124", "Staging-Web", true, json!([]));
+        let camera = member("abcdef0125", "cam01", true, json!([[3000, 2]]));
+        let laptop = member("abcdef0126", "laptop", false, json!([]));
+        let members = [&web, &staging, &camera, &laptop];
+
+        let allowed = |filter: MemberFilter| -> Vec<bool> {
+            members
+                .iter()
+                .map(|member| filter.allows(&network, member))
+                .collect()
+        };
+        let patterns = |patterns: &[&str]| patterns.iter().map(ToString::to_string).collect();
+
+        assert!(MemberFilter::default().is_empty());
+        assert_eq!(allowed(MemberFilter::default()), [true, true, true, true]);
+
+        assert_eq!(
+            allowed(MemberFilter {
+                exclude: patterns(&["staging-*", "tag:role=iot"]),
+                ..Default::default()
+            }),
+            [true, false, false, true]
+        );
+
+        assert_eq!(
+            allowed(MemberFilter {
+                include: patterns(&["tag:role=1", "abcdef0126", "cam??"]),
+                ..Default::default()
+            }),
+            [true, false, true, true]
+        );
+
+        assert_eq!(
+            allowed(MemberFilter {
+                include: patterns(&["tag:role"]),
+                authorized_only: true,
+                ..Default::default()
+            }),
+            [true, false, true, false]
+        );
+
+        // tags the network does not have match nothing.
+        assert_eq!(
+            allowed(MemberFilter {
+                include: patterns(&["tag:owner"]),
+                ..Default::default()
+            }),
+            [false, false, false, false]
+        );
+    }
+}
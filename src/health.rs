@@ -0,0 +1,506 @@
+/// health, readiness and metrics over HTTP for `--health-addr`.
+use std::{
+    collections::BTreeMap,
+    net::SocketAddr,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant, SystemTime},
+};
+
+use serde::{Deserialize, Serialize};
+use tokio::{net::TcpListener, task::JoinHandle};
+use trust_dns_resolver::Name;
+
+use crate::{
+    cache::{CacheStats, ForwardCache},
+    http::{self, Request, Response},
+};
+
+// how many poll intervals the refresh loop can miss before zeronsd is reported unhealthy, with
+// some slack for the retries of a slow Central.
+const MISSED_REFRESHES: u32 = 3;
+const REFRESH_SLACK: Duration = Duration::from_secs(120);
+
+const METRICS_CONTENT_TYPE: &str = "text/plain; version=0.0.4";
+
+#[derive(Default)]
+struct NetworkHealth {
+    network_id: String,
+    domain: String,
+    listening: Vec<SocketAddr>,
+    records: usize,
+    // whether the members are served, from Central or a snapshot.
+    loaded: bool,
+    refreshed: Option<(Instant, Duration)>,
+    polled: Option<SystemTime>,
+    // the error of the last poll of Central, if it failed.
+    central_error: Option<String>,
+    record_limit: Option<usize>,
+    members_left_out: usize,
+    wildcards_dropped: bool,
+    cache: Option<ForwardCache>,
+}
+
+// Health is what is known of the health of a network. It is updated by the refresh loop and the
+// listeners of the network, and read by the HTTP endpoint.
+#[derive(Clone, Default)]
+pub struct Health(Arc<Mutex<NetworkHealth>>);
+
+impl Health {
+    pub fn new(network_id: &str, domain: &Name) -> Self {
+        Self(Arc::new(Mutex::new(NetworkHealth {
+            network_id: network_id.to_string(),
+            domain: domain.to_string(),
+            ..Default::default()
+        })))
+    }
+
+    // refreshed records a pass of the refresh loop, which runs every interval, and what it left
+    // being served.
+    pub fn refreshed(&self, interval: Duration, records: usize, loaded: bool) {
+        let mut health = self.0.lock().unwrap();
+        health.refreshed = Some((Instant::now(), interval));
+        health.records = records;
+        health.loaded = loaded;
+    }
+
+    // polled records a successful poll of Central.
+    pub fn polled(&self) {
+        let mut health = self.0.lock().unwrap();
+        health.polled = Some(SystemTime::now());
+        health.central_error = None;
+    }
+
+    // polled_at records a successful poll of Central by an earlier run, restored from the state
+    // database.
+    pub fn polled_at(&self, at: SystemTime) {
+        self.0.lock().unwrap().polled = Some(at);
+    }
+
+    // failed records a failed poll of Central.
+    pub fn failed(&self, error: &anyhow::Error) {
+        self.0.lock().unwrap().central_error = Some(format!("{:#}", error));
+    }
+
+    // limited records what the record limit left out of the members last configured.
+    pub fn limited(&self, max: usize, members_left_out: usize, wildcards_dropped: bool) {
+        let mut health = self.0.lock().unwrap();
+        health.record_limit = Some(max);
+        health.members_left_out = members_left_out;
+        health.wildcards_dropped = wildcards_dropped;
+    }
+
+    // set_cache makes the metrics report the use of the forwarder cache of the network.
+    pub fn set_cache(&self, cache: ForwardCache) {
+        self.0.lock().unwrap().cache = Some(cache);
+    }
+
+    fn cache_stats(&self) -> Option<CacheStats> {
+        self.0
+            .lock()
+            .unwrap()
+            .cache
+            .as_ref()
+            .map(ForwardCache::stats)
+    }
+
+    // listening records the addresses the network is served on.
+    pub fn listening(&self, mut addrs: Vec<SocketAddr>) {
+        addrs.sort();
+        self.0.lock().unwrap().listening = addrs;
+    }
+
+    fn network_id(&self) -> String {
+        self.0.lock().unwrap().network_id.clone()
+    }
+
+    pub fn status(&self) -> NetworkStatus {
+        let health = self.0.lock().unwrap();
+        let now = Instant::now();
+
+        let live = health.refreshed.map_or(false, |(at, interval)| {
+            now.duration_since(at) <= interval * MISSED_REFRESHES + REFRESH_SLACK
+        });
+
+        NetworkStatus {
+            network_id: health.network_id.clone(),
+            domain: health.domain.clone(),
+            listening: health
+                .listening
+                .iter()
+                .map(|addr| addr.to_string())
+                .collect(),
+            records: health.records,
+            last_poll_seconds: health.polled.and_then(|polled| {
+                SystemTime::now()
+                    .duration_since(polled)
+                    .ok()
+                    .map(|elapsed| elapsed.as_secs())
+            }),
+            central_error: health.central_error.clone(),
+            record_limit: health.record_limit,
+            members_left_out: health.members_left_out,
+            wildcards_dropped: health.wildcards_dropped,
+            live,
+            ready: live && health.loaded && !health.listening.is_empty(),
+        }
+    }
+}
+
+// NetworkStatus is the health of a network, as reported by the endpoint.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct NetworkStatus {
+    pub network_id: String,
+    pub domain: String,
+    pub listening: Vec<String>,
+    pub records: usize,
+    // seconds since Central was last polled successfully, if it ever was.
+    pub last_poll_seconds: Option<u64>,
+    // why the last poll of Central failed, if it did.
+    pub central_error: Option<String>,
+    // the most records members get, and what it left out; see crate::limits.
+    #[serde(default)]
+    pub record_limit: Option<usize>,
+    #[serde(default)]
+    pub members_left_out: usize,
+    #[serde(default)]
+    pub wildcards_dropped: bool,
+    pub live: bool,
+    pub ready: bool,
+}
+
+// Sample is the value of a metric for a network, if it has one.
+type Sample = fn(&NetworkStatus, Option<&CacheStats>) -> Option<f64>;
+
+// the metrics reported, with their types and descriptions.
+const METRICS: &[(&str, &str, &str, Sample)] = &[
+    (
+        "zeronsd_live",
+        "gauge",
+        "Whether the refresh loop of the network is running.",
+        |status, _| Some(status.live as u8 as f64),
+    ),
+    (
+        "zeronsd_ready",
+        "gauge",
+        "Whether the network is listened on and served with its members.",
+        |status, _| Some(status.ready as u8 as f64),
+    ),
+    (
+        "zeronsd_records",
+        "gauge",
+        "Records served for the network, in all of its zones.",
+        |status, _| Some(status.records as f64),
+    ),
+    (
+        "zeronsd_record_limit",
+        "gauge",
+        "The most records the members of the network get.",
+        |status, _| status.record_limit.map(|max| max as f64),
+    ),
+    (
+        "zeronsd_members_left_out",
+        "gauge",
+        "Members given no records to stay within the record limit.",
+        |status, _| Some(status.members_left_out as f64),
+    ),
+    (
+        "zeronsd_wildcards_dropped",
+        "gauge",
+        "Whether the wildcard names of members were dropped to stay within the record limit.",
+        |status, _| Some(status.wildcards_dropped as u8 as f64),
+    ),
+    (
+        "zeronsd_cache_entries",
+        "gauge",
+        "Answers held by the forwarder cache.",
+        |_, cache| cache.map(|cache| cache.entries as f64),
+    ),
+    (
+        "zeronsd_cache_capacity",
+        "gauge",
+        "The most answers the forwarder cache holds.",
+        |_, cache| cache.map(|cache| cache.capacity as f64),
+    ),
+    (
+        "zeronsd_cache_hits_total",
+        "counter",
+        "Lookups answered from the forwarder cache.",
+        |_, cache| cache.map(|cache| cache.hits as f64),
+    ),
+    (
+        "zeronsd_cache_misses_total",
+        "counter",
+        "Lookups the forwarder cache could not answer.",
+        |_, cache| cache.map(|cache| cache.misses as f64),
+    ),
+    (
+        "zeronsd_cache_evictions_total",
+        "counter",
+        "Answers dropped from the forwarder cache to make room for others.",
+        |_, cache| cache.map(|cache| cache.evictions as f64),
+    ),
+];
+
+#[derive(Serialize)]
+struct Report {
+    status: &'static str,
+    networks: Vec<NetworkStatus>,
+}
+
+// Registry holds the health of every network served by the process, by network ID.
+#[derive(Clone, Default)]
+pub struct Registry(Arc<Mutex<BTreeMap<String, Health>>>);
+
+impl Registry {
+    // register adds the health of a network, in place of any the network had, as when it is
+    // started again under a new domain.
+    pub fn register(&self, health: Health) {
+        self.0.lock().unwrap().insert(health.network_id(), health);
+    }
+
+    pub fn statuses(&self) -> Vec<NetworkStatus> {
+        self.0
+            .lock()
+            .unwrap()
+            .values()
+            .map(Health::status)
+            .collect()
+    }
+
+    // metrics are the metrics of every network, in the Prometheus text format.
+    pub fn metrics(&self) -> String {
+        let networks: Vec<_> = self
+            .0
+            .lock()
+            .unwrap()
+            .values()
+            .map(|health| (health.status(), health.cache_stats()))
+            .collect();
+
+        let mut out = String::new();
+
+        for (name, kind, help, sample) in METRICS {
+            let samples: Vec<_> = networks
+                .iter()
+                .filter_map(|(status, cache)| {
+                    Some((&status.network_id, sample(status, cache.as_ref())?))
+                })
+                .collect();
+
+            if samples.is_empty() {
+                continue;
+            }
+
+            out.push_str(&format!(
+                "# HELP {} {}\n# TYPE {} {}\n",
+                name, help, name, kind
+            ));
+            for (network_id, value) in samples {
+                out.push_str(&format!(
+                    "{}{{network_id=\"{}\"}} {}\n",
+                    name, network_id, value
+                ));
+            }
+        }
+
+        out
+    }
+}
+
+// listen binds the address and answers health checks on it in the background.
+pub async fn listen(addr: SocketAddr, registry: Registry) -> Result<JoinHandle<()>, anyhow::Error> {
+    let listener = TcpListener::bind(addr)
+        .await
+        .map_err(|e| anyhow::anyhow!("could not listen for health checks on {}: {}", addr, e))?;
+
+    tracing::info!("Answering health checks on http://{}", addr);
+
+    Ok(tokio::spawn(async move {
+        loop {
+            match listener.accept().await {
+                Ok((stream, peer)) => {
+                    let registry = registry.clone();
+                    tokio::spawn(async move {
+                        let answer = move |request| async move { respond(&request, &registry) };
+
+                        if let Err(e) = http::handle(stream, answer).await {
+                            tracing::debug!("Health check from {} failed: {}", peer, e);
+                        }
+                    });
+                }
+                Err(e) => tracing::warn!("Could not accept health check: {}", e),
+            }
+        }
+    }))
+}
+
+// respond answers a health check.
+fn respond(request: &Request, registry: &Registry) -> Response {
+    if request.method != "GET" && request.method != "HEAD" {
+        return Response::error(405, "method not allowed");
+    }
+
+    if request.path == "/metrics" {
+        return Response::text(200, METRICS_CONTENT_TYPE, registry.metrics());
+    }
+
+    let networks = registry.statuses();
+    let healthy = match request.path.as_str() {
+        "/healthz" => networks.iter().all(|network| network.live),
+        "/readyz" => !networks.is_empty() && networks.iter().all(|network| network.ready),
+        _ => return Response::error(404, "not found"),
+    };
+
+    if healthy {
+        Response::json(
+            200,
+            &Report {
+                status: "ok",
+                networks,
+            },
+        )
+    } else {
+        Response::json(
+            503,
+            &Report {
+                status: "unavailable",
+                networks,
+            },
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{listen, Health, Registry};
+    use crate::cache::ForwardCache;
+    use std::{str::FromStr, time::Duration};
+    use tokio::{
+        io::{AsyncReadExt, AsyncWriteExt},
+        net::TcpStream,
+    };
+    use trust_dns_resolver::Name;
+
+    #[test]
+    fn test_registry() {
+        let registry = Registry::default();
+        let health = Health::new("8056c2e21c000001", &Name::from_str("home.arpa.").unwrap());
+        registry.register(health.clone());
+
+        let status = &registry.statuses()[0];
+        assert_eq!(status.network_id, "8056c2e21c000001");
+        assert_eq!(status.domain, "home.arpa.");
+        assert!(!status.live);
+        assert!(!status.ready);
+        assert_eq!(status.last_poll_seconds, None);
+
+        // live once refreshed, but only ready once listening with the members loaded.
+        health.refreshed(Duration::from_secs(30), 3, false);
+        health.listening(vec!["10.147.20.1:53".parse().unwrap()]);
+        let status = &registry.statuses()[0];
+        assert!(status.live);
+        assert!(!status.ready);
+        assert_eq!(status.records, 3);
+        assert_eq!(status.listening, vec!["10.147.20.1:53".to_string()]);
+
+        health.failed(&anyhow::anyhow!("connection refused"));
+        assert_eq!(
+            registry.statuses()[0].central_error.as_deref(),
+            Some("connection refused")
+        );
+
+        health.polled();
+        health.refreshed(Duration::from_secs(30), 5, true);
+        let status = &registry.statuses()[0];
+        assert!(status.ready);
+        assert_eq!(status.last_poll_seconds, Some(0));
+        assert_eq!(status.central_error, None);
+        assert_eq!(status.record_limit, None);
+
+        // the cache is only reported once there is one, and the record limit once it is set.
+        let metrics = registry.metrics();
+        assert!(metrics.contains("zeronsd_records{network_id=\"8056c2e21c000001\"} 5\n"));
+        assert!(metrics.contains("# TYPE zeronsd_ready gauge\n"));
+        assert!(!metrics.contains("zeronsd_record_limit"));
+        assert!(!metrics.contains("zeronsd_cache"));
+
+        health.limited(4, 1, true);
+        health.set_cache(ForwardCache::new(100, false));
+        let status = &registry.statuses()[0];
+        assert_eq!(status.record_limit, Some(4));
+        assert_eq!(status.members_left_out, 1);
+        assert!(status.wildcards_dropped);
+
+        let metrics = registry.metrics();
+        assert!(metrics.contains("zeronsd_record_limit{network_id=\"8056c2e21c000001\"} 4\n"));
+        assert!(metrics.contains("zeronsd_members_left_out{network_id=\"8056c2e21c000001\"} 1\n"));
+        assert!(metrics.contains("zeronsd_cache_capacity{network_id=\"8056c2e21c000001\"} 100\n"));
+
+        // a network started again replaces its old health.
+        registry.register(Health::new(
+            "8056c2e21c000001",
+            &Name::from_str("example.com.").unwrap(),
+        ));
+        let statuses = registry.statuses();
+        assert_eq!(statuses.len(), 1);
+        assert_eq!(statuses[0].domain, "example.com.");
+    }
+
+    async fn get(addr: std::net::SocketAddr, request: &str) -> String {
+        let mut stream = TcpStream::connect(addr).await.unwrap();
+        stream.write_all(request.as_bytes()).await.unwrap();
+
+        let mut response = String::new();
+        stream.read_to_string(&mut response).await.unwrap();
+        response
+    }
+
+    #[tokio::test]
+    async fn test_health_endpoint() {
+        let registry = Registry::default();
+
+        // there is no way to learn the port picked for 0 from listen, so pick one first.
+        let addr = std::net::TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap();
+        let server = listen(addr, registry.clone()).await.unwrap();
+
+        // nothing is ready before any network is registered.
+        let response = get(addr, "GET /readyz HTTP/1.1\r\nHost: localhost\r\n\r\n").await;
+        assert!(response.starts_with("HTTP/1.1 503"), "{}", response);
+
+        let health = Health::new("8056c2e21c000001", &Name::from_str("home.arpa.").unwrap());
+        health.refreshed(Duration::from_secs(30), 2, true);
+        health.listening(vec!["10.147.20.1:53".parse().unwrap()]);
+        registry.register(health);
+
+        let response = get(addr, "GET /readyz HTTP/1.1\r\nHost: localhost\r\n\r\n").await;
+        assert!(response.starts_with("HTTP/1.1 200"), "{}", response);
+        assert!(response.contains(r#""network_id":"8056c2e21c000001""#));
+        assert!(response.contains(r#""records":2"#));
+
+        let response = get(addr, "GET /healthz HTTP/1.1\r\n\r\n").await;
+        assert!(response.starts_with("HTTP/1.1 200"), "{}", response);
+
+        let response = get(addr, "HEAD /healthz HTTP/1.1\r\n\r\n").await;
+        assert!(response.ends_with("\r\n\r\n"), "{}", response);
+
+        let response = get(addr, "GET /metrics HTTP/1.1\r\n\r\n").await;
+        assert!(response.starts_with("HTTP/1.1 200"), "{}", response);
+        assert!(
+            response.contains("Content-Type: text/plain"),
+            "{}",
+            response
+        );
+        assert!(response.contains("zeronsd_ready{network_id=\"8056c2e21c000001\"} 1\n"));
+
+        let response = get(addr, "GET /metricz HTTP/1.1\r\n\r\n").await;
+        assert!(response.starts_with("HTTP/1.1 404"), "{}", response);
+
+        let response = get(addr, "POST /healthz HTTP/1.1\r\n\r\n").await;
+        assert!(response.starts_with("HTTP/1.1 405"), "{}", response);
+
+        server.abort();
+    }
+}
@@ -0,0 +1,187 @@
+/// webhooks sent to `--record-webhook` whenever a zone served changes.
+use std::sync::Arc;
+
+use anyhow::anyhow;
+use serde::{Deserialize, Serialize};
+use trust_dns_server::client::rr::{LowerName, Record};
+
+use crate::{admin::RecordEntry, central::RetryPolicy, webhook};
+
+// RecordChange is the body of a record-change webhook: what changed in a zone between two serials.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RecordChange {
+    pub zone: String,
+    pub previous_serial: u32,
+    pub serial: u32,
+    pub added: Vec<RecordEntry>,
+    pub removed: Vec<RecordEntry>,
+}
+
+impl RecordChange {
+    pub fn new(
+        zone: &LowerName,
+        previous_serial: u32,
+        serial: u32,
+        added: &[Record],
+        removed: &[Record],
+    ) -> Self {
+        let entries =
+            |records: &[Record]| records.iter().map(|r| RecordEntry::new(zone, r)).collect();
+
+        Self {
+            zone: zone.to_string(),
+            previous_serial,
+            serial,
+            added: entries(added),
+            removed: entries(removed),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.removed.is_empty()
+    }
+}
+
+// RecordHooks are the URLs record changes are sent to. It is shared by every zone of a network.
+#[derive(Clone, Default)]
+pub struct RecordHooks {
+    urls: Arc<Vec<String>>,
+    secret: Option<Arc<Vec<u8>>>,
+    client: reqwest::Client,
+}
+
+impl RecordHooks {
+    pub fn new(urls: Vec<String>, secret: Option<Vec<u8>>) -> Result<Self, anyhow::Error> {
+        for url in &urls {
+            reqwest::Url::parse(url)
+                .map_err(|e| anyhow!("Invalid record webhook {}: {}", url, e))?;
+        }
+
+        Ok(Self {
+            urls: Arc::new(urls),
+            secret: secret.map(Arc::new),
+            client: reqwest::Client::builder()
+                .timeout(std::time::Duration::from_secs(10))
+                .build()?,
+        })
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.urls.is_empty()
+    }
+
+    // send delivers the change to every URL in the background.
+    pub fn send(&self, change: RecordChange) {
+        if self.is_empty() || change.is_empty() {
+            return;
+        }
+
+        let body = match serde_json::to_vec(&change) {
+            Ok(body) => body,
+            Err(e) => {
+                tracing::error!("Could not encode the changes of {}: {}", change.zone, e);
+                return;
+            }
+        };
+
+        let signature = match self
+            .secret
+            .as_deref()
+            .map(|secret| webhook::sign(secret, &body))
+        {
+            Some(Ok(signature)) => Some(format!("sha256={}", hex::encode(signature))),
+            Some(Err(e)) => {
+                tracing::error!("Could not sign the changes of {}: {}", change.zone, e);
+                return;
+            }
+            None => None,
+        };
+
+        for url in self.urls.iter().cloned() {
+            let client = self.client.clone();
+            let body = body.clone();
+            let signature = signature.clone();
+
+            tokio::spawn(async move {
+                let result = RetryPolicy::default()
+                    .run(&format!("Sending record changes to {}", url), || {
+                        deliver(&client, &url, body.clone(), signature.as_deref())
+                    })
+                    .await;
+
+                if let Err(e) = result {
+                    tracing::warn!("Could not send record changes to {}: {:#}", url, e);
+                }
+            });
+        }
+    }
+}
+
+async fn deliver(
+    client: &reqwest::Client,
+    url: &str,
+    body: Vec<u8>,
+    signature: Option<&str>,
+) -> Result<(), anyhow::Error> {
+    let mut request = client
+        .post(url)
+        .header(reqwest::header::CONTENT_TYPE, "application/json")
+        .body(body);
+
+    if let Some(signature) = signature {
+        request = request.header("X-Hub-Signature-256", signature);
+    }
+
+    request.send().await?.error_for_status()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{RecordChange, RecordHooks};
+    use crate::{http, webhook};
+    use std::{net::Ipv4Addr, str::FromStr};
+    use tokio::net::TcpListener;
+    use trust_dns_server::client::rr::{LowerName, Name, RData, Record};
+
+    #[tokio::test]
+    async fn test_record_hooks() {
+        assert!(RecordHooks::new(vec!["not a url".to_string()], None).is_err());
+
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/changes", listener.local_addr().unwrap());
+        let hooks = RecordHooks::new(vec![url], Some(b"s3cret".to_vec())).unwrap();
+
+        let zone = LowerName::from(Name::from_str("home.arpa.").unwrap());
+        let added = Record::from_rdata(
+            Name::from_str("islay.home.arpa.").unwrap(),
+            60,
+            RData::A(Ipv4Addr::new(10, 147, 20, 5)),
+        );
+
+        // nothing is sent for changes to the records which are not reported.
+        assert!(RecordChange::new(&zone, 1, 2, &[], &[]).is_empty());
+
+        hooks.send(RecordChange::new(&zone, 1, 2, &[added], &[]));
+
+        let (mut stream, _) = listener.accept().await.unwrap();
+        let request = http::read_request(&mut stream).await.unwrap();
+        assert_eq!(request.method, "POST");
+        assert_eq!(request.path, "/changes");
+
+        let signature = hex::encode(webhook::sign(b"s3cret", &request.body).unwrap());
+        assert_eq!(
+            request.header("x-hub-signature-256"),
+            Some(format!("sha256={}", signature).as_str())
+        );
+
+        let change: RecordChange = serde_json::from_slice(&request.body).unwrap();
+        assert_eq!(change.zone, "home.arpa.");
+        assert_eq!((change.previous_serial, change.serial), (1, 2));
+        assert_eq!(change.added.len(), 1);
+        assert_eq!(change.added[0].name, "islay.home.arpa.");
+        assert_eq!(change.added[0].record_type, "A");
+        assert_eq!(change.added[0].data, "10.147.20.5");
+        assert!(change.removed.is_empty());
+    }
+}
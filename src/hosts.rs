@@ -1,11 +1,12 @@
 /// functionality to deal with the handling of /etc/hosts formatted files
 use std::{
-    collections::{hash_map::Entry, HashMap},
+    collections::{hash_map::Entry, HashMap, HashSet},
     net::IpAddr,
-    path::PathBuf,
+    path::{Path, PathBuf},
     str::FromStr,
+    time::{Duration, SystemTime},
 };
-use tracing::warn;
+use tracing::{info, warn};
 use trust_dns_server::client::rr::Name;
 
 use crate::traits::ToHostname;
@@ -87,3 +88,120 @@ pub fn parse_hosts(
 
     Ok(input)
 }
+
+// how often the hosts files are checked for changes between polls of Central.
+const HOSTS_CHECK_INTERVAL: Duration = Duration::from_secs(2);
+
+/// Expands the list of hosts paths into the files they name. Directories (e.g. a `hosts.d`) are
+/// expanded into the regular files they contain, in lexical order; hidden files are skipped.
+pub fn expand_hosts_paths(paths: &[PathBuf]) -> Result<Vec<PathBuf>, std::io::Error> {
+    let mut files = Vec::new();
+
+    for path in paths {
+        if path.is_dir() {
+            let mut entries = Vec::new();
+
+            for entry in std::fs::read_dir(path)? {
+                let entry = entry?;
+                let hidden = entry.file_name().to_string_lossy().starts_with('.');
+
+                if !hidden && entry.file_type()?.is_file() {
+                    entries.push(entry.path());
+                }
+            }
+
+            entries.sort();
+            files.append(&mut entries);
+        } else {
+            files.push(path.clone());
+        }
+    }
+
+    Ok(files)
+}
+
+/// Moves the names of a parsed hosts file from one domain to another, so that the same entries can
+/// be served under an alias of the domain they were parsed for. Names outside of `from` are
+/// dropped.
+pub fn rebase_hosts(hosts: &HostsFile, from: &Name, to: &Name) -> HostsFile {
+    let mut rebased: HostsFile = HashMap::new();
+
+    for (ip, names) in hosts {
+        for name in names {
+            if !from.zone_of(name) || name.num_labels() <= from.num_labels() {
+                continue;
+            }
+
+            let labels = (name.num_labels() - from.num_labels()) as usize;
+
+            match Name::from_labels(name.iter().take(labels)).and_then(|n| n.append_domain(to)) {
+                Ok(name) => rebased.entry(*ip).or_default().push(name),
+                Err(e) => warn!("Could not move {} to {}: {}", name, to, e),
+            }
+        }
+    }
+
+    rebased
+}
+
+/// Parses and merges every hosts file named by `paths`; see `expand_hosts_paths`.
+pub fn parse_hosts_files(
+    paths: &[PathBuf],
+    domain_name: Name,
+) -> Result<HostsFile, std::io::Error> {
+    let mut input: HostsFile = HashMap::new();
+
+    for file in expand_hosts_paths(paths)? {
+        for (ip, mut names) in parse_hosts(Some(file), domain_name.clone())? {
+            input.entry(ip).or_default().append(&mut names);
+        }
+    }
+
+    Ok(input)
+}
+
+/// Returns the modification times of the hosts files and any directories containing them. The
+/// result changes whenever a file is edited, added or removed.
+pub fn hosts_modified(paths: &[PathBuf]) -> Vec<Option<SystemTime>> {
+    let mut times: Vec<Option<SystemTime>> = paths.iter().map(|p| modified(p)).collect();
+
+    if let Ok(files) = expand_hosts_paths(paths) {
+        times.append(&mut files.iter().map(|p| modified(p)).collect());
+    }
+
+    times
+}
+
+fn modified(path: &Path) -> Option<SystemTime> {
+    std::fs::metadata(path).and_then(|m| m.modified()).ok()
+}
+
+/// Resolves once the result of `hosts_modified` differs from `last`. Never resolves if no hosts
+/// files are configured.
+pub async fn wait_for_hosts_change(paths: Vec<PathBuf>, last: Vec<Option<SystemTime>>) {
+    if paths.is_empty() {
+        return std::future::pending().await;
+    }
+
+    loop {
+        tokio::time::sleep(HOSTS_CHECK_INTERVAL).await;
+
+        if hosts_modified(&paths) != last {
+            return;
+        }
+    }
+}
+
+/// Logs the names which were added to or removed from the hosts file between two parses.
+pub fn log_hosts_diff(old: &HostsFile, new: &HostsFile) {
+    let old_names: HashSet<&Name> = old.values().flatten().collect();
+    let new_names: HashSet<&Name> = new.values().flatten().collect();
+
+    for name in new_names.difference(&old_names) {
+        info!("Hosts file added {}", name);
+    }
+
+    for name in old_names.difference(&new_names) {
+        info!("Hosts file removed {}", name);
+    }
+}
@@ -0,0 +1,270 @@
+/// the little HTTP/1.1 spoken by the endpoints of zeronsd and the mock of Central.
+use std::{future::Future, time::Duration};
+
+use anyhow::anyhow;
+use serde::Serialize;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+
+// the longest request read, headers and body included.
+const MAX_REQUEST_SIZE: usize = 65536;
+
+// how long a client has to send its request.
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(5);
+
+const JSON: &str = "application/json";
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Request {
+    pub method: String,
+    // the path, without the query string.
+    pub path: String,
+    // the headers, with their names in lowercase.
+    pub headers: Vec<(String, String)>,
+    pub body: Vec<u8>,
+}
+
+impl Request {
+    // header is the value of the first header of the name, given in lowercase.
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(header, _)| header == name)
+            .map(|(_, value)| value.as_str())
+    }
+
+    // segments are the parts of the path between slashes.
+    pub fn segments(&self) -> Vec<&str> {
+        self.path.split('/').filter(|s| !s.is_empty()).collect()
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Response {
+    pub status: u16,
+    pub content_type: &'static str,
+    pub body: String,
+}
+
+impl Response {
+    pub fn json(status: u16, body: &impl Serialize) -> Self {
+        Self {
+            status,
+            content_type: JSON,
+            body: serde_json::to_string(body).unwrap_or_default(),
+        }
+    }
+
+    pub fn text(status: u16, content_type: &'static str, body: String) -> Self {
+        Self {
+            status,
+            content_type,
+            body,
+        }
+    }
+
+    pub fn error(status: u16, message: impl std::fmt::Display) -> Self {
+        Self::json(status, &serde_json::json!({ "error": message.to_string() }))
+    }
+}
+
+fn reason(status: u16) -> &'static str {
+    match status {
+        200 => "OK",
+        201 => "Created",
+        202 => "Accepted",
+        400 => "Bad Request",
+        401 => "Unauthorized",
+        404 => "Not Found",
+        405 => "Method Not Allowed",
+        413 => "Payload Too Large",
+        500 => "Internal Server Error",
+        503 => "Service Unavailable",
+        _ => "",
+    }
+}
+
+// handle reads the one request of a connection and sends the answer respond makes for it.
+pub async fn handle<S, F, Fut>(mut stream: S, respond: F) -> Result<(), anyhow::Error>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+    F: FnOnce(Request) -> Fut,
+    Fut: Future<Output = Response>,
+{
+    let request = tokio::time::timeout(REQUEST_TIMEOUT, read_request(&mut stream)).await??;
+    let head = request.method == "HEAD";
+    let response = respond(request).await;
+
+    let mut out = format!(
+        "HTTP/1.1 {} {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+        response.status,
+        reason(response.status),
+        response.content_type,
+        response.body.len()
+    );
+
+    if !head {
+        out.push_str(&response.body);
+    }
+
+    stream.write_all(out.as_bytes()).await?;
+    stream.shutdown().await?;
+    Ok(())
+}
+
+// read_request reads the request line, the headers and, if it has one, the body.
+pub async fn read_request<S: AsyncRead + Unpin>(stream: &mut S) -> Result<Request, anyhow::Error> {
+    let mut buf = Vec::new();
+    let mut chunk = [0; 4096];
+
+    let header_end = loop {
+        if let Some(end) = buf.windows(4).position(|w| w == b"\r\n\r\n") {
+            break end + 4;
+        }
+
+        if buf.len() > MAX_REQUEST_SIZE {
+            return Err(anyhow!("request too large"));
+        }
+
+        match stream.read(&mut chunk).await? {
+            0 => return Err(anyhow!("connection closed before the end of the request")),
+            n => buf.extend_from_slice(&chunk[..n]),
+        }
+    };
+
+    let head = String::from_utf8_lossy(&buf[..header_end]).into_owned();
+    let mut lines = head.lines();
+
+    let mut parts = lines.next().unwrap_or_default().split_whitespace();
+    let (method, target) = match (parts.next(), parts.next()) {
+        (Some(method), Some(target)) => (method.to_string(), target),
+        _ => return Err(anyhow!("malformed request line")),
+    };
+
+    let headers: Vec<(String, String)> = lines
+        .filter_map(|line| line.split_once(':'))
+        .map(|(name, value)| (name.trim().to_lowercase(), value.trim().to_string()))
+        .collect();
+
+    let length = headers
+        .iter()
+        .find(|(name, _)| name == "content-length")
+        .map(|(_, value)| value.parse::<usize>())
+        .transpose()
+        .map_err(|_| anyhow!("invalid Content-Length"))?
+        .unwrap_or(0);
+
+    if header_end + length > MAX_REQUEST_SIZE {
+        return Err(anyhow!("request too large"));
+    }
+
+    let mut body = buf.split_off(header_end);
+    while body.len() < length {
+        match stream.read(&mut chunk).await? {
+            0 => return Err(anyhow!("connection closed before the end of the body")),
+            n => body.extend_from_slice(&chunk[..n]),
+        }
+    }
+    body.truncate(length);
+
+    Ok(Request {
+        method,
+        path: target.split('?').next().unwrap_or_default().to_string(),
+        headers,
+        body,
+    })
+}
+
+// get asks for the path over the connection and reads the answer.
+pub async fn get<S>(mut stream: S, path: &str) -> Result<Response, anyhow::Error>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    let request = format!(
+        "GET {} HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n",
+        path
+    );
+    stream.write_all(request.as_bytes()).await?;
+
+    let mut raw = Vec::new();
+    tokio::time::timeout(REQUEST_TIMEOUT, stream.read_to_end(&mut raw)).await??;
+    let raw = String::from_utf8_lossy(&raw);
+
+    let (head, body) = raw
+        .split_once("\r\n\r\n")
+        .ok_or_else(|| anyhow!("malformed response"))?;
+    let status = head
+        .split_whitespace()
+        .nth(1)
+        .and_then(|status| status.parse().ok())
+        .ok_or_else(|| anyhow!("malformed status line"))?;
+
+    // only JSON is ever asked for.
+    Ok(Response {
+        status,
+        content_type: JSON,
+        body: body.to_string(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{get, handle, read_request, Request, Response};
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    #[tokio::test]
+    async fn test_read_request() {
+        let (mut client, mut server) = tokio::io::duplex(1024);
+
+        client
+            .write_all(b"POST /networks/abc/records?x=1 HTTP/1.1\r\nHost: localhost\r\ncontent-length: 11\r\n\r\n{\"a\": true}")
+            .await
+            .unwrap();
+
+        let request = read_request(&mut server).await.unwrap();
+        assert_eq!(
+            request,
+            Request {
+                method: "POST".to_string(),
+                path: "/networks/abc/records".to_string(),
+                headers: vec![
+                    ("host".to_string(), "localhost".to_string()),
+                    ("content-length".to_string(), "11".to_string()),
+                ],
+                body: b"{\"a\": true}".to_vec(),
+            }
+        );
+        assert_eq!(request.segments(), vec!["networks", "abc", "records"]);
+        assert_eq!(request.header("host"), Some("localhost"));
+        assert_eq!(request.header("x-signature"), None);
+
+        let (mut client, mut server) = tokio::io::duplex(1024);
+        client.write_all(b"nonsense\r\n\r\n").await.unwrap();
+        assert!(read_request(&mut server).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_handle() {
+        let (mut client, server) = tokio::io::duplex(1024);
+        client.write_all(b"GET /x HTTP/1.1\r\n\r\n").await.unwrap();
+
+        handle(server, |request| async move {
+            Response::json(200, &serde_json::json!({ "path": request.path }))
+        })
+        .await
+        .unwrap();
+
+        let mut response = String::new();
+        client.read_to_string(&mut response).await.unwrap();
+        assert!(response.starts_with("HTTP/1.1 200 OK\r\n"), "{}", response);
+        assert!(response.ends_with(r#"{"path":"/x"}"#), "{}", response);
+
+        let (client, server) = tokio::io::duplex(1024);
+        let server = tokio::spawn(handle(server, |request| async move {
+            Response::error(404, request.path)
+        }));
+
+        let response = get(client, "/networks").await.unwrap();
+        assert_eq!(response, Response::error(404, "/networks"));
+        server.await.unwrap().unwrap();
+    }
+}
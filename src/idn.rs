@@ -0,0 +1,53 @@
+/// internationalized names (IDNA, RFC 5891) of members and hosts entries.
+use trust_dns_server::client::rr::{LowerName, Name};
+
+// to_a_labels encodes the labels of the name which are not ASCII as punycode. Names which are ASCII
+// already, or which are not valid UTF-8 or IDNA, give None.
+pub fn to_a_labels(name: &LowerName) -> Option<LowerName> {
+    let name = Name::from(name.clone());
+
+    if name.iter().all(<[u8]>::is_ascii) {
+        return None;
+    }
+
+    let labels = name
+        .iter()
+        .map(std::str::from_utf8)
+        .collect::<Result<Vec<_>, _>>()
+        .ok()?;
+
+    Name::from_labels(labels).ok().map(LowerName::from)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::to_a_labels;
+    use std::str::FromStr;
+    use trust_dns_server::client::rr::{LowerName, Name};
+
+    #[test]
+    fn test_to_a_labels() {
+        let raw = |labels: &[&[u8]]| LowerName::from(Name::from_labels(labels.to_vec()).unwrap());
+
+        assert_eq!(
+            to_a_labels(&raw(&["Büro".as_bytes(), b"home", b"arpa"])),
+            Some(LowerName::from_str("xn--bro-hoa.home.arpa.").unwrap())
+        );
+        assert_eq!(
+            to_a_labels(&raw(&["münchen".as_bytes(), "büro".as_bytes(), b"lab"])),
+            Some(LowerName::from_str("xn--mnchen-3ya.xn--bro-hoa.lab.").unwrap())
+        );
+
+        // names in punycode, or in ASCII anyway, are queried as they are.
+        assert_eq!(
+            to_a_labels(&LowerName::from_str("xn--bro-hoa.home.arpa.").unwrap()),
+            None
+        );
+        assert_eq!(
+            to_a_labels(&LowerName::from_str("islay.home.arpa.").unwrap()),
+            None
+        );
+
+        assert_eq!(to_a_labels(&raw(&[b"\xff\xfe", b"home", b"arpa"])), None);
+    }
+}
@@ -1,41 +1,193 @@
 use std::{
     collections::{hash_map::Entry, HashMap},
-    path::PathBuf,
+    net::{IpAddr, SocketAddr},
+    path::{Path, PathBuf},
     str::FromStr,
-    time::Duration,
+    sync::Arc,
+    time::{Duration, UNIX_EPOCH},
 };
 
 use anyhow::anyhow;
 use ipnetwork::IpNetwork;
-use serde::{Deserialize, Serialize};
-use tracing::{info, warn};
+use serde::{Deserialize, Deserializer, Serialize};
+use tokio::{
+    sync::{oneshot, watch},
+    task::JoinHandle,
+};
+use tracing::{info, info_span, warn, Instrument};
+
+use trust_dns_server::client::rr::{LowerName, Name};
 
-use openssl::{pkey::PKey, stack::Stack, x509::X509};
+use openssl::{
+    pkey::{PKey, Private},
+    x509::X509,
+};
 
 use crate::{
+    acl::{AclAction, QueryAcl},
     addresses::*,
-    authority::{find_members, RecordAuthority, ZTAuthority},
+    admin::Control,
+    authority::{find_members, RecordAuthority, Reload, SoaConfig, ZTAuthority, LAST_POLL},
+    blocklist::{BlockAction, Blocklist},
+    cache::{self, ForwardCache},
+    central::RetryPolicy,
+    classless::{Classless, ReverseZone},
+    cluster::Cluster,
+    collisions::CollisionPolicy,
+    controller::{
+        Central, CentralConfig, CentralFallback, Controller, LocalController, MemberSource,
+        MembersFile,
+    },
+    dnssec::DnssecConfig,
+    dnstap::Dnstap,
+    edns::EdnsConfig,
+    filter::MemberFilter,
+    health::Health,
+    hooks::RecordHooks,
+    limits::RecordEviction,
+    mock::{MockCentral, MockServer},
+    order::{AnswerOrder, AnswerOrdering},
+    peers::Peers,
+    querylog::{self, QueryLog},
+    records::{deserialize_records, parse_name, parse_records, Records},
+    replication::Primary,
+    rpz::{Rpz, RpzConfig},
     server::*,
+    snapshot::Snapshot,
+    store::{self, Saved, Store},
     traits::ToPointerSOA,
+    tsig::{self, TsigKey},
+    update::DynamicZone,
+    upstream::Upstreams,
     utils::*,
+    views::{View, ViewZones, Views},
+    webhook,
 };
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
 pub struct Launcher {
     pub domain: Option<String>,
-    pub hosts: Option<PathBuf>,
+    pub auto_domain: bool,
+    pub domain_aliases: Vec<String>,
+    #[serde(deserialize_with = "one_or_many")]
+    pub hosts: Vec<PathBuf>,
     pub secret: Option<PathBuf>,
     pub token: Option<PathBuf>,
+    pub token_stdin: bool,
+    pub token_keychain: bool,
+    pub insecure_token_permissions: bool,
     pub chain_cert: Option<PathBuf>,
     pub tls_cert: Option<PathBuf>,
     pub tls_key: Option<PathBuf>,
+    pub doh_port: Option<u16>,
+    pub doh_hostname: Option<String>,
     pub wildcard: bool,
+    pub wildcard_members: Vec<String>,
+    pub name_policy: Option<NamePolicy>,
+    pub name_collisions: Option<CollisionPolicy>,
+    pub include_members: Vec<String>,
+    pub exclude_members: Vec<String>,
+    pub authorized_only: bool,
+    pub node_id_names: bool,
+    pub all_ptr_names: bool,
+    pub ip_names: bool,
+    pub classless_reverse: bool,
+    pub reverse_zones: Vec<ReverseZone>,
+    pub member_txt: bool,
+    pub max_records: Option<usize>,
+    pub record_eviction: Option<RecordEviction>,
+    pub poll_interval: Option<u64>,
+    pub ttl: Option<u32>,
+    pub clear_dns_on_exit: bool,
+    pub no_update_central: bool,
+    pub central_dns_servers: Vec<IpAddr>,
+    pub peers: Vec<String>,
+    pub wait_for_network: Option<u64>,
+    pub state_dir: Option<PathBuf>,
+    pub state_db: Option<PathBuf>,
+    pub listen_port: Option<u16>,
+    pub listen_addrs: Vec<IpAddr>,
+    pub workers: Option<usize>,
+    pub worker_threads: Option<usize>,
+    pub max_blocking_threads: Option<usize>,
+    pub query_timeout: Option<u64>,
+    pub tcp_timeout: Option<u64>,
+    pub allow_transfer: Vec<IpNetwork>,
+    pub notify: Vec<String>,
+    pub upstreams: Vec<String>,
+    pub dnssec_validation: bool,
+    pub qname_minimization: bool,
+    pub cache: bool,
+    pub cache_size: Option<usize>,
+    pub serve_stale: bool,
+    pub edns_buffer_size: Option<u16>,
+    pub nsid: Option<String>,
+    pub dns_cookies: bool,
+    pub minimal_any: bool,
+    pub answer_order: Option<AnswerOrder>,
+    pub views: Vec<View>,
+    pub query_acl: Option<AclAction>,
+    pub query_log: Option<PathBuf>,
+    pub query_log_size: Option<u64>,
+    pub dnstap: Option<PathBuf>,
+    pub health_addr: Option<SocketAddr>,
+    pub admin_socket: Option<PathBuf>,
+    pub admin_addr: Option<SocketAddr>,
+    pub webhook_addr: Option<SocketAddr>,
+    pub webhook_secret: Option<PathBuf>,
+    pub record_webhooks: Vec<String>,
+    pub record_webhook_secret: Option<PathBuf>,
+    pub replication_addr: Option<SocketAddr>,
+    pub replication_secret: Option<PathBuf>,
+    pub cluster: Vec<String>,
+    #[serde(deserialize_with = "one_or_many")]
+    pub blocklists: Vec<PathBuf>,
+    pub block_action: Option<BlockAction>,
+    pub rpz: Vec<RpzConfig>,
+    pub user: Option<String>,
+    pub group: Option<String>,
     pub log_level: Option<crate::log::LevelFilter>,
+    pub log_format: crate::log::LogFormat,
     pub local_url: String,
+    pub central_url: Option<String>,
+    pub central_timeout: Option<u64>,
+    pub central_connect_timeout: Option<u64>,
+    pub central_proxy: Option<String>,
+    pub local_controller: bool,
+    pub members_file: Option<PathBuf>,
+    pub primary: Option<String>,
+    pub mock: Option<PathBuf>,
+    pub network_domains: HashMap<String, String>,
+    pub forward_zones: HashMap<String, Vec<String>>,
+    #[serde(deserialize_with = "deserialize_records")]
+    pub records: Records,
+    pub soa: SoaConfig,
+    pub tsig_keys: Vec<TsigKey>,
+    pub dynamic_zone: Option<String>,
+    pub dnssec: Option<DnssecConfig>,
     #[serde(skip_deserializing)]
     pub network_id: Option<String>,
 }
 
+// one_or_many accepts either a single path or a list of paths in configuration files.
+fn one_or_many<'de, D>(deserializer: D) -> Result<Vec<PathBuf>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum OneOrMany {
+        One(PathBuf),
+        Many(Vec<PathBuf>),
+    }
+
+    Ok(match OneOrMany::deserialize(deserializer)? {
+        OneOrMany::One(path) => vec![path],
+        OneOrMany::Many(paths) => paths,
+    })
+}
+
 #[derive(Debug, Clone, Serialize, PartialEq)]
 pub enum ConfigFormat {
     JSON,
@@ -58,36 +210,157 @@ impl FromStr for ConfigFormat {
     }
 }
 
+// Instance is a network being served, as started by Launcher::start.
+pub struct Instance {
+    pub zt: ZTAuthority,
+    // the TLD the network is served under; see Launcher::domain_name.
+    pub domain_name: Name,
+    refresh: JoinHandle<()>,
+    // probes the configured upstreams; see crate::upstream.
+    probe: Option<JoinHandle<()>>,
+    watch: JoinHandle<()>,
+    stop: Option<oneshot::Sender<()>>,
+    // the mock of Central the members come from, with --mock.
+    mock: Option<MockServer>,
+}
+
+impl Instance {
+    // stop stops serving the network, returning once its listeners are closed, so that another
+    // instance can bind the same addresses. It is then of no further use.
+    pub async fn stop(&mut self) {
+        self.refresh.abort();
+
+        if let Some(probe) = &self.probe {
+            probe.abort();
+        }
+
+        if let Some(stop) = self.stop.take() {
+            let _ = stop.send(());
+            let _ = (&mut self.watch).await;
+        }
+
+        self.mock = None;
+    }
+}
+
 impl Default for Launcher {
     fn default() -> Self {
         Launcher {
             domain: None,
-            hosts: None,
+            auto_domain: false,
+            domain_aliases: Vec::new(),
+            hosts: Vec::new(),
             secret: None,
             token: None,
+            token_stdin: false,
+            token_keychain: false,
+            insecure_token_permissions: false,
             chain_cert: None,
             tls_cert: None,
             tls_key: None,
+            doh_port: None,
+            doh_hostname: None,
             wildcard: false,
+            wildcard_members: Vec::new(),
+            name_policy: None,
+            name_collisions: None,
+            include_members: Vec::new(),
+            exclude_members: Vec::new(),
+            authorized_only: false,
+            node_id_names: false,
+            all_ptr_names: false,
+            ip_names: false,
+            classless_reverse: false,
+            reverse_zones: Vec::new(),
+            member_txt: false,
+            max_records: None,
+            record_eviction: None,
+            poll_interval: None,
+            ttl: None,
+            clear_dns_on_exit: false,
+            no_update_central: false,
+            central_dns_servers: Vec::new(),
+            peers: Vec::new(),
+            wait_for_network: None,
+            state_dir: None,
+            state_db: None,
+            listen_port: None,
+            listen_addrs: Vec::new(),
+            workers: None,
+            worker_threads: None,
+            max_blocking_threads: None,
+            query_timeout: None,
+            tcp_timeout: None,
+            allow_transfer: Vec::new(),
+            notify: Vec::new(),
+            upstreams: Vec::new(),
+            dnssec_validation: false,
+            qname_minimization: false,
+            cache: false,
+            cache_size: None,
+            serve_stale: false,
+            edns_buffer_size: None,
+            nsid: None,
+            dns_cookies: false,
+            minimal_any: false,
+            answer_order: None,
+            views: Vec::new(),
+            query_acl: None,
+            query_log: None,
+            query_log_size: None,
+            dnstap: None,
+            health_addr: None,
+            admin_socket: None,
+            admin_addr: None,
+            webhook_addr: None,
+            webhook_secret: None,
+            record_webhooks: Vec::new(),
+            record_webhook_secret: None,
+            replication_addr: None,
+            replication_secret: None,
+            cluster: Vec::new(),
+            blocklists: Vec::new(),
+            block_action: None,
+            rpz: Vec::new(),
+            user: None,
+            group: None,
             network_id: None,
             log_level: None,
+            log_format: crate::log::LogFormat::Text,
             local_url: ZEROTIER_LOCAL_URL.to_string(),
+            central_url: None,
+            central_timeout: None,
+            central_connect_timeout: None,
+            central_proxy: None,
+            local_controller: false,
+            members_file: None,
+            primary: None,
+            mock: None,
+            network_domains: HashMap::new(),
+            forward_zones: HashMap::new(),
+            records: Records::new(),
+            soa: SoaConfig::default(),
+            tsig_keys: Vec::new(),
+            dynamic_zone: None,
+            dnssec: None,
         }
     }
 }
 
 impl Launcher {
-    pub fn new_from_config(filename: &str, format: ConfigFormat) -> Result<Self, anyhow::Error> {
-        let res = std::fs::read_to_string(filename)?;
-        Self::parse_format(&res, format)
-    }
-
     pub fn parse_format(s: &str, format: ConfigFormat) -> Result<Self, anyhow::Error> {
-        Ok(match format {
+        let launcher: Self = match format {
             ConfigFormat::JSON => serde_json::from_str(s)?,
             ConfigFormat::YAML => serde_yaml::from_str(s)?,
             ConfigFormat::TOML => toml::from_str(s)?,
-        })
+        };
+
+        // members are polled, and listen addresses watched, at this interval; 0 would spin.
+        if launcher.poll_interval == Some(0) {
+            return Err(anyhow!("poll_interval must be at least 1 second"));
+        }
+
+        Ok(launcher)
     }
 
     pub fn parse(s: &str, network_id: String, format: ConfigFormat) -> Result<Self, anyhow::Error> {
@@ -96,41 +369,415 @@ impl Launcher {
         Ok(l)
     }
 
-    pub async fn start(&self) -> Result<ZTAuthority, anyhow::Error> {
-        crate::utils::init_logger(
+    // for_network returns a copy of this launcher bound to a single network. If the network has a
+    // domain configured in `network_domains`, it takes precedence over `domain`.
+    pub fn for_network(&self, network_id: String) -> Self {
+        let mut launcher = self.clone();
+
+        if let Some(domain) = self.network_domains.get(&network_id) {
+            launcher.domain = Some(domain.clone());
+        }
+
+        launcher.network_id = Some(network_id);
+        launcher
+    }
+
+    // member_filter is the filter of the members which get records.
+    pub fn member_filter(&self) -> MemberFilter {
+        MemberFilter {
+            include: self.include_members.clone(),
+            exclude: self.exclude_members.clone(),
+            authorized_only: self.authorized_only,
+        }
+    }
+
+    // reload returns the settings of this launcher which can be applied to a running instance.
+    pub fn reload(&self) -> Reload {
+        Reload {
+            hosts_files: self.hosts.clone(),
+            blocklists: self.blocklists.clone(),
+            block_action: self.block_action.unwrap_or_default(),
+            rpz: self.rpz.clone(),
+            wildcard: self.wildcard,
+            wildcard_members: self.wildcard_members.clone(),
+            name_policy: self.name_policy.unwrap_or_default(),
+            name_collisions: self.name_collisions.unwrap_or_default(),
+            member_filter: self.member_filter(),
+            node_id_names: self.node_id_names,
+            all_ptr_names: self.all_ptr_names,
+            member_txt: self.member_txt,
+            update_interval: Duration::new(self.poll_interval.unwrap_or(DEFAULT_POLL_INTERVAL), 0),
+            central: self.central_config(),
+            records: self.records.clone(),
+        }
+    }
+
+    // central_options are the settings of the client used to talk to Central.
+    pub fn central_options(&self) -> CentralOptions {
+        CentralOptions {
+            url: self.central_url.clone(),
+            timeout: self.central_timeout.map(|t| Duration::new(t, 0)),
+            connect_timeout: self.central_connect_timeout.map(|t| Duration::new(t, 0)),
+            proxy: self.central_proxy.clone(),
+        }
+    }
+
+    // central_config is what Central clients are built from, or None when members come from the
+    // local controller, a members file, a primary or the mock.
+    pub fn central_config(&self) -> Option<CentralConfig> {
+        if self.local_controller
+            || self.members_file.is_some()
+            || self.primary.is_some()
+            || self.mock.is_some()
+        {
+            return None;
+        }
+
+        Some(CentralConfig {
+            token: self.token_source(),
+            options: self.central_options(),
+        })
+    }
+
+    // node_id is the ZeroTier address of the member zeronsd runs on.
+    pub async fn node_id(&self) -> Result<String, anyhow::Error> {
+        let local = local_client_from_file(
+            authtoken_path(self.secret.as_deref()),
+            self.local_url.clone(),
+        )?;
+        get_node_id(&local).await
+    }
+
+    // worker_count is how many UDP sockets every address is served with: one per core unless
+    // configured otherwise. Only unix can share an address between sockets.
+    pub fn worker_count(&self) -> usize {
+        if !cfg!(unix) {
+            return 1;
+        }
+
+        self.workers
+            .unwrap_or_else(|| std::thread::available_parallelism().map_or(1, |n| n.get()))
+            .max(1)
+    }
+
+    // saved is where a piece of the state of the network is kept: in the state database if there
+    // is one, or else at its path in the state directory.
+    fn saved(
+        &self,
+        store: &Option<Store>,
+        kind: &'static str,
+        path: fn(&Path, &str) -> PathBuf,
+    ) -> Option<Saved> {
+        let network_id = self.network_id.clone().unwrap();
+
+        match (store, &self.state_dir) {
+            (Some(store), _) => Some(Saved::Store {
+                store: store.clone(),
+                network_id,
+                kind,
+            }),
+            (None, Some(dir)) => Some(Saved::File(path(dir, &network_id))),
+            (None, None) => None,
+        }
+    }
+
+    // read_replication_secret reads the secret shared by a primary and its secondaries, and by
+    // the members of a cluster.
+    pub fn read_replication_secret(&self) -> Result<Vec<u8>, anyhow::Error> {
+        let path = self
+            .replication_secret
+            .as_deref()
+            .ok_or_else(|| anyhow!("primary and replication_addr require replication_secret"))?;
+
+        webhook::read_secret(path)
+    }
+
+    // token_source is where the Central token is read from: the token file takes precedence over
+    // standard input, which takes precedence over the credential store and then the environment.
+    pub fn token_source(&self) -> TokenSource {
+        if let Some(token) = &self.token {
+            TokenSource::File(token.clone())
+        } else if self.token_stdin {
+            TokenSource::Stdin
+        } else if self.token_keychain {
+            TokenSource::Keychain
+        } else {
+            TokenSource::Environment
+        }
+    }
+
+    // record_authority creates an authority for one of the zones served, with the SOA, zone
+    // transfer, NOTIFY and ANY settings of this launcher.
+    async fn record_authority(
+        &self,
+        domain_name: LowerName,
+        member_name: LowerName,
+        ttl: u32,
+        hooks: &RecordHooks,
+    ) -> Result<RecordAuthority, anyhow::Error> {
+        let notify = self
+            .notify
+            .iter()
+            .map(|secondary| {
+                parse_dns_server(secondary)
+                    .map_err(|e| anyhow!("Invalid secondary {}: {}", secondary, e))
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let authority = RecordAuthority::with_soa(domain_name, member_name, ttl, self.soa.clone())
+            .await?
+            .with_transfers(self.allow_transfer.clone())
+            .with_keys(tsig::keys(&self.tsig_keys)?)
+            .with_notify(notify)
+            .with_minimal_any(self.minimal_any)
+            .with_hooks(hooks.clone());
+
+        Ok(match &self.dnssec {
+            Some(dnssec) => authority.with_signing(dnssec.zone_key()?),
+            None => authority,
+        })
+    }
+
+    // view_authority creates an authority for a view's copy of one of the zones served. It has the
+    // SOA and ANY settings of this launcher, but is neither transferred nor sent to the hooks.
+    async fn view_authority(
+        &self,
+        domain_name: LowerName,
+        member_name: LowerName,
+        ttl: u32,
+    ) -> Result<RecordAuthority, anyhow::Error> {
+        let authority = RecordAuthority::with_soa(domain_name, member_name, ttl, self.soa.clone())
+            .await?
+            .with_minimal_any(self.minimal_any);
+
+        Ok(match &self.dnssec {
+            Some(dnssec) => authority.with_signing(dnssec.zone_key()?),
+            None => authority,
+        })
+    }
+
+    /// Serves the network, with members from Central or the local controller. New settings sent
+    /// through `reload` are applied to the running instance, which is refreshed right away.
+    pub async fn start(
+        &self,
+        reload: Option<watch::Receiver<Reload>>,
+    ) -> Result<Instance, anyhow::Error> {
+        self.launch(None, reload).await
+    }
+
+    /// Serves the network like `start`, with the networks and members of the source instead of
+    /// those of Central or the local controller. The local zerotier-one is still asked for the
+    /// addresses to listen on.
+    pub async fn start_with_source(
+        &self,
+        source: Arc<dyn MemberSource>,
+        reload: Option<watch::Receiver<Reload>>,
+    ) -> Result<Instance, anyhow::Error> {
+        self.launch(Some(source), reload).await
+    }
+
+    async fn launch(
+        &self,
+        controller: Option<Controller>,
+        reload: Option<watch::Receiver<Reload>>,
+    ) -> Result<Instance, anyhow::Error> {
+        crate::utils::init_logger_with_format(
             self.log_level
                 .clone()
                 .unwrap_or(crate::log::LevelFilter::Info)
                 .to_log(),
+            self.log_format.clone(),
         );
 
         if self.network_id.is_none() {
             return Err(anyhow!("network ID is invalid; cannot continue"));
         }
 
-        let domain_name = domain_or_default(self.domain.as_deref())?;
+        // checked early, even though with auto_domain it is only a fallback.
+        domain_or_default(self.domain.as_deref())?;
+        for alias in &self.domain_aliases {
+            domain_or_default(Some(alias))?;
+        }
+
+        let forward_zones = parse_forward_zones(&self.forward_zones)?;
+
+        let hooks = RecordHooks::new(
+            self.record_webhooks.clone(),
+            self.record_webhook_secret
+                .as_deref()
+                .map(webhook::read_secret)
+                .transpose()?,
+        )?;
+
+        let upstreams = if self.upstreams.is_empty() {
+            None
+        } else {
+            Some(Upstreams::new(&self.upstreams, self.dnssec_validation).await?)
+        };
+
+        if self.dynamic_zone.is_some() && self.tsig_keys.is_empty() {
+            return Err(anyhow!(
+                "dynamic_zone requires tsig_keys to authenticate the updates"
+            ));
+        }
+
+        let ttl = self.ttl.unwrap_or(DEFAULT_TTL);
         let authtoken = authtoken_path(self.secret.as_deref());
-        let client = central_client(central_token(self.token.as_deref())?)?;
+        let local = local_client_from_file(authtoken, self.local_url.clone())?;
+
+        // Central is only used, and the token only read, without another source of members.
+        let central = self.central_config().filter(|_| controller.is_none());
+
+        // a missing token file is not refused here, but served without (see CentralFallback).
+        if let Some(token) = self
+            .token
+            .as_deref()
+            .filter(|token| central.is_some() && token.exists())
+        {
+            match check_token_permissions(token) {
+                Err(e) if self.insecure_token_permissions => warn!("{}", e),
+                res => res?,
+            }
+        }
+
+        // the mock runs for as long as the instance, which talks to it as it would to Central.
+        let mock = match self.mock.clone().filter(|_| controller.is_none()) {
+            Some(path) => Some(
+                MockCentral::from_file(path)
+                    .listen(SocketAddr::new(IpAddr::from([127, 0, 0, 1]), 0))
+                    .await?,
+            ),
+            None => None,
+        };
+
+        let store = self.state_db.as_deref().map(Store::open).transpose()?;
+        let snapshot = self.saved(&store, store::SNAPSHOT, Snapshot::path);
+
+        // with a snapshot to fall back on, zeronsd can start while Central is unreachable.
+        let saved = match &snapshot {
+            Some(saved) => Snapshot::load(saved)?,
+            None => None,
+        };
+
+        // without one, the peers of zerotier-one are served while the token is missing or rejected.
+        let controller: Controller = match (controller, central, &mock) {
+            (Some(controller), _, _) => controller,
+            (None, Some(central), _) if saved.is_none() => {
+                Arc::new(CentralFallback::new(central, local.clone()))
+            }
+            (None, Some(central), _) => Arc::new(Central::from_config(central)?),
+            (None, None, Some(mock)) => Arc::new(Central::new(mock.client()?, None)),
+            (None, None, None) => match (&self.primary, &self.members_file) {
+                (Some(url), _) => Arc::new(Primary::new(url, self.read_replication_secret()?)?),
+                (None, Some(path)) => Arc::new(MembersFile(path.clone())),
+                (None, None) => Arc::new(LocalController(local.clone())),
+            },
+        };
+
+        // in a cluster, only the leader reads from the source; the others read from the leader.
+        let controller: Controller = if self.cluster.is_empty() {
+            controller
+        } else {
+            if self.replication_addr.is_none() {
+                return Err(anyhow!(
+                    "cluster requires replication_addr, for the other members to read from"
+                ));
+            }
+
+            let secret = self.read_replication_secret()?;
+            let members = self
+                .cluster
+                .iter()
+                .map(|url| Primary::new(url, secret.clone()))
+                .collect::<Result<_, _>>()?;
+
+            Arc::new(Cluster::new(
+                get_node_id(&local).await?,
+                controller,
+                members,
+            ))
+        };
 
         info!("Welcome to ZeroNS!");
-        let ips = get_listen_ips(
-            &authtoken,
-            &self.network_id.clone().unwrap(),
-            self.local_url.clone(),
-        )
-        .await?;
+        let ips = match self.wait_for_network {
+            Some(timeout) => {
+                wait_for_assigned_ips(
+                    &local,
+                    &self.network_id.clone().unwrap(),
+                    Duration::new(timeout, 0),
+                )
+                .await?
+            }
+            None => assigned_ips(&local, &self.network_id.clone().unwrap()).await?,
+        };
 
         // more or less the setup for the "main loop"
         if !ips.is_empty() {
-            update_central_dns(
-                domain_name.clone(),
-                ips.iter()
-                    .map(|i| parse_ip_from_cidr(i.clone()).to_string())
-                    .collect(),
-                client.clone(),
-                self.network_id.clone().unwrap(),
-            )
-            .await?;
+            let policy = RetryPolicy::default();
+
+            let network = match policy
+                .run("Fetching the network", || {
+                    controller.network(&self.network_id.clone().unwrap())
+                })
+                .await
+            {
+                Ok(network) => network,
+                Err(e) => match &saved {
+                    Some(saved) => {
+                        warn!("Could not fetch the network: {}", e);
+                        saved.network.clone()
+                    }
+                    None => return Err(e),
+                },
+            };
+
+            let domain_name = self.domain_name(&network)?;
+            parse_records(&self.records, &domain_name)?;
+
+            // the other instances serving the network are published with this one.
+            let peers = Peers::new(
+                self.peers
+                    .iter()
+                    .map(|peer| parse_dns_server(peer))
+                    .collect::<Result<_, _>>()?,
+            );
+            let servers = peers.publish(
+                self.central_servers(
+                    &ips.iter()
+                        .map(|i| parse_ip_from_cidr(i.clone()))
+                        .collect::<Vec<_>>(),
+                ),
+            );
+
+            // what was last pushed, so that the watch pushes again only when it changes.
+            let mut published = Vec::new();
+
+            if self.no_update_central {
+                info!("Leaving the DNS settings of the network in Central alone");
+            } else {
+                match policy
+                    .run("Updating DNS settings", || {
+                        controller.update_dns(
+                            &self.network_id.clone().unwrap(),
+                            domain_name.clone(),
+                            servers.clone(),
+                        )
+                    })
+                    .await
+                {
+                    Ok(_) => published = servers,
+                    Err(e) => {
+                        if saved.is_none() {
+                            return Err(e);
+                        }
+
+                        warn!("Could not update DNS settings: {}", e);
+                    }
+                }
+            }
+
+            let member_name =
+                get_member_name(authtoken, domain_name.clone(), self.local_url.clone()).await?;
 
             let mut listen_ips = Vec::new();
             let mut ipmap = HashMap::new();
@@ -139,101 +786,523 @@ impl Launcher {
             for cidr in ips.clone() {
                 let listen_ip = parse_ip_from_cidr(cidr.clone());
                 listen_ips.push(listen_ip);
-                let cidr = IpNetwork::from_str(&cidr.clone())?;
+                let cidr = network_from_cidr(IpNetwork::from_str(&cidr.clone())?)?;
                 ipmap.entry(listen_ip).or_insert_with(|| cidr.network());
 
                 if let Entry::Vacant(e) = authority_map.entry(cidr) {
-                    tracing::debug!("{}", cidr.to_ptr_soa_name()?);
-                    let ptr_authority =
-                        RecordAuthority::new(cidr.to_ptr_soa_name()?, cidr.to_ptr_soa_name()?)
-                            .await?;
+                    // networks smaller than a /24 may be served in the zone delegated to them.
+                    let classless =
+                        Classless::for_network(cidr, self.classless_reverse, &self.reverse_zones)?;
+                    let zone = match &classless {
+                        Some(classless) => classless.zone().clone().into(),
+                        None => cidr.to_ptr_soa_name()?,
+                    };
+
+                    tracing::debug!("{}", zone);
+                    let ptr_authority = self
+                        .record_authority(zone, member_name.clone(), ttl, &hooks)
+                        .await?
+                        .with_classless(classless);
                     e.insert(ptr_authority);
                 }
             }
 
-            let member_name =
-                get_member_name(authtoken, domain_name.clone(), self.local_url.clone()).await?;
-
-            let network = client
-                .get_network_by_id(&self.network_id.clone().unwrap())
-                .await?;
-
             if let Some(v6assign) = network.config.clone().unwrap().v6_assign_mode {
+                let mut v6networks = Vec::new();
+
                 if v6assign._6plane.unwrap_or(false) {
-                    warn!("6PLANE PTR records are not yet supported");
+                    v6networks.push(network.clone().sixplane()?);
                 }
 
                 if v6assign.rfc4193.unwrap_or(false) {
-                    let cidr = network.clone().rfc4193().unwrap();
+                    v6networks.push(network.clone().rfc4193()?);
+                }
+
+                for cidr in v6networks {
+                    let cidr = network_from_cidr(cidr)?;
                     if let Entry::Vacant(e) = authority_map.entry(cidr) {
                         tracing::debug!("{}", cidr.to_ptr_soa_name()?);
-                        let ptr_authority =
-                            RecordAuthority::new(cidr.to_ptr_soa_name()?, cidr.to_ptr_soa_name()?)
-                                .await?;
+                        let ptr_authority = self
+                            .record_authority(
+                                cidr.to_ptr_soa_name()?,
+                                member_name.clone(),
+                                ttl,
+                                &hooks,
+                            )
+                            .await?;
                         e.insert(ptr_authority);
                     }
                 }
             }
 
-            let authority =
-                RecordAuthority::new(domain_name.clone().into(), member_name.clone()).await?;
+            // the networks served tell which addresses are in the client's, for the subnet order.
+            let ordering = AnswerOrdering::new(
+                self.answer_order.unwrap_or_default(),
+                authority_map.keys().cloned().collect(),
+            );
+            let views = Views::new(self.views.clone());
+
+            let authority = self
+                .record_authority(domain_name.clone().into(), member_name.clone(), ttl, &hooks)
+                .await?
+                .with_answer_order(ordering.clone())
+                .with_views(views.clone());
+
+            let mut domains = vec![domain_name.clone()];
+            let mut alias_authorities = Vec::new();
+            for alias in &self.domain_aliases {
+                let alias = domain_or_default(Some(alias))?;
+
+                if alias != domain_name {
+                    domains.push(alias.clone());
+                    alias_authorities.push(
+                        self.record_authority(alias.into(), member_name.clone(), ttl, &hooks)
+                            .await?
+                            .with_answer_order(ordering.clone())
+                            .with_views(views.clone()),
+                    );
+                }
+            }
+
+            // views with names of their own are served copies of the domain and its aliases.
+            let mut view_zones = Vec::new();
+            for (view, config) in self.views.iter().enumerate() {
+                if !config.has_zones() {
+                    continue;
+                }
+
+                let mut zones = Vec::new();
+                for domain in &domains {
+                    zones.push(
+                        self.view_authority(domain.clone().into(), member_name.clone(), ttl)
+                            .await?
+                            .with_answer_order(ordering.clone())
+                            .with_views(views.clone()),
+                    );
+                }
+
+                let forward_authority = zones.remove(0);
+                view_zones.push(ViewZones {
+                    view,
+                    forward_authority,
+                    alias_authorities: zones,
+                });
+            }
+
+            let dynamic_zone = match &self.dynamic_zone {
+                Some(zone) => {
+                    let zone = parse_name(zone, &domain_name)?;
+                    if zone == domain_name || !domain_name.zone_of(&zone) {
+                        return Err(anyhow!(
+                            "dynamic_zone {} must be a subdomain of {}",
+                            zone,
+                            domain_name
+                        ));
+                    }
+
+                    let saved = self.saved(&store, store::DYNAMIC_RECORDS, DynamicZone::path);
+                    if saved.is_none() {
+                        warn!(
+                            "Names registered in {} are lost on restart without a state directory or database",
+                            zone
+                        );
+                    }
+
+                    let authority = self
+                        .record_authority(zone.into(), member_name.clone(), ttl, &hooks)
+                        .await?
+                        .with_answer_order(ordering.clone())
+                        .with_views(views.clone());
+                    Some(DynamicZone::new(authority, saved).await?)
+                }
+                None => None,
+            };
+
+            // secondaries may be elsewhere, and still have to reach zeronsd to transfer the zones.
+            let query_acl = self.query_acl.map(|action| {
+                QueryAcl::new(
+                    action,
+                    authority_map
+                        .keys()
+                        .chain(self.allow_transfer.iter())
+                        .cloned()
+                        .collect(),
+                )
+            });
+
+            let query_log = match &self.query_log {
+                Some(path) => Some(QueryLog::new(
+                    path,
+                    self.query_log_size.unwrap_or(querylog::DEFAULT_MAX_SIZE) * 1024 * 1024,
+                )?),
+                None => None,
+            };
+
+            let dnstap = self.dnstap.clone().map(Dnstap::new).transpose()?;
+
+            // serving stale answers takes a cache to serve them from.
+            let cache = if self.cache || self.serve_stale {
+                Some(ForwardCache::new(
+                    self.cache_size.unwrap_or(cache::DEFAULT_CACHE_SIZE),
+                    self.serve_stale,
+                ))
+            } else {
+                None
+            };
+
+            let edns = EdnsConfig::new(self.edns_buffer_size, self.nsid.clone(), self.dns_cookies)?;
+
+            let blocklist = Blocklist::default();
+            blocklist.load(&self.blocklists, self.block_action.unwrap_or_default())?;
+
+            let rpz = Rpz::default();
+            rpz.load(&self.rpz)?;
+
+            // the health endpoint reports when Central was last polled, even by an earlier run.
+            let health = Health::new(&self.network_id.clone().unwrap(), &domain_name);
+            if let Some(cache) = &cache {
+                health.set_cache(cache.clone());
+            }
+            if let Some(store) = &store {
+                if let Some(at) = store
+                    .statistics(&self.network_id.clone().unwrap())?
+                    .get(LAST_POLL)
+                {
+                    health.polled_at(UNIX_EPOCH + Duration::from_secs(*at));
+                }
+            }
 
             let ztauthority = ZTAuthority {
-                client,
+                controller,
                 network_id: self.network_id.clone().unwrap(),
                 hosts: None, // this will be parsed later.
-                hosts_file: self.hosts.clone(),
+                hosts_files: self.hosts.clone(),
                 reverse_authority_map: authority_map,
                 forward_authority: authority,
+                alias_authorities,
+                forward_zones,
+                upstreams,
                 wildcard: self.wildcard,
-                update_interval: Duration::new(30, 0),
+                wildcard_members: self.wildcard_members.clone(),
+                name_policy: self.name_policy.unwrap_or_default(),
+                name_collisions: self.name_collisions.unwrap_or_default(),
+                member_filter: self.member_filter(),
+                node_id_names: self.node_id_names,
+                all_ptr_names: self.all_ptr_names,
+                member_txt: self.member_txt,
+                max_records: self.max_records,
+                record_eviction: self.record_eviction.unwrap_or_default(),
+                update_interval: Duration::new(
+                    self.poll_interval.unwrap_or(DEFAULT_POLL_INTERVAL),
+                    0,
+                ),
+                reload,
+                snapshot,
+                store,
+                records: self.records.clone(),
+                dynamic_zone,
+                dnssec_validation: self.dnssec_validation,
+                qname_minimization: self.qname_minimization,
+                ip_names: self.ip_names,
+                edns,
+                query_timeout: self.query_timeout.map(Duration::from_millis),
+                query_acl,
+                query_log,
+                dnstap,
+                health,
+                control: Control::default(),
+                replica: Default::default(),
+                cache,
+                blocklist,
+                rpz,
+                views,
+                view_zones,
             };
 
-            tokio::spawn(find_members(ztauthority.clone()));
+            let span = info_span!("zeronsd", network_id = %self.network_id.clone().unwrap());
 
-            let server = Server::new(ztauthority.to_owned());
-            for ip in listen_ips {
-                info!("Your IP for this network: {}", ip);
+            let refresh = tokio::spawn(find_members(ztauthority.clone()).instrument(span.clone()));
 
-                let tls_cert = if let Some(tls_cert) = self.tls_cert.clone() {
-                    let pem = std::fs::read(tls_cert)?;
-                    Some(X509::from_pem(&pem)?)
-                } else {
-                    None
-                };
+            let probe = ztauthority
+                .upstreams
+                .clone()
+                .map(|upstreams| tokio::spawn(upstreams.probe().instrument(span.clone())));
 
-                let chain = if let Some(chain_cert) = self.chain_cert.clone() {
-                    let pem = std::fs::read(chain_cert)?;
-                    let chain = X509::stack_from_pem(&pem)?;
+            let tls = self.tls_material()?;
+            let doh = self.doh_config(&member_name)?;
 
-                    let mut stack = Stack::new()?;
-                    for cert in chain {
-                        stack.push(cert)?;
-                    }
-                    Some(stack)
-                } else {
-                    None
-                };
+            for ip in &listen_ips {
+                info!("Your IP for this network: {}", ip);
+            }
 
-                let key = if let Some(key_path) = self.tls_key.clone() {
-                    let pem = std::fs::read(key_path)?;
-                    Some(PKey::private_key_from_pem(&pem)?)
-                } else {
-                    None
-                };
+            // the member's addresses are still the ones pushed to Central above; explicit listen
+            // addresses are for setups where something else answers on them and forwards to us.
+            if !self.listen_addrs.is_empty() {
+                listen_ips = self.listen_addrs.clone();
+            }
 
-                tokio::spawn(
-                    server
-                        .clone()
-                        .listen(ip, Duration::new(1, 0), tls_cert, chain, key),
-                );
+            let mut listeners = Listeners::new(
+                Server::new(ztauthority.to_owned()).with_workers(self.worker_count()),
+                self.listen_port.unwrap_or(DNS_PORT),
+                Duration::from_millis(self.tcp_timeout.unwrap_or(DEFAULT_TCP_TIMEOUT)),
+                tls,
+                doh,
+                span.clone(),
+            );
+
+            // binding happens here rather than in the spawned task, so that the listeners are
+            // known to be up once start returns.
+            for ip in listen_ips {
+                listeners.add(ip).await?;
             }
 
-            return Ok(ztauthority);
+            let (stop, stopped) = oneshot::channel();
+
+            let watch = tokio::spawn(
+                self.clone()
+                    .watch_listen_ips(
+                        local,
+                        ztauthority.controller.clone(),
+                        domain_name.clone(),
+                        ips,
+                        peers,
+                        published,
+                        listeners,
+                        stopped,
+                    )
+                    .instrument(span),
+            );
+
+            return Ok(Instance {
+                zt: ztauthority,
+                domain_name,
+                refresh,
+                probe,
+                watch,
+                stop: Some(stop),
+                mock,
+            });
         }
 
         return Err(anyhow!(
             "No listening IPs for your interface; assign one in ZeroTier Central."
         ));
     }
+
+    // shutdown undoes what start did to the outside world before the process exits. Currently
+    // this only removes the DNS settings from Central, and only if asked to.
+    pub async fn shutdown(&self, zt: &ZTAuthority) {
+        if self.clear_dns_on_exit && !self.no_update_central {
+            info!("Removing DNS settings from network {}", zt.network_id);
+
+            if let Err(e) = zt.controller.clear_dns(&zt.network_id).await {
+                warn!("Could not remove DNS settings: {}", e);
+            }
+        }
+    }
+
+    // central_servers are the DNS servers pushed to Central: those configured, such as the address
+    // of a load balancer in front of zeronsd, or else the member's own addresses.
+    fn central_servers(&self, ips: &[IpAddr]) -> Vec<String> {
+        if self.central_dns_servers.is_empty() {
+            dns_servers(ips)
+        } else {
+            self.central_dns_servers
+                .iter()
+                .map(ToString::to_string)
+                .collect()
+        }
+    }
+
+    // watch_listen_ips polls zerotier-one for the addresses assigned to this member and checks the
+    // peers, if any. When the DNS servers to publish change, Central is pointed at them, unless it
+    // is left alone, and unless --listen-addr was given, the listeners are moved to the new
+    // addresses. Reverse zones are only created at startup, so PTR records for a subnet which was
+    // not assigned then are not served until zeronsd is restarted.
+    #[allow(clippy::too_many_arguments)]
+    async fn watch_listen_ips(
+        self,
+        local: zerotier_one_api::Client,
+        controller: Controller,
+        domain_name: Name,
+        mut ips: Vec<String>,
+        mut peers: Peers,
+        mut published: Vec<String>,
+        mut listeners: Listeners,
+        mut stopped: oneshot::Receiver<()>,
+    ) {
+        let network_id = self.network_id.clone().unwrap();
+        let interval = Duration::new(self.poll_interval.unwrap_or(DEFAULT_POLL_INTERVAL), 0);
+
+        ips.sort();
+
+        loop {
+            tokio::select! {
+                _ = tokio::time::sleep(interval) => {}
+                Ok(_) = &mut stopped => {
+                    listeners.close().await;
+                    return;
+                }
+            }
+
+            match assigned_ips(&local, &network_id).await {
+                Ok(mut assigned) => {
+                    assigned.sort();
+
+                    if assigned != ips {
+                        info!(
+                            "Addresses of this member changed to {}",
+                            assigned
+                                .iter()
+                                .map(|cidr| parse_ip_from_cidr(cidr.clone()).to_string())
+                                .collect::<Vec<_>>()
+                                .join(", ")
+                        );
+
+                        ips = assigned;
+                    }
+                }
+                Err(e) => warn!("Could not refresh the addresses of this member: {}", e),
+            }
+
+            let addrs: Vec<IpAddr> = ips
+                .iter()
+                .map(|cidr| parse_ip_from_cidr(cidr.clone()))
+                .collect();
+
+            if !self.no_update_central {
+                if !peers.is_empty() {
+                    peers.check(&domain_name).await;
+                }
+
+                // configured servers do not move with the member's addresses, but peers do come
+                // and go.
+                let servers = peers.publish(self.central_servers(&addrs));
+
+                if servers != published {
+                    match RetryPolicy::default()
+                        .run("Updating DNS settings", || {
+                            controller.update_dns(&network_id, domain_name.clone(), servers.clone())
+                        })
+                        .await
+                    {
+                        // retried on the next pass if this fails, as published is left alone.
+                        Ok(_) => published = servers,
+                        Err(e) => warn!("Could not update DNS settings: {}", e),
+                    }
+                }
+            }
+
+            // this also retries addresses which could not be bound before.
+            if self.listen_addrs.is_empty() {
+                listeners.sync(&addrs).await;
+            }
+        }
+    }
+
+    // domain_name is the TLD the network is served under. With auto_domain it is taken from the
+    // network's name, falling back to `domain` when the name does not make a usable domain.
+    pub fn domain_name(
+        &self,
+        network: &zerotier_central_api::types::Network,
+    ) -> Result<Name, anyhow::Error> {
+        if self.auto_domain {
+            let name = network
+                .config
+                .as_ref()
+                .and_then(|config| config.name.clone())
+                .unwrap_or_default();
+
+            match domain_from_network_name(&name) {
+                Ok(domain_name) => return Ok(domain_name),
+                Err(e) => warn!("Could not use the network name as the domain: {}", e),
+            }
+        }
+
+        domain_or_default(self.domain.as_deref())
+    }
+
+    // tls_material loads the certificate, chain and key for the DNS-over-TLS listener. The
+    // listener is only started when both a certificate and a key are provided.
+    fn tls_material(
+        &self,
+    ) -> Result<(Option<X509>, Option<Vec<X509>>, Option<PKey<Private>>), anyhow::Error> {
+        match (&self.tls_cert, &self.tls_key) {
+            (Some(_), None) => return Err(anyhow!("--tls-cert was provided without --tls-key")),
+            (None, Some(_)) => return Err(anyhow!("--tls-key was provided without --tls-cert")),
+            (None, None) => {
+                if self.chain_cert.is_some() {
+                    warn!("--chain-cert has no effect without --tls-cert and --tls-key");
+                }
+
+                return Ok((None, None, None));
+            }
+            _ => {}
+        }
+
+        let tls_cert = if let Some(tls_cert) = self.tls_cert.clone() {
+            let pem = std::fs::read(&tls_cert).map_err(|e| {
+                anyhow!(
+                    "Could not read TLS certificate {}: {}",
+                    tls_cert.display(),
+                    e
+                )
+            })?;
+            Some(X509::from_pem(&pem)?)
+        } else {
+            None
+        };
+
+        let chain = if let Some(chain_cert) = self.chain_cert.clone() {
+            let pem = std::fs::read(&chain_cert).map_err(|e| {
+                anyhow!(
+                    "Could not read certificate chain {}: {}",
+                    chain_cert.display(),
+                    e
+                )
+            })?;
+            Some(X509::stack_from_pem(&pem)?)
+        } else {
+            None
+        };
+
+        let key = if let Some(key_path) = self.tls_key.clone() {
+            let pem = std::fs::read(&key_path)
+                .map_err(|e| anyhow!("Could not read TLS key {}: {}", key_path.display(), e))?;
+            Some(PKey::private_key_from_pem(&pem)?)
+        } else {
+            None
+        };
+
+        Ok((tls_cert, chain, key))
+    }
+
+    // doh_config describes the DNS-over-HTTPS listener, if one was requested. It shares the
+    // certificate and key with the DNS-over-TLS listener.
+    fn doh_config(&self, member_name: &LowerName) -> Result<Option<DoHConfig>, anyhow::Error> {
+        let port = match self.doh_port {
+            Some(port) => port,
+            None => return Ok(None),
+        };
+
+        match (self.tls_cert.clone(), self.tls_key.clone()) {
+            (Some(cert), Some(key)) => {
+                let hostname = self.doh_hostname.clone().unwrap_or_else(|| {
+                    let mut name: Name = member_name.clone().into();
+                    name.set_fqdn(false);
+                    name.to_string()
+                });
+
+                Ok(Some(DoHConfig {
+                    port,
+                    hostname,
+                    cert,
+                    key,
+                }))
+            }
+            _ => Err(anyhow!(
+                "--doh-port requires both --tls-cert and --tls-key to be provided"
+            )),
+        }
+    }
 }
@@ -0,0 +1,109 @@
+/// IP-literal names such as `ip-10-147-17-5.<domain>` for `--ip-names`.
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+
+use ipnetwork::IpNetwork;
+use trust_dns_server::client::rr::{LowerName, Name};
+
+#[derive(Clone)]
+pub struct IpNames {
+    // the domains the names are served under: the network's and its aliases.
+    domains: Vec<LowerName>,
+    networks: Vec<IpNetwork>,
+    // the TTL of the records answered, the zones' own.
+    ttl: u32,
+}
+
+impl IpNames {
+    pub fn new(domains: Vec<LowerName>, networks: Vec<IpNetwork>, ttl: u32) -> Self {
+        Self {
+            domains,
+            networks,
+            ttl,
+        }
+    }
+
+    pub fn ttl(&self) -> u32 {
+        self.ttl
+    }
+
+    // address is the address an IP-literal name spells, if it is directly under one of the domains
+    // and in one of the managed ranges.
+    pub fn address(&self, name: &LowerName) -> Option<IpAddr> {
+        if name.is_root() || !self.domains.contains(&name.base_name()) {
+            return None;
+        }
+
+        let name = Name::from(name);
+        let label = std::str::from_utf8(name.iter().next()?).ok()?;
+        let ip = parse_ip_label(label)?;
+
+        self.networks
+            .iter()
+            .any(|network| network.contains(ip))
+            .then(|| ip)
+    }
+}
+
+// parse_ip_label reads the address from a label such as `ip-10-147-17-5` or `ip-fd12-3456--1`.
+pub fn parse_ip_label(label: &str) -> Option<IpAddr> {
+    let spelled = label
+        .get(..3)
+        .filter(|prefix| prefix.eq_ignore_ascii_case("ip-"))
+        .and(label.get(3..))?;
+
+    if let Ok(ip) = spelled.replace('-', ".").parse::<Ipv4Addr>() {
+        return Some(ip.into());
+    }
+
+    spelled
+        .replace('-', ":")
+        .parse::<Ipv6Addr>()
+        .ok()
+        .map(IpAddr::from)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{parse_ip_label, IpNames};
+    use ipnetwork::IpNetwork;
+    use std::{net::IpAddr, str::FromStr};
+    use trust_dns_server::client::rr::LowerName;
+
+    #[test]
+    fn test_ip_names() {
+        let ip = |s: &str| Some(IpAddr::from_str(s).unwrap());
+        let name = |s: &str| LowerName::from_str(s).unwrap();
+
+        assert_eq!(parse_ip_label("ip-10-147-17-5"), ip("10.147.17.5"));
+        assert_eq!(parse_ip_label("IP-10-147-17-5"), ip("10.147.17.5"));
+        assert_eq!(parse_ip_label("ip-fd12-3456--1"), ip("fd12:3456::1"));
+        assert_eq!(parse_ip_label("ip-10-147-17"), None);
+        assert_eq!(parse_ip_label("ip-10-147-17-256"), None);
+        assert_eq!(parse_ip_label("web-10-147-17-5"), None);
+        assert_eq!(parse_ip_label("ip"), None);
+
+        let names = IpNames::new(
+            vec![name("home.arpa."), name("zt.example.com.")],
+            vec![
+                IpNetwork::from_str("10.147.17.0/24").unwrap(),
+                IpNetwork::from_str("fd12:3456::/64").unwrap(),
+            ],
+            60,
+        );
+
+        assert_eq!(
+            names.address(&name("ip-10-147-17-5.home.arpa.")),
+            ip("10.147.17.5")
+        );
+        assert_eq!(
+            names.address(&name("ip-fd12-3456--1.zt.example.com.")),
+            ip("fd12:3456::1")
+        );
+
+        // addresses outside the network, names outside the domains or below the names are not.
+        assert_eq!(names.address(&name("ip-10-147-18-5.home.arpa.")), None);
+        assert_eq!(names.address(&name("ip-10-147-17-5.example.com.")), None);
+        assert_eq!(names.address(&name("www.ip-10-147-17-5.home.arpa.")), None);
+        assert_eq!(names.address(&name("ip-10-147-17-5.sub.home.arpa.")), None);
+    }
+}
@@ -1,12 +1,84 @@
+//! zeronsd serves the members of ZeroTier networks over DNS. Besides the `zeronsd` binary, it can
+//! be embedded in another program: a network is served by filling in a [`init::Launcher`], as the
+//! configuration file would, and starting it. The returned [`init::Instance`] serves the network
+//! until it is stopped.
+//!
+//! ```no_run
+//! # async fn serve() -> Result<(), anyhow::Error> {
+//! use zeronsd::init::Launcher;
+//!
+//! let launcher = Launcher {
+//!     domain: Some("home.arpa".to_string()),
+//!     token: Some("/var/lib/zeronsd/central.token".into()),
+//!     ..Default::default()
+//! }
+//! .for_network("8056c2e21c000001".to_string());
+//!
+//! let mut instance = launcher.start(None).await?;
+//! // ...
+//! instance.stop().await;
+//! launcher.shutdown(&instance.zt).await;
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! Programs which keep track of members themselves can serve them instead of those of Central
+//! by implementing [`controller::MemberSource`] and starting the launcher with
+//! [`init::Launcher::start_with_source`]. The local zerotier-one service is still asked for the
+//! addresses to listen on.
+
+pub mod acl;
+pub mod activation;
 pub mod addresses;
+pub mod admin;
 pub mod authority;
+pub mod bench;
+pub mod blocklist;
+pub mod cache;
+pub mod central;
+pub mod classless;
 pub mod cli;
+pub mod clientsetup;
+pub mod cluster;
+pub mod collisions;
+pub mod completions;
+pub mod controller;
+pub mod dnssec;
+pub mod dnstap;
+pub mod edns;
+pub mod filter;
+pub mod health;
+pub mod hooks;
 pub mod hosts;
+pub mod http;
+pub mod idn;
+pub mod ipnames;
+pub mod limits;
 pub mod log;
+pub mod mock;
+pub mod order;
+pub mod peers;
+pub mod query;
+pub mod querylog;
+pub mod records;
+pub mod replication;
+pub mod rpz;
 pub mod server;
+#[cfg(target_os = "windows")]
+pub mod service;
+pub mod services;
+pub mod snapshot;
+pub mod store;
 pub mod supervise;
 pub mod traits;
+pub mod transfer;
+pub mod tsig;
+pub mod update;
+pub mod upstream;
 pub mod utils;
+pub mod validate;
+pub mod views;
+pub mod webhook;
 
 pub mod init;
 
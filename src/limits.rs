@@ -0,0 +1,196 @@
+/// limits on the records of members for `--max-records`.
+use std::str::FromStr;
+
+use anyhow::anyhow;
+use serde::{Deserialize, Serialize};
+
+// RecordEviction is what is left out first when the members would get more records than the
+// limit.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum RecordEviction {
+    // members, those which were last online the longest ago first.
+    Offline,
+    // the wildcard names of all members, and then members as with Offline.
+    Wildcards,
+}
+
+impl Default for RecordEviction {
+    fn default() -> Self {
+        RecordEviction::Offline
+    }
+}
+
+impl ToString for RecordEviction {
+    fn to_string(&self) -> String {
+        match self {
+            RecordEviction::Offline => "offline",
+            RecordEviction::Wildcards => "wildcards",
+        }
+        .to_string()
+    }
+}
+
+impl FromStr for RecordEviction {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "offline" => Ok(Self::Offline),
+            "wildcards" => Ok(Self::Wildcards),
+            _ => Err(anyhow!(
+                "invalid eviction policy: allowed values: [offline, wildcards]"
+            )),
+        }
+    }
+}
+
+// MemberRecords is how many records a member gets, with and without its wildcard names, and when
+// it was last online, in milliseconds since the epoch, if it ever was.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct MemberRecords {
+    pub records: usize,
+    pub without_wildcards: usize,
+    pub last_online: Option<i64>,
+}
+
+// Limited is what the members are served with under the limit.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Limited {
+    // the positions of the members served, in their order.
+    pub served: Vec<usize>,
+    pub wildcards: bool,
+    pub records: usize,
+}
+
+// limit picks the members served, and whether they get their wildcard names, so that they get
+// no more than max records.
+pub fn limit(members: &[MemberRecords], max: usize, eviction: RecordEviction) -> Limited {
+    let total = |wildcards: bool| {
+        members
+            .iter()
+            .map(|member| member.count(wildcards))
+            .sum::<usize>()
+    };
+
+    let all = |wildcards| Limited {
+        served: (0..members.len()).collect(),
+        wildcards,
+        records: total(wildcards),
+    };
+
+    if total(true) <= max {
+        return all(true);
+    }
+
+    let wildcards = eviction != RecordEviction::Wildcards;
+    if !wildcards && total(false) <= max {
+        return all(false);
+    }
+
+    // members seen online most recently are kept first; members never seen online go last.
+    let mut order: Vec<usize> = (0..members.len()).collect();
+    order.sort_by_key(|&i| std::cmp::Reverse(members[i].last_online));
+
+    let mut served = Vec::new();
+    let mut records = 0;
+
+    for i in order {
+        let count = members[i].count(wildcards);
+        if records + count > max {
+            break;
+        }
+
+        records += count;
+        served.push(i);
+    }
+
+    served.sort_unstable();
+
+    Limited {
+        served,
+        wildcards,
+        records,
+    }
+}
+
+impl MemberRecords {
+    fn count(&self, wildcards: bool) -> usize {
+        if wildcards {
+            self.records
+        } else {
+            self.without_wildcards
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{limit, Limited, MemberRecords, RecordEviction};
+    use std::str::FromStr;
+
+    fn member(last_online: Option<i64>) -> MemberRecords {
+        MemberRecords {
+            records: 4,
+            without_wildcards: 2,
+            last_online,
+        }
+    }
+
+    #[test]
+    fn test_record_eviction() {
+        assert_eq!(
+            RecordEviction::from_str("wildcards").unwrap(),
+            RecordEviction::Wildcards
+        );
+        assert_eq!(RecordEviction::default().to_string(), "offline");
+        assert!(RecordEviction::from_str("random").is_err());
+    }
+
+    #[test]
+    fn test_limit() {
+        let members = [member(Some(300)), member(None), member(Some(100))];
+
+        // everything fits.
+        assert_eq!(
+            limit(&members, 12, RecordEviction::Offline),
+            Limited {
+                served: vec![0, 1, 2],
+                wildcards: true,
+                records: 12,
+            }
+        );
+
+        // the member never seen online goes first, then the one offline the longest.
+        assert_eq!(
+            limit(&members, 11, RecordEviction::Offline),
+            Limited {
+                served: vec![0, 2],
+                wildcards: true,
+                records: 8,
+            }
+        );
+        assert_eq!(limit(&members, 7, RecordEviction::Offline).served, vec![0]);
+        assert!(limit(&members, 3, RecordEviction::Offline)
+            .served
+            .is_empty());
+
+        // wildcards go before any member does.
+        assert_eq!(
+            limit(&members, 11, RecordEviction::Wildcards),
+            Limited {
+                served: vec![0, 1, 2],
+                wildcards: false,
+                records: 6,
+            }
+        );
+        assert_eq!(
+            limit(&members, 5, RecordEviction::Wildcards),
+            Limited {
+                served: vec![0, 2],
+                wildcards: false,
+                records: 4,
+            }
+        );
+    }
+}
@@ -30,6 +30,18 @@ impl LevelFilter {
             LevelFilter::Debug => Some(tracing::Level::DEBUG),
         }
     }
+
+    #[cfg(target_os = "windows")]
+    pub fn to_event_log(&self) -> Option<::log::Level> {
+        match self {
+            LevelFilter::Off => None,
+            LevelFilter::Error => Some(::log::Level::Error),
+            LevelFilter::Warn => Some(::log::Level::Warn),
+            LevelFilter::Info => Some(::log::Level::Info),
+            LevelFilter::Trace => Some(::log::Level::Trace),
+            LevelFilter::Debug => Some(::log::Level::Debug),
+        }
+    }
 }
 
 impl ToString for LevelFilter {
@@ -64,9 +76,48 @@ impl FromStr for LevelFilter {
     }
 }
 
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub enum LogFormat {
+    #[serde(rename(deserialize = "text"))]
+    Text,
+    #[serde(rename(deserialize = "json"))]
+    JSON,
+}
+
+impl ToString for LogFormat {
+    fn to_string(&self) -> String {
+        match self {
+            LogFormat::Text => "text",
+            LogFormat::JSON => "json",
+        }
+        .to_string()
+    }
+}
+
+impl FromStr for LogFormat {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "text" => Ok(Self::Text),
+            "json" => Ok(Self::JSON),
+            _ => Err(anyhow!("invalid format: allowed values: [text, json]")),
+        }
+    }
+}
+
+// verbosity maps the number of -v flags to a log level.
+pub fn verbosity(count: usize) -> Option<LevelFilter> {
+    match count {
+        0 => None,
+        1 => Some(LevelFilter::Debug),
+        _ => Some(LevelFilter::Trace),
+    }
+}
+
 #[cfg(test)]
 mod tests {
-    use super::LevelFilter;
+    use super::{verbosity, LevelFilter, LogFormat};
     use std::str::FromStr;
 
     #[test]
@@ -98,4 +149,22 @@ mod tests {
             assert_eq!(item.0.to_string(), item.1)
         }
     }
+
+    #[test]
+    fn test_log_format() {
+        for item in vec![("text", LogFormat::Text), ("json", LogFormat::JSON)] {
+            assert_eq!(LogFormat::from_str(item.0).unwrap(), item.1);
+            assert_eq!(item.1.to_string(), item.0);
+        }
+
+        assert!(LogFormat::from_str("xml").is_err());
+    }
+
+    #[test]
+    fn test_verbosity() {
+        assert_eq!(verbosity(0), None);
+        assert_eq!(verbosity(1), Some(LevelFilter::Debug));
+        assert_eq!(verbosity(2), Some(LevelFilter::Trace));
+        assert_eq!(verbosity(5), Some(LevelFilter::Trace));
+    }
 }
@@ -0,0 +1,214 @@
+/// a stand-in for ZeroTier Central serving a fixed list of members, for `--mock`.
+use std::{
+    collections::HashMap,
+    net::SocketAddr,
+    path::PathBuf,
+    sync::{Arc, Mutex},
+};
+
+use tokio::{net::TcpListener, task::JoinHandle};
+use zerotier_central_api::types::{Dns, Network};
+
+use crate::{
+    controller::{Controller, MembersFile},
+    http::{self, Request, Response},
+    utils::central_client_with_url,
+};
+
+// the token clients of the mock send; it is not checked.
+pub const MOCK_TOKEN: &str = "mock";
+
+// MockCentral answers for any network ID with the network and members of its source.
+#[derive(Clone)]
+pub struct MockCentral {
+    source: Controller,
+    // the DNS settings pushed to each network, by network ID.
+    dns: Arc<Mutex<HashMap<String, Dns>>>,
+}
+
+impl MockCentral {
+    pub fn new(source: Controller) -> Self {
+        Self {
+            source,
+            dns: Default::default(),
+        }
+    }
+
+    // from_file serves the members of a members file, in the format of --members-file. The file
+    // is read again on every request.
+    pub fn from_file(path: PathBuf) -> Self {
+        Self::new(Arc::new(MembersFile(path)))
+    }
+
+    // dns is what was last pushed as the DNS settings of the network.
+    pub fn dns(&self, network_id: &str) -> Option<Dns> {
+        self.dns.lock().unwrap().get(network_id).cloned()
+    }
+
+    // listen binds the address and answers requests on it in the background, until the returned
+    // server is dropped.
+    pub async fn listen(&self, addr: SocketAddr) -> Result<MockServer, anyhow::Error> {
+        let listener = TcpListener::bind(addr)
+            .await
+            .map_err(|e| anyhow::anyhow!("could not listen for the mock on {}: {}", addr, e))?;
+        let addr = listener.local_addr()?;
+
+        let handle = tokio::spawn(self.clone().serve(listener));
+
+        Ok(MockServer { addr, handle })
+    }
+
+    async fn serve(self, listener: TcpListener) {
+        loop {
+            match listener.accept().await {
+                Ok((stream, peer)) => {
+                    let mock = self.clone();
+                    tokio::spawn(async move {
+                        let answer = move |request| async move { mock.respond(request).await };
+
+                        if let Err(e) = http::handle(stream, answer).await {
+                            tracing::debug!("Request to the mock from {} failed: {}", peer, e);
+                        }
+                    });
+                }
+                Err(e) => tracing::warn!("Could not accept request to the mock: {}", e),
+            }
+        }
+    }
+
+    async fn network(&self, network_id: &str) -> Result<Network, anyhow::Error> {
+        let mut network = self.source.network(network_id).await?;
+
+        if let (Some(config), Some(dns)) = (network.config.as_mut(), self.dns(network_id)) {
+            config.dns = Some(dns);
+        }
+
+        Ok(network)
+    }
+
+    // respond answers GET /api/v1/network/<id>, GET /api/v1/network/<id>/member and
+    // POST /api/v1/network/<id>, of which only the DNS settings are kept.
+    async fn respond(&self, request: Request) -> Response {
+        let result = match (request.method.as_str(), request.segments().as_slice()) {
+            ("GET", ["api", "v1", "network", network_id]) => self
+                .network(network_id)
+                .await
+                .map(|network| Response::json(200, &network)),
+            ("GET", ["api", "v1", "network", network_id, "member"]) => self
+                .source
+                .members(network_id)
+                .await
+                .map(|members| Response::json(200, &members)),
+            ("POST", ["api", "v1", "network", network_id]) => {
+                let network: Network = match serde_json::from_slice(&request.body) {
+                    Ok(network) => network,
+                    Err(e) => return Response::error(400, e),
+                };
+
+                if let Some(dns) = network.config.and_then(|config| config.dns) {
+                    self.dns.lock().unwrap().insert(network_id.to_string(), dns);
+                }
+
+                self.network(network_id)
+                    .await
+                    .map(|network| Response::json(200, &network))
+            }
+            (_, ["api", "v1", "network", ..]) => return Response::error(405, "method not allowed"),
+            _ => return Response::error(404, "not found"),
+        };
+
+        result.unwrap_or_else(|e| Response::error(500, e))
+    }
+}
+
+// MockServer is a MockCentral listening on an address. It stops when dropped.
+pub struct MockServer {
+    pub addr: SocketAddr,
+    handle: JoinHandle<()>,
+}
+
+impl MockServer {
+    // url is the base URL of the API, as given to --central-url.
+    pub fn url(&self) -> String {
+        format!("http://{}/api/v1", self.addr)
+    }
+
+    // client is a Central client talking to the mock.
+    pub fn client(&self) -> Result<zerotier_central_api::Client, anyhow::Error> {
+        central_client_with_url(MOCK_TOKEN.to_string(), Some(&self.url()))
+    }
+}
+
+impl Drop for MockServer {
+    fn drop(&mut self) {
+        self.handle.abort();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::MockCentral;
+    use crate::controller::{Central, MemberSource};
+    use std::str::FromStr;
+    use trust_dns_server::client::rr::Name;
+
+    #[tokio::test]
+    async fn test_mock_central() {
+        let path = std::env::temp_dir().join("zeronsd-test-mock.json");
+        std::fs::write(
+            &path,
+            r#"{"name": "lab", "members": [{"id": "a1b2c3d4e5", "name": "islay", "ips": ["10.147.20.5"]}]}"#,
+        )
+        .unwrap();
+
+        let mock = MockCentral::from_file(path.clone());
+        let server = mock.listen("127.0.0.1:0".parse().unwrap()).await.unwrap();
+        assert_eq!(server.url(), format!("http://{}/api/v1", server.addr));
+
+        // the mock is reached through the same client, and poller, as Central.
+        let central = Central::new(server.client().unwrap(), None);
+
+        let network = central.network("8056c2e21c000001").await.unwrap();
+        assert_eq!(network.config.unwrap().name, Some("lab".to_string()));
+
+        let members = central.members("8056c2e21c000001").await.unwrap();
+        assert_eq!(members.len(), 1);
+        assert_eq!(members[0].name, Some("islay".to_string()));
+        assert_eq!(
+            members[0].config.clone().unwrap().ip_assignments,
+            Some(vec!["10.147.20.5".to_string()])
+        );
+
+        central
+            .update_dns(
+                "8056c2e21c000001",
+                Name::from_str("home.arpa.").unwrap(),
+                vec!["10.147.20.1".to_string()],
+            )
+            .await
+            .unwrap();
+
+        let dns = mock.dns("8056c2e21c000001").unwrap();
+        assert_eq!(dns.domain, Some("home.arpa".to_string()));
+        assert_eq!(dns.servers, Some(vec!["10.147.20.1".to_string()]));
+        assert_eq!(
+            central
+                .network("8056c2e21c000001")
+                .await
+                .unwrap()
+                .config
+                .unwrap()
+                .dns
+                .unwrap()
+                .servers,
+            Some(vec!["10.147.20.1".to_string()])
+        );
+        assert!(mock.dns("8056c2e21c000002").is_none());
+
+        // a members file which cannot be read is an error of the API, as with Central.
+        std::fs::remove_file(&path).unwrap();
+        assert!(central.members("8056c2e21c000001").await.is_err());
+
+        drop(server);
+    }
+}
@@ -0,0 +1,195 @@
+/// the order the addresses of an answer are given in, for `--answer-order`.
+use std::{
+    net::IpAddr,
+    str::FromStr,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+};
+
+use anyhow::anyhow;
+use ipnetwork::IpNetwork;
+use rand::seq::SliceRandom;
+use serde::{Deserialize, Serialize};
+use trust_dns_server::proto::rr::Record;
+
+// AnswerOrder is the order the addresses of an answer are given in.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum AnswerOrder {
+    // the order the addresses are stored in, the same for every answer.
+    Fixed,
+    // each answer starts with the address after the one the previous answer started with.
+    Rotate,
+    // a random order for each answer.
+    Random,
+    // addresses in the same network as the client first, then the others, both rotated.
+    Subnet,
+}
+
+impl Default for AnswerOrder {
+    fn default() -> Self {
+        AnswerOrder::Fixed
+    }
+}
+
+impl ToString for AnswerOrder {
+    fn to_string(&self) -> String {
+        match self {
+            AnswerOrder::Fixed => "fixed",
+            AnswerOrder::Rotate => "rotate",
+            AnswerOrder::Random => "random",
+            AnswerOrder::Subnet => "subnet",
+        }
+        .to_string()
+    }
+}
+
+impl FromStr for AnswerOrder {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "fixed" => Ok(Self::Fixed),
+            "rotate" => Ok(Self::Rotate),
+            "random" => Ok(Self::Random),
+            "subnet" => Ok(Self::Subnet),
+            _ => Err(anyhow!(
+                "invalid order: allowed values: [fixed, rotate, random, subnet]"
+            )),
+        }
+    }
+}
+
+// AnswerOrdering orders the answers of a zone. Its copies share the rotation, so that the
+// listeners of a network take turns together.
+#[derive(Clone, Default)]
+pub struct AnswerOrdering {
+    order: AnswerOrder,
+    // the networks served, which tell the addresses in the same network as the client.
+    networks: Arc<Vec<IpNetwork>>,
+    next: Arc<AtomicUsize>,
+}
+
+impl AnswerOrdering {
+    pub fn new(order: AnswerOrder, networks: Vec<IpNetwork>) -> Self {
+        Self {
+            order,
+            networks: Arc::new(networks),
+            next: Arc::default(),
+        }
+    }
+
+    pub fn is_fixed(&self) -> bool {
+        self.order == AnswerOrder::Fixed
+    }
+
+    // apply puts the records of an answer to the client in order.
+    pub fn apply(&self, records: &mut [Record], client: IpAddr) {
+        if records.len() < 2 {
+            return;
+        }
+
+        match self.order {
+            AnswerOrder::Fixed => {}
+            AnswerOrder::Rotate => self.rotate(records),
+            AnswerOrder::Random => records.shuffle(&mut rand::thread_rng()),
+            AnswerOrder::Subnet => {
+                self.rotate(records);
+                records.sort_by_key(|record| !self.same_network(record, client));
+            }
+        }
+    }
+
+    fn rotate(&self, records: &mut [Record]) {
+        let next = self.next.fetch_add(1, Ordering::Relaxed);
+        records.rotate_left(next % records.len());
+    }
+
+    // same_network tells whether the address of the record is in a network served the client is
+    // in as well.
+    fn same_network(&self, record: &Record, client: IpAddr) -> bool {
+        let ip = match record.data().and_then(|rdata| rdata.to_ip_addr()) {
+            Some(ip) => ip,
+            None => return false,
+        };
+
+        self.networks
+            .iter()
+            .any(|network| network.contains(ip) && network.contains(client))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{AnswerOrder, AnswerOrdering};
+    use ipnetwork::IpNetwork;
+    use std::{net::IpAddr, str::FromStr};
+    use trust_dns_server::proto::rr::{Name, RData, Record};
+
+    #[test]
+    fn test_answer_ordering() {
+        let name = Name::from_str("web.home.arpa.").unwrap();
+        let records: Vec<Record> = ["10.147.17.5", "10.147.18.5", "10.147.19.5"]
+            .iter()
+            .map(|ip| Record::from_rdata(name.clone(), 60, RData::A(ip.parse().unwrap())))
+            .collect();
+
+        let networks = vec![
+            IpNetwork::from_str("10.147.18.0/24").unwrap(),
+            IpNetwork::from_str("10.147.19.0/24").unwrap(),
+        ];
+        let client = IpAddr::from_str("10.147.19.1").unwrap();
+
+        let answer = |ordering: &AnswerOrdering| {
+            let mut records = records.clone();
+            ordering.apply(&mut records, client);
+            records
+                .iter()
+                .map(|record| record.data().unwrap().to_ip_addr().unwrap().to_string())
+                .collect::<Vec<_>>()
+        };
+
+        let fixed = AnswerOrdering::new(AnswerOrder::Fixed, networks.clone());
+        assert!(fixed.is_fixed());
+        assert_eq!(
+            answer(&fixed),
+            ["10.147.17.5", "10.147.18.5", "10.147.19.5"]
+        );
+        assert_eq!(
+            answer(&fixed),
+            ["10.147.17.5", "10.147.18.5", "10.147.19.5"]
+        );
+
+        let rotate = AnswerOrdering::new(AnswerOrder::Rotate, networks.clone());
+        let shared = rotate.clone();
+        assert_eq!(
+            answer(&rotate),
+            ["10.147.17.5", "10.147.18.5", "10.147.19.5"]
+        );
+        assert_eq!(
+            answer(&shared),
+            ["10.147.18.5", "10.147.19.5", "10.147.17.5"]
+        );
+        assert_eq!(
+            answer(&rotate),
+            ["10.147.19.5", "10.147.17.5", "10.147.18.5"]
+        );
+        assert_eq!(
+            answer(&rotate),
+            ["10.147.17.5", "10.147.18.5", "10.147.19.5"]
+        );
+
+        let mut random = answer(&AnswerOrdering::new(AnswerOrder::Random, networks.clone()));
+        random.sort();
+        assert_eq!(random, ["10.147.17.5", "10.147.18.5", "10.147.19.5"]);
+
+        let subnet = AnswerOrdering::new(AnswerOrder::Subnet, networks);
+        for _ in 0..3 {
+            assert_eq!(answer(&subnet)[0], "10.147.19.5");
+        }
+
+        assert!("roundrobin".parse::<AnswerOrder>().is_err());
+    }
+}
@@ -0,0 +1,164 @@
+/// health checks of the high-availability peers of `--peer`.
+use std::net::{IpAddr, SocketAddr};
+
+use tracing::{debug, info, warn};
+use trust_dns_resolver::{
+    proto::{op::ResponseCode, rr::RecordType},
+    Name,
+};
+
+use crate::query::query;
+
+// how many checks in a row a peer has to fail to be taken out of Central.
+const PEER_FAILURES: u32 = 2;
+
+#[derive(Debug, Clone)]
+struct Peer {
+    addr: SocketAddr,
+    // the checks failed since it last answered.
+    failures: u32,
+}
+
+impl Peer {
+    fn healthy(&self) -> bool {
+        self.failures < PEER_FAILURES
+    }
+}
+
+// Peers are the other instances serving the network. They are taken to be healthy until checked.
+#[derive(Debug, Clone, Default)]
+pub struct Peers {
+    peers: Vec<Peer>,
+}
+
+impl Peers {
+    pub fn new(addrs: Vec<SocketAddr>) -> Self {
+        Self {
+            peers: addrs
+                .into_iter()
+                .map(|addr| Peer { addr, failures: 0 })
+                .collect(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.peers.is_empty()
+    }
+
+    // check asks every peer for the SOA of the domain, which it serves as well.
+    pub async fn check(&mut self, domain: &Name) {
+        for i in 0..self.peers.len() {
+            let addr = self.peers[i].addr;
+
+            let answered = match query(addr, domain, RecordType::SOA, false).await {
+                Ok(answer) => {
+                    let message = answer.message;
+                    if message.response_code() != ResponseCode::NoError
+                        || message.answers().is_empty()
+                    {
+                        debug!(
+                            "Peer {} answered {} for {}",
+                            addr,
+                            message.response_code(),
+                            domain
+                        );
+                    }
+
+                    message.response_code() == ResponseCode::NoError
+                        && !message.answers().is_empty()
+                }
+                Err(e) => {
+                    debug!("Could not check peer {}: {}", addr, e);
+                    false
+                }
+            };
+
+            self.record(i, answered);
+        }
+    }
+
+    // record counts a check of the peer, and logs it going down or coming back.
+    fn record(&mut self, index: usize, answered: bool) {
+        let peer = &mut self.peers[index];
+        let healthy = peer.healthy();
+
+        if answered {
+            peer.failures = 0;
+        } else {
+            peer.failures += 1;
+        }
+
+        match (healthy, peer.healthy()) {
+            (true, false) => warn!(
+                "Peer {} is not answering; taking it out of the DNS servers in Central",
+                peer.addr
+            ),
+            (false, true) => info!(
+                "Peer {} is answering again; putting it back in the DNS servers in Central",
+                peer.addr
+            ),
+            _ => {}
+        }
+    }
+
+    // healthy are the addresses of the peers which are answering.
+    pub fn healthy(&self) -> Vec<IpAddr> {
+        self.peers
+            .iter()
+            .filter(|peer| peer.healthy())
+            .map(|peer| peer.addr.ip())
+            .collect()
+    }
+
+    // publish are the DNS servers pushed to Central: this instance's and those of the healthy
+    // peers, sorted.
+    pub fn publish(&self, servers: Vec<String>) -> Vec<String> {
+        if self.is_empty() {
+            return servers;
+        }
+
+        let mut servers: Vec<String> = servers
+            .into_iter()
+            .chain(self.healthy().iter().map(ToString::to_string))
+            .collect();
+        servers.sort();
+        servers.dedup();
+        servers
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Peers;
+    use std::net::SocketAddr;
+
+    #[test]
+    fn test_peers() {
+        let addr = |s: &str| s.parse::<SocketAddr>().unwrap();
+        let own = || vec!["10.147.17.2".to_string()];
+
+        assert_eq!(Peers::default().publish(own()), ["10.147.17.2"]);
+
+        let mut peers = Peers::new(vec![addr("10.147.17.1:53"), addr("[fd12::1]:5353")]);
+        assert_eq!(
+            peers.publish(own()),
+            ["10.147.17.1", "10.147.17.2", "fd12::1"]
+        );
+
+        // a single failed check is not enough to be taken out.
+        peers.record(0, false);
+        assert_eq!(
+            peers.publish(own()),
+            ["10.147.17.1", "10.147.17.2", "fd12::1"]
+        );
+
+        peers.record(0, false);
+        assert_eq!(peers.publish(own()), ["10.147.17.2", "fd12::1"]);
+
+        peers.record(0, true);
+        assert_eq!(
+            peers.publish(own()),
+            ["10.147.17.1", "10.147.17.2", "fd12::1"]
+        );
+    }
+}
@@ -0,0 +1,333 @@
+/// code for `zeronsd query`, a small dig for hosts without one.
+use std::{
+    net::{IpAddr, SocketAddr},
+    str::FromStr,
+    time::{Duration, Instant},
+};
+
+use anyhow::anyhow;
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::{TcpStream, UdpSocket},
+};
+use trust_dns_resolver::{
+    proto::{
+        op::{Edns, Message, MessageType, OpCode, Query},
+        rr::{Record, RecordType},
+    },
+    Name,
+};
+
+// how long the nameserver is given to answer.
+const QUERY_TIMEOUT: Duration = Duration::from_secs(5);
+
+// the largest answer asked for over UDP.
+const MAX_PAYLOAD: u16 = 1232;
+
+// Transport is how an answer was received.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Transport {
+    Udp,
+    Tcp,
+}
+
+// Answer is the answer of a nameserver to a query, with how long it took.
+#[derive(Debug, Clone)]
+pub struct Answer {
+    pub server: SocketAddr,
+    pub transport: Transport,
+    pub elapsed: Duration,
+    pub message: Message,
+}
+
+// parse_server parses a nameserver given as an address, with port 53 unless one is given.
+pub fn parse_server(s: &str) -> Result<SocketAddr, anyhow::Error> {
+    if let Ok(addr) = SocketAddr::from_str(s) {
+        return Ok(addr);
+    }
+
+    match IpAddr::from_str(s) {
+        Ok(ip) => Ok(SocketAddr::new(ip, 53)),
+        Err(_) => Err(anyhow!(
+            "expected an address, with an optional port, e.g. 10.147.20.1 or [fd00::1]:5353"
+        )),
+    }
+}
+
+// query asks the nameserver for the records of the name, over TCP if tcp is set or if the answer
+// over UDP is truncated.
+pub async fn query(
+    server: SocketAddr,
+    name: &Name,
+    record_type: RecordType,
+    tcp: bool,
+) -> Result<Answer, anyhow::Error> {
+    let mut edns = Edns::new();
+    edns.set_max_payload(MAX_PAYLOAD);
+
+    let mut message = Message::new();
+    message
+        .set_id(rand::random())
+        .set_message_type(MessageType::Query)
+        .set_op_code(OpCode::Query)
+        .set_recursion_desired(true)
+        .add_query(Query::query(name.clone(), record_type))
+        .set_edns(edns);
+
+    let request = message.to_vec()?;
+    let start = Instant::now();
+
+    if !tcp {
+        let response = exchange_udp(server, &request).await?;
+        check_id(&message, &response)?;
+
+        if !response.truncated() {
+            return Ok(Answer {
+                server,
+                transport: Transport::Udp,
+                elapsed: start.elapsed(),
+                message: response,
+            });
+        }
+    }
+
+    let response = exchange_tcp(server, &request).await?;
+    check_id(&message, &response)?;
+
+    Ok(Answer {
+        server,
+        transport: Transport::Tcp,
+        elapsed: start.elapsed(),
+        message: response,
+    })
+}
+
+fn check_id(request: &Message, response: &Message) -> Result<(), anyhow::Error> {
+    if response.id() != request.id() {
+        return Err(anyhow!("unexpected response"));
+    }
+
+    Ok(())
+}
+
+async fn exchange_udp(server: SocketAddr, request: &[u8]) -> Result<Message, anyhow::Error> {
+    let bind: SocketAddr = if server.is_ipv4() {
+        "0.0.0.0:0".parse()?
+    } else {
+        "[::]:0".parse()?
+    };
+
+    let socket = UdpSocket::bind(bind).await?;
+    socket.connect(server).await?;
+    socket.send(request).await?;
+
+    let mut buf = vec![0u8; MAX_PAYLOAD as usize];
+    let len = tokio::time::timeout(QUERY_TIMEOUT, socket.recv(&mut buf))
+        .await
+        .map_err(|_| anyhow!("no answer from {}", server))??;
+
+    Ok(Message::from_vec(&buf[..len])?)
+}
+
+async fn exchange_tcp(server: SocketAddr, request: &[u8]) -> Result<Message, anyhow::Error> {
+    let exchange = async {
+        let mut stream = TcpStream::connect(server).await?;
+
+        let mut framed = (request.len() as u16).to_be_bytes().to_vec();
+        framed.extend_from_slice(request);
+        stream.write_all(&framed).await?;
+
+        let len = stream.read_u16().await? as usize;
+        let mut buf = vec![0u8; len];
+        stream.read_exact(&mut buf).await?;
+
+        Ok::<_, anyhow::Error>(Message::from_vec(&buf)?)
+    };
+
+    tokio::time::timeout(QUERY_TIMEOUT, exchange)
+        .await
+        .map_err(|_| anyhow!("no answer from {}", server))?
+}
+
+// format_answer lays out the answer as dig does.
+pub fn format_answer(answer: &Answer) -> String {
+    let message = &answer.message;
+    let mut out = String::new();
+
+    out.push_str(&format!(
+        ";; status: {}, id: {}\n",
+        format!("{:?}", message.response_code()).to_uppercase(),
+        message.id()
+    ));
+
+    let flags: Vec<&str> = [
+        (message.message_type() == MessageType::Response, "qr"),
+        (message.authoritative(), "aa"),
+        (message.truncated(), "tc"),
+        (message.recursion_desired(), "rd"),
+        (message.recursion_available(), "ra"),
+        (message.authentic_data(), "ad"),
+        (message.checking_disabled(), "cd"),
+    ]
+    .iter()
+    .filter(|(set, _)| *set)
+    .map(|(_, flag)| *flag)
+    .collect();
+
+    out.push_str(&format!(
+        ";; flags: {}; QUERY: {}, ANSWER: {}, AUTHORITY: {}, ADDITIONAL: {}\n",
+        flags.join(" "),
+        message.queries().len(),
+        message.answers().len(),
+        message.name_servers().len(),
+        message.additionals().len()
+    ));
+
+    if let Some(edns) = message.edns() {
+        out.push_str(&format!(
+            "\n;; OPT PSEUDOSECTION:\n; EDNS: version: {}, udp: {}\n",
+            edns.version(),
+            edns.max_payload()
+        ));
+    }
+
+    out.push_str("\n;; QUESTION SECTION:\n");
+    for query in message.queries() {
+        out.push_str(&format!(
+            ";{}\t\t{}\t{}\n",
+            query.name(),
+            query.query_class(),
+            query.query_type()
+        ));
+    }
+
+    for (section, records) in [
+        ("ANSWER", message.answers()),
+        ("AUTHORITY", message.name_servers()),
+        ("ADDITIONAL", message.additionals()),
+    ] {
+        if records.is_empty() {
+            continue;
+        }
+
+        out.push_str(&format!("\n;; {} SECTION:\n", section));
+        for record in records {
+            out.push_str(&format_record(record));
+        }
+    }
+
+    out.push_str(&format!(
+        "\n;; Query time: {} msec\n;; SERVER: {}#{} ({})\n",
+        answer.elapsed.as_millis(),
+        answer.server.ip(),
+        answer.server.port(),
+        match answer.transport {
+            Transport::Udp => "UDP",
+            Transport::Tcp => "TCP",
+        }
+    ));
+
+    out
+}
+
+fn format_record(record: &Record) -> String {
+    format!(
+        "{}\t{}\t{}\t{}\t{}\n",
+        record.name(),
+        record.ttl(),
+        record.dns_class(),
+        record.record_type(),
+        record
+            .data()
+            .map(|rdata| rdata.to_string())
+            .unwrap_or_default()
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{format_answer, parse_server, query, Answer, Transport};
+    use std::{str::FromStr, time::Duration};
+    use tokio::net::UdpSocket;
+    use trust_dns_resolver::{
+        proto::{
+            op::{Message, MessageType, ResponseCode},
+            rr::{RData, Record, RecordType},
+        },
+        Name,
+    };
+
+    #[test]
+    fn test_parse_server() {
+        assert_eq!(
+            parse_server("10.147.20.1").unwrap(),
+            "10.147.20.1:53".parse().unwrap()
+        );
+        assert_eq!(
+            parse_server("[fd00::1]:5353").unwrap(),
+            "[fd00::1]:5353".parse().unwrap()
+        );
+        assert_eq!(
+            parse_server("fd00::1").unwrap(),
+            "[fd00::1]:53".parse().unwrap()
+        );
+        assert!(parse_server("db.home.arpa").is_err());
+    }
+
+    #[tokio::test]
+    async fn test_query() {
+        let server = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let addr = server.local_addr().unwrap();
+        let name = Name::from_str("db.home.arpa.").unwrap();
+
+        // answer the one query with an address, as an authoritative server would.
+        let answered = name.clone();
+        let responder = tokio::spawn(async move {
+            let mut buf = [0u8; 512];
+            let (len, peer) = server.recv_from(&mut buf).await.unwrap();
+            let request = Message::from_vec(&buf[..len]).unwrap();
+            assert!(request.edns().is_some());
+
+            let mut response = Message::new();
+            response
+                .set_id(request.id())
+                .set_message_type(MessageType::Response)
+                .set_authoritative(true)
+                .set_recursion_desired(request.recursion_desired())
+                .add_queries(request.queries().to_vec())
+                .add_answer(Record::from_rdata(
+                    answered,
+                    60,
+                    RData::A("10.147.20.5".parse().unwrap()),
+                ));
+            server
+                .send_to(&response.to_vec().unwrap(), peer)
+                .await
+                .unwrap();
+        });
+
+        let answer = query(addr, &name, RecordType::A, false).await.unwrap();
+        responder.await.unwrap();
+
+        assert_eq!(answer.transport, Transport::Udp);
+        assert_eq!(answer.message.response_code(), ResponseCode::NoError);
+        assert_eq!(answer.message.answers().len(), 1);
+
+        let printed = format_answer(&Answer {
+            elapsed: Duration::from_millis(3),
+            ..answer
+        });
+        assert!(
+            printed.starts_with(";; status: NOERROR, id: "),
+            "{}",
+            printed
+        );
+        assert!(printed.contains(";; flags: qr aa rd; QUERY: 1, ANSWER: 1"));
+        assert!(printed.contains(";db.home.arpa.\t\tIN\tA\n"), "{}", printed);
+        assert!(printed.contains("\n;; ANSWER SECTION:\ndb.home.arpa.\t60\tIN\tA\t10.147.20.5\n"));
+        assert!(printed.ends_with(&format!(
+            ";; Query time: 3 msec\n;; SERVER: 127.0.0.1#{} (UDP)\n",
+            addr.port()
+        )));
+    }
+}
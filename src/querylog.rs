@@ -0,0 +1,249 @@
+/// query logging as lines of JSON, to stdout or a rotated file.
+use std::{
+    collections::HashMap,
+    fs::{File, OpenOptions},
+    io::Write,
+    net::IpAddr,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use arc_swap::ArcSwap;
+use serde::Serialize;
+use trust_dns_server::{
+    client::rr::Name,
+    proto::{op::ResponseCode, rr::RecordType},
+};
+
+// size a query log file may grow to before it is rotated, in MiB, unless configured otherwise.
+pub const DEFAULT_MAX_SIZE: u64 = 10;
+
+// how many rotated files are kept, as <path>.1 (the most recent) to <path>.5.
+pub const ROTATED_FILES: usize = 5;
+
+// Entry is a line of the query log.
+#[derive(Serialize)]
+struct Entry {
+    // seconds since the epoch, with millisecond precision.
+    timestamp: f64,
+    client: IpAddr,
+    // the name of the member the client address is assigned to, if any.
+    member: Option<String>,
+    name: String,
+    #[serde(rename = "type")]
+    record_type: String,
+    rcode: String,
+}
+
+enum Output {
+    Stdout,
+    File {
+        path: PathBuf,
+        file: File,
+        size: u64,
+        max_size: u64,
+    },
+}
+
+// QueryLog writes the query log. It is shared by the listeners of a network, and told the names
+// of the members at every poll.
+#[derive(Clone)]
+pub struct QueryLog {
+    output: Arc<Mutex<Output>>,
+    members: Arc<ArcSwap<HashMap<IpAddr, Name>>>,
+}
+
+impl QueryLog {
+    // new logs to the file at the path, or to stdout when it is `-`. Files are rotated once they
+    // would grow past max_size bytes.
+    pub fn new(path: &Path, max_size: u64) -> Result<Self, anyhow::Error> {
+        let output = if path == Path::new("-") {
+            Output::Stdout
+        } else {
+            let file = open(path)?;
+
+            Output::File {
+                path: path.to_path_buf(),
+                size: file.metadata()?.len(),
+                file,
+                max_size,
+            }
+        };
+
+        Ok(Self {
+            output: Arc::new(Mutex::new(output)),
+            members: Arc::new(ArcSwap::from_pointee(HashMap::new())),
+        })
+    }
+
+    // sync_members replaces the names of the members clients are attributed to.
+    pub fn sync_members(&self, members: HashMap<IpAddr, Name>) {
+        self.members.store(Arc::new(members));
+    }
+
+    // record logs a request. Failures to write are logged, but do not keep requests from being
+    // answered.
+    pub fn record(
+        &self,
+        client: IpAddr,
+        name: String,
+        record_type: RecordType,
+        rcode: ResponseCode,
+    ) {
+        let entry = Entry {
+            timestamp: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map_or(0.0, |d| d.as_millis() as f64 / 1000.0),
+            client,
+            member: self
+                .members
+                .load()
+                .get(&client)
+                .map(|name| name.to_string()),
+            name,
+            record_type: record_type.to_string(),
+            rcode: format!("{:?}", rcode).to_uppercase(),
+        };
+
+        let mut line = match serde_json::to_string(&entry) {
+            Ok(line) => line,
+            Err(e) => {
+                tracing::warn!("Could not log query: {}", e);
+                return;
+            }
+        };
+        line.push('\n');
+
+        let mut output = self.output.lock().unwrap();
+
+        if let Err(e) = output.write(line.as_bytes()) {
+            tracing::warn!("Could not write to the query log: {}", e);
+        }
+    }
+}
+
+impl Output {
+    fn write(&mut self, line: &[u8]) -> Result<(), anyhow::Error> {
+        match self {
+            Output::Stdout => std::io::stdout().lock().write_all(line)?,
+            Output::File {
+                path,
+                file,
+                size,
+                max_size,
+            } => {
+                if *size > 0 && *size + line.len() as u64 > *max_size {
+                    rotate(path)?;
+                    *file = open(path)?;
+                    *size = 0;
+                }
+
+                file.write_all(line)?;
+                *size += line.len() as u64;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+fn open(path: &Path) -> Result<File, anyhow::Error> {
+    OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)
+        .map_err(|e| anyhow::anyhow!("Could not open query log {}: {}", path.display(), e))
+}
+
+fn rotated(path: &Path, n: usize) -> PathBuf {
+    let mut rotated = path.as_os_str().to_owned();
+    rotated.push(format!(".{}", n));
+    rotated.into()
+}
+
+// rotate moves the log to <path>.1, after moving each of the previous files one further, and
+// dropping the oldest.
+fn rotate(path: &Path) -> Result<(), anyhow::Error> {
+    for n in (1..ROTATED_FILES).rev() {
+        let from = rotated(path, n);
+
+        if from.exists() {
+            std::fs::rename(from, rotated(path, n + 1))?;
+        }
+    }
+
+    std::fs::rename(path, rotated(path, 1))?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{rotated, QueryLog, ROTATED_FILES};
+    use std::{collections::HashMap, net::IpAddr, str::FromStr};
+    use trust_dns_server::{
+        client::rr::Name,
+        proto::{op::ResponseCode, rr::RecordType},
+    };
+
+    #[test]
+    fn test_query_log() {
+        let dir = std::env::temp_dir().join("zeronsd-test-querylog");
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+
+        let path = dir.join("queries.log");
+        let log = QueryLog::new(&path, 1000).unwrap();
+
+        let member = IpAddr::from_str("10.147.20.9").unwrap();
+        log.sync_members(HashMap::from([(
+            member,
+            Name::from_str("islay.home.arpa.").unwrap(),
+        )]));
+
+        log.record(
+            member,
+            "jura.home.arpa.".to_string(),
+            RecordType::A,
+            ResponseCode::NoError,
+        );
+        log.record(
+            IpAddr::from_str("10.147.20.10").unwrap(),
+            "arran.home.arpa.".to_string(),
+            RecordType::AAAA,
+            ResponseCode::NXDomain,
+        );
+
+        let lines = std::fs::read_to_string(&path).unwrap();
+        let entries: Vec<serde_json::Value> = lines
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[0]["client"], "10.147.20.9");
+        assert_eq!(entries[0]["member"], "islay.home.arpa.");
+        assert_eq!(entries[0]["name"], "jura.home.arpa.");
+        assert_eq!(entries[0]["type"], "A");
+        assert_eq!(entries[0]["rcode"], "NOERROR");
+        assert!(entries[0]["timestamp"].as_f64().unwrap() > 0.0);
+        assert!(entries[1]["member"].is_null());
+        assert_eq!(entries[1]["rcode"], "NXDOMAIN");
+
+        // files are rotated at the size cap, and only so many are kept.
+        for _ in 0..100 {
+            log.record(
+                member,
+                "jura.home.arpa.".to_string(),
+                RecordType::A,
+                ResponseCode::NoError,
+            );
+        }
+
+        assert!(std::fs::metadata(&path).unwrap().len() <= 1000);
+        assert!(rotated(&path, ROTATED_FILES).exists());
+        assert!(!rotated(&path, ROTATED_FILES + 1).exists());
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
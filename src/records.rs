@@ -0,0 +1,224 @@
+/// static records from the `records` section of the configuration file.
+use std::{
+    collections::BTreeMap,
+    net::{Ipv4Addr, Ipv6Addr},
+    str::FromStr,
+};
+
+use anyhow::anyhow;
+use serde::{Deserialize, Deserializer};
+use trust_dns_resolver::proto::rr::{
+    rdata::{MX, SRV, TXT},
+    RData,
+};
+use trust_dns_server::client::rr::Name;
+
+// Records is the `records` section as written in the configuration file.
+pub type Records = BTreeMap<String, Vec<String>>;
+
+// deserialize_records accepts either a single record or a list of them for each name.
+pub fn deserialize_records<'de, D>(deserializer: D) -> Result<Records, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum OneOrMany {
+        One(String),
+        Many(Vec<String>),
+    }
+
+    Ok(BTreeMap::<String, OneOrMany>::deserialize(deserializer)?
+        .into_iter()
+        .map(|(name, records)| match records {
+            OneOrMany::One(record) => (name, vec![record]),
+            OneOrMany::Many(records) => (name, records),
+        })
+        .collect())
+}
+
+// parse_records turns the records into names under the domain and their data.
+pub fn parse_records(
+    records: &Records,
+    domain: &Name,
+) -> Result<Vec<(Name, RData)>, anyhow::Error> {
+    let mut parsed = Vec::new();
+
+    for (name, values) in records {
+        let fqdn = parse_name(name, domain)?;
+
+        for value in values {
+            let rdata = parse_rdata(value, domain)
+                .map_err(|e| anyhow!("Invalid record for {}: {}: {}", name, value, e))?;
+            parsed.push((fqdn.clone(), rdata));
+        }
+    }
+
+    Ok(parsed)
+}
+
+// parse_name makes a name relative to the domain unless it ends in a period.
+pub fn parse_name(name: &str, domain: &Name) -> Result<Name, anyhow::Error> {
+    let name = name.trim();
+
+    if name == "@" {
+        return Ok(domain.clone());
+    }
+
+    if name.ends_with('.') {
+        return Ok(Name::from_str(name)?);
+    }
+
+    Ok(Name::from_str(name)?.append_domain(domain)?)
+}
+
+fn parse_rdata(value: &str, domain: &Name) -> Result<RData, anyhow::Error> {
+    let value = value.trim();
+    let (rtype, rest) = value.split_once(char::is_whitespace).unwrap_or((value, ""));
+    let rest = rest.trim();
+    let fields: Vec<&str> = rest.split_whitespace().collect();
+
+    let field = |i: usize| {
+        fields
+            .get(i)
+            .copied()
+            .ok_or_else(|| anyhow!("{} record is missing fields", rtype))
+    };
+
+    Ok(match rtype.to_uppercase().as_str() {
+        "A" => RData::A(Ipv4Addr::from_str(field(0)?)?),
+        "AAAA" => RData::AAAA(Ipv6Addr::from_str(field(0)?)?),
+        "CNAME" => RData::CNAME(parse_name(field(0)?, domain)?),
+        "MX" => RData::MX(MX::new(field(0)?.parse()?, parse_name(field(1)?, domain)?)),
+        "SRV" => RData::SRV(SRV::new(
+            field(0)?.parse()?,
+            field(1)?.parse()?,
+            field(2)?.parse()?,
+            parse_name(field(3)?, domain)?,
+        )),
+        "TXT" => {
+            if rest.is_empty() {
+                return Err(anyhow!("TXT record is empty"));
+            }
+
+            RData::TXT(TXT::new(vec![rest.trim_matches('"').to_string()]))
+        }
+        _ => {
+            return Err(anyhow!(
+                "unsupported record type {}; use A, AAAA, CNAME, MX, SRV or TXT",
+                rtype
+            ))
+        }
+    })
+}
+
+// format_rdata writes the data of a record the way parse_rdata reads it, with absolute names.
+// Types parse_rdata does not support give None.
+pub fn format_rdata(rdata: &RData) -> Option<String> {
+    Some(match rdata {
+        RData::A(ip) => format!("A {}", ip),
+        RData::AAAA(ip) => format!("AAAA {}", ip),
+        RData::CNAME(name) => format!("CNAME {}", fqdn(name)),
+        RData::MX(mx) => format!("MX {} {}", mx.preference(), fqdn(mx.exchange())),
+        RData::SRV(srv) => format!(
+            "SRV {} {} {} {}",
+            srv.priority(),
+            srv.weight(),
+            srv.port(),
+            fqdn(srv.target())
+        ),
+        RData::TXT(txt) => format!(
+            "TXT \"{}\"",
+            txt.iter()
+                .map(|s| String::from_utf8_lossy(s).into_owned())
+                .collect::<String>()
+        ),
+        _ => return None,
+    })
+}
+
+fn fqdn(name: &Name) -> String {
+    let name = name.to_string();
+    if name.ends_with('.') {
+        name
+    } else {
+        format!("{}.", name)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+
+    use super::{format_rdata, parse_rdata, parse_records, Records};
+    use trust_dns_resolver::proto::rr::{rdata::SRV, RData};
+    use trust_dns_server::client::rr::Name;
+
+    #[test]
+    fn test_parse_records() {
+        let domain = Name::from_str("home.arpa.").unwrap();
+        let mut records = Records::new();
+        records.insert(
+            "_ldap._tcp".to_string(),
+            vec!["SRV 0 5 389 dc1".to_string()],
+        );
+        records.insert(
+            "@".to_string(),
+            vec!["MX 10 mail".to_string(), "TXT \"v=spf1 -all\"".to_string()],
+        );
+        records.insert(
+            "wiki".to_string(),
+            vec!["CNAME www.example.com.".to_string()],
+        );
+
+        let parsed = parse_records(&records, &domain).unwrap();
+        assert_eq!(parsed.len(), 4);
+
+        assert!(parsed.contains(&(
+            Name::from_str("_ldap._tcp.home.arpa.").unwrap(),
+            RData::SRV(SRV::new(
+                0,
+                5,
+                389,
+                Name::from_str("dc1.home.arpa.").unwrap()
+            ))
+        )));
+        assert!(parsed.contains(&(
+            Name::from_str("wiki.home.arpa.").unwrap(),
+            RData::CNAME(Name::from_str("www.example.com.").unwrap())
+        )));
+        assert!(parsed
+            .iter()
+            .any(|(name, rdata)| name == &domain && matches!(rdata, RData::MX(_))));
+    }
+
+    #[test]
+    fn test_parse_records_invalid() {
+        let domain = Name::from_str("home.arpa.").unwrap();
+
+        for record in ["SRV 0 5 dc1", "MX mail", "A 10.0.0", "HINFO x y", "TXT"] {
+            let mut records = Records::new();
+            records.insert("bad".to_string(), vec![record.to_string()]);
+            assert!(parse_records(&records, &domain).is_err(), "{}", record);
+        }
+    }
+
+    #[test]
+    fn test_format_rdata() {
+        let domain = Name::from_str("home.arpa.").unwrap();
+
+        for record in [
+            "A 10.147.20.1",
+            "AAAA fd00::1",
+            "CNAME islay.home.arpa.",
+            "MX 10 mail.home.arpa.",
+            "SRV 0 5 389 dc1.home.arpa.",
+            "TXT \"v=spf1 -all\"",
+        ] {
+            let rdata = parse_rdata(record, &domain).unwrap();
+            assert_eq!(format_rdata(&rdata).unwrap(), record);
+        }
+
+        assert_eq!(format_rdata(&RData::NULL(Default::default())), None);
+    }
+}
@@ -0,0 +1,281 @@
+/// replication of the members of networks from a primary to its secondaries.
+use std::{
+    net::SocketAddr,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use anyhow::anyhow;
+use async_trait::async_trait;
+use openssl::memcmp;
+use tokio::{net::TcpListener, task::JoinHandle};
+use zerotier_central_api::types::{Member, Network};
+
+use crate::{
+    admin::Networks,
+    cluster::ClusterStatus,
+    controller::MemberSource,
+    http::{self, Request, Response},
+    snapshot::Snapshot,
+};
+
+// how long a secondary waits on the primary.
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+
+// Replica is the network and members a primary last fetched, handed to its secondaries.
+#[derive(Clone, Default)]
+pub struct Replica(Arc<Mutex<Option<Snapshot>>>);
+
+impl Replica {
+    pub fn set(&self, network: &Network, members: &[Member]) {
+        *self.0.lock().unwrap() = Some(Snapshot {
+            network: network.clone(),
+            members: members.to_vec(),
+        });
+    }
+
+    pub fn get(&self) -> Option<Snapshot> {
+        self.0.lock().unwrap().clone()
+    }
+}
+
+// listen binds the address and serves the members of the networks to secondaries on it in the
+// background. node_id is the ZeroTier address of the member zeronsd runs on.
+pub async fn listen(
+    addr: SocketAddr,
+    secret: Vec<u8>,
+    networks: Networks,
+    node_id: String,
+) -> Result<JoinHandle<()>, anyhow::Error> {
+    let listener = TcpListener::bind(addr)
+        .await
+        .map_err(|e| anyhow!("could not listen for secondaries on {}: {}", addr, e))?;
+
+    tracing::info!("Serving secondaries on http://{}", addr);
+
+    Ok(tokio::spawn(async move {
+        loop {
+            match listener.accept().await {
+                Ok((stream, peer)) => {
+                    let secret = secret.clone();
+                    let networks = networks.clone();
+                    let node_id = node_id.clone();
+                    tokio::spawn(async move {
+                        let answer = move |request| async move {
+                            respond(&request, &secret, &networks, &node_id)
+                        };
+
+                        if let Err(e) = http::handle(stream, answer).await {
+                            tracing::debug!("Replication request from {} failed: {}", peer, e);
+                        }
+                    });
+                }
+                Err(e) => tracing::warn!("Could not accept secondary: {}", e),
+            }
+        }
+    }))
+}
+
+// respond answers a secondary with the members of the network it asks for, or another member of
+// the cluster with the status of this instance, once it is authenticated.
+fn respond(request: &Request, secret: &[u8], networks: &Networks, node_id: &str) -> Response {
+    if !authorized(request, secret) {
+        tracing::warn!("Rejected replication request for {}", request.path);
+        return Response::error(401, "invalid secret");
+    }
+
+    let network_id = match request.segments().as_slice() {
+        ["networks", network_id] => network_id.to_string(),
+        ["cluster"] if request.method == "GET" => {
+            return Response::json(
+                200,
+                &ClusterStatus {
+                    node_id: node_id.to_string(),
+                    networks: networks.all().iter().map(|zt| zt.health.status()).collect(),
+                },
+            )
+        }
+        ["cluster"] => return Response::error(405, "method not allowed"),
+        _ => return Response::error(404, "not found"),
+    };
+
+    if request.method != "GET" {
+        return Response::error(405, "method not allowed");
+    }
+
+    let zt = match networks.get(&network_id) {
+        Some(zt) => zt,
+        None => return Response::error(404, format!("network {} is not served", network_id)),
+    };
+
+    match zt.replica.get() {
+        Some(snapshot) => Response::json(200, &snapshot),
+        None => Response::error(
+            503,
+            format!("the members of {} have not been fetched yet", network_id),
+        ),
+    }
+}
+
+// authorized compares the secret of the request with ours, in constant time.
+fn authorized(request: &Request, secret: &[u8]) -> bool {
+    match request
+        .header("authorization")
+        .and_then(|header| header.strip_prefix("Bearer "))
+    {
+        Some(given) => given.len() == secret.len() && memcmp::eq(given.as_bytes(), secret),
+        None => false,
+    }
+}
+
+// Primary is the zeronsd secondaries read the network and members from, instead of Central. It
+// pushes no DNS settings anywhere; the primary does, and publishes its secondaries with --peer.
+pub struct Primary {
+    url: String,
+    secret: String,
+    client: reqwest::Client,
+}
+
+impl Primary {
+    pub fn new(url: &str, secret: Vec<u8>) -> Result<Self, anyhow::Error> {
+        reqwest::Url::parse(url).map_err(|e| anyhow!("Invalid primary {}: {}", url, e))?;
+
+        Ok(Self {
+            url: url.trim_end_matches('/').to_string(),
+            secret: String::from_utf8(secret)?,
+            client: reqwest::Client::builder()
+                .timeout(REQUEST_TIMEOUT)
+                .build()?,
+        })
+    }
+
+    pub fn url(&self) -> &str {
+        &self.url
+    }
+
+    // snapshot fetches what the primary serves of the network. Failures are reqwest errors, so
+    // that the refresh loop retries and backs off as it does for Central.
+    async fn snapshot(&self, network_id: &str) -> Result<Snapshot, anyhow::Error> {
+        self.get(&format!("/networks/{}", network_id)).await
+    }
+
+    // cluster_status asks the instance for its status, as a member of a cluster.
+    pub async fn cluster_status(&self) -> Result<ClusterStatus, anyhow::Error> {
+        self.get("/cluster").await
+    }
+
+    async fn get<T: serde::de::DeserializeOwned>(&self, path: &str) -> Result<T, anyhow::Error> {
+        Ok(self
+            .client
+            .get(format!("{}{}", self.url, path))
+            .bearer_auth(&self.secret)
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await?)
+    }
+}
+
+#[async_trait]
+impl MemberSource for Primary {
+    async fn network(&self, network_id: &str) -> Result<Network, anyhow::Error> {
+        Ok(self.snapshot(network_id).await?.network)
+    }
+
+    async fn members(&self, network_id: &str) -> Result<Vec<Member>, anyhow::Error> {
+        Ok(self.snapshot(network_id).await?.members)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{authorized, respond, Primary, Replica};
+    use crate::{admin::Networks, http::Request};
+
+    fn request(method: &str, path: &str, authorization: Option<&str>) -> Request {
+        Request {
+            method: method.to_string(),
+            path: path.to_string(),
+            headers: authorization
+                .map(|value| ("authorization".to_string(), value.to_string()))
+                .into_iter()
+                .collect(),
+            body: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn test_authorized() {
+        let secret = b"s3cret";
+
+        assert!(authorized(
+            &request("GET", "/networks/x", Some("Bearer s3cret")),
+            secret
+        ));
+        assert!(!authorized(
+            &request("GET", "/networks/x", Some("Bearer s3cre")),
+            secret
+        ));
+        assert!(!authorized(
+            &request("GET", "/networks/x", Some("Basic s3cret")),
+            secret
+        ));
+        assert!(!authorized(&request("GET", "/networks/x", None), secret));
+    }
+
+    #[test]
+    fn test_respond() {
+        let networks = Networks::default();
+        let get = |path, authorization| {
+            respond(
+                &request("GET", path, authorization),
+                b"s3cret",
+                &networks,
+                "abcdef0123",
+            )
+            .status
+        };
+
+        assert_eq!(get("/networks/8056c2e21c000001", None), 401);
+        assert_eq!(
+            get("/networks/8056c2e21c000001", Some("Bearer s3cret")),
+            404
+        );
+        assert_eq!(get("/networks", Some("Bearer s3cret")), 404);
+        assert_eq!(get("/cluster", None), 401);
+        assert_eq!(get("/cluster", Some("Bearer s3cret")), 200);
+
+        let post = request("POST", "/networks/8056c2e21c000001", Some("Bearer s3cret"));
+        assert_eq!(
+            respond(&post, b"s3cret", &networks, "abcdef0123").status,
+            405
+        );
+    }
+
+    #[test]
+    fn test_replica() {
+        let replica = Replica::default();
+        assert!(replica.get().is_none());
+
+        let network =
+            serde_json::from_value(serde_json::json!({ "id": "8056c2e21c000001" })).unwrap();
+        let member = serde_json::from_value(serde_json::json!({ "nodeId": "abcdef0123" })).unwrap();
+        replica.set(&network, &[member]);
+
+        let snapshot = replica.clone().get().unwrap();
+        assert_eq!(snapshot.network.id, Some("8056c2e21c000001".to_string()));
+        assert_eq!(snapshot.members[0].node_id, Some("abcdef0123".to_string()));
+    }
+
+    #[test]
+    fn test_primary() {
+        assert!(Primary::new("10.147.17.2:8056", b"s3cret".to_vec()).is_err());
+        assert_eq!(
+            Primary::new("http://10.147.17.2:8056/", b"s3cret".to_vec())
+                .unwrap()
+                .url,
+            "http://10.147.17.2:8056"
+        );
+    }
+}
@@ -0,0 +1,367 @@
+/// response policy zones (RPZ) applied to the zones served and forwarded queries.
+use std::{collections::HashMap, path::PathBuf, str::FromStr, sync::Arc};
+
+use anyhow::anyhow;
+use arc_swap::ArcSwap;
+use serde::{Deserialize, Serialize};
+use trust_dns_server::client::{
+    rr::{DNSClass, Name, RData, Record, RecordType},
+    serialize::txt::{Lexer, Parser},
+};
+
+// RpzConfig is a policy zone, as given in the `rpz` section of the configuration file or with
+// --rpz ZONE=PATH.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RpzConfig {
+    pub zone: String,
+    pub file: PathBuf,
+}
+
+impl FromStr for RpzConfig {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.split_once('=') {
+            Some((zone, file)) if !zone.is_empty() && !file.is_empty() => Ok(Self {
+                zone: zone.to_string(),
+                file: PathBuf::from(file),
+            }),
+            _ => Err(anyhow!(
+                "expected ZONE=PATH, e.g. rpz.example=/etc/zeronsd/rpz.db"
+            )),
+        }
+    }
+}
+
+// Policy is what is done with a query matching a trigger.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Policy {
+    // CNAME . answers that the name does not exist.
+    Nxdomain,
+    // CNAME *. answers with no records.
+    Nodata,
+    // CNAME rpz-passthru. answers as if there were no policy, exempting the name from the
+    // policies of later zones and from blocklists.
+    Passthru,
+    // CNAME rpz-drop. sends no answer.
+    Drop,
+    // CNAME rpz-tcp-only. answers queries over UDP with an empty truncated answer, so that they
+    // are asked again over TCP, where they are answered as if there were no policy.
+    TcpOnly,
+    // any other records are the answer, in place of the name's own; a CNAME is followed.
+    Local(Vec<Record>),
+}
+
+// PolicyZone holds the triggers of a zone.
+struct PolicyZone {
+    origin: Name,
+    exact: HashMap<Name, Policy>,
+    // keyed by the domain the names under which are matched.
+    wildcard: HashMap<Name, Policy>,
+}
+
+impl PolicyZone {
+    // parse reads the triggers of a zone file.
+    fn parse(origin: Name, contents: &str) -> Result<Self, anyhow::Error> {
+        let (origin, records) = Parser::new()
+            .parse(Lexer::new(contents), Some(origin), Some(DNSClass::IN))
+            .map_err(|e| anyhow!("{}", e))?;
+
+        let mut triggers: HashMap<Name, Vec<Record>> = HashMap::new();
+        let mut skipped = 0;
+
+        for rrset in records.values() {
+            let name = rrset.name();
+
+            if name == &origin || !origin.zone_of(name) {
+                continue;
+            }
+
+            let labels = name.iter().count() - origin.iter().count();
+            let trigger = Name::from_labels(name.iter().take(labels))?;
+
+            if trigger.iter().any(|label| {
+                matches!(
+                    label,
+                    b"rpz-ip" | b"rpz-nsip" | b"rpz-nsdname" | b"rpz-client-ip"
+                )
+            }) {
+                skipped += 1;
+                continue;
+            }
+
+            triggers
+                .entry(trigger)
+                .or_default()
+                .extend(rrset.records_without_rrsigs().cloned());
+        }
+
+        if skipped > 0 {
+            tracing::warn!(
+                "Skipped {} triggers of {} other than QNAME triggers, which are not supported",
+                skipped,
+                origin
+            );
+        }
+
+        let (mut exact, mut wildcard) = (HashMap::new(), HashMap::new());
+
+        for (trigger, records) in triggers {
+            let policy = match policy(&records) {
+                Some(policy) => policy,
+                None => {
+                    tracing::warn!("Skipped unsupported policy of {} in {}", trigger, origin);
+                    continue;
+                }
+            };
+
+            if trigger.is_wildcard() {
+                wildcard.insert(trigger.base_name(), policy);
+            } else {
+                exact.insert(trigger, policy);
+            }
+        }
+
+        Ok(Self {
+            origin,
+            exact,
+            wildcard,
+        })
+    }
+
+    // policy returns the policy of the name: that of its own trigger, or else that of the closest
+    // wildcard trigger above it.
+    fn policy(&self, name: &Name) -> Option<&Policy> {
+        if let Some(policy) = self.exact.get(name) {
+            return Some(policy);
+        }
+
+        let mut domain = name.base_name();
+        loop {
+            if let Some(policy) = self.wildcard.get(&domain) {
+                return Some(policy);
+            }
+
+            if domain.is_root() {
+                return None;
+            }
+
+            domain = domain.base_name();
+        }
+    }
+}
+
+// policy reads the action of a trigger's records.
+fn policy(records: &[Record]) -> Option<Policy> {
+    let cname = records.iter().find_map(|record| match record.data() {
+        Some(RData::CNAME(target)) => Some(target),
+        _ => None,
+    });
+
+    match cname {
+        Some(target) if target.is_root() => Some(Policy::Nxdomain),
+        Some(target) if target.is_wildcard() && target.base_name().is_root() => {
+            Some(Policy::Nodata)
+        }
+        Some(target) if target == &Name::from_ascii("rpz-passthru.").unwrap() => {
+            Some(Policy::Passthru)
+        }
+        Some(target) if target == &Name::from_ascii("rpz-drop.").unwrap() => Some(Policy::Drop),
+        Some(target) if target == &Name::from_ascii("rpz-tcp-only.").unwrap() => {
+            Some(Policy::TcpOnly)
+        }
+        _ => Some(Policy::Local(records.to_vec())),
+    }
+}
+
+// Rpz holds the policy zones. It is shared by the listeners of a network, and replaced as a
+// whole when the zones are read again.
+#[derive(Clone, Default)]
+pub struct Rpz(Arc<ArcSwap<Vec<PolicyZone>>>);
+
+impl Rpz {
+    // load reads the zones. The zones read so far are kept if any of them cannot be read.
+    pub fn load(&self, configs: &[RpzConfig]) -> Result<(), anyhow::Error> {
+        let mut zones = Vec::new();
+
+        for config in configs {
+            let origin = Name::from_str(&config.zone)?.append_domain(&Name::root())?;
+            let contents = std::fs::read_to_string(&config.file).map_err(|e| {
+                anyhow!(
+                    "Could not read policy zone {}: {}",
+                    config.file.display(),
+                    e
+                )
+            })?;
+
+            let zone = PolicyZone::parse(origin, &contents)
+                .map_err(|e| anyhow!("Invalid policy zone {}: {}", config.file.display(), e))?;
+
+            tracing::info!(
+                "Loaded {} triggers from policy zone {}",
+                zone.exact.len() + zone.wildcard.len(),
+                zone.origin
+            );
+            zones.push(zone);
+        }
+
+        self.0.store(Arc::new(zones));
+        Ok(())
+    }
+
+    // policy returns the policy of the first zone, in the order they are configured in, with a
+    // trigger for the name.
+    pub fn policy(&self, name: &Name) -> Option<Policy> {
+        self.0
+            .load()
+            .iter()
+            .find_map(|zone| zone.policy(name).cloned())
+    }
+}
+
+// local_answers are the records of a local policy answering a query for the name and type, with
+// the name as their owner. A CNAME answers queries of every type; one to a wildcard name, as in
+// `*.garden.example.`, points at the name under that domain instead.
+pub fn local_answers(records: &[Record], name: &Name, query_type: RecordType) -> Vec<Record> {
+    let cname = records
+        .iter()
+        .find(|record| record.record_type() == RecordType::CNAME);
+
+    let answers: Vec<&Record> = match cname {
+        Some(cname) => vec![cname],
+        None => records
+            .iter()
+            .filter(|record| query_type == RecordType::ANY || record.record_type() == query_type)
+            .collect(),
+    };
+
+    answers
+        .into_iter()
+        .filter_map(|record| {
+            let rdata = match record.data()? {
+                RData::CNAME(target) if target.is_wildcard() => {
+                    RData::CNAME(name.clone().append_domain(&target.base_name()).ok()?)
+                }
+                rdata => rdata.clone(),
+            };
+
+            Some(Record::from_rdata(name.clone(), record.ttl(), rdata))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{local_answers, Policy, Rpz, RpzConfig};
+    use std::{net::Ipv4Addr, str::FromStr};
+    use trust_dns_server::client::rr::{Name, RData, RecordType};
+
+    const ZONE: &str = "$TTL 300
+@ IN SOA localhost. root.localhost. 1 3600 600 86400 300
+@ IN NS localhost.
+bad.example.com CNAME .
+*.bad.example.com CNAME .
+empty.example.com CNAME *.
+good.bad.example.com CNAME rpz-passthru.
+silent.example.com CNAME rpz-drop.
+large.example.com CNAME rpz-tcp-only.
+moved.example.com CNAME walled.garden.example.
+*.tracked.example.com CNAME *.garden.example.
+local.example.com A 10.147.20.1
+32.1.20.147.10.rpz-ip CNAME .
+";
+
+    #[test]
+    fn test_rpz_config() {
+        assert_eq!(
+            RpzConfig::from_str("rpz.example=/etc/zeronsd/rpz.db").unwrap(),
+            RpzConfig {
+                zone: "rpz.example".to_string(),
+                file: "/etc/zeronsd/rpz.db".into(),
+            }
+        );
+        assert!(RpzConfig::from_str("/etc/zeronsd/rpz.db").is_err());
+    }
+
+    #[test]
+    fn test_rpz() {
+        let dir = std::env::temp_dir().join("zeronsd-test-rpz");
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+
+        let file = dir.join("rpz.db");
+        std::fs::write(&file, ZONE).unwrap();
+
+        let rpz = Rpz::default();
+        rpz.load(&[RpzConfig {
+            zone: "rpz.example".to_string(),
+            file,
+        }])
+        .unwrap();
+
+        let name = |s: &str| Name::from_str(s).unwrap();
+
+        for (query, policy) in [
+            ("bad.example.com.", Some(Policy::Nxdomain)),
+            ("BAD.example.com.", Some(Policy::Nxdomain)),
+            ("www.bad.example.com.", Some(Policy::Nxdomain)),
+            ("good.bad.example.com.", Some(Policy::Passthru)),
+            ("empty.example.com.", Some(Policy::Nodata)),
+            ("silent.example.com.", Some(Policy::Drop)),
+            ("large.example.com.", Some(Policy::TcpOnly)),
+            ("example.com.", None),
+            ("tracked.example.com.", None),
+            ("10.147.20.1.", None),
+        ] {
+            assert_eq!(rpz.policy(&name(query)), policy, "{}", query);
+        }
+
+        // CNAMEs answer queries of every type.
+        let moved = name("moved.example.com.");
+        match rpz.policy(&moved) {
+            Some(Policy::Local(records)) => {
+                let answers = local_answers(&records, &moved, RecordType::AAAA);
+                assert_eq!(answers.len(), 1);
+                assert_eq!(answers[0].name(), &moved);
+                assert_eq!(
+                    answers[0].data(),
+                    Some(&RData::CNAME(name("walled.garden.example.")))
+                );
+            }
+            policy => panic!("unexpected policy {:?}", policy),
+        }
+
+        // wildcard targets keep the name queried.
+        let tracked = name("www.tracked.example.com.");
+        match rpz.policy(&tracked) {
+            Some(Policy::Local(records)) => {
+                let answers = local_answers(&records, &tracked, RecordType::A);
+                assert_eq!(
+                    answers[0].data(),
+                    Some(&RData::CNAME(name(
+                        "www.tracked.example.com.garden.example."
+                    )))
+                );
+            }
+            policy => panic!("unexpected policy {:?}", policy),
+        }
+
+        // other records only answer queries of their type.
+        let local = name("local.example.com.");
+        match rpz.policy(&local) {
+            Some(Policy::Local(records)) => {
+                let answers = local_answers(&records, &local, RecordType::A);
+                assert_eq!(answers.len(), 1);
+                assert_eq!(answers[0].ttl(), 300);
+                assert_eq!(
+                    answers[0].data(),
+                    Some(&RData::A(Ipv4Addr::new(10, 147, 20, 1)))
+                );
+                assert!(local_answers(&records, &local, RecordType::TXT).is_empty());
+            }
+            policy => panic!("unexpected policy {:?}", policy),
+        }
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
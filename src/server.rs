@@ -1,42 +1,233 @@
 use std::{
-    net::{IpAddr, SocketAddr},
-    time::Duration,
+    collections::HashMap,
+    io::ErrorKind,
+    net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr},
+    path::PathBuf,
+    sync::atomic::{AtomicUsize, Ordering},
+    time::{Duration, Instant, SystemTime},
 };
-use tracing::info;
+use tracing::{debug, info, info_span, warn, Instrument, Span};
 
 use openssl::{
     pkey::{PKey, Private},
     stack::Stack,
     x509::X509,
 };
-use tokio::net::{TcpListener, UdpSocket};
+use tokio::{
+    net::{TcpListener, UdpSocket},
+    task::JoinHandle,
+};
+
+use trust_dns_resolver::error::ResolveErrorKind;
+use trust_dns_server::{
+    authority::{AuthorityObject, Catalog, LookupError, LookupOptions, MessageResponseBuilder},
+    proto::{
+        op::{Header, MessageType, OpCode, ResponseCode},
+        rr::{LowerName, Name, RData, Record, RecordType},
+        rustls::tls_server::{read_cert, read_key},
+    },
+    server::{Protocol, Request, RequestHandler, ResponseHandler, ResponseInfo, ServerFuture},
+};
+
+use crate::{
+    acl::{AclAction, QueryAcl},
+    activation,
+    authority::{init_catalog, RecordAuthority, ZTAuthority},
+    blocklist::{BlockAction, Blocklist, BLOCKED_TTL},
+    cache::{Answer, ForwardCache},
+    classless::Classless,
+    dnstap::Dnstap,
+    edns::{EdnsConfig, EdnsResponse, UdpResponse},
+    idn::to_a_labels,
+    ipnames::IpNames,
+    querylog::QueryLog,
+    rpz::{local_answers, Policy, Rpz},
+    transfer::soa_serial,
+    tsig::verify,
+    update::DynamicZone,
+    upstream::Upstreams,
+    views::Views,
+};
+
+// the requests being answered by every listener; see drain.
+static IN_FLIGHT: AtomicUsize = AtomicUsize::new(0);
+
+// InFlight counts a request as being answered for as long as it lives.
+struct InFlight;
+
+impl InFlight {
+    fn new() -> Self {
+        IN_FLIGHT.fetch_add(1, Ordering::SeqCst);
+        Self
+    }
+}
+
+impl Drop for InFlight {
+    fn drop(&mut self) {
+        IN_FLIGHT.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+// drain waits for the requests being answered to finish, for up to the grace period, and tells
+// whether they did. The listeners are meant to be closed by then, so that no more come in.
+pub async fn drain(grace: Duration) -> bool {
+    let deadline = Instant::now() + grace;
+
+    while IN_FLIGHT.load(Ordering::SeqCst) > 0 {
+        if Instant::now() >= deadline {
+            return false;
+        }
+
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    }
 
-use trust_dns_server::server::ServerFuture;
+    true
+}
 
-use crate::authority::{init_catalog, ZTAuthority};
+// DoHConfig is the configuration of the DNS-over-HTTPS listener.
+#[derive(Clone, Debug)]
+pub struct DoHConfig {
+    pub port: u16,
+    pub hostname: String,
+    pub cert: PathBuf,
+    pub key: PathBuf,
+}
 
 #[derive(Clone)]
-pub struct Server(ZTAuthority);
+pub struct Server {
+    zt: ZTAuthority,
+    // the UDP sockets every address is served with; see bind_udp.
+    workers: usize,
+}
 
 impl Server {
     pub fn new(zt: ZTAuthority) -> Self {
-        Self(zt)
+        Self { zt, workers: 1 }
+    }
+
+    // with_workers serves every address with this many UDP sockets, each with its own receive
+    // loop.
+    pub fn with_workers(self, workers: usize) -> Self {
+        Self {
+            workers: workers.max(1),
+            ..self
+        }
     }
 
     // listener routine for TCP and UDP.
     pub async fn listen(
         self,
-        ip: IpAddr,
+        sa: SocketAddr,
         tcp_timeout: Duration,
         certs: Option<X509>,
         cert_chain: Option<Stack<X509>>,
         key: Option<PKey<Private>>,
+        doh: Option<DoHConfig>,
     ) -> Result<(), anyhow::Error> {
-        let sa = SocketAddr::new(ip, 53);
-        let tcp = TcpListener::bind(sa).await?;
-        let udp = UdpSocket::bind(sa).await?;
+        let sf = self
+            .bind(sa, tcp_timeout, certs, cert_chain, key, doh)
+            .await?;
+
+        match sf.block_until_done().await {
+            Ok(_) => Ok(()),
+            Err(e) => Err(anyhow::anyhow!("{}", e)),
+        }
+    }
 
-        let mut sf = ServerFuture::new(init_catalog(self.0).await?);
+    // bind opens every listener for the address; DNS-over-TLS and DNS-over-HTTPS are served on the
+    // same IP, on their own ports. Queries are answered once the returned server is awaited.
+    pub async fn bind(
+        self,
+        sa: SocketAddr,
+        tcp_timeout: Duration,
+        certs: Option<X509>,
+        cert_chain: Option<Stack<X509>>,
+        key: Option<PKey<Private>>,
+        doh: Option<DoHConfig>,
+    ) -> Result<ServerFuture<Handler>, anyhow::Error> {
+        let ip = sa.ip();
+        let (tcp, udp) = bind_sockets(sa, self.workers).await?;
+
+        let dynamic = self.zt.dynamic_zone.clone();
+        let zones = std::iter::once(self.zt.forward_authority.clone())
+            .chain(self.zt.alias_authorities.iter().cloned())
+            .chain(self.zt.reverse_authority_map.values().cloned())
+            .chain(dynamic.iter().map(|zone| zone.authority().clone()))
+            .collect();
+
+        let cache = self.zt.cache.clone();
+
+        // configured upstreams are always resolved with here, so that queries go to the healthiest.
+        let resolver = match self.zt.upstreams.clone() {
+            Some(upstreams) => Some(upstreams),
+            None if self.zt.dnssec_validation || self.zt.qname_minimization || cache.is_some() => {
+                Some(Upstreams::system(self.zt.dnssec_validation)?)
+            }
+            None => None,
+        }
+        .map(|upstreams| upstreams.with_qname_minimization(self.zt.qname_minimization));
+
+        let acl = self.zt.query_acl.clone();
+        let log = self.zt.query_log.clone();
+        let dnstap = self.zt.dnstap.clone();
+        let blocklist = self.zt.blocklist.clone();
+        let rpz = self.zt.rpz.clone();
+        let ip_names = self.zt.ip_names.then(|| {
+            IpNames::new(
+                std::iter::once(self.zt.forward_authority.origin().clone())
+                    .chain(
+                        self.zt
+                            .alias_authorities
+                            .iter()
+                            .map(|zone| zone.origin().clone()),
+                    )
+                    .collect(),
+                self.zt.reverse_authority_map.keys().cloned().collect(),
+                self.zt.forward_authority.ttl(),
+            )
+        });
+        let classless = self
+            .zt
+            .reverse_authority_map
+            .values()
+            .filter_map(|zone| zone.classless().cloned())
+            .collect();
+        let edns = self.zt.edns.clone();
+        let query_timeout = self.zt.query_timeout;
+
+        let views = self.zt.views.clone();
+        let view_catalogs = self
+            .zt
+            .view_zones
+            .iter()
+            .map(|zones| {
+                let mut catalog = Catalog::new();
+                for zone in zones.zones() {
+                    catalog.upsert(zone.origin().clone(), zone.box_clone());
+                }
+
+                (zones.view, catalog)
+            })
+            .collect();
+
+        let mut sf = ServerFuture::new(Handler {
+            acl,
+            log,
+            dnstap,
+            blocklist,
+            rpz,
+            ip_names,
+            classless,
+            edns,
+            query_timeout,
+            catalog: init_catalog(self.zt).await?,
+            views,
+            view_catalogs,
+            zones,
+            dynamic,
+            cache,
+            resolver,
+        });
 
         if let (Some(certs), Some(key)) = (certs.clone(), key.clone()) {
             info!("Configuring DoT Listener");
@@ -48,12 +239,1181 @@ impl Server {
             }
         }
 
-        sf.register_socket(udp);
+        if let Some(doh) = doh {
+            info!("Configuring DoH Listener");
+            let https = TcpListener::bind(SocketAddr::new(ip, doh.port)).await?;
+            let certs = read_cert(&doh.cert)?;
+            let key = read_key(&doh.key)?;
+
+            match sf.register_https_listener(https, tcp_timeout, (certs, key), doh.hostname) {
+                Ok(_) => {}
+                Err(e) => tracing::error!("Cannot start DoH listener: {}", e),
+            }
+        }
+
+        for udp in udp {
+            sf.register_socket(udp);
+        }
         sf.register_listener(tcp, tcp_timeout);
 
-        match sf.block_until_done().await {
-            Ok(_) => Ok(()),
-            Err(e) => Err(anyhow::anyhow!("{}", e)),
+        Ok(sf)
+    }
+}
+
+// Handler answers transfers (AXFR and IXFR) of the zones served and updates of the dynamic zone
+// itself, checking and making their TSIG signatures, queries a response policy zone has a policy
+// for, queries for names in UTF-8 served in punycode, IP-literal names, the reverse names of
+// addresses delegated classless, as well as forwarded
+// queries when they are blocked, cached, validated or sent to the configured upstreams, and leaves
+// every other request to the catalog, that of the client's view if it has names of its own.
+// Requests from clients outside the query ACL are turned away first.
+// Every request is written to the query log and sent to the dnstap collector, if there are any.
+// Requests other than transfers which are not answered within the query timeout get SERVFAIL.
+pub struct Handler {
+    acl: Option<QueryAcl>,
+    log: Option<QueryLog>,
+    dnstap: Option<Dnstap>,
+    blocklist: Blocklist,
+    rpz: Rpz,
+    // the IP-literal names answered, if they are; see crate::ipnames.
+    ip_names: Option<IpNames>,
+    // the delegated reverse zones of networks smaller than a /24; see crate::classless.
+    classless: Vec<Classless>,
+    // the EDNS options of answers other than those to transfers and updates.
+    edns: EdnsConfig,
+    // how long a request other than a transfer may take to answer; SERVFAIL is sent after that.
+    query_timeout: Option<Duration>,
+    catalog: Catalog,
+    // the views of clients, and the catalogs of those with names of their own; see crate::views.
+    views: Views,
+    view_catalogs: Vec<(usize, Catalog)>,
+    zones: Vec<RecordAuthority>,
+    dynamic: Option<DynamicZone>,
+    // the answers to forwarded queries, if they are cached; see crate::cache.
+    cache: Option<ForwardCache>,
+    // resolves forwarded queries when they are cached or validated, or when upstreams are
+    // configured; see `forward`.
+    resolver: Option<Upstreams>,
+}
+
+impl Handler {
+    // deny refuses or drops a request from a client the query ACL does not allow.
+    async fn deny<R: ResponseHandler>(
+        &self,
+        action: AclAction,
+        request: &Request,
+        mut response_handle: R,
+    ) -> ResponseInfo {
+        debug!(
+            "Denying request for {} from {}: not in the query ACL",
+            request.query().name(),
+            request.src()
+        );
+
+        let mut header = Header::response_from_request(request.header());
+        header.set_response_code(ResponseCode::Refused);
+
+        if action == AclAction::Drop {
+            return header.into();
+        }
+
+        response_handle
+            .send_response(
+                MessageResponseBuilder::from_message_request(request)
+                    .error_msg(request.header(), ResponseCode::Refused),
+            )
+            .await
+            .unwrap_or_else(|e| {
+                tracing::error!("Could not respond to {}: {}", request.src(), e);
+                header.into()
+            })
+    }
+
+    // timed_out answers SERVFAIL to a request which took longer than the query timeout.
+    async fn timed_out<R: ResponseHandler>(
+        &self,
+        request: &Request,
+        mut response_handle: R,
+    ) -> ResponseInfo {
+        debug!(
+            "Query for {} from {} timed out",
+            request.query().name(),
+            request.src()
+        );
+
+        let mut header = Header::response_from_request(request.header());
+        header.set_response_code(ResponseCode::ServFail);
+
+        response_handle
+            .send_response(
+                MessageResponseBuilder::from_message_request(request)
+                    .error_msg(request.header(), ResponseCode::ServFail),
+            )
+            .await
+            .unwrap_or_else(|e| {
+                tracing::error!("Could not respond to {}: {}", request.src(), e);
+                header.into()
+            })
+    }
+
+    // forwarded tells whether a query for the name would be forwarded to the system's resolvers,
+    // rather than answered from a zone served or sent to the upstreams of a forward zone.
+    fn forwarded(&self, request: &Request) -> bool {
+        request.message_type() == MessageType::Query
+            && request.op_code() == OpCode::Query
+            && request.recursion_desired()
+            && self
+                .catalog
+                .find(request.query().name())
+                .map_or(false, |authority| authority.origin().is_root())
+    }
+
+    // forward answers a query from the cache, or else through the resolver, caching the answer.
+    // Unlike the catalog, which answers with no records whenever forwarding fails, answers which
+    // fail validation, like those which cannot be had at all, are answered with SERVFAIL, and are
+    // not cached.
+    async fn forward<R: ResponseHandler>(
+        &self,
+        resolver: &Upstreams,
+        request: &Request,
+        mut response_handle: R,
+    ) -> ResponseInfo {
+        let query = request.query();
+
+        let mut header = Header::response_from_request(request.header());
+        header.set_recursion_available(true);
+
+        let cached = self
+            .cache
+            .as_ref()
+            .and_then(|cache| cache.get(query.name(), query.query_type()));
+
+        let answer = match cached {
+            Some(answer) => answer,
+            None => self.resolve(resolver, request).await,
+        };
+
+        header.set_response_code(answer.response_code);
+        let answers = answer.records;
+
+        response_handle
+            .send_response(MessageResponseBuilder::from_message_request(request).build(
+                header,
+                answers.iter(),
+                std::iter::empty(),
+                std::iter::empty(),
+                std::iter::empty(),
+            ))
+            .await
+            .unwrap_or_else(|e| {
+                tracing::error!("Could not respond to {}: {}", request.src(), e);
+
+                let mut header = Header::new();
+                header.set_response_code(ResponseCode::ServFail);
+                header.into()
+            })
+    }
+
+    // resolve looks a forwarded query up through the resolver, and caches the answer, with the
+    // lowest TTL of its records, or the negative TTL of the zone for negative answers.
+    async fn resolve(&self, resolver: &Upstreams, request: &Request) -> Answer {
+        let query = request.query();
+
+        let (answer, ttl) = match resolver.lookup(query.name(), query.query_type()).await {
+            Ok(lookup) => {
+                let records = lookup.records().to_vec();
+                let ttl = records.iter().map(|record| record.ttl()).min();
+
+                (
+                    Answer {
+                        records,
+                        response_code: ResponseCode::NoError,
+                    },
+                    ttl,
+                )
+            }
+            Err(e) => match e.kind() {
+                ResolveErrorKind::NoRecordsFound {
+                    response_code,
+                    negative_ttl,
+                    ..
+                } => (
+                    Answer {
+                        records: Vec::new(),
+                        response_code: *response_code,
+                    },
+                    *negative_ttl,
+                ),
+                _ => {
+                    warn!(
+                        "Could not resolve {} {} for {}: {}",
+                        query.name(),
+                        query.query_type(),
+                        request.src(),
+                        e
+                    );
+
+                    // a stale answer is better than none to members behind a flaky link.
+                    if let Some(answer) = self
+                        .cache
+                        .as_ref()
+                        .and_then(|cache| cache.stale(query.name(), query.query_type()))
+                    {
+                        debug!("Answering {} {} stale", query.name(), query.query_type());
+                        return answer;
+                    }
+
+                    return Answer {
+                        records: Vec::new(),
+                        response_code: ResponseCode::ServFail,
+                    };
+                }
+            },
+        };
+
+        // negative answers without an SOA to tell how long they hold are not cached.
+        if let (Some(cache), Some(ttl)) = (&self.cache, ttl) {
+            cache.insert(query.name(), query.query_type(), answer.clone(), ttl);
+        }
+
+        answer
+    }
+
+    // a_labels is the punycode form of the name queried, if it was queried in UTF-8 and the
+    // punycode form is in a zone served; see crate::idn.
+    fn a_labels(&self, request: &Request) -> Option<LowerName> {
+        if request.message_type() != MessageType::Query || request.op_code() != OpCode::Query {
+            return None;
+        }
+
+        to_a_labels(request.query().name()).filter(|name| {
+            self.catalog
+                .find(name)
+                .map_or(false, |authority| !authority.origin().is_root())
+        })
+    }
+
+    // idn answers a query for a name in UTF-8 with the records of its punycode form, given under
+    // the name queried.
+    async fn idn<R: ResponseHandler>(
+        &self,
+        name: LowerName,
+        request: &Request,
+        mut response_handle: R,
+    ) -> ResponseInfo {
+        let query = request.query();
+
+        debug!(
+            "Answering {} {} for {} as {}",
+            query.name(),
+            query.query_type(),
+            request.src(),
+            name
+        );
+
+        let mut header = Header::response_from_request(request.header());
+        let mut answers = Vec::new();
+
+        if let Some(authority) = self.catalog.find(&name) {
+            header.set_authoritative(
+                self.zones
+                    .iter()
+                    .any(|zone| zone.origin() == authority.origin()),
+            );
+
+            match authority
+                .lookup(&name, query.query_type(), LookupOptions::default())
+                .await
+            {
+                Ok(lookup) => answers.extend(lookup.iter().cloned().map(|mut record| {
+                    if LowerName::from(record.name()) == name {
+                        record.set_name(query.name().clone().into());
+                    }
+
+                    record
+                })),
+                Err(e) => header.set_response_code(match e {
+                    LookupError::NameExists => ResponseCode::NoError,
+                    LookupError::ResponseCode(code) => code,
+                    _ => ResponseCode::ServFail,
+                }),
+            }
+        }
+
+        response_handle
+            .send_response(MessageResponseBuilder::from_message_request(request).build(
+                header,
+                answers.iter(),
+                std::iter::empty(),
+                std::iter::empty(),
+                std::iter::empty(),
+            ))
+            .await
+            .unwrap_or_else(|e| {
+                tracing::error!("Could not respond to {}: {}", request.src(), e);
+
+                let mut header = Header::new();
+                header.set_response_code(ResponseCode::ServFail);
+                header.into()
+            })
+    }
+
+    // ip_name is the address the IP-literal name queried spells, unless the zone has records for
+    // the name itself.
+    async fn ip_name(&self, request: &Request) -> Option<IpAddr> {
+        if request.message_type() != MessageType::Query || request.op_code() != OpCode::Query {
+            return None;
+        }
+
+        let name = request.query().name();
+        let ip = self.ip_names.as_ref()?.address(name)?;
+
+        match self
+            .catalog
+            .find(name)?
+            .lookup(name, RecordType::A, LookupOptions::default())
+            .await
+        {
+            Ok(_) | Err(LookupError::NameExists) => None,
+            Err(_) => Some(ip),
+        }
+    }
+
+    // ip_literal answers a query for an IP-literal name with the address it spells. Queries for
+    // other types get no records, with the zone's SOA.
+    async fn ip_literal<R: ResponseHandler>(
+        &self,
+        ip: IpAddr,
+        request: &Request,
+        mut response_handle: R,
+    ) -> ResponseInfo {
+        let query = request.query();
+
+        debug!(
+            "Answering {} {} for {} with {}",
+            query.name(),
+            query.query_type(),
+            request.src(),
+            ip
+        );
+
+        let mut header = Header::response_from_request(request.header());
+        header.set_authoritative(true);
+
+        let rdata = match (ip, query.query_type()) {
+            (IpAddr::V4(ip), RecordType::A | RecordType::ANY) => Some(RData::A(ip)),
+            (IpAddr::V6(ip), RecordType::AAAA | RecordType::ANY) => Some(RData::AAAA(ip)),
+            _ => None,
+        };
+
+        let ttl = self.ip_names.as_ref().map_or(0, IpNames::ttl);
+        let answers: Vec<Record> = rdata
+            .into_iter()
+            .map(|rdata| Record::from_rdata(query.name().clone().into(), ttl, rdata))
+            .collect();
+
+        let mut soa = Vec::new();
+        if answers.is_empty() {
+            if let Some(authority) = self.catalog.find(query.name()) {
+                if let Ok(lookup) = authority.soa().await {
+                    soa.extend(lookup.iter().cloned());
+                }
+            }
+        }
+
+        response_handle
+            .send_response(MessageResponseBuilder::from_message_request(request).build(
+                header,
+                answers.iter(),
+                std::iter::empty(),
+                soa.iter(),
+                std::iter::empty(),
+            ))
+            .await
+            .unwrap_or_else(|e| {
+                tracing::error!("Could not respond to {}: {}", request.src(), e);
+
+                let mut header = Header::new();
+                header.set_response_code(ResponseCode::ServFail);
+                header.into()
+            })
+    }
+
+    // classless_target is the name in a delegated reverse zone the reverse name queried is a CNAME
+    // of, if its address is in a network delegated classless.
+    fn classless_target(&self, request: &Request) -> Option<Name> {
+        if request.message_type() != MessageType::Query || request.op_code() != OpCode::Query {
+            return None;
+        }
+
+        self.classless
+            .iter()
+            .find_map(|classless| classless.target(request.query().name()))
+    }
+
+    // classless_alias answers a query for the reverse name of an address delegated classless with
+    // the CNAME the parent zone has for it, followed by the records of its target.
+    async fn classless_alias<R: ResponseHandler>(
+        &self,
+        target: Name,
+        request: &Request,
+        mut response_handle: R,
+    ) -> ResponseInfo {
+        let query = request.query();
+
+        debug!(
+            "Answering {} {} for {} with CNAME {}",
+            query.name(),
+            query.query_type(),
+            request.src(),
+            target
+        );
+
+        let header = Header::response_from_request(request.header());
+        let name = LowerName::from(&target);
+
+        let mut answers = Vec::new();
+        if let Some(zone) = self.zones.iter().find(|zone| zone.origin().zone_of(&name)) {
+            answers.push(Record::from_rdata(
+                query.name().clone().into(),
+                zone.ttl(),
+                RData::CNAME(target.clone()),
+            ));
+
+            if query.query_type() != RecordType::CNAME {
+                match zone
+                    .lookup(&name, query.query_type(), LookupOptions::default())
+                    .await
+                {
+                    Ok(lookup) => answers.extend(lookup.iter().cloned()),
+                    Err(e) => debug!("Could not look up {} for {}: {}", target, query.name(), e),
+                }
+            }
+        }
+
+        response_handle
+            .send_response(MessageResponseBuilder::from_message_request(request).build(
+                header,
+                answers.iter(),
+                std::iter::empty(),
+                std::iter::empty(),
+                std::iter::empty(),
+            ))
+            .await
+            .unwrap_or_else(|e| {
+                tracing::error!("Could not respond to {}: {}", request.src(), e);
+
+                let mut header = Header::new();
+                header.set_response_code(ResponseCode::ServFail);
+                header.into()
+            })
+    }
+
+    // block answers a query for a blocked name instead of forwarding it.
+    async fn block<R: ResponseHandler>(
+        &self,
+        action: BlockAction,
+        request: &Request,
+        mut response_handle: R,
+    ) -> ResponseInfo {
+        let query = request.query();
+
+        debug!(
+            "Blocking {} {} for {}",
+            query.name(),
+            query.query_type(),
+            request.src()
+        );
+
+        let mut header = Header::response_from_request(request.header());
+        header.set_recursion_available(true);
+
+        let rdata = match (action, query.query_type()) {
+            (BlockAction::Nxdomain, _) => {
+                header.set_response_code(ResponseCode::NXDomain);
+                None
+            }
+            (BlockAction::Null, RecordType::A) => Some(RData::A(Ipv4Addr::UNSPECIFIED)),
+            (BlockAction::Null, RecordType::AAAA) => Some(RData::AAAA(Ipv6Addr::UNSPECIFIED)),
+            (BlockAction::Null, _) => None,
+        };
+
+        let answers: Vec<Record> = rdata
+            .into_iter()
+            .map(|rdata| Record::from_rdata(query.name().clone().into(), BLOCKED_TTL, rdata))
+            .collect();
+
+        response_handle
+            .send_response(MessageResponseBuilder::from_message_request(request).build(
+                header,
+                answers.iter(),
+                std::iter::empty(),
+                std::iter::empty(),
+                std::iter::empty(),
+            ))
+            .await
+            .unwrap_or_else(|e| {
+                tracing::error!("Could not respond to {}: {}", request.src(), e);
+
+                let mut header = Header::new();
+                header.set_response_code(ResponseCode::ServFail);
+                header.into()
+            })
+    }
+
+    // rewrite answers a query as a response policy directs, instead of with the name's own records.
+    // The target of a CNAME given as the answer is looked up as well, in the zones served or
+    // through the system's resolvers.
+    async fn rewrite<R: ResponseHandler>(
+        &self,
+        policy: Policy,
+        request: &Request,
+        mut response_handle: R,
+    ) -> ResponseInfo {
+        let query = request.query();
+
+        debug!(
+            "Applying response policy to {} {} for {}",
+            query.name(),
+            query.query_type(),
+            request.src()
+        );
+
+        let mut header = Header::response_from_request(request.header());
+        header.set_recursion_available(true);
+
+        let mut answers = Vec::new();
+
+        match policy {
+            Policy::Drop => {
+                header.set_response_code(ResponseCode::Refused);
+                return header.into();
+            }
+            Policy::Nxdomain => header.set_response_code(ResponseCode::NXDomain),
+            Policy::TcpOnly => header.set_truncated(true),
+            Policy::Nodata | Policy::Passthru => {}
+            Policy::Local(records) => {
+                answers = local_answers(&records, &query.name().clone().into(), query.query_type());
+
+                let target = answers.iter().find_map(|record| match record.data() {
+                    Some(RData::CNAME(target)) if query.query_type() != RecordType::CNAME => {
+                        Some(LowerName::from(target))
+                    }
+                    _ => None,
+                });
+
+                if let Some((target, authority)) = target
+                    .as_ref()
+                    .and_then(|target| Some((target, self.catalog.find(target)?)))
+                {
+                    match authority
+                        .lookup(target, query.query_type(), LookupOptions::default())
+                        .await
+                    {
+                        Ok(lookup) => answers.extend(lookup.iter().cloned()),
+                        Err(e) => {
+                            debug!("Could not look up {} for {}: {}", target, query.name(), e)
+                        }
+                    }
+                }
+            }
         }
+
+        response_handle
+            .send_response(MessageResponseBuilder::from_message_request(request).build(
+                header,
+                answers.iter(),
+                std::iter::empty(),
+                std::iter::empty(),
+                std::iter::empty(),
+            ))
+            .await
+            .unwrap_or_else(|e| {
+                tracing::error!("Could not respond to {}: {}", request.src(), e);
+
+                let mut header = Header::new();
+                header.set_response_code(ResponseCode::ServFail);
+                header.into()
+            })
+    }
+
+    async fn update<R: ResponseHandler>(
+        &self,
+        zone: &DynamicZone,
+        request: &Request,
+        mut response_handle: R,
+    ) -> ResponseInfo {
+        let origin = zone.authority().origin();
+
+        let (signed, code) = match verify(zone.authority().keys(), request) {
+            Err(e) => {
+                warn!(
+                    "Refusing update of {} from {}: {}",
+                    origin,
+                    request.src(),
+                    e
+                );
+                (None, ResponseCode::NotAuth)
+            }
+            Ok(None) => {
+                warn!(
+                    "Refusing unsigned update of {} from {}",
+                    origin,
+                    request.src()
+                );
+                (None, ResponseCode::Refused)
+            }
+            Ok(Some(signed)) => match zone.update(request).await {
+                Ok(()) => {
+                    info!(
+                        "Updated {} for {} (key {})",
+                        origin,
+                        request.src(),
+                        signed.key_name()
+                    );
+                    (Some(signed), ResponseCode::NoError)
+                }
+                Err(code) => {
+                    warn!(
+                        "Could not update {} for {}: {}",
+                        origin,
+                        request.src(),
+                        code
+                    );
+                    (Some(signed), code)
+                }
+            },
+        };
+
+        let mut header = Header::response_from_request(request.header());
+        header.set_response_code(code);
+
+        let tsig = signed.and_then(|signed| {
+            signed
+                .sign(&header, request.query().original(), &[])
+                .map_err(|e| {
+                    tracing::error!("Could not sign the response to {}: {}", request.src(), e)
+                })
+                .ok()
+        });
+
+        response_handle
+            .send_response(MessageResponseBuilder::from_message_request(request).build(
+                header,
+                std::iter::empty(),
+                std::iter::empty(),
+                std::iter::empty(),
+                tsig.iter(),
+            ))
+            .await
+            .unwrap_or_else(|e| {
+                tracing::error!("Could not respond to the update of {}: {}", origin, e);
+
+                let mut header = Header::new();
+                header.set_response_code(ResponseCode::ServFail);
+                header.into()
+            })
+    }
+
+    async fn transfer<R: ResponseHandler>(
+        &self,
+        zone: &RecordAuthority,
+        request: &Request,
+        mut response_handle: R,
+    ) -> ResponseInfo {
+        let response = MessageResponseBuilder::from_message_request(request);
+
+        let result = match verify(zone.keys(), request) {
+            Err(e) => {
+                warn!(
+                    "Refusing transfer of {} to {}: {}",
+                    zone.origin(),
+                    request.src(),
+                    e
+                );
+
+                response_handle
+                    .send_response(response.error_msg(request.header(), ResponseCode::NotAuth))
+                    .await
+            }
+            Ok(signed)
+                if !zone.transfer_allowed(request.src().ip())
+                    || (!zone.keys().is_empty() && signed.is_none()) =>
+            {
+                warn!(
+                    "Refusing transfer of {} to {}",
+                    zone.origin(),
+                    request.src()
+                );
+
+                response_handle
+                    .send_response(response.error_msg(request.header(), ResponseCode::Refused))
+                    .await
+            }
+            Ok(signed) => {
+                // secondaries put the SOA they have in the authority section of an IXFR.
+                let client_serial = match request.query().query_type() {
+                    RecordType::IXFR => request.name_servers().iter().find_map(soa_serial),
+                    _ => None,
+                };
+
+                match zone.ixfr(client_serial).await {
+                    Some(answers) => {
+                        let mut header = Header::response_from_request(request.header());
+                        header.set_authoritative(true);
+
+                        let tsig = match &signed {
+                            Some(signed) => {
+                                match signed.sign(&header, request.query().original(), &answers) {
+                                    Ok(tsig) => Some(tsig),
+                                    Err(e) => {
+                                        tracing::error!(
+                                            "Could not sign the transfer of {} with key {}: {}",
+                                            zone.origin(),
+                                            signed.key_name(),
+                                            e
+                                        );
+                                        None
+                                    }
+                                }
+                            }
+                            None => None,
+                        };
+
+                        // the TSIG record must come last, so the response carries no EDNS.
+                        response_handle
+                            .send_response(response.build(
+                                header,
+                                answers.iter(),
+                                std::iter::empty(),
+                                std::iter::empty(),
+                                tsig.iter(),
+                            ))
+                            .await
+                    }
+                    None => {
+                        response_handle
+                            .send_response(
+                                response.error_msg(request.header(), ResponseCode::ServFail),
+                            )
+                            .await
+                    }
+                }
+            }
+        };
+
+        result.unwrap_or_else(|e| {
+            tracing::error!("Could not send the transfer of {}: {}", zone.origin(), e);
+
+            let mut header = Header::new();
+            header.set_response_code(ResponseCode::ServFail);
+            header.into()
+        })
     }
 }
+
+#[async_trait::async_trait]
+impl RequestHandler for Handler {
+    async fn handle_request<R: ResponseHandler>(
+        &self,
+        request: &Request,
+        response_handle: R,
+    ) -> ResponseInfo {
+        let _in_flight = InFlight::new();
+        let received = SystemTime::now();
+
+        // what is logged while answering carries what was asked, and by whom, as fields.
+        let span = info_span!(
+            "query",
+            qname = %request.query().name(),
+            qtype = %request.query().query_type(),
+            client = %request.src()
+        );
+
+        // transfers are streamed for as long as they take.
+        let info = async move {
+            match self.query_timeout.filter(|_| !is_transfer(request)) {
+                Some(timeout) => {
+                    match tokio::time::timeout(
+                        timeout,
+                        self.respond(request, response_handle.clone()),
+                    )
+                    .await
+                    {
+                        Ok(info) => info,
+                        Err(_) => self.timed_out(request, response_handle).await,
+                    }
+                }
+                None => self.respond(request, response_handle).await,
+            }
+        }
+        .instrument(span)
+        .await;
+
+        if let Some(log) = &self.log {
+            log.record(
+                request.src().ip(),
+                request.query().name().to_string(),
+                request.query().query_type(),
+                info.response_code(),
+            );
+        }
+
+        if let Some(dnstap) = &self.dnstap {
+            dnstap.record(request, received, &info);
+        }
+
+        info
+    }
+}
+
+impl Handler {
+    // respond hands the request to whatever answers it.
+    async fn respond<R: ResponseHandler>(
+        &self,
+        request: &Request,
+        response_handle: R,
+    ) -> ResponseInfo {
+        if let Some(acl) = self
+            .acl
+            .as_ref()
+            .filter(|acl| !acl.allowed(request.src().ip()))
+        {
+            return self.deny(acl.action(), request, response_handle).await;
+        }
+
+        if is_transfer(request) {
+            if let Some(zone) = self
+                .zones
+                .iter()
+                .find(|zone| zone.origin() == request.query().name())
+            {
+                return self.transfer(zone, request, response_handle).await;
+            }
+        }
+
+        if request.message_type() == MessageType::Query && request.op_code() == OpCode::Update {
+            if let Some(zone) = self
+                .dynamic
+                .as_ref()
+                .filter(|zone| zone.authority().origin() == request.query().name())
+            {
+                return self.update(zone, request, response_handle).await;
+            }
+        }
+
+        // transfers and updates are left alone: their TSIG record has to be the last one.
+        let max_size =
+            (request.protocol() == Protocol::Udp).then(|| self.edns.max_size(request.edns()));
+        let response_handle = EdnsResponse::new(
+            UdpResponse::new(response_handle, max_size),
+            self.edns.response_edns(request.edns(), request.src().ip()),
+        );
+
+        // names passed through by a policy are exempt from blocklists as well.
+        let mut passthru = false;
+
+        if request.message_type() == MessageType::Query && request.op_code() == OpCode::Query {
+            match self.rpz.policy(&Name::from(request.query().name().clone())) {
+                Some(Policy::Passthru) => passthru = true,
+                Some(Policy::TcpOnly) if request.protocol() != Protocol::Udp => {}
+                Some(policy) => return self.rewrite(policy, request, response_handle).await,
+                None => {}
+            }
+        }
+
+        if let Some(name) = self.a_labels(request) {
+            return self.idn(name, request, response_handle).await;
+        }
+
+        if let Some(ip) = self.ip_name(request).await {
+            return self.ip_literal(ip, request, response_handle).await;
+        }
+
+        if let Some(target) = self.classless_target(request) {
+            return self.classless_alias(target, request, response_handle).await;
+        }
+
+        if self.forwarded(request) {
+            if let Some(action) = self
+                .blocklist
+                .blocked(request.query().name())
+                .filter(|_| !passthru)
+            {
+                return self.block(action, request, response_handle).await;
+            }
+
+            if let Some(resolver) = &self.resolver {
+                return self.forward(resolver, request, response_handle).await;
+            }
+        }
+
+        self.catalog_for(request)
+            .handle_request(request, response_handle)
+            .await
+    }
+
+    // catalog_for is the catalog the request is answered from: that of the client's view, if the
+    // view has names of its own and the name is in one of its zones, and the network's otherwise.
+    fn catalog_for(&self, request: &Request) -> &Catalog {
+        let view = match self.views.view_of(request.src().ip()) {
+            Some(view) => view,
+            None => return &self.catalog,
+        };
+
+        let name = request.query().name();
+        let origin = |catalog: &Catalog| catalog.find(name).map(|zone| zone.origin().clone());
+
+        self.view_catalogs
+            .iter()
+            .find(|(index, _)| *index == view)
+            .map(|(_, catalog)| catalog)
+            .filter(|catalog| origin(catalog).is_some() && origin(catalog) == origin(&self.catalog))
+            .unwrap_or(&self.catalog)
+    }
+}
+
+// is_transfer tells whether the request is for a transfer (AXFR or IXFR) of a zone.
+fn is_transfer(request: &Request) -> bool {
+    request.message_type() == MessageType::Query
+        && request.op_code() == OpCode::Query
+        && matches!(
+            request.query().query_type(),
+            RecordType::AXFR | RecordType::IXFR
+        )
+}
+
+// Listeners are the servers of a network, one for each address it is served on. Addresses can be
+// added and removed while running, as ZeroTier assigns them.
+pub struct Listeners {
+    server: Server,
+    port: u16,
+    tcp_timeout: Duration,
+    certs: Option<X509>,
+    cert_chain: Option<Vec<X509>>,
+    key: Option<PKey<Private>>,
+    doh: Option<DoHConfig>,
+    span: Span,
+    running: HashMap<IpAddr, JoinHandle<()>>,
+}
+
+impl Listeners {
+    // tls is the DNS-over-TLS certificate, chain and key, as returned by Launcher::tls_material.
+    pub fn new(
+        server: Server,
+        port: u16,
+        tcp_timeout: Duration,
+        tls: (Option<X509>, Option<Vec<X509>>, Option<PKey<Private>>),
+        doh: Option<DoHConfig>,
+        span: Span,
+    ) -> Self {
+        let (certs, cert_chain, key) = tls;
+
+        Self {
+            server,
+            port,
+            tcp_timeout,
+            certs,
+            cert_chain,
+            key,
+            doh,
+            span,
+            running: HashMap::new(),
+        }
+    }
+
+    // addresses returns the addresses currently listened on.
+    pub fn addresses(&self) -> Vec<IpAddr> {
+        self.running.keys().cloned().collect()
+    }
+
+    // add binds the address and serves it in the background.
+    pub async fn add(&mut self, ip: IpAddr) -> Result<(), anyhow::Error> {
+        if self.running.contains_key(&ip) {
+            return Ok(());
+        }
+
+        let sa = SocketAddr::new(ip, self.port);
+        info!("Listening on {}", sa);
+
+        let cert_chain = match &self.cert_chain {
+            Some(chain) => {
+                let mut stack = Stack::new()?;
+                for cert in chain {
+                    stack.push(cert.clone())?;
+                }
+                Some(stack)
+            }
+            None => None,
+        };
+
+        let sf = self
+            .server
+            .clone()
+            .bind(
+                sa,
+                self.tcp_timeout,
+                self.certs.clone(),
+                cert_chain,
+                self.key.clone(),
+                self.doh.clone(),
+            )
+            .instrument(self.span.clone())
+            .await?;
+
+        let handle = tokio::spawn(
+            async move {
+                if let Err(e) = sf.block_until_done().await {
+                    tracing::error!("Listener on {} stopped: {}", sa, e);
+                }
+            }
+            .instrument(self.span.clone()),
+        );
+
+        self.running.insert(ip, handle);
+        self.report();
+        Ok(())
+    }
+
+    // report tells the health endpoint the addresses listened on.
+    fn report(&self) {
+        self.server.zt.health.listening(
+            self.addresses()
+                .into_iter()
+                .map(|ip| SocketAddr::new(ip, self.port))
+                .collect(),
+        );
+    }
+
+    // remove stops serving the address. Dropping the server closes its sockets.
+    pub fn remove(&mut self, ip: &IpAddr) {
+        if let Some(handle) = self.running.remove(ip) {
+            info!("No longer listening on {}", SocketAddr::new(*ip, self.port));
+            handle.abort();
+            self.report();
+        }
+    }
+
+    // close stops serving every address, returning once their sockets are closed.
+    pub async fn close(&mut self) {
+        for (ip, handle) in self.running.drain() {
+            info!("No longer listening on {}", SocketAddr::new(ip, self.port));
+            handle.abort();
+            let _ = handle.await;
+        }
+
+        self.report();
+    }
+
+    // sync makes the listeners match the given addresses. Addresses which fail to bind are logged
+    // and retried on the next sync.
+    pub async fn sync(&mut self, ips: &[IpAddr]) {
+        for ip in self.addresses() {
+            if !ips.contains(&ip) {
+                self.remove(&ip);
+            }
+        }
+
+        for ip in ips {
+            if let Err(e) = self.add(*ip).await {
+                tracing::error!("Could not listen on {}: {}", ip, e);
+            }
+        }
+    }
+}
+
+// how many times to retry binding an address which is not yet available.
+const BIND_RETRIES: usize = 10;
+
+// bind_sockets binds the TCP socket and the UDP sockets of the workers for the address, unless
+// systemd passed them; see crate::activation. IPv6 addresses assigned by ZeroTier (including
+// 6PLANE and RFC4193 addresses) may still be undergoing duplicate address detection when zeronsd
+// starts, which makes them unavailable for a few seconds; those binds are retried.
+async fn bind_sockets(
+    sa: SocketAddr,
+    workers: usize,
+) -> Result<(TcpListener, Vec<UdpSocket>), anyhow::Error> {
+    let mut tries = 0;
+
+    loop {
+        let (passed_tcp, passed_udp) = activation::take(sa)?;
+
+        let tcp = match passed_tcp {
+            Some(tcp) => {
+                tcp.set_nonblocking(true)?;
+                TcpListener::from_std(tcp)
+            }
+            None => TcpListener::bind(sa).await,
+        };
+
+        // the workers share the socket systemd passed, rather than binding their own.
+        let udp = match passed_udp {
+            Some(udp) => (0..workers)
+                .map(|_| {
+                    let udp = udp.try_clone()?;
+                    udp.set_nonblocking(true)?;
+                    UdpSocket::from_std(udp)
+                })
+                .collect(),
+            None => bind_udp(sa, workers).await,
+        };
+
+        let res = match (tcp, udp) {
+            (Ok(tcp), Ok(udp)) => Ok((tcp, udp)),
+            (Err(e), _) | (_, Err(e)) => Err(e),
+        };
+
+        match res {
+            Ok(sockets) => return Ok(sockets),
+            Err(e) if e.kind() == ErrorKind::AddrNotAvailable && tries < BIND_RETRIES => {
+                tries += 1;
+                warn!("{} is not available yet ({}); retrying", sa, e);
+                tokio::time::sleep(Duration::new(1, 0)).await;
+            }
+            Err(e) => return Err(anyhow::anyhow!("could not listen on {}: {}", sa, e)),
+        }
+    }
+}
+
+// bind_udp binds the UDP sockets of the workers for the address. Several workers share it with
+// SO_REUSEPORT, and the kernel spreads the queries between their sockets; a single one binds it
+// as any other program would.
+async fn bind_udp(sa: SocketAddr, workers: usize) -> std::io::Result<Vec<UdpSocket>> {
+    let first = if workers > 1 {
+        reuse_port(sa)?
+    } else {
+        UdpSocket::bind(sa).await?
+    };
+
+    // the others take the port the first one got, in case the system picked it.
+    let sa = first.local_addr()?;
+    let mut sockets = vec![first];
+
+    while sockets.len() < workers {
+        sockets.push(reuse_port(sa)?);
+    }
+
+    Ok(sockets)
+}
+
+// reuse_port binds a UDP socket the address can be shared with.
+#[cfg(unix)]
+fn reuse_port(sa: SocketAddr) -> std::io::Result<UdpSocket> {
+    use socket2::{Domain, Protocol, Socket, Type};
+
+    let socket = Socket::new(Domain::for_address(sa), Type::DGRAM, Some(Protocol::UDP))?;
+    socket.set_reuse_port(true)?;
+    socket.set_nonblocking(true)?;
+    socket.bind(&sa.into())?;
+
+    UdpSocket::from_std(socket.into())
+}
+
+// other platforms serve every address with a single socket; see Launcher::workers.
+#[cfg(not(unix))]
+fn reuse_port(sa: SocketAddr) -> std::io::Result<UdpSocket> {
+    Err(std::io::Error::new(
+        ErrorKind::Unsupported,
+        format!("cannot share {} between workers on this platform", sa),
+    ))
+}
@@ -0,0 +1,205 @@
+/// code to run zeronsd as a Windows service.
+use std::{ffi::OsString, sync::Mutex, time::Duration};
+
+use anyhow::anyhow;
+use lazy_static::lazy_static;
+use windows_service::{
+    define_windows_service,
+    service::{
+        ServiceAccess, ServiceControl, ServiceControlAccept, ServiceDependency,
+        ServiceErrorControl, ServiceExitCode, ServiceInfo, ServiceStartType, ServiceState,
+        ServiceStatus, ServiceType,
+    },
+    service_control_handler::{self, ServiceControlHandlerResult, ServiceStatusHandle},
+    service_dispatcher,
+    service_manager::{ServiceManager, ServiceManagerAccess},
+};
+
+use crate::cli::{serve, StartArgs};
+
+// the service zerotier-one is installed as.
+const ZEROTIER_SERVICE: &str = "ZeroTierOneService";
+
+lazy_static! {
+    // the arguments `service_main` runs with; it is called by the service control manager, which
+    // cannot pass them along.
+    static ref ARGS: Mutex<Option<StartArgs>> = Mutex::new(None);
+}
+
+define_windows_service!(ffi_service_main, service_main);
+
+// service_name is the name of the service installed for a network, or of the one service serving
+// several networks when there is no unit_id.
+pub fn service_name(unit_id: Option<&str>) -> String {
+    match unit_id {
+        Some(unit_id) => format!("zeronsd-{}", unit_id),
+        None => "zeronsd".to_string(),
+    }
+}
+
+// install registers a service which is started at boot, after zerotier-one, and an event log
+// source of the same name for its logs.
+pub fn install(
+    unit_id: Option<&str>,
+    networks: &str,
+    arguments: Vec<OsString>,
+) -> Result<(), anyhow::Error> {
+    let name = service_name(unit_id);
+    let manager = ServiceManager::local_computer(
+        None::<&str>,
+        ServiceManagerAccess::CONNECT | ServiceManagerAccess::CREATE_SERVICE,
+    )
+    .map_err(|e| {
+        anyhow!(
+            "Could not connect to the service manager; are you an administrator? ({})",
+            e
+        )
+    })?;
+
+    let info = ServiceInfo {
+        name: OsString::from(&name),
+        display_name: OsString::from(format!("ZeroNS for {}", networks)),
+        service_type: ServiceType::OWN_PROCESS,
+        start_type: ServiceStartType::AutoStart,
+        error_control: ServiceErrorControl::Normal,
+        executable_path: std::env::current_exe()?,
+        launch_arguments: arguments,
+        dependencies: vec![ServiceDependency::Service(OsString::from(ZEROTIER_SERVICE))],
+        account_name: None, // LocalSystem
+        account_password: None,
+    };
+
+    manager
+        .create_service(&info, ServiceAccess::QUERY_STATUS)
+        .map_err(|e| anyhow!("Could not create service {}: {}", name, e))?;
+
+    if let Err(e) = eventlog::register(&name) {
+        tracing::warn!("Could not register event log source {}: {}", name, e);
+    }
+
+    Ok(())
+}
+
+// uninstall stops and removes a service installed by `install`.
+pub fn uninstall(unit_id: Option<&str>) -> Result<(), anyhow::Error> {
+    let name = service_name(unit_id);
+    let manager = ServiceManager::local_computer(None::<&str>, ServiceManagerAccess::CONNECT)
+        .map_err(|e| {
+            anyhow!(
+                "Could not connect to the service manager; are you an administrator? ({})",
+                e
+            )
+        })?;
+
+    let service = manager
+        .open_service(
+            &name,
+            ServiceAccess::QUERY_STATUS | ServiceAccess::STOP | ServiceAccess::DELETE,
+        )
+        .map_err(|e| anyhow!("Could not open service {}: {}", name, e))?;
+
+    if service.query_status()?.current_state != ServiceState::Stopped {
+        service.stop()?;
+    }
+
+    service
+        .delete()
+        .map_err(|e| anyhow!("Could not remove service {}: {}", name, e))?;
+
+    if let Err(e) = eventlog::deregister(&name) {
+        tracing::warn!("Could not remove event log source {}: {}", name, e);
+    }
+
+    Ok(())
+}
+
+// dispatch hands the current thread to the service control manager, returning once the service
+// has stopped.
+pub fn dispatch(args: StartArgs) -> Result<(), anyhow::Error> {
+    *ARGS.lock().unwrap() = Some(args);
+
+    // the name is ignored for services which run in a process of their own.
+    service_dispatcher::start(service_name(None), ffi_service_main).map_err(|e| {
+        anyhow!(
+            "Could not start the service; it may only be started by the service manager ({})",
+            e
+        )
+    })
+}
+
+// service_main is called with the name of the service being started as the first argument.
+fn service_main(arguments: Vec<OsString>) {
+    let name = match arguments.first() {
+        Some(name) => name.to_string_lossy().into_owned(),
+        None => service_name(None),
+    };
+
+    if let Some(args) = ARGS.lock().unwrap().take() {
+        if let Err(e) = run(&name, args) {
+            tracing::error!("{}", e);
+        }
+    }
+}
+
+fn run(name: &str, args: StartArgs) -> Result<(), anyhow::Error> {
+    crate::utils::init_event_logger(
+        name,
+        args.log_level
+            .clone()
+            .unwrap_or(crate::log::LevelFilter::Info),
+    );
+
+    let (stop, stopped) = tokio::sync::oneshot::channel();
+    let stop = Mutex::new(Some(stop));
+
+    let status = service_control_handler::register(name, move |control| match control {
+        ServiceControl::Stop | ServiceControl::Shutdown => {
+            if let Some(stop) = stop.lock().unwrap().take() {
+                let _ = stop.send(());
+            }
+
+            ServiceControlHandlerResult::NoError
+        }
+        ServiceControl::Interrogate => ServiceControlHandlerResult::NoError,
+        _ => ServiceControlHandlerResult::NotImplemented,
+    })?;
+
+    set_status(&status, ServiceState::Running, ServiceExitCode::Win32(0))?;
+
+    let res = args.runtime()?.block_on(serve(args, async move {
+        stopped.await?;
+        Ok(())
+    }));
+
+    let exit_code = match &res {
+        Ok(_) => ServiceExitCode::Win32(0),
+        Err(_) => ServiceExitCode::ServiceSpecific(1),
+    };
+
+    set_status(&status, ServiceState::Stopped, exit_code)?;
+
+    res
+}
+
+fn set_status(
+    status: &ServiceStatusHandle,
+    state: ServiceState,
+    exit_code: ServiceExitCode,
+) -> Result<(), anyhow::Error> {
+    let controls_accepted = match state {
+        ServiceState::Running => ServiceControlAccept::STOP | ServiceControlAccept::SHUTDOWN,
+        _ => ServiceControlAccept::empty(),
+    };
+
+    status.set_service_status(ServiceStatus {
+        service_type: ServiceType::OWN_PROCESS,
+        current_state: state,
+        controls_accepted,
+        exit_code,
+        checkpoint: 0,
+        wait_hint: Duration::default(),
+        process_id: None,
+    })?;
+
+    Ok(())
+}
@@ -0,0 +1,208 @@
+/// SRV records for services advertised through member tags in Central.
+use std::str::FromStr;
+
+use serde_json::Value;
+use trust_dns_resolver::proto::rr::{rdata::SRV, RData};
+use trust_dns_server::client::rr::Name;
+use zerotier_central_api::types::{Member, Network};
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Service {
+    pub name: String,
+    pub protocol: &'static str,
+    pub port: u16,
+}
+
+impl Service {
+    // srv returns the record for the service under the domain, pointing at the target.
+    pub fn srv(&self, domain: &Name, target: Name) -> Result<(Name, RData), anyhow::Error> {
+        let name =
+            Name::from_str(&format!("_{}._{}", self.name, self.protocol))?.append_domain(domain)?;
+
+        Ok((name, RData::SRV(SRV::new(0, 0, self.port, target))))
+    }
+}
+
+// member_tags are the tags of the member, as [id, value] pairs.
+fn member_tags(member: &Member) -> Vec<(u64, u64)> {
+    serde_json::to_value(member.config.as_ref().map(|c| &c.tags))
+        .unwrap_or_default()
+        .as_array()
+        .map(|tags| {
+            tags.iter()
+                .filter_map(|tag| Some((tag.get(0)?.as_u64()?, tag.get(1)?.as_u64()?)))
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+// member_tag is the member's value for the tag of the network with the given name, if the
+// network has such a tag and the member is tagged with it.
+pub fn member_tag(network: &Network, member: &Member, name: &str) -> Option<u64> {
+    let tags = serde_json::to_value(&network.tags_by_name).unwrap_or_default();
+    let id = tags.get(name)?.get("id")?.as_u64()?;
+
+    member_tags(member)
+        .into_iter()
+        .find(|(tag_id, _)| *tag_id == id)
+        .map(|(_, value)| value)
+}
+
+// tag_enum is the value the tag of the network with the given name has for the enum name, which is
+// matched regardless of case.
+pub fn tag_enum(network: &Network, tag: &str, name: &str) -> Option<u64> {
+    let tags = serde_json::to_value(&network.tags_by_name).unwrap_or_default();
+
+    tags.get(tag)?
+        .get("enums")?
+        .as_object()?
+        .iter()
+        .find(|(enum_name, _)| enum_name.eq_ignore_ascii_case(name))?
+        .1
+        .as_u64()
+}
+
+// member_services lists the services advertised by the member's tags. Tags without a name for
+// the member's value are ignored, as there is nothing to call the service.
+pub fn member_services(network: &Network, member: &Member) -> Vec<Service> {
+    let tags = serde_json::to_value(&network.tags_by_name).unwrap_or_default();
+    let tags = match tags.as_object() {
+        Some(tags) => tags,
+        None => return Vec::new(),
+    };
+
+    let member_tags = member_tags(member);
+
+    let mut services = Vec::new();
+
+    for (tag, definition) in tags {
+        let protocol = match tag.strip_prefix("svc") {
+            Some(rest) if rest.trim_start_matches('_').starts_with("udp") => "udp",
+            Some(_) => "tcp",
+            None => continue,
+        };
+
+        let id = match definition.get("id").and_then(Value::as_u64) {
+            Some(id) => id,
+            None => continue,
+        };
+
+        for (_, value) in member_tags.iter().filter(|(tag_id, _)| *tag_id == id) {
+            let port = match u16::try_from(*value) {
+                Ok(port) if port != 0 => port,
+                _ => continue,
+            };
+
+            if let Some(enums) = definition.get("enums").and_then(Value::as_object) {
+                for (name, enum_value) in enums {
+                    if enum_value.as_u64() == Some(*value) {
+                        services.push(Service {
+                            name: name.to_lowercase(),
+                            protocol,
+                            port,
+                        });
+                    }
+                }
+            }
+        }
+    }
+
+    services
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+
+    use super::{member_services, member_tag, Service};
+    use serde_json::json;
+    use trust_dns_resolver::proto::rr::{rdata::SRV, RData};
+    use trust_dns_server::client::rr::Name;
+    use zerotier_central_api::types::{Member, Network};
+
+    #[test]
+    fn test_member_services() {
+        let network: Network = serde_json::from_value(json!({
+            "id": "1234567891011121",
+            "tagsByName": {
+                "svc": { "id": 1000, "enums": { "https": 443, "ldap": 389 } },
+                "svc_udp": { "id": 1001, "enums": { "syslog": 514 } },
+                "department": { "id": 1002, "enums": { "sales": 443 } },
+            },
+        }))
+        .unwrap();
+
+        let member: Member = serde_json::from_value(json!({
+            "nodeId": "abcdef0123",
+            "config": { "tags": [[1000, 443], [1001, 514], [1002, 443]] },
+        }))
+        .unwrap();
+
+        let mut services = member_services(&network, &member);
+        services.sort_by_key(|s| s.port);
+
+        assert_eq!(
+            services,
+            vec![
+                Service {
+                    name: "https".to_string(),
+                    protocol: "tcp",
+                    port: 443
+                },
+                Service {
+                    name: "syslog".to_string(),
+                    protocol: "udp",
+                    port: 514
+                },
+            ]
+        );
+
+        let untagged: Member = serde_json::from_value(json!({
+            "nodeId": "abcdef0124",
+            "config": { "tags": [] },
+        }))
+        .unwrap();
+        assert!(member_services(&network, &untagged).is_empty());
+    }
+
+    #[test]
+    fn test_member_tag() {
+        let network: Network = serde_json::from_value(json!({
+            "id": "1234567891011121",
+            "tagsByName": { "wildcard": { "id": 2000 } },
+        }))
+        .unwrap();
+
+        let member: Member = serde_json::from_value(json!({
+            "nodeId": "abcdef0123",
+            "config": { "tags": [[1000, 443], [2000, 1]] },
+        }))
+        .unwrap();
+        assert_eq!(member_tag(&network, &member, "wildcard"), Some(1));
+        assert_eq!(member_tag(&network, &member, "svc"), None);
+
+        let untagged: Member = serde_json::from_value(json!({
+            "nodeId": "abcdef0124",
+            "config": { "tags": [[1000, 443]] },
+        }))
+        .unwrap();
+        assert_eq!(member_tag(&network, &untagged, "wildcard"), None);
+    }
+
+    #[test]
+    fn test_service_srv() {
+        let domain = Name::from_str("home.arpa.").unwrap();
+        let target = Name::from_str("islay.home.arpa.").unwrap();
+
+        let (name, rdata) = Service {
+            name: "https".to_string(),
+            protocol: "tcp",
+            port: 443,
+        }
+        .srv(&domain, target.clone())
+        .unwrap();
+
+        assert_eq!(name, Name::from_str("_https._tcp.home.arpa.").unwrap());
+        assert_eq!(rdata, RData::SRV(SRV::new(0, 0, 443, target)));
+    }
+}
@@ -0,0 +1,91 @@
+/// the network and members last fetched from Central, kept on disk.
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+use zerotier_central_api::types::{Member, Network};
+
+use crate::store::Saved;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Snapshot {
+    pub network: Network,
+    pub members: Vec<Member>,
+}
+
+impl Snapshot {
+    // path is where the snapshot of a network is kept in the state directory.
+    pub fn path(state_dir: &Path, network_id: &str) -> PathBuf {
+        state_dir.join(format!("{}.json", network_id))
+    }
+
+    // load reads a snapshot, returning None if none has been saved yet.
+    pub fn load(saved: &Saved) -> Result<Option<Self>, anyhow::Error> {
+        match saved.read()? {
+            Some(contents) => Ok(Some(serde_json::from_slice(&contents)?)),
+            None => Ok(None),
+        }
+    }
+
+    // save writes the snapshot. It lists every member's addresses, so it is only readable by its
+    // owner.
+    pub fn save(&self, saved: &Saved) -> Result<(), anyhow::Error> {
+        saved.write(&serde_json::to_vec(self)?)
+    }
+}
+
+// write_private writes a file only readable by its owner in the state directory. The contents
+// go to a temporary file which then replaces the previous one, so that an interrupted write
+// never leaves a truncated file behind.
+pub fn write_private(path: &Path, contents: &[u8]) -> Result<(), anyhow::Error> {
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir)?;
+    }
+
+    let tmp = path.with_extension("json.tmp");
+    std::fs::write(&tmp, contents)?;
+
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        std::fs::set_permissions(&tmp, std::fs::Permissions::from_mode(0o600))?;
+    }
+
+    std::fs::rename(tmp, path)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Snapshot;
+    use crate::store::Saved;
+    use zerotier_central_api::types::{Member, Network};
+
+    #[test]
+    fn test_snapshot_roundtrip() {
+        let dir = std::env::temp_dir().join("zeronsd-test-snapshot");
+        let path = Snapshot::path(&dir, "1234567891011121");
+        let _ = std::fs::remove_file(&path);
+        let saved = Saved::File(path);
+
+        assert!(Snapshot::load(&saved).unwrap().is_none());
+
+        let member: Member = serde_json::from_value(serde_json::json!({
+            "nodeId": "abcdef0123",
+            "name": "islay",
+        }))
+        .unwrap();
+
+        Snapshot {
+            network: serde_json::from_value::<Network>(serde_json::json!({})).unwrap(),
+            members: vec![member],
+        }
+        .save(&saved)
+        .unwrap();
+
+        let loaded = Snapshot::load(&saved).unwrap().unwrap();
+        assert_eq!(loaded.members.len(), 1);
+        assert_eq!(loaded.members[0].name, Some("islay".to_string()));
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,233 @@
+/// the state database of `--state-db`, an embedded SQLite file.
+use std::{
+    collections::BTreeMap,
+    fmt,
+    io::ErrorKind,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use anyhow::anyhow;
+use rusqlite::{params, Connection, OptionalExtension};
+
+use crate::snapshot::write_private;
+
+// how long a write waits for another process sharing the database to finish its own.
+const BUSY_TIMEOUT: Duration = Duration::from_secs(5);
+
+const SCHEMA: &str = "
+CREATE TABLE IF NOT EXISTS state (
+    network_id TEXT NOT NULL,
+    kind TEXT NOT NULL,
+    contents BLOB NOT NULL,
+    PRIMARY KEY (network_id, kind)
+);
+CREATE TABLE IF NOT EXISTS statistics (
+    network_id TEXT NOT NULL,
+    name TEXT NOT NULL,
+    value INTEGER NOT NULL,
+    PRIMARY KEY (network_id, name)
+);
+";
+
+// the kinds of state kept for a network.
+pub const SNAPSHOT: &str = "snapshot";
+pub const DYNAMIC_RECORDS: &str = "dynamic";
+
+// Store is an open state database. Clones share the connection.
+#[derive(Clone)]
+pub struct Store {
+    path: PathBuf,
+    conn: Arc<Mutex<Connection>>,
+}
+
+impl Store {
+    // open opens the database, creating it if needed. It lists every member's addresses, so it is
+    // only readable by its owner.
+    pub fn open(path: &Path) -> Result<Self, anyhow::Error> {
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir)?;
+        }
+
+        let conn = Connection::open(path)
+            .map_err(|e| anyhow!("Could not open {}: {}", path.display(), e))?;
+
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            std::fs::set_permissions(path, std::fs::Permissions::from_mode(0o600))?;
+        }
+
+        conn.busy_timeout(BUSY_TIMEOUT)?;
+        conn.execute_batch(SCHEMA)
+            .map_err(|e| anyhow!("Could not set up {}: {}", path.display(), e))?;
+
+        Ok(Self {
+            path: path.to_path_buf(),
+            conn: Arc::new(Mutex::new(conn)),
+        })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    fn read(&self, network_id: &str, kind: &str) -> Result<Option<Vec<u8>>, anyhow::Error> {
+        Ok(self
+            .conn
+            .lock()
+            .unwrap()
+            .query_row(
+                "SELECT contents FROM state WHERE network_id = ?1 AND kind = ?2",
+                params![network_id, kind],
+                |row| row.get(0),
+            )
+            .optional()?)
+    }
+
+    fn write(&self, network_id: &str, kind: &str, contents: &[u8]) -> Result<(), anyhow::Error> {
+        self.conn.lock().unwrap().execute(
+            "INSERT INTO state (network_id, kind, contents) VALUES (?1, ?2, ?3)
+             ON CONFLICT (network_id, kind) DO UPDATE SET contents = excluded.contents",
+            params![network_id, kind, contents],
+        )?;
+        Ok(())
+    }
+
+    // count adds to a counter of the network.
+    pub fn count(&self, network_id: &str, name: &str, n: u64) -> Result<(), anyhow::Error> {
+        self.conn.lock().unwrap().execute(
+            "INSERT INTO statistics (network_id, name, value) VALUES (?1, ?2, ?3)
+             ON CONFLICT (network_id, name) DO UPDATE SET value = value + excluded.value",
+            params![network_id, name, n as i64],
+        )?;
+        Ok(())
+    }
+
+    // set sets a statistic of the network, e.g. a timestamp.
+    pub fn set(&self, network_id: &str, name: &str, value: u64) -> Result<(), anyhow::Error> {
+        self.conn.lock().unwrap().execute(
+            "INSERT INTO statistics (network_id, name, value) VALUES (?1, ?2, ?3)
+             ON CONFLICT (network_id, name) DO UPDATE SET value = excluded.value",
+            params![network_id, name, value as i64],
+        )?;
+        Ok(())
+    }
+
+    // statistics returns the statistics of the network, by name.
+    pub fn statistics(&self, network_id: &str) -> Result<BTreeMap<String, u64>, anyhow::Error> {
+        let conn = self.conn.lock().unwrap();
+        let mut statement =
+            conn.prepare("SELECT name, value FROM statistics WHERE network_id = ?1")?;
+
+        let rows = statement.query_map(params![network_id], |row| {
+            Ok((row.get::<_, String>(0)?, row.get::<_, i64>(1)? as u64))
+        })?;
+
+        Ok(rows.collect::<Result<_, _>>()?)
+    }
+}
+
+// Saved is where a piece of the state of a network is kept: a file of the state directory, or a
+// row of the state database.
+#[derive(Clone)]
+pub enum Saved {
+    File(PathBuf),
+    Store {
+        store: Store,
+        network_id: String,
+        kind: &'static str,
+    },
+}
+
+impl Saved {
+    // read returns what was saved, or None if nothing has been yet.
+    pub fn read(&self) -> Result<Option<Vec<u8>>, anyhow::Error> {
+        match self {
+            Self::File(path) => match std::fs::read(path) {
+                Ok(contents) => Ok(Some(contents)),
+                Err(e) if e.kind() == ErrorKind::NotFound => Ok(None),
+                Err(e) => Err(e.into()),
+            },
+            Self::Store {
+                store,
+                network_id,
+                kind,
+            } => store.read(network_id, kind),
+        }
+    }
+
+    // write replaces what was saved. Neither a file nor a row is ever left half-written.
+    pub fn write(&self, contents: &[u8]) -> Result<(), anyhow::Error> {
+        match self {
+            Self::File(path) => write_private(path, contents),
+            Self::Store {
+                store,
+                network_id,
+                kind,
+            } => store.write(network_id, kind, contents),
+        }
+    }
+}
+
+impl fmt::Display for Saved {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::File(path) => write!(f, "{}", path.display()),
+            Self::Store {
+                store,
+                network_id,
+                kind,
+            } => write!(f, "{} ({} of {})", store.path().display(), kind, network_id),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Saved, Store, SNAPSHOT};
+
+    #[test]
+    fn test_store() {
+        let dir = std::env::temp_dir().join("zeronsd-test-store");
+        let _ = std::fs::remove_dir_all(&dir);
+
+        let store = Store::open(&dir.join("state.db")).unwrap();
+        let saved = Saved::Store {
+            store: store.clone(),
+            network_id: "1234567891011121".to_string(),
+            kind: SNAPSHOT,
+        };
+
+        assert!(saved.read().unwrap().is_none());
+        saved.write(b"first").unwrap();
+        saved.write(b"second").unwrap();
+
+        // a database opened again, as after a restart, has what was written.
+        let reopened = Store::open(&dir.join("state.db")).unwrap();
+        let saved = Saved::Store {
+            store: reopened.clone(),
+            network_id: "1234567891011121".to_string(),
+            kind: SNAPSHOT,
+        };
+        assert_eq!(saved.read().unwrap(), Some(b"second".to_vec()));
+
+        store.count("1234567891011121", "polls", 2).unwrap();
+        store.count("1234567891011121", "polls", 1).unwrap();
+        store
+            .set("1234567891011121", "last_poll", 1700000000)
+            .unwrap();
+        store
+            .set("1234567891011121", "last_poll", 1700000060)
+            .unwrap();
+        store.count("8056c2e21c000001", "polls", 5).unwrap();
+
+        let statistics = reopened.statistics("1234567891011121").unwrap();
+        assert_eq!(statistics["polls"], 3);
+        assert_eq!(statistics["last_poll"], 1700000060);
+        assert!(reopened.statistics("0000000000000000").unwrap().is_empty());
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
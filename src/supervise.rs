@@ -1,7 +1,12 @@
 /// code to tickle various supervisors to enable the `zeronsd supervise` command.
 /// this code is hard to read but fundamentally launchd and systemd are controlled through a
 /// library called `tinytemplate` and of course serde.
-use std::path::{Path, PathBuf};
+use std::{
+    ffi::OsStr,
+    net::SocketAddr,
+    path::{Path, PathBuf},
+    str::FromStr,
+};
 
 use anyhow::anyhow;
 use regex::Regex;
@@ -9,14 +14,17 @@ use serde::Serialize;
 use tinytemplate::TinyTemplate;
 use trust_dns_resolver::Name;
 
-#[cfg(target_os = "linux")]
+#[cfg(unix)]
 use std::os::unix::fs::PermissionsExt;
 
 use crate::{
-    cli::{StartArgs, UnsuperviseArgs},
+    cli::UnsuperviseArgs,
     init::{ConfigFormat, Launcher},
+    log::LogFormat,
+    utils::ZEROTIER_LOCAL_URL,
 };
 
+// Windows services are registered with the service manager rather than written to a file.
 #[cfg(target_os = "windows")]
 const SUPERVISE_SYSTEM_DIR: &str = "";
 #[cfg(target_os = "windows")]
@@ -24,7 +32,9 @@ const SERVICE_TEMPLATE: &str = "";
 #[cfg(target_os = "windows")]
 const OS_RELEASE_FILE: &str = "";
 #[cfg(target_os = "windows")]
-const ALPINE_INIT_DIR: &str = "";
+const OPENRC_INIT_DIR: &str = "";
+#[cfg(target_os = "windows")]
+const RUNIT_SV_DIR: &str = "";
 
 #[cfg(target_os = "linux")]
 const SUPERVISE_SYSTEM_DIR: &str = "/lib/systemd/system";
@@ -34,24 +44,47 @@ const OS_RELEASE_FILE: &str = "/etc/os-release";
 #[cfg(target_os = "linux")]
 const SYSTEMD_TEMPLATE: &str = r#"
 [Unit]
-Description=zeronsd for network {launcher.network_id}
-Requires=zerotier-one.service
-After=zerotier-one.service
+Description=zeronsd for {{ for network in networks }}{{ if not @first }}, {{ endif }}network {network}{{ endfor }}
+Requires=zerotier-one.service{{ if socket_unit }} {socket_unit}{{ endif }}
+After=zerotier-one.service{{ if socket_unit }} {socket_unit}{{ endif }}
 
 [Service]
-Type=simple
-ExecStart={binpath} start -t {launcher.token} {{ if config }}-c {config} {{endif}}{{ if config_type_supplied }}--config-type {config_type} {{endif}}{{ if launcher.wildcard }}-w {{endif}}{{ if launcher.secret }}-s {launcher.secret} {{endif}}{{ if launcher.hosts }}-f {launcher.hosts} {{ endif }}{{ if launcher.domain }}-d {launcher.domain} {{ endif }}{launcher.network_id}
-TimeoutStopSec=30
+Type=notify
+ExecStart={binpath} start {start_args | unescaped}
+ExecReload=/bin/kill -HUP $MAINPID
+{{ if launcher.user }}User={launcher.user}
+{{ if not socket_unit }}AmbientCapabilities=CAP_NET_BIND_SERVICE
+{{ endif }}{{ endif }}{{ if launcher.group }}Group={launcher.group}
+{{ endif }}{{ if launcher.wait_for_network }}TimeoutStartSec=infinity
+{{ endif }}TimeoutStopSec=30
+WatchdogSec=120
 Restart=always
 
 [Install]
 WantedBy=default.target
 "#;
 
+// the socket unit of --socket: systemd listens on the addresses and passes the sockets to the
+// service, which needs no privileges to bind them then. The addresses are ZeroTier addresses,
+// which may not be assigned yet when systemd binds them.
 #[cfg(target_os = "linux")]
-const ALPINE_INIT_DIR: &str = "/etc/init.d";
+const SYSTEMD_SOCKET_TEMPLATE: &str = r#"
+[Unit]
+Description=Sockets of zeronsd for {{ for network in networks }}{{ if not @first }}, {{ endif }}network {network}{{ endfor }}
+
+[Socket]
+{{ for addr in sockets }}ListenStream={addr}
+ListenDatagram={addr}
+{{ endfor }}FreeBind=yes
+
+[Install]
+WantedBy=sockets.target
+"#;
+
+#[cfg(target_os = "linux")]
+const OPENRC_INIT_DIR: &str = "/etc/init.d";
 #[cfg(target_os = "linux")]
-const ALPINE_TEMPLATE: &str = r#"
+const OPENRC_TEMPLATE: &str = r#"
 #!/sbin/openrc-run
 
 depend() \{
@@ -59,57 +92,49 @@ depend() \{
     use network dns logger netmount
 }
 
-description="zeronsd for network {launcher.network_id}"
+description="zeronsd for {{ for network in networks }}{{ if not @first }}, {{ endif }}network {network}{{ endfor }}"
 command="{binpath}"
-command_args="start -t {launcher.token} {{ if config }}-c {config} {{endif}}{{ if config_type_supplied }}--config-type {config_type} {{endif}}{{ if launcher.wildcard }}-w {{endif}}{{ if launcher.secret }}-s {launcher.secret} {{endif}}{{ if launcher.hosts }}-f {launcher.hosts} {{ endif }}{{ if launcher.domain }}-d {launcher.domain} {{ endif }}{launcher.network_id}"
+command_args="start {start_args | unescaped}"
 command_background="yes"
 pidfile="/run/$RC_SVCNAME.pid"
 "#;
 
+#[cfg(target_os = "linux")]
+const RUNIT_SV_DIR: &str = "/etc/sv";
+#[cfg(target_os = "linux")]
+const RUNIT_TEMPLATE: &str = r#"
+#!/bin/sh
+# zeronsd for {{ for network in networks }}{{ if not @first }}, {{ endif }}network {network}{{ endfor }}
+sv check zerotier-one >/dev/null || exit 1
+exec 2>&1
+exec {binpath} start {start_args | unescaped}
+"#;
+
 #[cfg(target_os = "macos")]
 const OS_RELEASE_FILE: &str = "";
 #[cfg(target_os = "macos")]
-const ALPINE_INIT_DIR: &str = "";
+const OPENRC_INIT_DIR: &str = "";
+#[cfg(target_os = "macos")]
+const RUNIT_SV_DIR: &str = "";
 #[cfg(target_os = "macos")]
 const SUPERVISE_SYSTEM_DIR: &str = "/Library/LaunchDaemons/";
+// where launchd sends the output of the service (see the template); it must exist before the
+// service starts.
+#[cfg(target_os = "macos")]
+const LAUNCHD_LOG_DIR: &str = "/var/log/zerotier/nsd";
 #[cfg(target_os = "macos")]
 const SERVICE_TEMPLATE: &str = r#"
 <?xml version="1.0" encoding="UTF-8"?>
 <!DOCTYPE plist PUBLIC "-//Apple//DTD PLIST 1.0//EN" "http://www.apple.com/DTDs/PropertyList-1.0.dtd">
 <plist version="1.0">
   <dict>
-    <key>Label</key> <string>com.zerotier.nsd.{launcher.network_id}</string>
+    <key>Label</key> <string>com.zerotier.nsd{{ if unit_id }}.{unit_id}{{ endif }}</string>
 
     <key>ProgramArguments</key>
     <array>
       <string>{binpath}</string>
-      <string>start</string>
-      <string>-t</string>
-      <string>{launcher.token}</string>
-      {{ if launcher.wildcard }}
-      <string>-w</string>
-      {{endif}}
-      {{ if launcher.secret }}
-      <string>-s</string>
-      <string>{launcher.secret}</string>
-      {{endif}}
-      {{ if launcher.hosts }}
-      <string>-f</string>
-      <string>{launcher.hosts}</string>
-      {{ endif }}
-      {{ if launcher.domain }}
-      <string>-d</string>
-      <string>{launcher.domain}</string>
-      {{ endif }}
-      {{ if config }}
-      <string>-c</string>
-      <string>{config}</string>
-      {{ endif }}
-      {{ if config_type_supplied }}
-      <string>--config-type</string>
-      <string>{config_type}</string>
-      {{ endif }}
-      <string>{launcher.network_id}</string>
+      <string>start</string>{{ for arg in start_argv }}
+      <string>{arg}</string>{{ endfor }}
     </array>
 
     <key>UserName</key> <string>root</string>
@@ -118,13 +143,84 @@ const SERVICE_TEMPLATE: &str = r#"
 
     <key>KeepAlive</key> <true/>
 
-    <key>StandardErrorPath</key> <string>/var/log/zerotier/nsd/{launcher.network_id}.err</string>
-    <key>StandardOutPath</key> <string>/var/log/zerotier/nsd/{launcher.network_id}.log</string>
+    <key>StandardErrorPath</key> <string>/var/log/zerotier/nsd/{{ if unit_id }}{unit_id}{{ else }}zeronsd{{ endif }}.err</string>
+    <key>StandardOutPath</key> <string>/var/log/zerotier/nsd/{{ if unit_id }}{unit_id}{{ else }}zeronsd{{ endif }}.log</string>
 
   </dict>
-    </plist>
+</plist>
 "#;
 
+// InitStyle is the init system a supervisor is installed for. It is only meaningful on Linux;
+// other platforms have a single supervisor.
+#[derive(Debug, Clone, Serialize, PartialEq)]
+pub enum InitStyle {
+    #[serde(rename = "systemd")]
+    Systemd,
+    #[serde(rename = "openrc")]
+    OpenRC,
+    #[serde(rename = "runit")]
+    Runit,
+}
+
+impl FromStr for InitStyle {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "systemd" => Ok(Self::Systemd),
+            "openrc" => Ok(Self::OpenRC),
+            "runit" => Ok(Self::Runit),
+            _ => Err(anyhow!(
+                "invalid init style: allowed values: [systemd, openrc, runit]"
+            )),
+        }
+    }
+}
+
+impl InitStyle {
+    // for_distro picks the init system a distribution ships with by default.
+    fn for_distro(distro: &str) -> Self {
+        match distro {
+            "alpine" => Self::OpenRC,
+            "void" => Self::Runit,
+            _ => Self::Systemd,
+        }
+    }
+
+    // quote makes an argument one word of the command line the template renders it into as is:
+    // systemd splits ExecStart itself and expands `%` specifiers and `$` variables in it, OpenRC
+    // evaluates command_args, which is in double quotes, with the shell, and the run script of
+    // runit is a shell script.
+    fn quote(&self, arg: &str) -> String {
+        if !arg.is_empty()
+            && arg
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || "-_./:=,@+".contains(c))
+        {
+            return arg.to_string();
+        }
+
+        let shell = || format!("'{}'", arg.replace('\'', r"'\''"));
+
+        match self {
+            Self::Systemd => format!(
+                "\"{}\"",
+                arg.replace('\\', r"\\")
+                    .replace('"', r#"\""#)
+                    .replace('\n', r"\n")
+                    .replace('%', "%%")
+                    .replace('$', "$$")
+            ),
+            Self::OpenRC => shell()
+                .replace('\\', r"\\")
+                .replace('"', r#"\""#)
+                .replace('$', r"\$")
+                .replace('`', r"\`"),
+            Self::Runit => shell(),
+        }
+    }
+}
+
 #[derive(Serialize)]
 pub struct Properties {
     pub launcher: Launcher,
@@ -133,25 +229,76 @@ pub struct Properties {
     pub config_type: ConfigFormat,
     pub config_type_supplied: bool,
     pub distro: Option<String>,
+    pub init_style: InitStyle,
+    // networks served by a single service; when empty, the service serves launcher.network_id.
+    pub networks: Vec<String>,
+    // the addresses systemd listens on for the service; see crate::activation.
+    pub sockets: Vec<SocketAddr>,
+}
+
+// Context is what the service templates are rendered with.
+#[derive(Serialize)]
+struct Context<'a> {
+    #[serde(flatten)]
+    properties: &'a Properties,
+    networks: Vec<String>,
+    network_args: Vec<String>,
+    unit_id: Option<String>,
+    socket_unit: Option<String>,
+    // the arguments to `zeronsd start`, quoted and joined for the init system, and listed for
+    // launchd.
+    start_args: String,
+    start_argv: Vec<String>,
 }
 
-impl From<StartArgs> for Properties {
-    fn from(args: StartArgs) -> Self {
-        let launcher: crate::init::Launcher = args.clone().into();
+// StartFlags are the flags the service runs `zeronsd start` with, each with its value if it takes
+// one. The systemd, OpenRC and runit templates, the launchd plist and the Windows service are all
+// given the arguments of this one list.
+#[derive(Default)]
+struct StartFlags(Vec<(&'static str, Option<String>)>);
 
-        // FIXME rewrite this to use a struct init later
-        Self::new(launcher, args.config.as_deref(), args.config_type).unwrap()
+impl StartFlags {
+    fn flag(&mut self, flag: &'static str, set: bool) {
+        if set {
+            self.0.push((flag, None));
+        }
+    }
+
+    // values adds the flag once for each value; an Option adds it at most once.
+    fn values<T: ToString>(&mut self, flag: &'static str, values: impl IntoIterator<Item = T>) {
+        self.0.extend(
+            values
+                .into_iter()
+                .map(|value| (flag, Some(value.to_string()))),
+        );
+    }
+
+    fn paths<T: AsRef<OsStr>>(&mut self, flag: &'static str, paths: impl IntoIterator<Item = T>) {
+        self.0.extend(
+            paths
+                .into_iter()
+                .map(|path| (flag, Some(path.as_ref().to_string_lossy().into_owned()))),
+        );
+    }
+
+    fn into_args(self) -> Vec<String> {
+        self.0
+            .into_iter()
+            .flat_map(|(flag, value)| std::iter::once(flag.to_string()).chain(value))
+            .collect()
     }
 }
 
-impl From<UnsuperviseArgs> for Properties {
-    fn from(args: UnsuperviseArgs) -> Self {
+impl TryFrom<UnsuperviseArgs> for Properties {
+    type Error = anyhow::Error;
+
+    fn try_from(args: UnsuperviseArgs) -> Result<Self, Self::Error> {
         let l = Launcher {
-            network_id: Some(args.network_id),
+            network_id: args.network_id,
             ..Default::default()
         };
 
-        Self::new(l, None, ConfigFormat::YAML).unwrap()
+        Self::new(l, None, ConfigFormat::YAML, args.init_style)
     }
 }
 
@@ -164,6 +311,9 @@ impl Default for Properties {
             config_type: ConfigFormat::YAML,
             config_type_supplied: false,
             distro: None,
+            init_style: InitStyle::Systemd,
+            networks: Vec::new(),
+            sockets: Vec::new(),
         }
     }
 }
@@ -173,33 +323,236 @@ impl Properties {
         launcher: Launcher,
         config: Option<&'_ Path>,
         config_type: ConfigFormat,
+        init_style: Option<InitStyle>,
     ) -> Result<Self, anyhow::Error> {
+        if init_style.is_some() && !cfg!(target_os = "linux") {
+            return Err(anyhow!("--init-style is only supported on Linux"));
+        }
+
         let distro = if cfg!(target_os = "linux") {
-            if let Ok(release) = std::fs::read_to_string(OS_RELEASE_FILE) {
-                let id_regex = Regex::new(r#"\nID=(.+)\n"#)?;
-                if let Some(caps) = id_regex.captures(&release) {
-                    caps.get(1)
-                        .map(|distro| distro.clone().as_str().to_string())
-                } else {
-                    return Err(anyhow!("Could not determine Linux distribution; you'll need to configure supervision manually. Sorry!"));
-                }
-            } else {
-                return Err(anyhow!("Could not determine Linux distribution; you'll need to configure supervision manually. Sorry!"));
+            match Self::detect_distro() {
+                Ok(distro) => distro,
+                // the distribution only matters if we have to guess the init system.
+                Err(_) if init_style.is_some() => None,
+                Err(e) => return Err(e),
             }
         } else {
             None
         };
 
+        let init_style = init_style.unwrap_or_else(|| {
+            distro
+                .as_deref()
+                .map(InitStyle::for_distro)
+                .unwrap_or(InitStyle::Systemd)
+        });
+
         Ok(Self {
             distro,
+            init_style,
             binpath: String::from(std::env::current_exe()?.to_string_lossy()),
             config_type: config_type.clone(),
             config_type_supplied: config_type != ConfigFormat::YAML,
             config: config.map(|config| config.to_owned()),
             launcher,
+            networks: Vec::new(),
+            sockets: Vec::new(),
         })
     }
 
+    // networks returns the networks the service serves.
+    fn networks(&self) -> Vec<String> {
+        if self.networks.is_empty() {
+            self.launcher.network_id.clone().into_iter().collect()
+        } else {
+            self.networks.clone()
+        }
+    }
+
+    // network_args are the arguments naming the networks the service serves. A service serving
+    // several networks passes their domains along with them; one serving a single network is
+    // given its domain with -d instead.
+    fn network_args(&self) -> Vec<String> {
+        let mut args = Vec::new();
+
+        for network in self.networks() {
+            match self.launcher.network_domains.get(&network) {
+                Some(domain) if self.unit_id().is_none() => {
+                    args.extend(["--network".to_string(), format!("{}={}", network, domain)])
+                }
+                _ => args.push(network),
+            }
+        }
+
+        args
+    }
+
+    // start_args are the arguments the service runs `zeronsd start` with.
+    fn start_args(&self) -> Vec<String> {
+        let launcher = &self.launcher;
+        let mut args = StartFlags::default();
+
+        args.paths("-t", &launcher.token);
+        args.flag(
+            "--insecure-token-permissions",
+            launcher.insecure_token_permissions,
+        );
+        args.flag("--token-keychain", launcher.token_keychain);
+        args.flag("--local-controller", launcher.local_controller);
+        args.paths("--members-file", &launcher.members_file);
+        args.values("--primary", &launcher.primary);
+        args.paths("-c", &self.config);
+        if self.config_type_supplied {
+            args.values("--config-type", Some(format!("{:?}", self.config_type)));
+        }
+        args.flag("-w", launcher.wildcard);
+        args.values("--wildcard-member", &launcher.wildcard_members);
+        args.values("--name-policy", launcher.name_policy);
+        args.values("--name-collisions", launcher.name_collisions);
+        args.values("--include-member", &launcher.include_members);
+        args.values("--exclude-member", &launcher.exclude_members);
+        args.flag("--authorized-only", launcher.authorized_only);
+        args.flag("--node-id-names", launcher.node_id_names);
+        args.flag("--all-ptr-names", launcher.all_ptr_names);
+        args.flag("--ip-names", launcher.ip_names);
+        args.flag("--classless-reverse", launcher.classless_reverse);
+        args.values(
+            "--reverse-zone",
+            launcher
+                .reverse_zones
+                .iter()
+                .map(|zone| format!("{}={}", zone.network, zone.zone)),
+        );
+        args.flag("--member-txt", launcher.member_txt);
+        args.values("--max-records", launcher.max_records);
+        args.values("--record-eviction", launcher.record_eviction);
+        args.values("--poll-interval", launcher.poll_interval);
+        args.values("--ttl", launcher.ttl);
+        args.flag("--clear-dns-on-exit", launcher.clear_dns_on_exit);
+        args.flag("--no-update-central", launcher.no_update_central);
+        args.values("--central-dns-servers", &launcher.central_dns_servers);
+        args.values("--peer", &launcher.peers);
+        args.values("--wait-for-network", launcher.wait_for_network);
+        args.values("--central-url", &launcher.central_url);
+        args.values("--central-timeout", launcher.central_timeout);
+        args.values(
+            "--central-connect-timeout",
+            launcher.central_connect_timeout,
+        );
+        args.values("--central-proxy", &launcher.central_proxy);
+        args.paths("--state-dir", &launcher.state_dir);
+        args.paths("--state-db", &launcher.state_db);
+        args.values("--listen-port", launcher.listen_port);
+        args.values("--listen-addr", &launcher.listen_addrs);
+        args.values("--workers", launcher.workers);
+        args.values("--worker-threads", launcher.worker_threads);
+        args.values("--max-blocking-threads", launcher.max_blocking_threads);
+        args.values("--query-timeout", launcher.query_timeout);
+        args.values("--tcp-timeout", launcher.tcp_timeout);
+        args.values("--allow-transfer", &launcher.allow_transfer);
+        args.values("--notify", &launcher.notify);
+        args.values("--upstream", &launcher.upstreams);
+        args.flag("--dnssec-validation", launcher.dnssec_validation);
+        args.flag("--qname-minimization", launcher.qname_minimization);
+        args.flag("--cache", launcher.cache);
+        args.values("--cache-size", launcher.cache_size);
+        args.flag("--serve-stale", launcher.serve_stale);
+        args.values("--edns-buffer-size", launcher.edns_buffer_size);
+        args.values("--nsid", &launcher.nsid);
+        args.flag("--dns-cookies", launcher.dns_cookies);
+        args.flag("--minimal-any", launcher.minimal_any);
+        args.values("--answer-order", launcher.answer_order);
+        args.values("--query-acl", launcher.query_acl);
+        args.paths("--query-log", &launcher.query_log);
+        args.values("--query-log-size", launcher.query_log_size);
+        args.paths("--dnstap", &launcher.dnstap);
+        args.values("--health-addr", launcher.health_addr);
+        args.paths("--admin-socket", &launcher.admin_socket);
+        args.values("--admin-addr", launcher.admin_addr);
+        args.values("--webhook-addr", launcher.webhook_addr);
+        args.paths("--webhook-secret", &launcher.webhook_secret);
+        args.values("--record-webhook", &launcher.record_webhooks);
+        args.paths("--record-webhook-secret", &launcher.record_webhook_secret);
+        args.values("--replication-addr", launcher.replication_addr);
+        args.paths("--replication-secret", &launcher.replication_secret);
+        args.values("--cluster-member", &launcher.cluster);
+        args.paths("--blocklist", &launcher.blocklists);
+        args.values("--block-action", launcher.block_action);
+        args.values(
+            "--rpz",
+            launcher
+                .rpz
+                .iter()
+                .map(|rpz| format!("{}={}", rpz.zone, rpz.file.display())),
+        );
+        // the Windows service runs as the account it is registered with.
+        if !cfg!(target_os = "windows") {
+            args.values("--user", &launcher.user);
+            args.values("--group", &launcher.group);
+        }
+        args.paths("-s", &launcher.secret);
+        args.paths("-f", &launcher.hosts);
+        args.values("-d", launcher.domain.iter().chain(&launcher.domain_aliases));
+        args.flag("--auto-domain", launcher.auto_domain);
+        args.paths("--tls-cert", &launcher.tls_cert);
+        args.paths("--chain-cert", &launcher.chain_cert);
+        args.paths("--tls-key", &launcher.tls_key);
+        args.values("--doh-port", launcher.doh_port);
+        args.values("--doh-hostname", &launcher.doh_hostname);
+        args.values(
+            "--local-url",
+            Some(&launcher.local_url).filter(|url| *url != ZEROTIER_LOCAL_URL),
+        );
+        args.values("--log-level", launcher.log_level.clone());
+        args.values(
+            "--log-format",
+            Some(launcher.log_format.clone()).filter(|format| *format != LogFormat::Text),
+        );
+
+        let mut args = args.into_args();
+        args.extend(self.network_args());
+        args
+    }
+
+    // unit_id distinguishes the services of different networks from each other. A service serving
+    // several networks (see `networks`) has none, and is simply named `zeronsd`.
+    fn unit_id(&self) -> Option<String> {
+        if self.networks.is_empty() {
+            self.launcher.network_id.clone()
+        } else {
+            None
+        }
+    }
+
+    // socket_name is the name of the socket unit of the service, whether it has one or not.
+    fn socket_name(&self) -> String {
+        match self.unit_id() {
+            Some(unit_id) => format!("zeronsd-{}.socket", unit_id),
+            None => "zeronsd.socket".to_string(),
+        }
+    }
+
+    // socket_unit is the name of the socket unit passing the sockets of --socket to the service,
+    // if there are any.
+    fn socket_unit(&self) -> Option<String> {
+        (!self.sockets.is_empty()).then(|| self.socket_name())
+    }
+
+    fn detect_distro() -> Result<Option<String>, anyhow::Error> {
+        if let Ok(release) = std::fs::read_to_string(OS_RELEASE_FILE) {
+            let id_regex = Regex::new(r#"\nID=(.+)\n"#)?;
+            if let Some(caps) = id_regex.captures(&release) {
+                Ok(caps
+                    .get(1)
+                    .map(|distro| distro.as_str().trim_matches('"').to_string()))
+            } else {
+                Err(anyhow!("Could not determine Linux distribution; pass --init-style or configure supervision manually. Sorry!"))
+            }
+        } else {
+            Err(anyhow!("Could not determine Linux distribution; pass --init-style or configure supervision manually. Sorry!"))
+        }
+    }
+
     pub fn validate(&mut self) -> Result<(), anyhow::Error> {
         self.config = match self.config.clone() {
             Some(config) => match config.canonicalize() {
@@ -209,40 +562,63 @@ impl Properties {
             None => None,
         };
 
-        let token = self
-            .launcher
-            .token
-            .clone()
-            .expect("Could not find token file: {}")
-            .canonicalize()?;
-
-        let tstat = match std::fs::metadata(token.clone()) {
-            Ok(ts) => ts,
-            Err(e) => {
-                return Err(anyhow!(
-                    "Could not stat token file {}: {}",
-                    token.display(),
-                    e
-                ))
+        if self.launcher.token_stdin {
+            return Err(anyhow!(
+                "A service cannot read the token from standard input; use -t or --token-keychain"
+            ));
+        }
+
+        if self.launcher.mock.is_some() {
+            return Err(anyhow!(
+                "The mock of Central is for tests and demos, and cannot be run as a service"
+            ));
+        }
+
+        // the local controller is reached with authtoken.secret instead, and the credential store
+        // needs no file.
+        if let Some(token) = self.launcher.token.clone() {
+            let token = token.canonicalize()?;
+
+            let tstat = match std::fs::metadata(token.clone()) {
+                Ok(ts) => ts,
+                Err(e) => {
+                    return Err(anyhow!(
+                        "Could not stat token file {}: {}",
+                        token.display(),
+                        e
+                    ))
+                }
+            };
+
+            if !tstat.is_file() {
+                return Err(anyhow!("Token file {} is not a file", token.display()));
             }
-        };
+        } else if !(self.launcher.local_controller
+            || self.launcher.members_file.is_some()
+            || self.launcher.primary.is_some()
+            || self.launcher.token_keychain)
+        {
+            return Err(anyhow!("Could not find token file; provide it with -t"));
+        }
 
-        if !tstat.is_file() {
-            return Err(anyhow!("Token file {} is not a file", token.display()));
+        if self.networks().is_empty() {
+            return Err(anyhow!("network_id is not provided"));
         }
 
-        if self
-            .launcher
-            .network_id
-            .clone()
-            .expect("network_id is not provided")
-            .len()
-            != 16
+        if !self.sockets.is_empty()
+            && (!cfg!(target_os = "linux") || self.init_style != InitStyle::Systemd)
         {
-            return Err(anyhow!("Network ID must be 16 characters"));
+            return Err(anyhow!("--socket is only supported with systemd"));
         }
 
-        if let Some(hosts_file) = self.launcher.hosts.clone() {
+        for network_id in self.networks() {
+            if network_id.len() != 16 {
+                return Err(anyhow!("Network ID must be 16 characters"));
+            }
+        }
+
+        let mut hosts = Vec::new();
+        for hosts_file in self.launcher.hosts.clone() {
             let hstat = match std::fs::metadata(hosts_file.clone()) {
                 Ok(hs) => hs,
                 Err(e) => {
@@ -254,19 +630,28 @@ impl Properties {
                 }
             };
 
-            if !hstat.is_file() {
-                return Err(anyhow!("Hosts file {} is not a file", hosts_file.display()));
+            if !hstat.is_file() && !hstat.is_dir() {
+                return Err(anyhow!(
+                    "Hosts file {} is not a file or directory",
+                    hosts_file.display()
+                ));
             }
 
-            self.launcher.hosts = Some(hosts_file.canonicalize()?);
+            hosts.push(hosts_file.canonicalize()?);
         }
+        self.launcher.hosts = hosts;
 
-        if let Some(domain) = self.launcher.domain.clone() {
+        for domain in self
+            .launcher
+            .domain
+            .iter()
+            .chain(self.launcher.domain_aliases.iter())
+        {
             if domain.trim().is_empty() {
                 return Err(anyhow!("Domain name cannot be empty"));
             }
 
-            if let Err(e) = Name::parse(&domain, None) {
+            if let Err(e) = Name::parse(domain, None) {
                 return Err(anyhow!("Domain name is invalid: {}", e));
             }
         }
@@ -293,6 +678,22 @@ impl Properties {
             self.launcher.secret = Some(authtoken.canonicalize()?);
         }
 
+        for (desc, path) in [
+            ("TLS certificate", &mut self.launcher.tls_cert),
+            ("TLS certificate chain", &mut self.launcher.chain_cert),
+            ("TLS key", &mut self.launcher.tls_key),
+        ] {
+            if let Some(file) = path.clone() {
+                match std::fs::metadata(file.clone()) {
+                    Ok(st) if st.is_file() => *path = Some(file.canonicalize()?),
+                    Ok(_) => return Err(anyhow!("{} {} is not a file", desc, file.display())),
+                    Err(e) => {
+                        return Err(anyhow!("Could not stat {} {}: {}", desc, file.display(), e))
+                    }
+                }
+            }
+        }
+
         Ok(())
     }
 
@@ -301,17 +702,50 @@ impl Properties {
 
         let mut t = TinyTemplate::new();
         t.add_template("supervise", template)?;
-        match t.render("supervise", self) {
+
+        match t.render("supervise", &self.context()) {
             Ok(x) => Ok(x),
             Err(e) => Err(anyhow!(e)),
         }
     }
 
+    // socket_template renders the socket unit of the service, if it has one.
+    #[cfg(target_os = "linux")]
+    pub fn socket_template(&self) -> Result<Option<String>, anyhow::Error> {
+        if self.socket_unit().is_none() {
+            return Ok(None);
+        }
+
+        let mut t = TinyTemplate::new();
+        t.add_template("socket", SYSTEMD_SOCKET_TEMPLATE)?;
+
+        Ok(Some(t.render("socket", &self.context())?))
+    }
+
+    fn context(&self) -> Context<'_> {
+        let start_argv = self.start_args();
+
+        Context {
+            properties: self,
+            networks: self.networks(),
+            network_args: self.network_args(),
+            unit_id: self.unit_id(),
+            socket_unit: self.socket_unit(),
+            start_args: start_argv
+                .iter()
+                .map(|arg| self.init_style.quote(arg))
+                .collect::<Vec<_>>()
+                .join(" "),
+            start_argv,
+        }
+    }
+
     #[cfg(target_os = "linux")]
     fn get_service_template(&self) -> &str {
-        match self.distro.as_deref() {
-            Some("alpine") => ALPINE_TEMPLATE.trim(),
-            _ => SYSTEMD_TEMPLATE,
+        match self.init_style {
+            InitStyle::OpenRC => OPENRC_TEMPLATE.trim(),
+            InitStyle::Runit => RUNIT_TEMPLATE.trim_start(),
+            InitStyle::Systemd => SYSTEMD_TEMPLATE,
         }
     }
 
@@ -320,58 +754,141 @@ impl Properties {
         return SERVICE_TEMPLATE;
     }
 
+    // the XML declaration has to be the first thing in a plist.
     #[cfg(target_os = "macos")]
     fn get_service_template(&self) -> &str {
-        return SERVICE_TEMPLATE;
+        return SERVICE_TEMPLATE.trim_start();
     }
 
     #[cfg(target_os = "windows")]
     fn service_name(&self) -> String {
-        return String::new();
+        crate::service::service_name(self.unit_id().as_deref())
+    }
+
+    // service_arguments are the arguments the Windows service is started with.
+    #[cfg(target_os = "windows")]
+    fn service_arguments(&self) -> Vec<std::ffi::OsString> {
+        std::iter::once("service".to_string())
+            .chain(self.start_args())
+            .map(|arg| arg.into())
+            .collect()
     }
 
     #[cfg(target_os = "linux")]
     fn service_name(&self) -> String {
-        let network_id = self
-            .launcher
-            .network_id
-            .clone()
-            .expect("network_id missing");
-        match self.distro.as_deref() {
-            Some("alpine") => format!("zeronsd-{}", network_id),
-            _ => format!("zeronsd-{}.service", network_id),
+        let name = match self.unit_id() {
+            Some(unit_id) => format!("zeronsd-{}", unit_id),
+            None => "zeronsd".to_string(),
+        };
+
+        match self.init_style {
+            InitStyle::OpenRC | InitStyle::Runit => name,
+            InitStyle::Systemd => format!("{}.service", name),
         }
     }
 
     #[cfg(target_os = "macos")]
     fn service_name(&self) -> String {
-        format!(
-            "com.zerotier.nsd.{}.plist",
-            self.launcher
-                .network_id
-                .as_ref()
-                .expect("network_id missing")
-        )
-    }
-
-    fn service_path(&self) -> PathBuf {
-        let dir = match self.distro.as_deref() {
-            Some("alpine") => ALPINE_INIT_DIR,
-            _ => SUPERVISE_SYSTEM_DIR,
-        };
-        PathBuf::from(dir).join(self.service_name())
+        match self.unit_id() {
+            Some(unit_id) => format!("com.zerotier.nsd.{}.plist", unit_id),
+            None => "com.zerotier.nsd.plist".to_string(),
+        }
+    }
+
+    pub fn service_path(&self) -> PathBuf {
+        match self.init_style {
+            InitStyle::OpenRC => PathBuf::from(OPENRC_INIT_DIR).join(self.service_name()),
+            // runit services are directories holding a `run` script.
+            InitStyle::Runit => PathBuf::from(RUNIT_SV_DIR)
+                .join(self.service_name())
+                .join("run"),
+            InitStyle::Systemd => PathBuf::from(SUPERVISE_SYSTEM_DIR).join(self.service_name()),
+        }
+    }
+
+    #[cfg(target_os = "linux")]
+    pub fn socket_path(&self) -> PathBuf {
+        PathBuf::from(SUPERVISE_SYSTEM_DIR).join(self.socket_name())
+    }
+
+    // secure_token makes the token file the service is given only accessible to the account it
+    // runs as, as zeronsd refuses to start otherwise.
+    fn secure_token(&self) -> Result<(), anyhow::Error> {
+        #[cfg(unix)]
+        if let Some(token) = &self.launcher.token {
+            use std::os::unix::fs::PermissionsExt;
+
+            std::fs::set_permissions(token, std::fs::Permissions::from_mode(0o600))
+                .map_err(|e| anyhow!("Could not restrict token file {}: {}", token.display(), e))?;
+
+            if let Some(user) = &self.launcher.user {
+                let uid = nix::unistd::User::from_name(user)?
+                    .ok_or_else(|| anyhow!("Could not find user {}", user))?
+                    .uid;
+
+                nix::unistd::chown(token, Some(uid), None).map_err(|e| {
+                    anyhow!(
+                        "Could not give token file {} to {}: {}",
+                        token.display(),
+                        user,
+                        e
+                    )
+                })?;
+            }
+        }
+
+        Ok(())
+    }
+
+    // print_supervisor writes what install_supervisor would install to stdout instead. Where it
+    // would be installed goes to stderr, so stdout can be redirected straight into a file.
+    pub fn print_supervisor(&mut self) -> Result<(), anyhow::Error> {
+        self.validate()?;
+
+        #[cfg(target_os = "windows")]
+        {
+            let args = self
+                .service_arguments()
+                .iter()
+                .map(|arg| arg.to_string_lossy().into_owned())
+                .collect::<Vec<String>>();
+
+            eprintln!("Service {}:", self.service_name());
+            println!("{} {}", self.binpath, args.join(" "));
+        }
+
+        #[cfg(not(target_os = "windows"))]
+        {
+            eprintln!("{}:", self.service_path().display());
+            print!("{}", self.supervise_template()?);
+
+            #[cfg(target_os = "linux")]
+            if let Some(socket) = self.socket_template()? {
+                eprintln!("{}:", self.socket_path().display());
+                print!("{}", socket);
+            }
+        }
+
+        Ok(())
     }
 
     pub fn install_supervisor(&mut self) -> Result<(), anyhow::Error> {
         self.validate()?;
+        self.secure_token()?;
 
         if cfg!(target_os = "linux") {
             #[cfg(target_os = "linux")]
-            let executable = self.distro.as_deref() == Some("alpine");
+            let executable = self.init_style != InitStyle::Systemd;
 
             let template = self.supervise_template()?;
             let service_path = self.service_path();
 
+            if self.init_style == InitStyle::Runit {
+                if let Some(dir) = service_path.parent() {
+                    std::fs::create_dir_all(dir)?;
+                }
+            }
+
             match std::fs::write(service_path.clone(), template) {
                 Ok(_) => {}
                 Err(e) => {
@@ -392,20 +909,37 @@ impl Properties {
                 std::fs::set_permissions(service_path.clone(), perms)?;
             }
 
-            let network = self
-                .launcher
-                .network_id
-                .clone()
-                .expect("network_id missing");
-            let systemd_help = format!("Don't forget to `systemctl daemon-reload`, `systemctl enable zeronsd-{}` and `systemctl start zeronsd-{}`.", network, network);
-            let alpine_help = format!(
-                "Don't forget to `rc-update add zeronsd-{}` and `rc-service zeronsd-{} start`",
-                network, network
+            #[cfg(target_os = "linux")]
+            if let Some(socket) = self.socket_template()? {
+                std::fs::write(self.socket_path(), socket).map_err(|e| {
+                    anyhow!(
+                        "Could not write the socket unit {}; are you root? ({})",
+                        self.socket_path().display(),
+                        e
+                    )
+                })?;
+            }
+
+            let name = self.service_name();
+            // the socket is started first, so that it is there to pass to the service.
+            let units = match self.socket_unit() {
+                Some(socket) => format!("{} {}", socket, name),
+                None => name.clone(),
+            };
+            let systemd_help = format!("Don't forget to `systemctl daemon-reload`, `systemctl enable {}` and `systemctl start {}`.", units, units);
+            let openrc_help = format!(
+                "Don't forget to `rc-update add {}` and `rc-service {} start`",
+                name, name
+            );
+            let runit_help = format!(
+                "Don't forget to enable it: `ln -s {}/{} /var/service/`",
+                RUNIT_SV_DIR, name
             );
 
-            let help = match self.distro.as_deref() {
-                Some("alpine") => alpine_help,
-                _ => systemd_help,
+            let help = match self.init_style {
+                InitStyle::OpenRC => openrc_help,
+                InitStyle::Runit => runit_help,
+                InitStyle::Systemd => systemd_help,
             };
 
             eprintln!(
@@ -432,11 +966,47 @@ impl Properties {
                 }
             };
 
+            // launchd refuses to load daemons which are writable by anyone but root.
+            #[cfg(target_os = "macos")]
+            {
+                let mut perms = std::fs::metadata(service_path.clone())?.permissions();
+                perms.set_mode(0o644);
+                std::fs::set_permissions(service_path.clone(), perms)?;
+
+                if let Err(e) = std::fs::create_dir_all(LAUNCHD_LOG_DIR) {
+                    return Err(anyhow!(
+                        "Could not create the log directory {}: {}",
+                        LAUNCHD_LOG_DIR,
+                        e
+                    ));
+                }
+            }
+
             eprintln!(
-                "Service definition written to {}.\nTo start the service, run:\nsudo launchctl load {}",
+                "Service definition written to {}.\nTo start the service, run:\nsudo launchctl bootstrap system {}",
                 service_path.to_str().expect("Could not coerce service path to string"),
                 service_path.to_str().expect("Could not coerce service path to string")
             );
+        } else if cfg!(target_os = "windows") {
+            #[cfg(target_os = "windows")]
+            {
+                crate::service::install(
+                    self.unit_id().as_deref(),
+                    &self
+                        .networks()
+                        .iter()
+                        .map(|network| format!("network {}", network))
+                        .collect::<Vec<_>>()
+                        .join(", "),
+                    self.service_arguments(),
+                )?;
+
+                eprintln!(
+                    "Service {} installed.\nTo start the service, run:\nsc.exe start {}",
+                    self.service_name(),
+                    self.service_name()
+                );
+            }
         } else {
             return Err(anyhow!("Your platform is not supported for this command"));
         }
@@ -445,7 +1015,16 @@ impl Properties {
 
     pub fn uninstall_supervisor(&self) -> Result<(), anyhow::Error> {
         if cfg!(target_os = "linux") {
-            match std::fs::remove_file(self.service_path()) {
+            let res = match self.init_style {
+                // the whole service directory is ours; `run` is only the script inside it.
+                InitStyle::Runit => match self.service_path().parent() {
+                    Some(dir) => std::fs::remove_dir_all(dir),
+                    None => std::fs::remove_file(self.service_path()),
+                },
+                _ => std::fs::remove_file(self.service_path()),
+            };
+
+            match res {
                 Ok(_) => {}
                 Err(e) => {
                     return Err(anyhow!(
@@ -457,9 +1036,41 @@ impl Properties {
                     ))
                 }
             };
+
+            // the socket unit of --socket goes with the service, if it was installed.
+            #[cfg(target_os = "linux")]
+            if self.init_style == InitStyle::Systemd {
+                if let Err(e) = std::fs::remove_file(self.socket_path()) {
+                    if e.kind() != std::io::ErrorKind::NotFound {
+                        return Err(anyhow!(
+                            "Could not remove the socket unit {}: {}",
+                            self.socket_path().display(),
+                            e
+                        ));
+                    }
+                }
+            }
+
+            let help = match self.init_style {
+                InitStyle::OpenRC => format!(
+                    "Don't forget to remove it from its runlevel:\nsudo rc-update del {}",
+                    self.service_name()
+                ),
+                InitStyle::Runit => format!(
+                    "Don't forget to remove its link:\nsudo rm /var/service/{}",
+                    self.service_name()
+                ),
+                InitStyle::Systemd => {
+                    "Don't forget to reload systemd:\nsudo systemctl daemon-reload".to_string()
+                }
+            };
+
             eprintln!(
-                "Service definition removed from {}.\nDon't forget to reload systemd:\nsudo systemctl daemon-reload",
-                self.service_path().to_str().expect("Could not coerce service path to string"),
+                "Service definition removed from {}.\n{}",
+                self.service_path()
+                    .to_str()
+                    .expect("Could not coerce service path to string"),
+                help,
             );
         } else if cfg!(target_os = "macos") {
             match std::fs::remove_file(self.service_path()) {
@@ -476,10 +1087,17 @@ impl Properties {
             };
 
             eprintln!(
-                "Service definition removed from {}.\nDon't forget to stop it:\nsudo launchctl remove {}",
+                "Service definition removed from {}.\nDon't forget to stop it:\nsudo launchctl bootout system/{}",
                 self.service_path().to_str().expect("Could not coerce service path to string"),
                 self.service_name().replace(".plist", "")
             );
+        } else if cfg!(target_os = "windows") {
+            #[cfg(target_os = "windows")]
+            {
+                crate::service::uninstall(self.unit_id().as_deref())?;
+
+                eprintln!("Service {} removed.", self.service_name());
+            }
         } else {
             return Err(anyhow!("Your platform is not supported for this command"));
         }
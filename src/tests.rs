@@ -9,7 +9,7 @@ use crate::utils::domain_or_default;
 
 #[test]
 fn test_parse_member_name() {
-    use crate::utils::parse_member_name;
+    use crate::utils::{parse_member_name, NamePolicy};
 
     let actual_domains: &mut Vec<Option<&str>> =
         &mut vec!["tld", "domain", "zerotier", "test.subdomain"]
@@ -22,11 +22,18 @@ fn test_parse_member_name() {
     for domain in actual_domains {
         let domain_name = domain_or_default(*domain).unwrap().clone();
 
-        assert_eq!(parse_member_name(None, domain_name.clone()), None);
+        assert_eq!(
+            parse_member_name(None, domain_name.clone(), NamePolicy::Idna),
+            None
+        );
 
         for name in ["islay", "ALL-CAPS", "Capitalized", "with.dots"] {
             assert_eq!(
-                parse_member_name(Some(name.to_string()), domain_name.clone()),
+                parse_member_name(
+                    Some(name.to_string()),
+                    domain_name.clone(),
+                    NamePolicy::Idna
+                ),
                 Some(name.to_fqdn(domain_name.clone()).unwrap()),
                 "{}",
                 name,
@@ -35,7 +42,11 @@ fn test_parse_member_name() {
 
         for bad_name in [".", "!", "arghle."] {
             assert_eq!(
-                parse_member_name(Some(bad_name.to_string()), domain_name.clone()),
+                parse_member_name(
+                    Some(bad_name.to_string()),
+                    domain_name.clone(),
+                    NamePolicy::Idna
+                ),
                 None,
                 "{}",
                 bad_name,
@@ -44,7 +55,11 @@ fn test_parse_member_name() {
 
         for (orig, translated) in [("Erik's laptop", "eriks-laptop"), ("!foo", "foo")] {
             assert_eq!(
-                parse_member_name(Some(orig.to_string()), domain_name.clone()),
+                parse_member_name(
+                    Some(orig.to_string()),
+                    domain_name.clone(),
+                    NamePolicy::Idna
+                ),
                 Some(translated.to_fqdn(domain_name.clone()).unwrap()),
                 "{}",
                 orig,
@@ -53,6 +68,95 @@ fn test_parse_member_name() {
     }
 }
 
+#[test]
+fn test_parse_member_aliases() {
+    use crate::utils::{parse_member_aliases, NamePolicy};
+
+    let domain_name = domain_or_default(None).unwrap();
+
+    assert!(parse_member_aliases(None, domain_name.clone(), NamePolicy::Idna).is_empty());
+    assert!(parse_member_aliases(
+        Some("database server".to_string()),
+        domain_name.clone(),
+        NamePolicy::Idna
+    )
+    .is_empty());
+
+    assert_eq!(
+        parse_member_aliases(
+            Some("database server aliases=db,Primary-DB,,! backups nightly".to_string()),
+            domain_name.clone(),
+            NamePolicy::Idna
+        ),
+        vec![
+            "db".to_fqdn(domain_name.clone()).unwrap(),
+            "primary-db".to_fqdn(domain_name.clone()).unwrap(),
+        ]
+    );
+}
+
+#[test]
+fn test_name_policy() {
+    use crate::utils::{parse_member_name, NamePolicy};
+
+    let domain_name = domain_or_default(None).unwrap();
+    let parse = |name: &str, policy| {
+        parse_member_name(Some(name.to_string()), domain_name.clone(), policy)
+            .map(|name| name.to_ascii().to_lowercase())
+    };
+
+    let fqdn = |name: &str| format!("{}.home.arpa.", name);
+    let names = ["islay", "Erik's laptop", "web_01", "Büro"];
+
+    for (policy, expected) in [
+        (
+            NamePolicy::Idna,
+            [
+                Some("islay"),
+                Some("eriks-laptop"),
+                None,
+                Some("xn--bro-hoa"),
+            ],
+        ),
+        (
+            NamePolicy::Dash,
+            [
+                Some("islay"),
+                Some("erik-s-laptop"),
+                Some("web-01"),
+                Some("b-ro"),
+            ],
+        ),
+        (
+            NamePolicy::Strip,
+            [
+                Some("islay"),
+                Some("erikslaptop"),
+                Some("web01"),
+                Some("bro"),
+            ],
+        ),
+        (NamePolicy::Reject, [Some("islay"), None, None, None]),
+    ] {
+        for (name, expected) in names.iter().zip(expected) {
+            assert_eq!(
+                parse(name, policy),
+                expected.map(fqdn),
+                "{} {:?}",
+                name,
+                policy
+            );
+        }
+    }
+
+    // names with nothing left of them get no record.
+    assert_eq!(parse("!!", NamePolicy::Dash), None);
+    assert_eq!(parse("!!", NamePolicy::Strip), None);
+
+    assert_eq!("punycode".parse::<NamePolicy>().unwrap(), NamePolicy::Idna);
+    assert!("mangle".parse::<NamePolicy>().is_err());
+}
+
 #[test]
 fn test_parse_ip_from_cidr() {
     use crate::utils::parse_ip_from_cidr;
@@ -73,6 +177,135 @@ fn test_parse_ip_from_cidr() {
     }
 }
 
+#[test]
+fn test_dns_servers() {
+    use crate::utils::dns_servers;
+
+    let ips = |ips: &[&str]| {
+        ips.iter()
+            .map(|ip| IpAddr::from_str(ip).unwrap())
+            .collect::<Vec<_>>()
+    };
+
+    assert_eq!(dns_servers(&ips(&["10.147.17.1"])), ["10.147.17.1"]);
+    assert_eq!(
+        dns_servers(&ips(&["fd12:3456::1", "fc9c:56c2::1", "10.147.17.1"])),
+        ["10.147.17.1", "fd12:3456::1", "fc9c:56c2::1"]
+    );
+
+    // both families make the cut when there are more addresses than members use.
+    assert_eq!(
+        dns_servers(&ips(&[
+            "10.147.17.1",
+            "10.147.18.1",
+            "10.147.19.1",
+            "10.147.20.1",
+            "fd12:3456::1"
+        ])),
+        ["10.147.17.1", "fd12:3456::1", "10.147.18.1", "10.147.19.1"]
+    );
+}
+
+#[test]
+fn test_network_from_cidr() {
+    use crate::utils::network_from_cidr;
+    use ipnetwork::IpNetwork;
+
+    let results = vec![
+        ("192.168.12.1/16", "192.168.0.0/16"),
+        ("10.0.0.0/8", "10.0.0.0/8"),
+        ("fc9c:56c2:e3ef:cc1b:947::1/40", "fc9c:56c2:e300::/40"),
+    ];
+
+    for (cidr, network) in results {
+        assert_eq!(
+            network_from_cidr(IpNetwork::from_str(cidr).unwrap()).unwrap(),
+            IpNetwork::from_str(network).unwrap(),
+            "{}",
+            cidr
+        );
+    }
+}
+
+#[test]
+fn test_parse_dns_server() {
+    use crate::utils::parse_dns_server;
+    use std::net::SocketAddr;
+
+    for (server, addr) in [
+        ("10.147.20.53", "10.147.20.53:53"),
+        ("10.147.20.53:5353", "10.147.20.53:5353"),
+        ("fd00::53", "[fd00::53]:53"),
+        ("[fd00::53]:5353", "[fd00::53]:5353"),
+    ] {
+        assert_eq!(
+            parse_dns_server(server).unwrap(),
+            SocketAddr::from_str(addr).unwrap()
+        );
+    }
+
+    assert!(parse_dns_server("ns1.example.com").is_err());
+}
+
+#[test]
+fn test_parse_forward_zones() {
+    use crate::utils::parse_forward_zones;
+    use std::collections::HashMap;
+    use std::net::SocketAddr;
+    use trust_dns_server::client::rr::Name;
+
+    let mut zones = HashMap::new();
+    zones.insert("corp.example".to_string(), vec!["10.1.1.53".to_string()]);
+    zones.insert(
+        "consul.".to_string(),
+        vec!["127.0.0.1:8600".to_string(), "[::1]:8600".to_string()],
+    );
+
+    let parsed = parse_forward_zones(&zones).unwrap();
+    assert_eq!(
+        parsed
+            .get(&Name::from_str("corp.example.").unwrap())
+            .unwrap(),
+        &vec![SocketAddr::from_str("10.1.1.53:53").unwrap()]
+    );
+    assert_eq!(
+        parsed.get(&Name::from_str("consul.").unwrap()).unwrap(),
+        &vec![
+            SocketAddr::from_str("127.0.0.1:8600").unwrap(),
+            SocketAddr::from_str("[::1]:8600").unwrap()
+        ]
+    );
+
+    for bad in [vec!["not-an-ip".to_string()], vec![]] {
+        let mut zones = HashMap::new();
+        zones.insert("corp.example".to_string(), bad);
+        assert!(parse_forward_zones(&zones).is_err());
+    }
+}
+
+#[test]
+fn test_domain_from_network_name() {
+    use crate::utils::domain_from_network_name;
+    use trust_dns_server::client::rr::Name;
+
+    for (name, domain) in [
+        ("lab", "lab."),
+        ("Home Lab", "home-lab."),
+        ("beyond.corp", "beyond.corp."),
+        ("ACME (prod)", "acme-prod."),
+    ] {
+        assert_eq!(
+            domain_from_network_name(name).unwrap(),
+            Name::from_str(domain).unwrap(),
+            "{}",
+            name
+        );
+    }
+
+    assert!(domain_from_network_name("").is_err());
+    assert!(domain_from_network_name("()").is_err());
+}
+
 #[test]
 fn test_domain_or_default() {
     use crate::utils::{domain_or_default, DOMAIN_NAME};
@@ -113,6 +346,82 @@ fn test_central_token() {
     assert_eq!(token.unwrap(), hosts.trim());
 }
 
+#[test]
+fn test_central_client_with_url() {
+    use crate::utils::central_client_with_url;
+
+    let client = central_client_with_url(
+        "token".to_string(),
+        Some("http://ztnet.example.com/api/v1/"),
+    )
+    .unwrap();
+    assert_eq!(client.baseurl(), "http://ztnet.example.com/api/v1");
+
+    assert!(central_client_with_url("token".to_string(), Some("ztnet.example.com")).is_err());
+}
+
+#[test]
+fn test_central_client_with_options() {
+    use crate::utils::{central_client_with_options, CentralOptions};
+    use std::time::Duration;
+
+    let options = CentralOptions {
+        timeout: Some(Duration::new(5, 0)),
+        connect_timeout: Some(Duration::new(2, 0)),
+        proxy: Some("http://proxy.example.com:3128".to_string()),
+        ..Default::default()
+    };
+    assert!(central_client_with_options("token".to_string(), &options).is_ok());
+
+    let options = CentralOptions {
+        proxy: Some("not a proxy".to_string()),
+        ..Default::default()
+    };
+    assert!(central_client_with_options("token".to_string(), &options).is_err());
+}
+
+#[test]
+fn test_token_source() {
+    use crate::{init::Launcher, utils::TokenSource};
+    use std::path::PathBuf;
+
+    let mut launcher = Launcher {
+        token_stdin: true,
+        token_keychain: true,
+        ..Default::default()
+    };
+    assert_eq!(launcher.token_source(), TokenSource::Stdin);
+
+    launcher.token_stdin = false;
+    assert_eq!(launcher.token_source(), TokenSource::Keychain);
+
+    launcher.token = Some(PathBuf::from("/proc/cpuinfo"));
+    assert_eq!(
+        launcher.token_source(),
+        TokenSource::File(PathBuf::from("/proc/cpuinfo"))
+    );
+
+    assert_eq!(Launcher::default().token_source(), TokenSource::Environment);
+}
+
+#[test]
+#[cfg(unix)]
+fn test_check_token_permissions() {
+    use crate::utils::check_token_permissions;
+    use std::os::unix::fs::PermissionsExt;
+
+    let path = std::env::temp_dir().join("zeronsd-test-token-permissions");
+    std::fs::write(&path, "abcdef").unwrap();
+
+    std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o644)).unwrap();
+    assert!(check_token_permissions(&path).is_err());
+
+    std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o600)).unwrap();
+    assert!(check_token_permissions(&path).is_ok());
+
+    std::fs::remove_file(path).unwrap();
+}
+
 #[test]
 #[should_panic]
 fn test_central_token_panic() {
@@ -149,7 +458,7 @@ fn test_supervise_systemd_green() {
                     token: Some(PathBuf::from("/proc/cpuinfo")),
                     domain: Some(String::from("zerotier")),
                     secret: Some(PathBuf::from("/var/lib/zerotier-one/authtoken.secret")),
-                    hosts: Some(PathBuf::from("/etc/hosts")),
+                    hosts: vec![PathBuf::from("/etc/hosts")],
                     wildcard: true,
                     ..Default::default()
                 },
@@ -157,6 +466,37 @@ fn test_supervise_systemd_green() {
                 ..Default::default()
             },
         ),
+        (
+            "with-user",
+            crate::supervise::Properties {
+                binpath: String::from("zeronsd"),
+                launcher: Launcher {
+                    network_id: Some(String::from("1234567891011121")),
+                    token: Some(PathBuf::from("/proc/cpuinfo")),
+                    user: Some(String::from("zeronsd")),
+                    group: Some(String::from("zerotier-one")),
+                    ..Default::default()
+                },
+                ..Default::default()
+            },
+        ),
+        (
+            "with-sockets",
+            crate::supervise::Properties {
+                binpath: String::from("zeronsd"),
+                launcher: Launcher {
+                    network_id: Some(String::from("1234567891011121")),
+                    token: Some(PathBuf::from("/proc/cpuinfo")),
+                    user: Some(String::from("zeronsd")),
+                    ..Default::default()
+                },
+                sockets: vec![
+                    "10.147.17.5:53".parse().unwrap(),
+                    "[fd80:56c2:e21c::5]:53".parse().unwrap(),
+                ],
+                ..Default::default()
+            },
+        ),
     ];
 
     let write = match std::env::var("WRITE_FIXTURES") {
@@ -192,6 +532,209 @@ fn test_supervise_systemd_green() {
     }
 }
 
+#[test]
+#[cfg(target_os = "linux")]
+fn test_supervise_init_styles() {
+    use std::path::PathBuf;
+
+    use crate::{init::Launcher, supervise::InitStyle};
+
+    for (dir, init_style, service_path) in [
+        (
+            "openrc",
+            InitStyle::OpenRC,
+            "/etc/init.d/zeronsd-1234567891011121",
+        ),
+        (
+            "runit",
+            InitStyle::Runit,
+            "/etc/sv/zeronsd-1234567891011121/run",
+        ),
+        (
+            "systemd",
+            InitStyle::Systemd,
+            "/lib/systemd/system/zeronsd-1234567891011121.service",
+        ),
+    ] {
+        let props = crate::supervise::Properties {
+            binpath: String::from("zeronsd"),
+            launcher: Launcher {
+                network_id: Some(String::from("1234567891011121")),
+                token: Some(PathBuf::from("/proc/cpuinfo")),
+                ..Default::default()
+            },
+            init_style: init_style.clone(),
+            ..Default::default()
+        };
+
+        let fixture = match init_style {
+            InitStyle::Systemd => format!("testdata/supervise/{}/basic.unit", dir),
+            _ => format!("testdata/supervise/{}/basic", dir),
+        };
+
+        assert_eq!(
+            props.supervise_template().unwrap(),
+            std::fs::read_to_string(fixture).unwrap(),
+            "{}",
+            dir
+        );
+        assert_eq!(props.service_path(), PathBuf::from(service_path), "{}", dir);
+    }
+
+    assert_eq!(InitStyle::from_str("runit").unwrap(), InitStyle::Runit);
+    assert!(InitStyle::from_str("upstart").is_err());
+}
+
+#[test]
+#[cfg(target_os = "linux")]
+fn test_supervise_start_args_quoting() {
+    use std::path::PathBuf;
+
+    use crate::{
+        init::Launcher,
+        log::{LevelFilter, LogFormat},
+        supervise::InitStyle,
+    };
+
+    for (init_style, line) in [
+        (
+            InitStyle::Systemd,
+            r#"ExecStart=zeronsd start -t "/etc/zeronsd/it's $$HOME 100%%.token" --insecure-token-permissions -d "a\"b" --local-url http://127.0.0.1:9994 --log-level debug --log-format json 1234567891011121"#,
+        ),
+        (
+            InitStyle::OpenRC,
+            r#"command_args="start -t '/etc/zeronsd/it'\\''s \$HOME 100%.token' --insecure-token-permissions -d 'a\"b' --local-url http://127.0.0.1:9994 --log-level debug --log-format json 1234567891011121""#,
+        ),
+        (
+            InitStyle::Runit,
+            r#"exec zeronsd start -t '/etc/zeronsd/it'\''s $HOME 100%.token' --insecure-token-permissions -d 'a"b' --local-url http://127.0.0.1:9994 --log-level debug --log-format json 1234567891011121"#,
+        ),
+    ] {
+        let props = crate::supervise::Properties {
+            binpath: String::from("zeronsd"),
+            launcher: Launcher {
+                network_id: Some(String::from("1234567891011121")),
+                token: Some(PathBuf::from("/etc/zeronsd/it's $HOME 100%.token")),
+                insecure_token_permissions: true,
+                domain: Some(String::from("a\"b")),
+                local_url: String::from("http://127.0.0.1:9994"),
+                log_level: Some(LevelFilter::Debug),
+                log_format: LogFormat::JSON,
+                ..Default::default()
+            },
+            init_style: init_style.clone(),
+            ..Default::default()
+        };
+
+        let template = props.supervise_template().unwrap();
+        assert!(
+            template.lines().any(|l| l == line),
+            "{:?}: {}",
+            init_style,
+            template
+        );
+    }
+}
+
+#[test]
+#[cfg(target_os = "linux")]
+fn test_supervise_single_unit() {
+    use std::{collections::HashMap, path::PathBuf};
+
+    use crate::init::Launcher;
+
+    let props = crate::supervise::Properties {
+        binpath: String::from("zeronsd"),
+        launcher: Launcher {
+            token: Some(PathBuf::from("/proc/cpuinfo")),
+            network_domains: HashMap::from([(
+                String::from("2234567891011121"),
+                String::from("lab.internal"),
+            )]),
+            ..Default::default()
+        },
+        networks: vec![
+            String::from("1234567891011121"),
+            String::from("2234567891011121"),
+        ],
+        ..Default::default()
+    };
+
+    assert_eq!(
+        props.supervise_template().unwrap(),
+        std::fs::read_to_string("testdata/supervise/systemd/multiple-networks.unit").unwrap(),
+    );
+    assert_eq!(
+        props.service_path(),
+        PathBuf::from("/lib/systemd/system/zeronsd.service")
+    );
+}
+
+#[test]
+#[cfg(target_os = "linux")]
+fn test_supervise_socket_unit() {
+    use std::path::PathBuf;
+
+    use crate::init::Launcher;
+
+    let mut props = crate::supervise::Properties {
+        binpath: String::from("zeronsd"),
+        launcher: Launcher {
+            network_id: Some(String::from("1234567891011121")),
+            token: Some(PathBuf::from("/proc/cpuinfo")),
+            ..Default::default()
+        },
+        ..Default::default()
+    };
+
+    assert!(props.socket_template().unwrap().is_none());
+
+    props.sockets = vec![
+        "10.147.17.5:53".parse().unwrap(),
+        "[fd80:56c2:e21c::5]:53".parse().unwrap(),
+    ];
+
+    assert_eq!(
+        props.socket_template().unwrap().unwrap(),
+        std::fs::read_to_string("testdata/supervise/systemd/with-sockets.socket").unwrap(),
+    );
+    assert_eq!(
+        props.socket_path(),
+        PathBuf::from("/lib/systemd/system/zeronsd-1234567891011121.socket")
+    );
+    assert!(props.validate().is_ok());
+
+    props.init_style = crate::supervise::InitStyle::OpenRC;
+    assert!(props.validate().is_err());
+}
+
+#[test]
+#[cfg(target_os = "macos")]
+fn test_supervise_launchd() {
+    use std::path::PathBuf;
+
+    use crate::init::Launcher;
+
+    let props = crate::supervise::Properties {
+        binpath: String::from("zeronsd"),
+        launcher: Launcher {
+            network_id: Some(String::from("1234567891011121")),
+            token: Some(PathBuf::from("/proc/cpuinfo")),
+            ..Default::default()
+        },
+        ..Default::default()
+    };
+
+    assert_eq!(
+        props.supervise_template().unwrap(),
+        std::fs::read_to_string("testdata/supervise/launchd/basic.plist").unwrap()
+    );
+    assert_eq!(
+        props.service_path(),
+        PathBuf::from("/Library/LaunchDaemons/com.zerotier.nsd.1234567891011121.plist")
+    );
+}
+
 #[test]
 #[cfg(target_os = "linux")]
 fn test_supervise_systemd_red() {
@@ -236,6 +779,18 @@ fn test_supervise_systemd_red() {
                 ..Default::default()
             },
         ),
+        (
+            "bad token (stdin)",
+            crate::supervise::Properties {
+                binpath: String::from("zeronsd"),
+                launcher: Launcher {
+                    network_id: Some(String::from("1234567891011121")),
+                    token_stdin: true,
+                    ..Default::default()
+                },
+                ..Default::default()
+            },
+        ),
         (
             "bad hosts (no file)",
             crate::supervise::Properties {
@@ -243,20 +798,20 @@ fn test_supervise_systemd_red() {
                 launcher: Launcher {
                     network_id: Some(String::from("1234567891011121")),
                     token: Some(PathBuf::from("/proc/cpuinfo")),
-                    hosts: Some(PathBuf::from("~")),
+                    hosts: vec![PathBuf::from("~")],
                     ..Default::default()
                 },
                 ..Default::default()
             },
         ),
         (
-            "bad hosts (dir)",
+            "bad hosts (device)",
             crate::supervise::Properties {
                 binpath: String::from("zeronsd"),
                 launcher: Launcher {
                     network_id: Some(String::from("1234567891011121")),
                     token: Some(PathBuf::from("/proc/cpuinfo")),
-                    hosts: Some(PathBuf::from(".")),
+                    hosts: vec![PathBuf::from("/dev/null")],
                     ..Default::default()
                 },
                 ..Default::default()
@@ -384,6 +939,34 @@ fn test_parse_hosts() {
     }
 }
 
+#[test]
+fn test_rebase_hosts() {
+    use crate::hosts::{rebase_hosts, HostsFile};
+    use trust_dns_resolver::Name;
+
+    let from = Name::from_str("home.arpa.").unwrap();
+    let to = Name::from_str("beyond.corp.").unwrap();
+
+    let mut hosts = HostsFile::new();
+    hosts.insert(
+        IpAddr::from_str("10.0.0.1").unwrap(),
+        vec![
+            Name::from_str("islay.home.arpa.").unwrap(),
+            Name::from_str("www.islay.home.arpa.").unwrap(),
+            Name::from_str("elsewhere.example.").unwrap(),
+        ],
+    );
+
+    let rebased = rebase_hosts(&hosts, &from, &to);
+    assert_eq!(
+        rebased.get(&IpAddr::from_str("10.0.0.1").unwrap()).unwrap(),
+        &vec![
+            Name::from_str("islay.beyond.corp.").unwrap(),
+            Name::from_str("www.islay.beyond.corp.").unwrap(),
+        ]
+    );
+}
+
 #[test]
 fn test_parse_hosts_duplicate() {
     use crate::hosts::parse_hosts;
@@ -416,3 +999,1470 @@ fn test_parse_hosts_duplicate() {
             .unwrap()
     ));
 }
+
+#[test]
+fn test_parse_hosts_idn() {
+    use crate::hosts::parse_hosts;
+    use trust_dns_resolver::Name;
+
+    let domain = Name::from_str("home.arpa.").unwrap();
+    let table = parse_hosts(Some(PathBuf::from("testdata/hosts-files/idn")), domain).unwrap();
+
+    // names outside ASCII are served as punycode.
+    assert_eq!(
+        table
+            .get(&IpAddr::from_str("10.147.20.5").unwrap())
+            .unwrap(),
+        &vec![
+            Name::from_str("xn--bro-hoa.home.arpa.").unwrap(),
+            Name::from_str("xn--mnchen-3ya.lab.home.arpa.").unwrap(),
+        ]
+    );
+}
+
+#[test]
+fn test_member_wildcard() {
+    use crate::authority::member_wildcard;
+    use serde_json::{json, Value};
+    use zerotier_central_api::types::{Member, Network};
+
+    let network: Network = serde_json::from_value(json!({
+        "id": "1234567891011121",
+        "tagsByName": { "wildcard": { "id": 2000 } },
+    }))
+    .unwrap();
+
+    let wildcard = |listed: &[String], node_id: &str, name: &str, tags: Value| {
+        let member: Member = serde_json::from_value(json!({
+            "nodeId": node_id,
+            "name": name,
+            "config": { "tags": tags },
+        }))
+        .unwrap();
+
+        member_wildcard(listed, &network, &member)
+    };
+
+    let listed = vec!["Proxy".to_string(), "abcdef0124".to_string()];
+    assert!(wildcard(&listed, "abcdef0123", "proxy", json!([])));
+    assert!(wildcard(&listed, "abcdef0124", "web", json!([])));
+    assert!(!wildcard(&listed, "abcdef0125", "db", json!([])));
+
+    // tagged in Central, unless the tag is 0.
+    assert!(wildcard(&[], "abcdef0125", "db", json!([[2000, 1]])));
+    assert!(!wildcard(&[], "abcdef0125", "db", json!([[2000, 0]])));
+}
+
+#[test]
+fn test_member_txt() {
+    use crate::authority::member_txt;
+    use trust_dns_resolver::proto::rr::{rdata::TXT, RData};
+    use zerotier_central_api::types::Member;
+
+    let member: Member = serde_json::from_value(serde_json::json!({
+        "nodeId": "abcdef0123",
+        "description": "build server",
+        "config": { "lastAuthorizedTime": 1599853637989u64 },
+    }))
+    .unwrap();
+
+    assert_eq!(
+        member_txt(&member),
+        RData::TXT(TXT::new(vec![
+            "node=abcdef0123".to_string(),
+            "description=build server".to_string(),
+            "authorized=1599853637".to_string(),
+        ]))
+    );
+
+    let member: Member = serde_json::from_value(serde_json::json!({
+        "nodeId": "abcdef0123",
+        "description": "x".repeat(300),
+        "config": { "lastAuthorizedTime": 0 },
+    }))
+    .unwrap();
+
+    match member_txt(&member) {
+        RData::TXT(txt) => {
+            assert_eq!(txt.txt_data().len(), 2);
+            assert_eq!(txt.txt_data()[1].len(), 255);
+        }
+        _ => unreachable!(),
+    }
+}
+
+#[test]
+fn test_launcher_for_network() {
+    use crate::init::{ConfigFormat, Launcher};
+
+    let launcher = Launcher::parse_format(
+        r#"
+token: /proc/cpuinfo
+domain: zerotier
+local_url: http://127.0.0.1:9993
+wildcard: false
+network_domains:
+  "1234567891011121": lab.internal
+"#,
+        ConfigFormat::YAML,
+    )
+    .unwrap();
+
+    let first = launcher.for_network("1234567891011121".to_string());
+    assert_eq!(first.network_id, Some("1234567891011121".to_string()));
+    assert_eq!(first.domain, Some("lab.internal".to_string()));
+
+    let second = launcher.for_network("2122232425262728".to_string());
+    assert_eq!(second.network_id, Some("2122232425262728".to_string()));
+    assert_eq!(second.domain, Some("zerotier".to_string()));
+}
+
+#[test]
+fn test_launcher_records() {
+    use crate::init::{ConfigFormat, Launcher};
+
+    let launcher = Launcher::parse_format(
+        r#"
+domain = "home.arpa"
+
+[records]
+wiki = "CNAME islay"
+"@" = ["MX 10 mail", "TXT \"v=spf1 -all\""]
+"_ldap._tcp" = "SRV 0 5 389 dc1"
+"#,
+        ConfigFormat::TOML,
+    )
+    .unwrap();
+
+    assert_eq!(launcher.records.len(), 3);
+    assert_eq!(launcher.records["wiki"], vec!["CNAME islay".to_string()]);
+    assert_eq!(launcher.records["@"].len(), 2);
+    assert_eq!(
+        launcher.records["_ldap._tcp"],
+        vec!["SRV 0 5 389 dc1".to_string()]
+    );
+}
+
+#[test]
+fn test_launcher_tsig_keys() {
+    use crate::init::{ConfigFormat, Launcher};
+    use crate::tsig::keys;
+
+    let launcher = Launcher::parse_format(
+        r#"
+domain = "home.arpa"
+
+[[tsig_keys]]
+name = "transfer"
+secret = "c2VjcmV0c2VjcmV0c2VjcmV0c2VjcmV0"
+
+[[tsig_keys]]
+name = "ns2.home.arpa"
+algorithm = "hmac-sha512"
+secret = "c2VjcmV0c2VjcmV0c2VjcmV0c2VjcmV0"
+"#,
+        ConfigFormat::TOML,
+    )
+    .unwrap();
+
+    assert_eq!(launcher.tsig_keys.len(), 2);
+    assert_eq!(launcher.tsig_keys[0].algorithm, "hmac-sha256");
+
+    let keys = keys(&launcher.tsig_keys).unwrap();
+    assert_eq!(keys[1].name.to_string(), "ns2.home.arpa.");
+}
+
+#[test]
+fn test_start_args_config_override() {
+    use crate::cli::{Cli, Command};
+    use crate::init::Launcher;
+    use clap::Parser;
+
+    // named after the test and the process, so that concurrent runs do not share it.
+    let config = std::env::temp_dir().join(format!(
+        "zeronsd-test-config-override-{}.toml",
+        std::process::id()
+    ));
+    std::fs::write(
+        &config,
+        "token = \"/proc/cpuinfo\"\ndomain = \"zerotier\"\npoll_interval = 10\nlisten_port = 5353\n",
+    )
+    .unwrap();
+
+    let cli = Cli::try_parse_from([
+        "zeronsd",
+        "start",
+        "--config-type",
+        "toml",
+        "-c",
+        config.to_str().unwrap(),
+        "-d",
+        "override",
+        "--listen-addr",
+        "127.0.0.1",
+        "--listen-addr",
+        "::1",
+        "1234567891011121",
+    ])
+    .unwrap();
+
+    let launcher: Launcher = match cli.command {
+        Command::Start(args) => args.into(),
+        _ => panic!("expected start command"),
+    };
+    std::fs::remove_file(&config).unwrap();
+
+    // once gone, loading it fails, naming the file.
+    let err = crate::utils::load_config(&config, crate::init::ConfigFormat::TOML).unwrap_err();
+    assert!(err.to_string().contains(&config.display().to_string()));
+
+    assert_eq!(launcher.token, Some(PathBuf::from("/proc/cpuinfo")));
+    assert_eq!(launcher.domain, Some("override".to_string()));
+    assert_eq!(launcher.poll_interval, Some(10));
+    assert_eq!(launcher.listen_port, Some(5353));
+    assert_eq!(
+        launcher.listen_addrs,
+        vec![
+            "127.0.0.1".parse::<std::net::IpAddr>().unwrap(),
+            "::1".parse().unwrap()
+        ]
+    );
+    assert!(!launcher.wildcard);
+}
+
+#[test]
+fn test_poll_interval_zero() {
+    use crate::cli::Cli;
+    use crate::init::{ConfigFormat, Launcher};
+    use clap::Parser;
+
+    // a poll interval of 0 would make the timers of the members and listen addresses spin.
+    assert!(Cli::try_parse_from([
+        "zeronsd",
+        "start",
+        "--poll-interval",
+        "0",
+        "1234567891011121",
+    ])
+    .is_err());
+    assert!(Cli::try_parse_from([
+        "zeronsd",
+        "start",
+        "--poll-interval",
+        "1",
+        "1234567891011121",
+    ])
+    .is_ok());
+
+    assert!(Launcher::parse_format("poll_interval: 0", ConfigFormat::YAML).is_err());
+    assert!(Launcher::parse_format("poll_interval = 0", ConfigFormat::TOML).is_err());
+    assert!(Launcher::parse_format(r#"{"poll_interval": 0}"#, ConfigFormat::JSON).is_err());
+    assert_eq!(
+        Launcher::parse_format("poll_interval: 5", ConfigFormat::YAML)
+            .unwrap()
+            .poll_interval,
+        Some(5)
+    );
+}
+
+#[test]
+fn test_start_args_network_domains() {
+    use crate::cli::{Cli, Command};
+    use clap::Parser;
+
+    let cli = Cli::try_parse_from([
+        "zeronsd",
+        "start",
+        "-d",
+        "home.arpa",
+        "-d",
+        "zt",
+        "1234567891011121",
+        "--network",
+        "2122232425262728=lab.internal",
+    ])
+    .unwrap();
+
+    let launchers = match cli.command {
+        Command::Start(args) => args.launchers(),
+        _ => panic!("expected start command"),
+    };
+
+    assert_eq!(launchers.len(), 2);
+    assert_eq!(
+        launchers[0].network_id,
+        Some("1234567891011121".to_string())
+    );
+    assert_eq!(launchers[0].domain, Some("home.arpa".to_string()));
+    assert_eq!(launchers[0].domain_aliases, vec!["zt".to_string()]);
+    assert_eq!(
+        launchers[1].network_id,
+        Some("2122232425262728".to_string())
+    );
+    assert_eq!(launchers[1].domain, Some("lab.internal".to_string()));
+
+    assert!(Cli::try_parse_from(["zeronsd", "start", "--network", "2122232425262728"]).is_err());
+}
+
+#[test]
+fn test_start_args_central_dns_servers() {
+    use crate::cli::{Cli, Command};
+    use clap::Parser;
+
+    let cli = Cli::try_parse_from([
+        "zeronsd",
+        "start",
+        "--central-dns-servers",
+        "10.147.17.53,fd12:3456::53",
+        "1234567891011121",
+    ])
+    .unwrap();
+
+    let launchers = match cli.command {
+        Command::Start(args) => args.launchers(),
+        _ => panic!("expected start command"),
+    };
+
+    assert_eq!(
+        launchers[0].central_dns_servers,
+        vec![
+            IpAddr::from_str("10.147.17.53").unwrap(),
+            IpAddr::from_str("fd12:3456::53").unwrap()
+        ]
+    );
+    assert!(!launchers[0].no_update_central);
+
+    assert!(Cli::try_parse_from([
+        "zeronsd",
+        "start",
+        "--no-update-central",
+        "--central-dns-servers",
+        "10.147.17.53",
+        "1234567891011121",
+    ])
+    .is_err());
+}
+
+#[test]
+fn test_start_args_dnssec_validation() {
+    use crate::cli::{Cli, Command};
+    use clap::Parser;
+
+    let cli = Cli::try_parse_from([
+        "zeronsd",
+        "start",
+        "--dnssec-validation",
+        "1234567891011121",
+    ])
+    .unwrap();
+
+    let launcher = match cli.command {
+        Command::Start(args) => args.launcher().unwrap(),
+        _ => panic!("expected start command"),
+    };
+
+    assert!(launcher.dnssec_validation);
+}
+
+#[test]
+fn test_format_zones() {
+    use crate::{admin::RecordEntry, cli::format_zones};
+
+    let entry = |zone: &str, name: &str, record_type: &str, data: &str| RecordEntry {
+        zone: zone.to_string(),
+        name: name.to_string(),
+        record_type: record_type.to_string(),
+        ttl: 60,
+        data: data.to_string(),
+    };
+
+    let records = vec![
+        entry(
+            "home.arpa.",
+            "home.arpa.",
+            "SOA",
+            "ns.home.arpa. hostmaster.home.arpa. 1 30 30 30 60",
+        ),
+        entry("home.arpa.", "islay.home.arpa.", "A", "10.147.20.216"),
+        entry(
+            "20.147.10.in-addr.arpa.",
+            "216.20.147.10.in-addr.arpa.",
+            "PTR",
+            "islay.home.arpa.",
+        ),
+    ];
+
+    assert_eq!(
+        format_zones(&records),
+        "$ORIGIN home.arpa.\n\
+         home.arpa.\t60\tIN\tSOA\tns.home.arpa. hostmaster.home.arpa. 1 30 30 30 60\n\
+         islay.home.arpa.\t60\tIN\tA\t10.147.20.216\n\
+         \n\
+         $ORIGIN 20.147.10.in-addr.arpa.\n\
+         216.20.147.10.in-addr.arpa.\t60\tIN\tPTR\tislay.home.arpa.\n"
+    );
+}
+
+#[test]
+fn test_status_args() {
+    use crate::cli::{Cli, Command};
+    use clap::Parser;
+
+    let cli = Cli::try_parse_from([
+        "zeronsd",
+        "status",
+        "--admin-socket",
+        "/run/zeronsd/admin.sock",
+    ])
+    .unwrap();
+
+    match cli.command {
+        Command::Status(args) => {
+            assert_eq!(
+                args.admin.admin_socket,
+                Some(PathBuf::from("/run/zeronsd/admin.sock"))
+            );
+            assert_eq!(args.admin.admin_addr, None);
+        }
+        _ => panic!("expected status command"),
+    }
+
+    assert!(Cli::try_parse_from([
+        "zeronsd",
+        "status",
+        "--admin-socket",
+        "/run/zeronsd/admin.sock",
+        "--admin-addr",
+        "127.0.0.1:8054",
+    ])
+    .is_err());
+}
+
+#[test]
+fn test_parse_hosts_files() {
+    use crate::hosts::{expand_hosts_paths, parse_hosts_files};
+    use trust_dns_resolver::Name;
+
+    let domain = Name::from_str("zombocom").unwrap();
+    let dir = PathBuf::from(crate::utils::TEST_HOSTS_DIR);
+
+    let files = expand_hosts_paths(&[dir.clone()]).unwrap();
+    let mut expected = std::fs::read_dir(&dir)
+        .unwrap()
+        .map(|p| p.unwrap().path())
+        .filter(|p| p.is_file())
+        .collect::<Vec<PathBuf>>();
+    expected.sort();
+    assert_eq!(files, expected);
+
+    let table =
+        parse_hosts_files(&[dir.join("duplicates"), dir.join("basic")], domain.clone()).unwrap();
+
+    for (ip, name) in [("10.147.20.216", "hostname1"), ("127.0.1.1", "islay")] {
+        assert!(table.get(&IpAddr::from_str(ip).unwrap()).unwrap().contains(
+            &Name::from_str(name)
+                .unwrap()
+                .append_domain(&domain)
+                .unwrap()
+        ));
+    }
+
+    assert!(parse_hosts_files(&[], domain).unwrap().is_empty());
+}
+
+#[test]
+fn test_soa_config() {
+    use crate::authority::SoaConfig;
+    use trust_dns_server::client::rr::Name;
+
+    let domain = Name::from_str("home.arpa.").unwrap();
+    let member = Name::from_str("zt-abcdef0123.home.arpa.").unwrap();
+
+    let soa = SoaConfig::default().soa(&domain, &member, 7, 60).unwrap();
+    assert_eq!(soa.mname(), &member);
+    assert_eq!(
+        soa.rname(),
+        &Name::from_str("administrator.home.arpa.").unwrap()
+    );
+    assert_eq!(soa.serial(), 7);
+    assert_eq!(soa.minimum(), 60);
+
+    let soa = SoaConfig {
+        mname: Some("ns1".to_string()),
+        rname: Some("hostmaster@example.com".to_string()),
+        refresh: Some(3600),
+        retry: Some(600),
+        expire: Some(86400),
+        minimum: Some(30),
+    }
+    .soa(&domain, &member, 7, 60)
+    .unwrap();
+    assert_eq!(soa.mname(), &Name::from_str("ns1.home.arpa.").unwrap());
+    assert_eq!(
+        soa.rname(),
+        &Name::from_str("hostmaster.example.com.").unwrap()
+    );
+    assert_eq!(
+        (soa.refresh(), soa.retry(), soa.expire(), soa.minimum()),
+        (3600, 600, 86400, 30)
+    );
+
+    assert!(SoaConfig {
+        rname: Some("first.last@example.com".to_string()),
+        ..Default::default()
+    }
+    .soa(&domain, &member, 7, 60)
+    .is_err());
+}
+
+#[tokio::test]
+async fn test_dynamic_zone_restore() {
+    use crate::authority::RecordAuthority;
+    use crate::store::Saved;
+    use crate::update::DynamicZone;
+    use trust_dns_resolver::proto::rr::RecordType;
+    use trust_dns_server::authority::{AuthorityObject, LookupOptions};
+    use trust_dns_server::client::rr::{LowerName, Name};
+
+    let dir = std::env::temp_dir().join("zeronsd-test-dynamic");
+    let path = DynamicZone::path(&dir, "1234567891011121");
+    std::fs::create_dir_all(&dir).unwrap();
+    std::fs::write(
+        &path,
+        r#"{"laptop.dyn.home.arpa.": ["A 10.147.20.9", "TXT \"registered\""]}"#,
+    )
+    .unwrap();
+
+    let origin: LowerName = Name::from_str("dyn.home.arpa.").unwrap().into();
+    let authority = RecordAuthority::new(origin.clone(), origin, 60)
+        .await
+        .unwrap();
+    let zone = DynamicZone::new(authority, Some(Saved::File(path.clone())))
+        .await
+        .unwrap();
+
+    let laptop: LowerName = Name::from_str("laptop.dyn.home.arpa.").unwrap().into();
+    for rtype in [RecordType::A, RecordType::TXT] {
+        let lookup = zone
+            .authority()
+            .lookup(&laptop, rtype, LookupOptions::default())
+            .await
+            .unwrap();
+        assert_eq!(lookup.iter().count(), 1);
+    }
+
+    std::fs::remove_file(path).unwrap();
+}
+
+#[tokio::test]
+async fn test_record_authority_transfers() {
+    use crate::authority::RecordAuthority;
+    use ipnetwork::IpNetwork;
+    use trust_dns_server::authority::AuthorityObject;
+    use trust_dns_server::client::rr::LowerName;
+
+    let domain: LowerName = domain_or_default(None).unwrap().into();
+
+    let authority = RecordAuthority::new(domain.clone(), domain.clone(), 60)
+        .await
+        .unwrap();
+    assert!(!authority.is_axfr_allowed());
+    assert!(!authority.transfer_allowed(IpAddr::from_str("10.0.0.5").unwrap()));
+
+    let authority = authority.with_transfers(vec![
+        IpNetwork::from_str("10.0.0.0/24").unwrap(),
+        IpNetwork::from_str("fd00::53").unwrap(),
+    ]);
+    assert!(authority.is_axfr_allowed());
+    assert!(authority.transfer_allowed(IpAddr::from_str("10.0.0.5").unwrap()));
+    assert!(authority.transfer_allowed(IpAddr::from_str("fd00::53").unwrap()));
+    assert!(!authority.transfer_allowed(IpAddr::from_str("10.0.1.5").unwrap()));
+
+    // staged copies keep the allowed clients.
+    assert!(authority
+        .stage()
+        .await
+        .unwrap()
+        .transfer_allowed(IpAddr::from_str("10.0.0.5").unwrap()));
+}
+
+#[tokio::test]
+async fn test_record_authority_stage_commit() {
+    use crate::authority::RecordAuthority;
+    use trust_dns_resolver::{proto::rr::RecordType, Name};
+    use trust_dns_server::authority::{AuthorityObject, LookupOptions};
+    use trust_dns_server::client::rr::LowerName;
+
+    let domain: LowerName = domain_or_default(None).unwrap().into();
+    let name = Name::from_str("islay")
+        .unwrap()
+        .append_domain(&domain.clone().into())
+        .unwrap();
+    let ip = IpAddr::from_str("10.0.0.1").unwrap();
+
+    let authority = RecordAuthority::new(domain.clone(), domain.clone(), 60)
+        .await
+        .unwrap();
+    let catalog_copy = authority.box_clone();
+
+    let staged = authority.stage().await.unwrap();
+    staged.match_or_insert(name.clone(), &[ip]).await;
+
+    // nothing is served until the staged copy is committed.
+    assert!(catalog_copy
+        .lookup(
+            &name.clone().into(),
+            RecordType::A,
+            LookupOptions::default()
+        )
+        .await
+        .is_err());
+
+    authority.commit(&staged).await;
+
+    assert!(catalog_copy
+        .lookup(
+            &name.clone().into(),
+            RecordType::A,
+            LookupOptions::default()
+        )
+        .await
+        .is_ok());
+
+    // the serial only changes along with the records.
+    let serial = authority.serial().await;
+
+    let staged = authority.stage().await.unwrap();
+    staged.match_or_insert(name.clone(), &[ip]).await;
+    authority.commit(&staged).await;
+    assert_eq!(authority.serial().await, serial);
+
+    // a refresh that drops the record removes it.
+    authority.commit(&authority.stage().await.unwrap()).await;
+    assert_eq!(authority.serial().await, serial.wrapping_add(1));
+
+    assert!(catalog_copy
+        .lookup(&name.into(), RecordType::A, LookupOptions::default())
+        .await
+        .is_err());
+}
+
+#[tokio::test]
+async fn test_record_authority_signing() {
+    use crate::{authority::RecordAuthority, dnssec::DnssecConfig};
+    use trust_dns_resolver::{proto::rr::RecordType, Name};
+    use trust_dns_server::authority::{AuthorityObject, LookupOptions};
+    use trust_dns_server::client::rr::{dnssec::SupportedAlgorithms, LowerName};
+
+    let dir = std::env::temp_dir().join("zeronsd-test-signing");
+    let _ = std::fs::remove_dir_all(&dir);
+
+    let domain: LowerName = domain_or_default(None).unwrap().into();
+    let name = Name::from_str("islay")
+        .unwrap()
+        .append_domain(&domain.clone().into())
+        .unwrap();
+    let key = DnssecConfig::new(dir.join("zone.pk8")).zone_key().unwrap();
+
+    let authority = RecordAuthority::new(domain.clone(), domain.clone(), 60)
+        .await
+        .unwrap()
+        .with_signing(key);
+    assert!(authority.ds().is_some());
+
+    let staged = authority.stage().await.unwrap();
+    staged
+        .match_or_insert(name.clone(), &[IpAddr::from_str("10.0.0.1").unwrap()])
+        .await;
+    authority.commit(&staged).await;
+
+    let dnssec = LookupOptions::for_dnssec(true, SupportedAlgorithms::all());
+    let lookup = authority
+        .lookup(&name.clone().into(), RecordType::A, dnssec)
+        .await
+        .unwrap();
+    assert!(lookup.iter().any(|r| r.record_type() == RecordType::RRSIG));
+
+    assert!(authority
+        .lookup(&domain, RecordType::DNSKEY, LookupOptions::default())
+        .await
+        .is_ok());
+
+    // signatures are fresh, so an unchanged zone is not signed again.
+    let serial = authority.serial().await;
+    authority.resign().await.unwrap();
+    assert_eq!(authority.serial().await, serial);
+
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[tokio::test]
+async fn test_record_authority_minimal_any() {
+    use crate::authority::RecordAuthority;
+    use std::net::SocketAddr;
+    use trust_dns_resolver::{
+        proto::{
+            op::{Header, Query},
+            rr::{RData, RecordType},
+        },
+        Name,
+    };
+    use trust_dns_server::{
+        authority::{AuthorityObject, LookupOptions},
+        client::{op::LowerQuery, rr::LowerName},
+        server::{Protocol, RequestInfo},
+    };
+
+    let domain: LowerName = domain_or_default(None).unwrap().into();
+    let name = Name::from_str("islay")
+        .unwrap()
+        .append_domain(&domain.clone().into())
+        .unwrap();
+
+    let authority = RecordAuthority::new(domain.clone(), domain.clone(), 60)
+        .await
+        .unwrap()
+        .with_minimal_any(true);
+
+    let staged = authority.stage().await.unwrap();
+    staged
+        .match_or_insert(
+            name.clone(),
+            &[
+                IpAddr::from_str("10.0.0.1").unwrap(),
+                IpAddr::from_str("fd00::1").unwrap(),
+            ],
+        )
+        .await;
+    authority.commit(&staged).await;
+
+    let header = Header::new();
+    let any = |name: &Name| LowerQuery::from(Query::query(name.clone(), RecordType::ANY));
+    let src = SocketAddr::from_str("10.0.0.2:53").unwrap();
+
+    let query = any(&name);
+    let lookup = authority
+        .search(
+            RequestInfo::new(src, Protocol::Udp, &header, &query),
+            LookupOptions::default(),
+        )
+        .await
+        .unwrap();
+    let records: Vec<_> = lookup.iter().collect();
+    assert_eq!(records.len(), 1);
+    assert_eq!(records[0].record_type(), RecordType::HINFO);
+    assert!(matches!(records[0].data(), Some(RData::HINFO(hinfo)) if hinfo.cpu() == b"RFC8482"));
+
+    // names without records are not answered for.
+    let query = any(&Name::from_str("missing")
+        .unwrap()
+        .append_domain(&domain.into())
+        .unwrap());
+    assert!(authority
+        .search(
+            RequestInfo::new(src, Protocol::Udp, &header, &query),
+            LookupOptions::default(),
+        )
+        .await
+        .map_or(true, |lookup| lookup
+            .iter()
+            .all(|r| r.record_type() != RecordType::HINFO)));
+}
+
+#[tokio::test]
+async fn test_record_authority_views() {
+    use crate::{
+        authority::RecordAuthority,
+        init::{ConfigFormat, Launcher},
+        views::Views,
+    };
+    use std::net::SocketAddr;
+    use trust_dns_resolver::{
+        proto::{
+            op::{Header, Query},
+            rr::RecordType,
+        },
+        Name,
+    };
+    use trust_dns_server::{
+        authority::{AuthorityObject, LookupOptions},
+        client::{op::LowerQuery, rr::LowerName},
+        server::{Protocol, RequestInfo},
+    };
+
+    let launcher = Launcher::parse_format(
+        r#"
+[[views]]
+clients = ["192.168.1.0/24"]
+addresses = ["192.168.1.0/24"]
+"#,
+        ConfigFormat::TOML,
+    )
+    .unwrap();
+
+    let domain: LowerName = domain_or_default(None).unwrap().into();
+    let name = Name::from_str("nas")
+        .unwrap()
+        .append_domain(&domain.clone().into())
+        .unwrap();
+
+    let authority = RecordAuthority::new(domain.clone(), domain.clone(), 60)
+        .await
+        .unwrap()
+        .with_views(Views::new(launcher.views));
+
+    let staged = authority.stage().await.unwrap();
+    staged
+        .match_or_insert(
+            name.clone(),
+            &[
+                IpAddr::from_str("10.147.17.5").unwrap(),
+                IpAddr::from_str("192.168.1.5").unwrap(),
+            ],
+        )
+        .await;
+    authority.commit(&staged).await;
+
+    let header = Header::new();
+    let query = LowerQuery::from(Query::query(name, RecordType::A));
+
+    // clients outside the view are given every address.
+    for (src, expected) in [
+        ("192.168.1.20:53", vec!["192.168.1.5"]),
+        ("10.147.17.20:53", vec!["10.147.17.5", "192.168.1.5"]),
+    ] {
+        let lookup = authority
+            .search(
+                RequestInfo::new(
+                    SocketAddr::from_str(src).unwrap(),
+                    Protocol::Udp,
+                    &header,
+                    &query,
+                ),
+                LookupOptions::default(),
+            )
+            .await
+            .unwrap();
+
+        let mut ips: Vec<_> = lookup
+            .iter()
+            .filter_map(|record| record.data()?.to_ip_addr())
+            .map(|ip| ip.to_string())
+            .collect();
+        ips.sort();
+        assert_eq!(ips, expected);
+    }
+}
+
+#[test]
+fn test_launcher_dnssec() {
+    use crate::dnssec::DEFAULT_VALIDITY;
+    use crate::init::{ConfigFormat, Launcher};
+
+    let launcher = Launcher::parse_format(
+        r#"
+domain = "home.arpa"
+
+[dnssec]
+key = "/var/lib/zeronsd/dnssec.pk8"
+"#,
+        ConfigFormat::TOML,
+    )
+    .unwrap();
+
+    let dnssec = launcher.dnssec.unwrap();
+    assert_eq!(dnssec.algorithm, "ECDSAP256SHA256");
+    assert_eq!(dnssec.validity, DEFAULT_VALIDITY);
+}
+
+// authority is a ZTAuthority serving home.arpa. with nothing configured, for tests.
+async fn authority() -> crate::authority::ZTAuthority {
+    use std::{collections::HashMap, sync::Arc, time::Duration};
+
+    use crate::{
+        authority::{RecordAuthority, ZTAuthority},
+        controller::Central,
+        utils::central_client,
+    };
+
+    let domain = domain_or_default(None).unwrap();
+    ZTAuthority {
+        controller: Arc::new(Central::new(
+            central_client("token".to_string()).unwrap(),
+            None,
+        )),
+        network_id: "1234567891011121".to_string(),
+        hosts: None,
+        hosts_files: Vec::new(),
+        reverse_authority_map: HashMap::new(),
+        forward_authority: RecordAuthority::new(domain.clone().into(), domain.into(), 60)
+            .await
+            .unwrap(),
+        alias_authorities: Vec::new(),
+        forward_zones: HashMap::new(),
+        upstreams: None,
+        wildcard: false,
+        wildcard_members: Vec::new(),
+        name_policy: Default::default(),
+        name_collisions: Default::default(),
+        member_filter: Default::default(),
+        node_id_names: false,
+        all_ptr_names: false,
+        member_txt: false,
+        max_records: None,
+        record_eviction: Default::default(),
+        update_interval: Duration::new(30, 0),
+        reload: None,
+        snapshot: None,
+        store: None,
+        records: Default::default(),
+        dynamic_zone: None,
+        dnssec_validation: false,
+        qname_minimization: false,
+        ip_names: false,
+        edns: Default::default(),
+        query_timeout: None,
+        query_acl: None,
+        query_log: None,
+        dnstap: None,
+        health: Default::default(),
+        control: Default::default(),
+        replica: Default::default(),
+        cache: None,
+        blocklist: Default::default(),
+        rpz: Default::default(),
+        views: Default::default(),
+        view_zones: Vec::new(),
+    }
+}
+
+#[tokio::test]
+async fn test_node_id_names() {
+    use trust_dns_resolver::Name;
+    use zerotier_central_api::types::{Member, Network};
+
+    let network: Network =
+        serde_json::from_value(serde_json::json!({ "id": "1234567891011121", "config": {} }))
+            .unwrap();
+    let member: Member = serde_json::from_value(serde_json::json!({
+        "nodeId": "8badf00d42",
+        "name": "islay",
+        "config": { "ipAssignments": ["10.147.20.5"] },
+    }))
+    .unwrap();
+
+    let names = |zones: Vec<(Name, Vec<trust_dns_resolver::proto::rr::Record>)>| {
+        zones
+            .into_iter()
+            .flat_map(|(_, records)| records)
+            .map(|record| record.name().to_string())
+            .collect::<Vec<_>>()
+    };
+
+    let mut zt = authority().await;
+    zt.configure_members(network.clone(), vec![member.clone()])
+        .await
+        .unwrap();
+    let served = names(zt.records().await);
+    assert!(served.contains(&"zt-8badf00d42.home.arpa.".to_string()));
+    assert!(served.contains(&"islay.home.arpa.".to_string()));
+    assert!(!served.contains(&"8badf00d42.home.arpa.".to_string()));
+
+    zt.node_id_names = true;
+    zt.configure_members(network, vec![member]).await.unwrap();
+    let served = names(zt.records().await);
+    assert!(served.contains(&"8badf00d42.home.arpa.".to_string()));
+    assert!(served.contains(&"islay.home.arpa.".to_string()));
+}
+
+#[tokio::test]
+async fn test_all_ptr_names() {
+    use crate::authority::{RecordAuthority, ZTAuthority};
+    use ipnetwork::IpNetwork;
+    use trust_dns_resolver::{proto::rr::RData, Name};
+    use zerotier_central_api::types::{Member, Network};
+
+    let network: Network =
+        serde_json::from_value(serde_json::json!({ "id": "1234567891011121", "config": {} }))
+            .unwrap();
+    let member: Member = serde_json::from_value(serde_json::json!({
+        "nodeId": "8badf00d42",
+        "name": "islay",
+        "description": "aliases=web,db",
+        "config": { "ipAssignments": ["10.147.20.5"] },
+    }))
+    .unwrap();
+
+    let domain = domain_or_default(None).unwrap();
+    let mut zt = authority().await;
+    zt.reverse_authority_map.insert(
+        IpNetwork::from_str("10.147.20.0/24").unwrap(),
+        RecordAuthority::new(
+            Name::from_str("20.147.10.in-addr.arpa.").unwrap().into(),
+            domain.into(),
+            60,
+        )
+        .await
+        .unwrap(),
+    );
+
+    let ptrs = |zt: &ZTAuthority| {
+        let zone = zt.reverse_authority_map.values().next().unwrap().clone();
+        async move {
+            zone.records()
+                .await
+                .into_iter()
+                .filter_map(|record| match record.data() {
+                    Some(RData::PTR(name)) => Some(name.to_string()),
+                    _ => None,
+                })
+                .collect::<Vec<_>>()
+        }
+    };
+
+    zt.configure_members(network.clone(), vec![member.clone()])
+        .await
+        .unwrap();
+    assert_eq!(ptrs(&zt).await, ["islay.home.arpa."]);
+
+    // the canonical name stays first, the others follow sorted.
+    zt.all_ptr_names = true;
+    zt.configure_members(network.clone(), vec![member.clone()])
+        .await
+        .unwrap();
+    assert_eq!(
+        ptrs(&zt).await,
+        [
+            "islay.home.arpa.",
+            "db.home.arpa.",
+            "web.home.arpa.",
+            "zt-8badf00d42.home.arpa."
+        ]
+    );
+
+    zt.all_ptr_names = false;
+    zt.configure_members(network, vec![member]).await.unwrap();
+    assert_eq!(ptrs(&zt).await, ["islay.home.arpa."]);
+}
+
+#[tokio::test]
+async fn test_classless_refresh() {
+    use crate::{authority::RecordAuthority, classless::Classless};
+    use ipnetwork::IpNetwork;
+    use trust_dns_resolver::proto::rr::RData;
+    use zerotier_central_api::types::{Member, Network};
+
+    let network: Network =
+        serde_json::from_value(serde_json::json!({ "id": "1234567891011121", "config": {} }))
+            .unwrap();
+    let member: Member = serde_json::from_value(serde_json::json!({
+        "nodeId": "8badf00d42",
+        "name": "islay",
+        "config": { "ipAssignments": ["10.147.20.5"] },
+    }))
+    .unwrap();
+
+    let subnet = IpNetwork::from_str("10.147.20.0/28").unwrap();
+    let classless = Classless::for_network(subnet, true, &[]).unwrap().unwrap();
+
+    let domain = domain_or_default(None).unwrap();
+    let mut zt = authority().await;
+    zt.reverse_authority_map.insert(
+        subnet,
+        RecordAuthority::new(classless.zone().clone().into(), domain.into(), 60)
+            .await
+            .unwrap()
+            .with_classless(Some(classless.clone())),
+    );
+
+    // every refresh stages a new copy of the zone and commits it; the delegation has to survive.
+    for _ in 0..2 {
+        zt.configure_members(network.clone(), vec![member.clone()])
+            .await
+            .unwrap();
+
+        let zone = zt.reverse_authority_map.get(&subnet).unwrap();
+        assert_eq!(zone.classless(), Some(&classless));
+
+        let ptrs = zone
+            .records()
+            .await
+            .into_iter()
+            .filter_map(|record| match record.data() {
+                Some(RData::PTR(name)) => Some((record.name().clone(), name.to_string())),
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            ptrs,
+            [(
+                classless.ptr_name("10.147.20.5".parse().unwrap()).unwrap(),
+                "islay.home.arpa.".to_string()
+            )]
+        );
+    }
+}
+
+#[tokio::test]
+async fn test_max_records() {
+    use crate::limits::RecordEviction;
+    use zerotier_central_api::types::{Member, Network};
+
+    let network: Network =
+        serde_json::from_value(serde_json::json!({ "id": "1234567891011121", "config": {} }))
+            .unwrap();
+    let member = |node_id: &str, name: &str, ip: &str, last_online: i64| -> Member {
+        serde_json::from_value(serde_json::json!({
+            "nodeId": node_id,
+            "name": name,
+            "lastOnline": last_online,
+            "config": { "ipAssignments": [ip] },
+        }))
+        .unwrap()
+    };
+    let members = vec![
+        member("8badf00d41", "laptop", "10.147.20.4", 1700000000000),
+        member("8badf00d42", "islay", "10.147.20.5", 1700000300000),
+        member("8badf00d43", "vault", "10.147.20.6", 1700000200000),
+    ];
+
+    let names = |zone: Vec<trust_dns_resolver::proto::rr::Record>| {
+        zone.into_iter()
+            .map(|record| record.name().to_string())
+            .collect::<Vec<_>>()
+    };
+
+    // every member gets its name, zt-<member id> and a PTR record; the one offline the longest
+    // does not fit.
+    let mut zt = authority().await;
+    zt.max_records = Some(8);
+    zt.configure_members(network.clone(), members.clone())
+        .await
+        .unwrap();
+
+    let served = names(zt.forward_authority.records().await);
+    assert!(served.contains(&"islay.home.arpa.".to_string()));
+    assert!(served.contains(&"vault.home.arpa.".to_string()));
+    assert!(!served.contains(&"laptop.home.arpa.".to_string()));
+    assert!(!served.contains(&"zt-8badf00d41.home.arpa.".to_string()));
+
+    let status = zt.health.status();
+    assert_eq!(status.record_limit, Some(8));
+    assert_eq!(status.members_left_out, 1);
+    assert!(!status.wildcards_dropped);
+
+    // polls change when members were last online, which only matters once it changes who fits.
+    let fp = zt.fingerprint(&network, &members);
+    let mut polled = members.clone();
+    polled[1].last_online = Some(1700000600000);
+    assert_eq!(zt.fingerprint(&network, &polled), fp);
+    polled[0].last_online = Some(1700000900000);
+    assert_ne!(zt.fingerprint(&network, &polled), fp);
+
+    // wildcards go first, and then every member fits.
+    zt.wildcard = true;
+    zt.record_eviction = RecordEviction::Wildcards;
+    zt.max_records = Some(9);
+    zt.configure_members(network, members).await.unwrap();
+
+    let served = names(zt.forward_authority.records().await);
+    assert!(served.contains(&"laptop.home.arpa.".to_string()));
+    assert!(!served.iter().any(|name| name.starts_with('*')));
+
+    let status = zt.health.status();
+    assert_eq!(status.members_left_out, 0);
+    assert!(status.wildcards_dropped);
+}
+
+#[tokio::test]
+async fn test_view_zones() {
+    use crate::{
+        authority::RecordAuthority,
+        views::{View, ViewZones, Views},
+    };
+    use std::net::IpAddr;
+    use zerotier_central_api::types::{Member, Network};
+
+    let network: Network = serde_json::from_value(serde_json::json!({
+        "id": "1234567891011121",
+        "config": {},
+        "tagsByName": { "role": { "id": 3000, "enums": { "admin": 1 } } },
+    }))
+    .unwrap();
+    let member = |node_id: &str, name: &str, ip: &str, tags: serde_json::Value| -> Member {
+        serde_json::from_value(serde_json::json!({
+            "nodeId": node_id,
+            "name": name,
+            "config": { "ipAssignments": [ip], "tags": tags },
+        }))
+        .unwrap()
+    };
+    let members = vec![
+        member(
+            "8badf00d41",
+            "laptop",
+            "10.147.20.4",
+            serde_json::json!([[3000, 1]]),
+        ),
+        member("8badf00d42", "islay", "10.147.20.5", serde_json::json!([])),
+        member("8badf00d43", "vault", "10.147.20.6", serde_json::json!([])),
+    ];
+
+    let views = Views::new(vec![View {
+        name: Some("admins".to_string()),
+        members: vec!["tag:role=admin".to_string()],
+        records: [("grafana".to_string(), vec!["CNAME islay".to_string()])].into(),
+        ..Default::default()
+    }]);
+
+    let domain = domain_or_default(None).unwrap();
+    let mut zt = authority().await;
+    zt.member_filter.exclude = vec!["vault".to_string()];
+    zt.views = views.clone();
+    zt.view_zones = vec![ViewZones {
+        view: 0,
+        forward_authority: RecordAuthority::new(domain.clone().into(), domain.into(), 60)
+            .await
+            .unwrap(),
+        alias_authorities: Vec::new(),
+    }];
+
+    zt.configure_members(network, members).await.unwrap();
+
+    let names = |zone: Vec<trust_dns_resolver::proto::rr::Record>| {
+        zone.into_iter()
+            .map(|record| record.name().to_string())
+            .collect::<Vec<_>>()
+    };
+    let served = names(zt.forward_authority.records().await);
+    let admins = names(zt.view_zones[0].forward_authority.records().await);
+
+    // the view has every member, the filter of the network aside, and records of its own.
+    assert!(served.contains(&"islay.home.arpa.".to_string()));
+    assert!(!served.contains(&"vault.home.arpa.".to_string()));
+    assert!(!served.contains(&"grafana.home.arpa.".to_string()));
+    assert!(admins.contains(&"vault.home.arpa.".to_string()));
+    assert!(admins.contains(&"grafana.home.arpa.".to_string()));
+
+    // clients are in the view as the members it is for.
+    assert_eq!(views.view_of(IpAddr::from([10, 147, 20, 4])), Some(0));
+    assert_eq!(views.view_of(IpAddr::from([10, 147, 20, 5])), None);
+}
+
+#[tokio::test]
+async fn test_listeners_sync() {
+    use std::time::Duration;
+
+    use crate::server::{Listeners, Server};
+
+    let zt = authority().await;
+
+    // port 0 lets the system pick free ports.
+    let mut listeners = Listeners::new(
+        Server::new(zt),
+        0,
+        Duration::new(1, 0),
+        (None, None, None),
+        None,
+        tracing::Span::none(),
+    );
+
+    let localhost = IpAddr::from_str("127.0.0.1").unwrap();
+
+    listeners.sync(&[localhost]).await;
+    assert_eq!(listeners.addresses(), vec![localhost]);
+
+    // syncing again leaves the running listener alone.
+    listeners.sync(&[localhost]).await;
+    assert_eq!(listeners.addresses(), vec![localhost]);
+
+    listeners.sync(&[]).await;
+    assert!(listeners.addresses().is_empty());
+}
+
+#[tokio::test]
+#[cfg(unix)]
+async fn test_listeners_workers() {
+    use std::time::Duration;
+
+    use crate::server::{Listeners, Server};
+
+    let zt = authority().await;
+
+    // the workers share the port the system picks for the first of them.
+    let mut listeners = Listeners::new(
+        Server::new(zt).with_workers(4),
+        0,
+        Duration::new(1, 0),
+        (None, None, None),
+        None,
+        tracing::Span::none(),
+    );
+
+    let localhost = IpAddr::from_str("127.0.0.1").unwrap();
+
+    listeners.add(localhost).await.unwrap();
+    assert_eq!(listeners.addresses(), vec![localhost]);
+
+    listeners.sync(&[]).await;
+    assert!(listeners.addresses().is_empty());
+}
+
+#[tokio::test]
+async fn test_udp_truncation() {
+    use std::{net::SocketAddr, time::Duration};
+
+    use trust_dns_resolver::{
+        proto::{
+            op::{Message, Query},
+            rr::RecordType,
+        },
+        Name,
+    };
+    use zerotier_central_api::types::{Member, Network};
+
+    use crate::{
+        query::{query, Transport},
+        server::Server,
+    };
+
+    let network: Network =
+        serde_json::from_value(serde_json::json!({ "id": "1234567891011121", "config": {} }))
+            .unwrap();
+    // members named alike are all served under the name, which makes for a large answer.
+    let members = (1..=120)
+        .map(|i| {
+            serde_json::from_value(serde_json::json!({
+                "nodeId": format!("8badf00{:03x}", i),
+                "name": "web",
+                "config": { "ipAssignments": [format!("10.147.20.{}", i)] },
+            }))
+            .unwrap()
+        })
+        .collect::<Vec<Member>>();
+
+    let zt = authority().await;
+    zt.configure_members(network, members).await.unwrap();
+
+    let port = std::net::UdpSocket::bind("127.0.0.1:0")
+        .unwrap()
+        .local_addr()
+        .unwrap()
+        .port();
+    let server = SocketAddr::from(([127, 0, 0, 1], port));
+    let serving = Server::new(zt)
+        .bind(server, Duration::new(1, 0), None, None, None, None)
+        .await
+        .unwrap();
+    let serving = tokio::spawn(serving.block_until_done());
+
+    let name = Name::from_str("web.home.arpa.").unwrap();
+
+    // without EDNS, the answer is cut down to 512 bytes and marked truncated.
+    let mut request = Message::new();
+    request
+        .set_id(rand::random())
+        .add_query(Query::query(name.clone(), RecordType::A));
+
+    let socket = tokio::net::UdpSocket::bind("127.0.0.1:0").await.unwrap();
+    socket
+        .send_to(&request.to_vec().unwrap(), server)
+        .await
+        .unwrap();
+
+    let mut buf = vec![0u8; 4096];
+    let len = tokio::time::timeout(Duration::new(5, 0), socket.recv(&mut buf))
+        .await
+        .unwrap()
+        .unwrap();
+    assert!(len <= 512);
+
+    let response = Message::from_vec(&buf[..len]).unwrap();
+    assert_eq!(response.id(), request.id());
+    assert!(response.truncated());
+    assert!(response.answers().is_empty());
+
+    // the client asks again over TCP, where the answer is whole.
+    let answer = query(server, &name, RecordType::A, false).await.unwrap();
+    assert_eq!(answer.transport, Transport::Tcp);
+    assert!(!answer.message.truncated());
+    assert_eq!(answer.message.answers().len(), 120);
+
+    serving.abort();
+}
+
+#[tokio::test]
+async fn test_wait_for_assigned_ips_timeout() {
+    use std::time::{Duration, Instant};
+
+    use crate::utils::{local_client, wait_for_assigned_ips};
+
+    // nothing listens on port 1, so the network never comes up.
+    let client = local_client("token".to_string(), "http://127.0.0.1:1".to_string()).unwrap();
+
+    let start = Instant::now();
+    assert!(
+        wait_for_assigned_ips(&client, "1234567891011121", Duration::new(2, 0))
+            .await
+            .is_err()
+    );
+    assert!(start.elapsed() >= Duration::new(2, 0));
+}
+
+#[tokio::test]
+async fn test_replication() {
+    use zerotier_central_api::types::{Member, Network};
+
+    use crate::{admin::Networks, controller::MemberSource, replication};
+
+    let zt = authority().await;
+    let networks = Networks::default();
+    networks.register(zt.clone());
+
+    // there is no way to learn the port picked for 0 from listen, so pick one first.
+    let addr = std::net::TcpListener::bind("127.0.0.1:0")
+        .unwrap()
+        .local_addr()
+        .unwrap();
+    let server = replication::listen(addr, b"s3cret".to_vec(), networks, "abcdef0123".to_string())
+        .await
+        .unwrap();
+
+    let url = format!("http://{}", addr);
+    let primary = replication::Primary::new(&url, b"s3cret".to_vec()).unwrap();
+
+    // nothing has been fetched from Central yet.
+    assert!(primary.members("1234567891011121").await.is_err());
+
+    let network: Network =
+        serde_json::from_value(serde_json::json!({ "id": "1234567891011121", "config": {} }))
+            .unwrap();
+    let member: Member = serde_json::from_value(serde_json::json!({
+        "nodeId": "8badf00d42",
+        "name": "islay",
+        "config": { "ipAssignments": ["10.147.20.5"] },
+    }))
+    .unwrap();
+    zt.replica.set(&network, &[member]);
+
+    let members = primary.members("1234567891011121").await.unwrap();
+    assert_eq!(members.len(), 1);
+    assert_eq!(members[0].name, Some("islay".to_string()));
+    assert_eq!(
+        primary.network("1234567891011121").await.unwrap().id,
+        Some("1234567891011121".to_string())
+    );
+
+    assert!(primary.members("8056c2e21c000001").await.is_err());
+
+    // other members of a cluster learn who serves what.
+    let status = primary.cluster_status().await.unwrap();
+    assert_eq!(status.node_id, "abcdef0123");
+    assert_eq!(status.networks.len(), 1);
+    assert_eq!(status.networks[0].network_id, "1234567891011121");
+
+    let impostor = replication::Primary::new(&url, b"guess".to_vec()).unwrap();
+    assert!(impostor.members("1234567891011121").await.is_err());
+
+    server.abort();
+}
@@ -0,0 +1,297 @@
+/// incremental zone transfers (IXFR) and NOTIFY messages.
+use std::{
+    collections::{BTreeMap, VecDeque},
+    net::SocketAddr,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use tokio::net::UdpSocket;
+use trust_dns_resolver::{
+    proto::{
+        op::{Message, MessageType, OpCode, Query},
+        rr::{RData, Record, RecordSet, RecordType},
+    },
+    Name,
+};
+use trust_dns_server::client::rr::RrKey;
+
+// number of changes remembered for each zone
+pub const HISTORY_LENGTH: usize = 32;
+// how long a secondary is given to acknowledge a NOTIFY
+const NOTIFY_TIMEOUT: Duration = Duration::from_secs(2);
+
+// ZoneChange is what changed between two versions of a zone, each identified by its SOA record.
+#[derive(Debug, Clone)]
+pub struct ZoneChange {
+    pub from: Record,
+    pub to: Record,
+    pub removed: Vec<Record>,
+    pub added: Vec<Record>,
+}
+
+// History holds the most recent changes of a zone, oldest first. It is shared by every copy of
+// the zone's authority.
+#[derive(Debug, Clone, Default)]
+pub struct History(Arc<Mutex<VecDeque<ZoneChange>>>);
+
+impl History {
+    pub fn push(&self, change: ZoneChange) {
+        let mut changes = self.0.lock().unwrap();
+        changes.push_back(change);
+
+        while changes.len() > HISTORY_LENGTH {
+            changes.pop_front();
+        }
+    }
+
+    // since returns the changes made after the given serial, or None if they are not all known.
+    pub fn since(&self, serial: u32) -> Option<Vec<ZoneChange>> {
+        let changes = self.0.lock().unwrap();
+        let start = changes
+            .iter()
+            .position(|change| soa_serial(&change.from) == Some(serial))?;
+
+        Some(changes.iter().skip(start).cloned().collect())
+    }
+}
+
+// soa_serial returns the serial of an SOA record.
+pub fn soa_serial(record: &Record) -> Option<u32> {
+    match record.data() {
+        Some(RData::SOA(soa)) => Some(soa.serial()),
+        _ => None,
+    }
+}
+
+// diff returns the records removed from and added to a zone, leaving out its SOA.
+pub fn diff(
+    old: &BTreeMap<RrKey, Arc<RecordSet>>,
+    new: &BTreeMap<RrKey, Arc<RecordSet>>,
+) -> (Vec<Record>, Vec<Record>) {
+    let missing = |from: &BTreeMap<RrKey, Arc<RecordSet>>, to: &BTreeMap<RrKey, Arc<RecordSet>>| {
+        let mut records = Vec::new();
+
+        for (rrkey, rset) in from {
+            if rrkey.record_type == RecordType::SOA {
+                continue;
+            }
+
+            for record in rset.records_without_rrsigs() {
+                let kept = to.get(rrkey).map_or(false, |other| {
+                    other
+                        .records_without_rrsigs()
+                        .any(|r| r.data() == record.data())
+                });
+
+                if !kept {
+                    records.push(record.clone());
+                }
+            }
+        }
+
+        records
+    };
+
+    (missing(old, new), missing(new, old))
+}
+
+// ixfr_answers lays out the answer to an IXFR: just the SOA if the secondary is up to date, the
+// changes since its serial if they are known, and otherwise the whole zone.
+pub fn ixfr_answers(
+    soa: &Record,
+    client_serial: Option<u32>,
+    changes: Option<Vec<ZoneChange>>,
+    records: &BTreeMap<RrKey, Arc<RecordSet>>,
+) -> Vec<Record> {
+    if client_serial.is_some() && client_serial == soa_serial(soa) {
+        return vec![soa.clone()];
+    }
+
+    let mut answers = vec![soa.clone()];
+
+    match changes {
+        Some(changes) => {
+            for change in changes {
+                answers.push(change.from);
+                answers.extend(change.removed);
+                answers.push(change.to);
+                answers.extend(change.added);
+            }
+        }
+        None => {
+            // signatures of signed zones are transferred along with the records they cover.
+            for (rrkey, rset) in records {
+                if rrkey.record_type != RecordType::SOA {
+                    answers.extend(rset.records_without_rrsigs().cloned());
+                }
+
+                answers.extend(rset.rrsigs().iter().cloned());
+            }
+        }
+    }
+
+    answers.push(soa.clone());
+    answers
+}
+
+// notify tells the secondaries that the zone has changed, so that they transfer it right away
+// instead of waiting for the SOA refresh interval.
+pub async fn notify(zone: Name, secondaries: Arc<Vec<SocketAddr>>) {
+    for secondary in secondaries.iter() {
+        match send_notify(&zone, *secondary).await {
+            Ok(_) => tracing::debug!("Notified {} of changes to {}", secondary, zone),
+            Err(e) => tracing::warn!(
+                "Could not notify {} of changes to {}: {}",
+                secondary,
+                zone,
+                e
+            ),
+        }
+    }
+}
+
+async fn send_notify(zone: &Name, secondary: SocketAddr) -> Result<(), anyhow::Error> {
+    let mut message = Message::new();
+    message
+        .set_id(rand::random())
+        .set_message_type(MessageType::Query)
+        .set_op_code(OpCode::Notify)
+        .set_authoritative(true)
+        .add_query(Query::query(zone.clone(), RecordType::SOA));
+
+    let bind: SocketAddr = if secondary.is_ipv4() {
+        "0.0.0.0:0".parse()?
+    } else {
+        "[::]:0".parse()?
+    };
+
+    let socket = UdpSocket::bind(bind).await?;
+    socket.connect(secondary).await?;
+    socket.send(&message.to_vec()?).await?;
+
+    let mut buf = [0u8; 512];
+    let len = tokio::time::timeout(NOTIFY_TIMEOUT, socket.recv(&mut buf))
+        .await
+        .map_err(|_| anyhow::anyhow!("no acknowledgement"))??;
+
+    let response = Message::from_vec(&buf[..len])?;
+    if response.id() != message.id() {
+        return Err(anyhow::anyhow!("unexpected response"));
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{collections::BTreeMap, str::FromStr, sync::Arc};
+
+    use super::{diff, ixfr_answers, soa_serial, History, ZoneChange, HISTORY_LENGTH};
+    use trust_dns_resolver::{
+        proto::rr::{rdata::SOA, RData, Record, RecordSet},
+        Name,
+    };
+    use trust_dns_server::client::rr::RrKey;
+
+    fn soa(serial: u32) -> Record {
+        let name = Name::from_str("home.arpa.").unwrap();
+        Record::from_rdata(
+            name.clone(),
+            60,
+            RData::SOA(SOA::new(name.clone(), name, serial, 30, 10, 604800, 60)),
+        )
+    }
+
+    fn a(name: &str, ip: &str) -> Record {
+        Record::from_rdata(
+            Name::from_str(name).unwrap(),
+            60,
+            RData::A(ip.parse().unwrap()),
+        )
+    }
+
+    fn zone(records: &[Record]) -> BTreeMap<RrKey, Arc<RecordSet>> {
+        let mut map: BTreeMap<RrKey, RecordSet> = BTreeMap::new();
+
+        for record in records {
+            map.entry(RrKey::new(record.name().into(), record.record_type()))
+                .or_insert_with(|| RecordSet::new(record.name(), record.record_type(), 0))
+                .insert(record.clone(), 0);
+        }
+
+        map.into_iter().map(|(k, v)| (k, Arc::new(v))).collect()
+    }
+
+    #[test]
+    fn test_diff() {
+        let old = zone(&[
+            soa(1),
+            a("islay.home.arpa.", "10.0.0.1"),
+            a("jura.home.arpa.", "10.0.0.2"),
+        ]);
+        let new = zone(&[
+            soa(2),
+            a("islay.home.arpa.", "10.0.0.1"),
+            a("jura.home.arpa.", "10.0.0.3"),
+        ]);
+
+        let (removed, added) = diff(&old, &new);
+        assert_eq!(removed, vec![a("jura.home.arpa.", "10.0.0.2")]);
+        assert_eq!(added, vec![a("jura.home.arpa.", "10.0.0.3")]);
+    }
+
+    #[test]
+    fn test_history() {
+        let history = History::default();
+
+        for serial in 1..=(HISTORY_LENGTH as u32 + 1) {
+            history.push(ZoneChange {
+                from: soa(serial),
+                to: soa(serial + 1),
+                removed: Vec::new(),
+                added: Vec::new(),
+            });
+        }
+
+        // the oldest change has been forgotten.
+        assert!(history.since(1).is_none());
+
+        let changes = history.since(HISTORY_LENGTH as u32).unwrap();
+        assert_eq!(changes.len(), 2);
+        assert_eq!(soa_serial(&changes[0].from), Some(HISTORY_LENGTH as u32));
+    }
+
+    #[test]
+    fn test_ixfr_answers() {
+        let records = zone(&[soa(3), a("islay.home.arpa.", "10.0.0.1")]);
+
+        // up to date
+        assert_eq!(ixfr_answers(&soa(3), Some(3), None, &records), vec![soa(3)]);
+
+        // incremental
+        let changes = vec![ZoneChange {
+            from: soa(2),
+            to: soa(3),
+            removed: vec![a("islay.home.arpa.", "10.0.0.9")],
+            added: vec![a("islay.home.arpa.", "10.0.0.1")],
+        }];
+        assert_eq!(
+            ixfr_answers(&soa(3), Some(2), Some(changes), &records),
+            vec![
+                soa(3),
+                soa(2),
+                a("islay.home.arpa.", "10.0.0.9"),
+                soa(3),
+                a("islay.home.arpa.", "10.0.0.1"),
+                soa(3),
+            ]
+        );
+
+        // unknown serial: the whole zone
+        assert_eq!(
+            ixfr_answers(&soa(3), Some(1), None, &records),
+            vec![soa(3), a("islay.home.arpa.", "10.0.0.1"), soa(3)]
+        );
+    }
+}
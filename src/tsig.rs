@@ -0,0 +1,423 @@
+/// TSIG keys (RFC 8945) authenticating secondaries and other clients.
+use std::{
+    str::FromStr,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use anyhow::anyhow;
+use serde::{Deserialize, Serialize};
+use trust_dns_server::{
+    authority::MessageRequest,
+    client::rr::Name,
+    proto::{
+        op::{message::emit_message_parts, Edns, Header, Query},
+        rr::{
+            dnssec::rdata::{
+                tsig::{make_tsig_record, TsigAlgorithm, TSIG},
+                DNSSECRData,
+            },
+            RData, Record, RecordType,
+        },
+        serialize::binary::BinEncoder,
+    },
+};
+
+// seconds a signature is valid for either side of the time it was made at.
+const FUDGE: u16 = 300;
+
+// TsigKey is a key as written in the configuration file: its name, its algorithm and its secret,
+// encoded in base64.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TsigKey {
+    pub name: String,
+    #[serde(default = "default_algorithm")]
+    pub algorithm: String,
+    pub secret: String,
+}
+
+fn default_algorithm() -> String {
+    "hmac-sha256".to_string()
+}
+
+impl TsigKey {
+    // key checks the name, algorithm and secret of the key. Only the HMAC-SHA256, HMAC-SHA384
+    // and HMAC-SHA512 algorithms are supported.
+    pub fn key(&self) -> Result<Key, anyhow::Error> {
+        let name = Name::from_str(&format!("{}.", self.name.trim_end_matches('.')))?;
+
+        let algorithm = TsigAlgorithm::from_name(Name::from_ascii(self.algorithm.to_lowercase())?);
+        if !algorithm.supported() {
+            return Err(anyhow!("unsupported algorithm {}", self.algorithm));
+        }
+
+        let secret = data_encoding::BASE64
+            .decode(self.secret.trim().as_bytes())
+            .map_err(|e| anyhow!("secret is not valid base64: {}", e))?;
+        if secret.is_empty() {
+            return Err(anyhow!("secret is empty"));
+        }
+
+        Ok(Key {
+            name,
+            algorithm,
+            secret,
+        })
+    }
+}
+
+// keys checks every key of the configuration.
+pub fn keys(config: &[TsigKey]) -> Result<Vec<Key>, anyhow::Error> {
+    config
+        .iter()
+        .map(|key| {
+            key.key()
+                .map_err(|e| anyhow!("Invalid TSIG key {}: {}", key.name, e))
+        })
+        .collect()
+}
+
+// Key is a TSIG key ready to authenticate messages.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Key {
+    pub name: Name,
+    pub algorithm: TsigAlgorithm,
+    secret: Vec<u8>,
+}
+
+impl Key {
+    // mac computes the MAC of an encoded message signed with the TSIG. Responses also cover the
+    // MAC of the request they answer.
+    fn mac(
+        &self,
+        request_mac: Option<&[u8]>,
+        message: &[u8],
+        tsig: &TSIG,
+    ) -> Result<Vec<u8>, anyhow::Error> {
+        Ok(self
+            .algorithm
+            .mac_data(&self.secret, &self.tbs(request_mac, message, tsig)?)?)
+    }
+
+    // tbs is the data a MAC is computed over.
+    fn tbs(
+        &self,
+        request_mac: Option<&[u8]>,
+        message: &[u8],
+        tsig: &TSIG,
+    ) -> Result<Vec<u8>, anyhow::Error> {
+        let mut buf = Vec::with_capacity(message.len() + 128);
+
+        {
+            let mut encoder = BinEncoder::new(&mut buf);
+            if let Some(mac) = request_mac {
+                encoder.emit_u16(mac.len() as u16)?;
+                encoder.emit_vec(mac)?;
+            }
+            encoder.emit_vec(message)?;
+            tsig.emit_tsig_for_mac(&mut encoder, &self.name)?;
+        }
+
+        Ok(buf)
+    }
+}
+
+// Signed is a request signed with one of the keys; see `verify`.
+#[derive(Debug, Clone)]
+pub struct Signed {
+    key: Key,
+    mac: Vec<u8>,
+}
+
+impl Signed {
+    pub fn key_name(&self) -> &Name {
+        &self.key.name
+    }
+
+    // sign returns the TSIG record to append to the response to the request, made of the given
+    // header, query and answers.
+    pub fn sign(
+        &self,
+        header: &Header,
+        query: &Query,
+        answers: &[Record],
+    ) -> Result<Record, anyhow::Error> {
+        let tsig = TSIG::new(
+            self.key.algorithm.clone(),
+            now(),
+            FUDGE,
+            Vec::new(),
+            header.id(),
+            0,
+            Vec::new(),
+        );
+        let mac = self.key.mac(
+            Some(&self.mac),
+            &message(header, query, answers, &[], &[], None)?,
+            &tsig,
+        )?;
+
+        Ok(make_tsig_record(self.key.name.clone(), tsig.set_mac(mac)))
+    }
+}
+
+// verify checks the TSIG record signing the request. Unsigned requests are returned as None, and
+// requests signed with an unknown key, with the wrong secret or at the wrong time are errors.
+//
+// The request is encoded again to check its MAC, as the server only keeps it decoded; this gives
+// the same bytes as the client sent for the queries secondaries make.
+pub fn verify(keys: &[Key], request: &MessageRequest) -> Result<Option<Signed>, anyhow::Error> {
+    let record = match request
+        .sig0()
+        .iter()
+        .find(|record| record.record_type() == RecordType::TSIG)
+    {
+        Some(record) => record,
+        None => return Ok(None),
+    };
+
+    let tsig = match record.data() {
+        Some(RData::DNSSEC(DNSSECRData::TSIG(tsig))) => tsig,
+        _ => return Err(anyhow!("malformed TSIG record")),
+    };
+
+    let key = keys
+        .iter()
+        .find(|key| key.name == *record.name() && key.algorithm == *tsig.algorithm())
+        .ok_or_else(|| anyhow!("unknown key {} ({})", record.name(), tsig.algorithm()))?;
+
+    let message = message(
+        request.header(),
+        request.query().original(),
+        request.answers(),
+        request.name_servers(),
+        request.additionals(),
+        request.edns(),
+    )?;
+    key.algorithm
+        .verify_mac(&key.secret, &key.tbs(None, &message, tsig)?, tsig.mac())
+        .map_err(|_| anyhow!("bad signature with key {}", key.name))?;
+
+    if now().abs_diff(tsig.time()) > tsig.fudge() as u64 {
+        return Err(anyhow!(
+            "signature with key {} was made at {}, too far from now",
+            key.name,
+            tsig.time()
+        ));
+    }
+
+    Ok(Some(Signed {
+        key: key.clone(),
+        mac: tsig.mac().to_vec(),
+    }))
+}
+
+// message encodes the parts of a message, leaving out its signature.
+fn message(
+    header: &Header,
+    query: &Query,
+    answers: &[Record],
+    name_servers: &[Record],
+    additionals: &[Record],
+    edns: Option<&Edns>,
+) -> Result<Vec<u8>, anyhow::Error> {
+    let mut buf = Vec::with_capacity(512);
+
+    {
+        let mut encoder = BinEncoder::new(&mut buf);
+        emit_message_parts(
+            header,
+            &mut std::iter::once(query),
+            &mut answers.iter(),
+            &mut name_servers.iter(),
+            &mut additionals.iter(),
+            edns,
+            &[],
+            &mut encoder,
+        )?;
+    }
+
+    Ok(buf)
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+
+    use super::{keys, message, now, verify, Key, TsigKey, FUDGE};
+    use trust_dns_server::{
+        authority::MessageRequest,
+        client::rr::Name,
+        proto::{
+            op::{message::emit_message_parts, Header, Query},
+            rr::{
+                dnssec::rdata::{
+                    tsig::{make_tsig_record, TSIG},
+                    DNSSECRData,
+                },
+                RData, Record, RecordType,
+            },
+            serialize::binary::{BinDecodable, BinEncoder},
+        },
+    };
+
+    fn key(name: &str, secret: &str) -> Key {
+        TsigKey {
+            name: name.to_string(),
+            algorithm: "hmac-sha256".to_string(),
+            secret: secret.to_string(),
+        }
+        .key()
+        .unwrap()
+    }
+
+    fn transfer_key() -> Key {
+        key("transfer", "c2VjcmV0c2VjcmV0c2VjcmV0c2VjcmV0")
+    }
+
+    fn header() -> Header {
+        let mut header = Header::new();
+        header.set_id(1234);
+        header
+    }
+
+    fn query() -> Query {
+        Query::query(Name::from_str("home.arpa.").unwrap(), RecordType::AXFR)
+    }
+
+    // request makes an AXFR of home.arpa signed with the key at the given time, as a secondary
+    // would.
+    fn request(key: &Key, time: u64) -> MessageRequest {
+        let (header, query) = (header(), query());
+        let tsig = TSIG::new(
+            key.algorithm.clone(),
+            time,
+            FUDGE,
+            Vec::new(),
+            header.id(),
+            0,
+            Vec::new(),
+        );
+        let mac = key
+            .mac(
+                None,
+                &message(&header, &query, &[], &[], &[], None).unwrap(),
+                &tsig,
+            )
+            .unwrap();
+        let tsig = make_tsig_record(key.name.clone(), tsig.set_mac(mac));
+
+        let mut buf = Vec::new();
+        {
+            let mut encoder = BinEncoder::new(&mut buf);
+            emit_message_parts(
+                &header,
+                &mut std::iter::once(&query),
+                &mut std::iter::empty::<&Record>(),
+                &mut std::iter::empty::<&Record>(),
+                &mut std::iter::empty::<&Record>(),
+                None,
+                &[tsig],
+                &mut encoder,
+            )
+            .unwrap();
+        }
+
+        MessageRequest::from_bytes(&buf).unwrap()
+    }
+
+    #[test]
+    fn test_tsig_keys() {
+        let key = transfer_key();
+        assert_eq!(key.name, Name::from_str("transfer.").unwrap());
+
+        for (algorithm, secret) in [
+            ("hmac-sha256", "not base64!"),
+            ("hmac-sha256", ""),
+            ("hmac-md5", "c2VjcmV0"),
+        ] {
+            assert!(keys(&[TsigKey {
+                name: "transfer".to_string(),
+                algorithm: algorithm.to_string(),
+                secret: secret.to_string(),
+            }])
+            .is_err());
+        }
+    }
+
+    #[test]
+    fn test_verify() {
+        let transfer = transfer_key();
+        let keys = [transfer.clone()];
+
+        let signed = verify(&keys, &request(&transfer, now())).unwrap();
+        assert_eq!(signed.unwrap().key_name(), &transfer.name);
+
+        let mut unsigned = Vec::new();
+        {
+            let mut encoder = BinEncoder::new(&mut unsigned);
+            emit_message_parts(
+                &header(),
+                &mut std::iter::once(&query()),
+                &mut std::iter::empty::<&Record>(),
+                &mut std::iter::empty::<&Record>(),
+                &mut std::iter::empty::<&Record>(),
+                None,
+                &[],
+                &mut encoder,
+            )
+            .unwrap();
+        }
+        assert!(
+            verify(&keys, &MessageRequest::from_bytes(&unsigned).unwrap())
+                .unwrap()
+                .is_none()
+        );
+
+        // the wrong secret, another key and a stale signature
+        let impostor = key("transfer", "aW1wb3N0b3JpbXBvc3RvcmltcG9zdG9y");
+        assert!(verify(&keys, &request(&impostor, now())).is_err());
+        assert!(verify(&keys, &request(&key("other", "c2VjcmV0"), now())).is_err());
+        assert!(verify(&keys, &request(&transfer, now() - 3600)).is_err());
+    }
+
+    #[test]
+    fn test_sign() {
+        let key = transfer_key();
+        let signed = verify(&[key.clone()], &request(&key, now()))
+            .unwrap()
+            .unwrap();
+
+        let answers = vec![Record::from_rdata(
+            Name::from_str("islay.home.arpa.").unwrap(),
+            60,
+            RData::A("10.0.0.1".parse().unwrap()),
+        )];
+        let mut header = Header::response_from_request(&header());
+        header.set_authoritative(true);
+
+        let record = signed.sign(&header, &query(), &answers).unwrap();
+        assert_eq!(record.name(), &key.name);
+
+        let tsig = match record.data() {
+            Some(RData::DNSSEC(DNSSECRData::TSIG(tsig))) => tsig,
+            _ => panic!("not a TSIG record"),
+        };
+
+        // the secondary checks the response against the MAC of its request.
+        let message = message(&header, &query(), &answers, &[], &[], None).unwrap();
+        key.algorithm
+            .verify_mac(
+                &key.secret,
+                &key.tbs(Some(&signed.mac), &message, &tsig).unwrap(),
+                tsig.mac(),
+            )
+            .unwrap();
+    }
+}
@@ -0,0 +1,494 @@
+/// dynamic updates (RFC 2136) of the zone set aside for them.
+use std::{
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use anyhow::anyhow;
+use tokio::sync::Mutex;
+use trust_dns_server::{
+    authority::{AuthorityObject, MessageRequest},
+    client::rr::Name,
+    proto::{
+        op::ResponseCode,
+        rr::{DNSClass, RData, Record, RecordType},
+    },
+};
+
+use crate::{
+    authority::RecordAuthority,
+    records::{format_rdata, parse_records, Records},
+    store::Saved,
+};
+
+// DynamicZone is the authority of the dynamic zone, with the records registered in it.
+#[derive(Clone)]
+pub struct DynamicZone {
+    authority: RecordAuthority,
+    records: Arc<Mutex<Vec<(Name, RData)>>>,
+    saved: Option<Saved>,
+}
+
+impl DynamicZone {
+    // path is where the records of a network's dynamic zone are kept in the state directory.
+    pub fn path(state_dir: &Path, network_id: &str) -> PathBuf {
+        state_dir.join(format!("{}.dynamic.json", network_id))
+    }
+
+    // new serves the records saved, if any. Without anywhere to save them, records are lost when
+    // zeronsd stops.
+    pub async fn new(
+        authority: RecordAuthority,
+        saved: Option<Saved>,
+    ) -> Result<Self, anyhow::Error> {
+        let origin: Name = authority.origin().clone().into();
+
+        let records = match &saved {
+            Some(saved) => match saved.read()? {
+                Some(contents) => parse_records(&serde_json::from_slice(&contents)?, &origin)
+                    .map_err(|e| anyhow!("Could not read {}: {}", saved, e))?,
+                None => Vec::new(),
+            },
+            None => Vec::new(),
+        };
+
+        let zone = Self {
+            authority,
+            records: Arc::new(Mutex::new(Vec::new())),
+            saved,
+        };
+
+        zone.publish(&records).await?;
+        *zone.records.lock().await = records;
+
+        Ok(zone)
+    }
+
+    pub fn authority(&self) -> &RecordAuthority {
+        &self.authority
+    }
+
+    // update applies the update request, checking its prerequisites first. Either all of the
+    // updates are made, or none of them; the response code to send back is returned otherwise.
+    pub async fn update(&self, request: &MessageRequest) -> Result<(), ResponseCode> {
+        let origin: Name = self.authority.origin().clone().into();
+
+        if request.query().query_type() != RecordType::SOA {
+            return Err(ResponseCode::FormErr);
+        }
+
+        let mut records = self.records.lock().await;
+
+        check_prerequisites(&origin, &records, request.answers())?;
+        let updated = apply_updates(&origin, &records, request.name_servers())?;
+
+        if updated == *records {
+            return Ok(());
+        }
+
+        if let Err(e) = self.save(&updated) {
+            tracing::error!("Could not save the records of {}: {}", origin, e);
+            return Err(ResponseCode::ServFail);
+        }
+
+        if let Err(e) = self.publish(&updated).await {
+            tracing::error!("Could not update {}: {}", origin, e);
+            return Err(ResponseCode::ServFail);
+        }
+
+        *records = updated;
+        Ok(())
+    }
+
+    // publish serves the records, through a staged copy of the zone.
+    async fn publish(&self, records: &[(Name, RData)]) -> Result<(), anyhow::Error> {
+        let staged = self.authority.stage().await?;
+        staged.insert_records(records.to_vec()).await;
+        self.authority.commit(&staged).await;
+        Ok(())
+    }
+
+    // resign renews the signatures of the zone once they are due; publishing the same records
+    // only commits them then.
+    pub async fn resign(&self) -> Result<(), anyhow::Error> {
+        let records = self.records.lock().await;
+        self.publish(&records).await
+    }
+
+    // save writes the records in the format of the `records` section of the configuration file.
+    fn save(&self, records: &[(Name, RData)]) -> Result<(), anyhow::Error> {
+        let saved = match &self.saved {
+            Some(saved) => saved,
+            None => return Ok(()),
+        };
+
+        let mut formatted = Records::new();
+        for (name, rdata) in records {
+            if let Some(value) = format_rdata(rdata) {
+                formatted.entry(name.to_string()).or_default().push(value);
+            }
+        }
+
+        saved.write(&serde_json::to_vec_pretty(&formatted)?)
+    }
+}
+
+// the types of the records which can be registered.
+const UPDATE_TYPES: [RecordType; 6] = [
+    RecordType::A,
+    RecordType::AAAA,
+    RecordType::CNAME,
+    RecordType::MX,
+    RecordType::SRV,
+    RecordType::TXT,
+];
+
+// check_prerequisites checks the prerequisite section of an update against the records of the
+// zone, as described in RFC 2136 section 3.2. The SOA and NS records of the zone exist at its
+// origin.
+pub fn check_prerequisites(
+    origin: &Name,
+    records: &[(Name, RData)],
+    prerequisites: &[Record],
+) -> Result<(), ResponseCode> {
+    let in_use = |name: &Name| name == origin || records.iter().any(|(n, _)| n == name);
+    let exists = |name: &Name, rtype: RecordType| {
+        (name == origin && matches!(rtype, RecordType::SOA | RecordType::NS))
+            || records
+                .iter()
+                .any(|(n, rdata)| n == name && rdata.to_record_type() == rtype)
+    };
+
+    // value dependent prerequisites are compared as whole RRsets.
+    let mut rrsets: Vec<(Name, RecordType, Vec<RData>)> = Vec::new();
+
+    for prerequisite in prerequisites {
+        let name = prerequisite.name();
+
+        if !origin.zone_of(name) {
+            return Err(ResponseCode::NotZone);
+        }
+
+        if prerequisite.ttl() != 0 {
+            return Err(ResponseCode::FormErr);
+        }
+
+        let rtype = prerequisite.record_type();
+
+        match (prerequisite.dns_class(), prerequisite.data()) {
+            (DNSClass::ANY, None) if rtype == RecordType::ANY => {
+                if !in_use(name) {
+                    return Err(ResponseCode::NXDomain);
+                }
+            }
+            (DNSClass::ANY, None) => {
+                if !exists(name, rtype) {
+                    return Err(ResponseCode::NXRRSet);
+                }
+            }
+            (DNSClass::NONE, None) if rtype == RecordType::ANY => {
+                if in_use(name) {
+                    return Err(ResponseCode::YXDomain);
+                }
+            }
+            (DNSClass::NONE, None) => {
+                if exists(name, rtype) {
+                    return Err(ResponseCode::YXRRSet);
+                }
+            }
+            (DNSClass::IN, Some(rdata)) => {
+                match rrsets.iter_mut().find(|(n, t, _)| n == name && *t == rtype) {
+                    Some((_, _, rdatas)) => rdatas.push(rdata.clone()),
+                    None => rrsets.push((name.clone(), rtype, vec![rdata.clone()])),
+                }
+            }
+            _ => return Err(ResponseCode::FormErr),
+        }
+    }
+
+    for (name, rtype, expected) in rrsets {
+        let actual: Vec<RData> = records
+            .iter()
+            .filter(|(n, rdata)| *n == name && rdata.to_record_type() == rtype)
+            .map(|(_, rdata)| rdata.clone())
+            .collect();
+
+        if expected.iter().any(|r| !actual.contains(r))
+            || actual.iter().any(|r| !expected.contains(r))
+        {
+            return Err(ResponseCode::NXRRSet);
+        }
+    }
+
+    Ok(())
+}
+
+// apply_updates returns the records of the zone once the update section of an update is applied
+// to them, as described in RFC 2136 section 3.4. Records can be added and removed anywhere in the
+// zone but at its origin, whose SOA and NS records are managed by zeronsd. Records are served with
+// the TTL of the zone, whatever TTL they are added with.
+pub fn apply_updates(
+    origin: &Name,
+    records: &[(Name, RData)],
+    updates: &[Record],
+) -> Result<Vec<(Name, RData)>, ResponseCode> {
+    // the whole update section is checked before anything is changed.
+    for update in updates {
+        let name = update.name();
+
+        if !origin.zone_of(name) {
+            return Err(ResponseCode::NotZone);
+        }
+
+        if name == origin {
+            return Err(ResponseCode::Refused);
+        }
+
+        match (update.dns_class(), update.data()) {
+            (DNSClass::IN, Some(_)) if !UPDATE_TYPES.contains(&update.record_type()) => {
+                return Err(ResponseCode::Refused)
+            }
+            (DNSClass::IN, Some(_)) | (DNSClass::ANY, None) | (DNSClass::NONE, Some(_)) => {}
+            _ => return Err(ResponseCode::FormErr),
+        }
+    }
+
+    let mut records = records.to_vec();
+
+    for update in updates {
+        let name = update.name();
+        let rtype = update.record_type();
+
+        match (update.dns_class(), update.data()) {
+            (DNSClass::IN, Some(rdata)) => {
+                // a name with a CNAME has no other records, and the other way around.
+                let conflicts = records.iter().any(|(n, r)| {
+                    n == name
+                        && ((rtype == RecordType::CNAME)
+                            != (r.to_record_type() == RecordType::CNAME))
+                });
+
+                if rtype == RecordType::CNAME {
+                    records
+                        .retain(|(n, r)| !(n == name && r.to_record_type() == RecordType::CNAME));
+                }
+
+                if !conflicts && !records.contains(&(name.clone(), rdata.clone())) {
+                    records.push((name.clone(), rdata.clone()));
+                }
+            }
+            (DNSClass::ANY, None) if rtype == RecordType::ANY => {
+                records.retain(|(n, _)| n != name);
+            }
+            (DNSClass::ANY, None) => {
+                records.retain(|(n, r)| !(n == name && r.to_record_type() == rtype));
+            }
+            (DNSClass::NONE, Some(rdata)) => {
+                records.retain(|(n, r)| !(n == name && r == rdata));
+            }
+            _ => return Err(ResponseCode::FormErr),
+        }
+    }
+
+    Ok(records)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+
+    use super::{apply_updates, check_prerequisites};
+    use trust_dns_server::{
+        client::rr::Name,
+        proto::{
+            op::ResponseCode,
+            rr::{DNSClass, RData, Record, RecordType},
+        },
+    };
+
+    fn name(s: &str) -> Name {
+        Name::from_str(s).unwrap()
+    }
+
+    fn a(ip: &str) -> RData {
+        RData::A(ip.parse().unwrap())
+    }
+
+    // rr makes a record as found in the prerequisite and update sections.
+    fn rr(owner: &str, class: DNSClass, rtype: RecordType, rdata: Option<RData>) -> Record {
+        let mut record = Record::with(name(owner), rtype, 0);
+        record.set_dns_class(class).set_data(rdata);
+        record
+    }
+
+    #[test]
+    fn test_check_prerequisites() {
+        let origin = name("dyn.home.arpa.");
+        let records = vec![(name("laptop.dyn.home.arpa."), a("10.147.20.9"))];
+
+        for (prerequisite, result) in [
+            // name is in use
+            (
+                rr(
+                    "laptop.dyn.home.arpa.",
+                    DNSClass::ANY,
+                    RecordType::ANY,
+                    None,
+                ),
+                Ok(()),
+            ),
+            (
+                rr("other.dyn.home.arpa.", DNSClass::ANY, RecordType::ANY, None),
+                Err(ResponseCode::NXDomain),
+            ),
+            // RRset exists
+            (
+                rr(
+                    "laptop.dyn.home.arpa.",
+                    DNSClass::ANY,
+                    RecordType::AAAA,
+                    None,
+                ),
+                Err(ResponseCode::NXRRSet),
+            ),
+            // name is not in use
+            (
+                rr(
+                    "laptop.dyn.home.arpa.",
+                    DNSClass::NONE,
+                    RecordType::ANY,
+                    None,
+                ),
+                Err(ResponseCode::YXDomain),
+            ),
+            // RRset does not exist
+            (
+                rr("laptop.dyn.home.arpa.", DNSClass::NONE, RecordType::A, None),
+                Err(ResponseCode::YXRRSet),
+            ),
+            // RRset exists with these values
+            (
+                rr(
+                    "laptop.dyn.home.arpa.",
+                    DNSClass::IN,
+                    RecordType::A,
+                    Some(a("10.147.20.9")),
+                ),
+                Ok(()),
+            ),
+            (
+                rr(
+                    "laptop.dyn.home.arpa.",
+                    DNSClass::IN,
+                    RecordType::A,
+                    Some(a("10.147.20.10")),
+                ),
+                Err(ResponseCode::NXRRSet),
+            ),
+            // outside the zone
+            (
+                rr("laptop.home.arpa.", DNSClass::ANY, RecordType::ANY, None),
+                Err(ResponseCode::NotZone),
+            ),
+        ] {
+            assert_eq!(
+                check_prerequisites(&origin, &records, &[prerequisite.clone()]),
+                result,
+                "{:?}",
+                prerequisite
+            );
+        }
+    }
+
+    #[test]
+    fn test_apply_updates() {
+        let origin = name("dyn.home.arpa.");
+        let laptop = name("laptop.dyn.home.arpa.");
+        let records = vec![(laptop.clone(), a("10.147.20.9"))];
+
+        // replace the address of laptop and add another name
+        let updated = apply_updates(
+            &origin,
+            &records,
+            &[
+                rr("laptop.dyn.home.arpa.", DNSClass::ANY, RecordType::A, None),
+                rr(
+                    "laptop.dyn.home.arpa.",
+                    DNSClass::IN,
+                    RecordType::A,
+                    Some(a("10.147.20.10")),
+                ),
+                rr(
+                    "web.dyn.home.arpa.",
+                    DNSClass::IN,
+                    RecordType::CNAME,
+                    Some(RData::CNAME(laptop.clone())),
+                ),
+            ],
+        )
+        .unwrap();
+
+        assert_eq!(
+            updated,
+            vec![
+                (laptop.clone(), a("10.147.20.10")),
+                (name("web.dyn.home.arpa."), RData::CNAME(laptop.clone())),
+            ]
+        );
+
+        // names with a CNAME have no other records
+        let conflicting = apply_updates(
+            &origin,
+            &updated,
+            &[rr(
+                "web.dyn.home.arpa.",
+                DNSClass::IN,
+                RecordType::A,
+                Some(a("10.147.20.11")),
+            )],
+        )
+        .unwrap();
+        assert_eq!(conflicting, updated);
+
+        // delete one record, then every record of a name
+        let deleted = apply_updates(
+            &origin,
+            &updated,
+            &[
+                rr(
+                    "laptop.dyn.home.arpa.",
+                    DNSClass::NONE,
+                    RecordType::A,
+                    Some(a("10.147.20.10")),
+                ),
+                rr("web.dyn.home.arpa.", DNSClass::ANY, RecordType::ANY, None),
+            ],
+        )
+        .unwrap();
+        assert!(deleted.is_empty());
+
+        // the origin, other zones and other types are left alone
+        for update in [
+            rr(
+                "dyn.home.arpa.",
+                DNSClass::IN,
+                RecordType::A,
+                Some(a("10.147.20.10")),
+            ),
+            rr(
+                "laptop.home.arpa.",
+                DNSClass::IN,
+                RecordType::A,
+                Some(a("10.147.20.10")),
+            ),
+            rr(
+                "laptop.dyn.home.arpa.",
+                DNSClass::IN,
+                RecordType::NS,
+                Some(RData::NS(laptop.clone())),
+            ),
+        ] {
+            assert!(apply_updates(&origin, &records, &[update]).is_err());
+        }
+    }
+}
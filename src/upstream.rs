@@ -0,0 +1,818 @@
+/// upstream resolvers for forwarded queries, over plain DNS, TLS or HTTPS.
+use std::{
+    future::Future,
+    net::{IpAddr, SocketAddr},
+    str::FromStr,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use anyhow::anyhow;
+use serde::Serialize;
+use trust_dns_resolver::{
+    config::{NameServerConfigGroup, Protocol, ResolverConfig, ResolverOpts},
+    error::{ResolveError, ResolveErrorKind},
+    lookup::Lookup,
+    proto::{op::ResponseCode, rr::RecordType, xfer::DnsRequestOptions},
+    Name, TokioAsyncResolver,
+};
+use trust_dns_server::client::rr::LowerName;
+
+// the only path DNS-over-HTTPS queries can be sent to.
+const DOH_PATH: &str = "/dns-query";
+
+// how often the upstreams are probed.
+const PROBE_INTERVAL: Duration = Duration::from_secs(10);
+
+// how long a probe waits for an answer.
+const PROBE_TIMEOUT: Duration = Duration::from_secs(2);
+
+// failures in a row, of queries or probes, after which an upstream is considered down.
+const MAX_FAILURES: u32 = 3;
+
+// how often the statistics of the upstreams are logged.
+const REPORT_INTERVAL: Duration = Duration::from_secs(300);
+
+// weight of the latest probe in the smoothed latency of an upstream.
+const LATENCY_WEIGHT: f64 = 0.3;
+
+// the most labels a minimized lookup adds one at a time; the rest are sent at once, so that a
+// name with many labels cannot make a lookup take as many queries (RFC 9156).
+const MAX_MINIMIZE_COUNT: usize = 10;
+
+// Upstream is an upstream resolver as configured.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Upstream {
+    // Udp stands for plain DNS over UDP with a fallback to TCP, unless given as udp://.
+    pub protocol: Protocol,
+    // whether the upstream was given as a bare address, to be asked over both UDP and TCP.
+    pub plain: bool,
+    // an address, or a name resolved with the system's resolvers at startup.
+    pub host: String,
+    pub port: u16,
+    // the name on the certificate of TLS and HTTPS upstreams.
+    pub tls_name: Option<String>,
+}
+
+impl FromStr for Upstream {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (scheme, rest, plain) = match s.split_once("://") {
+            Some((scheme, rest)) => (scheme, rest, false),
+            None => ("udp", s, true),
+        };
+
+        let (protocol, default_port) = match scheme {
+            "udp" => (Protocol::Udp, 53),
+            "tcp" => (Protocol::Tcp, 53),
+            "tls" => (Protocol::Tls, 853),
+            "https" => (Protocol::Https, 443),
+            _ => {
+                return Err(anyhow!(
+                    "unknown scheme {}: allowed values: [udp, tcp, tls, https]",
+                    scheme
+                ))
+            }
+        };
+
+        let (rest, tls_name) = match rest.split_once('#') {
+            Some((rest, name)) => (rest, Some(name.to_string())),
+            None => (rest, None),
+        };
+
+        let (authority, path) = match rest.find('/') {
+            Some(i) => rest.split_at(i),
+            None => (rest, ""),
+        };
+
+        if !path.is_empty() && !(protocol == Protocol::Https && path == DOH_PATH) {
+            return Err(anyhow!(
+                "only DNS-over-HTTPS upstreams have a path, which must be {}",
+                DOH_PATH
+            ));
+        }
+
+        let (host, port) = if let Ok(addr) = SocketAddr::from_str(authority) {
+            (addr.ip().to_string(), addr.port())
+        } else if let Ok(ip) = IpAddr::from_str(authority) {
+            (ip.to_string(), default_port)
+        } else {
+            match authority.rsplit_once(':') {
+                Some((host, port)) => (host.to_string(), port.parse()?),
+                None => (authority.to_string(), default_port),
+            }
+        };
+
+        if host.is_empty() {
+            return Err(anyhow!("missing host"));
+        }
+
+        let named = IpAddr::from_str(&host).is_err();
+
+        let tls_name = match protocol {
+            Protocol::Tls | Protocol::Https => match tls_name {
+                Some(name) => Some(name),
+                None if named => Some(host.clone()),
+                None => {
+                    return Err(anyhow!(
+                    "give the name on the server's certificate after a #, as in {}#dns.example.com",
+                    s
+                ))
+                }
+            },
+            _ if tls_name.is_some() => {
+                return Err(anyhow!(
+                    "only TLS and HTTPS upstreams have a certificate name"
+                ))
+            }
+            _ => None,
+        };
+
+        Ok(Self {
+            protocol,
+            plain,
+            host,
+            port,
+            tls_name,
+        })
+    }
+}
+
+impl Upstream {
+    // name_servers returns the name server configurations of the upstream, one for each of the
+    // addresses of its host.
+    async fn name_servers(&self) -> Result<NameServerConfigGroup, anyhow::Error> {
+        let ips: Vec<IpAddr> = tokio::net::lookup_host((self.host.as_str(), self.port))
+            .await
+            .map_err(|e| anyhow!("Could not resolve {}: {}", self.host, e))?
+            .map(|addr| addr.ip())
+            .collect();
+
+        let mut group = match (&self.protocol, &self.tls_name) {
+            (Protocol::Tls, Some(name)) => {
+                NameServerConfigGroup::from_ips_tls(&ips, self.port, name.clone(), true)
+            }
+            (Protocol::Https, Some(name)) => {
+                NameServerConfigGroup::from_ips_https(&ips, self.port, name.clone(), true)
+            }
+            _ => NameServerConfigGroup::from_ips_clear(&ips, self.port, true),
+        };
+
+        if !self.plain {
+            group.retain(|config| config.protocol == self.protocol);
+        }
+
+        Ok(group)
+    }
+}
+
+// UpstreamStats is the health of an upstream, as probed and as seen by the queries sent to it.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct UpstreamStats {
+    pub upstream: String,
+    pub healthy: bool,
+    // smoothed latency of the probes, in milliseconds, once one has been answered.
+    pub latency_ms: Option<f64>,
+    pub queries: u64,
+    pub failures: u64,
+}
+
+impl UpstreamStats {
+    fn new(upstream: &str) -> Self {
+        Self {
+            upstream: upstream.to_string(),
+            healthy: true,
+            latency_ms: None,
+            queries: 0,
+            failures: 0,
+        }
+    }
+}
+
+struct Health {
+    stats: UpstreamStats,
+    // failures in a row, of queries or probes.
+    failures: u32,
+}
+
+impl Health {
+    fn success(&mut self) {
+        self.failures = 0;
+
+        if !self.stats.healthy {
+            tracing::info!("Upstream {} has recovered", self.stats.upstream);
+            self.stats.healthy = true;
+        }
+    }
+
+    fn failure(&mut self) {
+        self.failures += 1;
+
+        if self.stats.healthy && self.failures >= MAX_FAILURES {
+            tracing::warn!(
+                "Upstream {} is down after {} failures in a row; failing over",
+                self.stats.upstream,
+                self.failures
+            );
+            self.stats.healthy = false;
+        }
+    }
+}
+
+struct Member {
+    resolver: TokioAsyncResolver,
+    // resolves the probes, without a cache, so that they reach the upstream.
+    prober: TokioAsyncResolver,
+    health: Mutex<Health>,
+}
+
+// Upstreams are the resolvers forwarded queries are sent to, with their health. They are shared
+// by the listeners of a network.
+#[derive(Clone)]
+pub struct Upstreams {
+    members: Arc<Vec<Member>>,
+    // the name servers of every upstream, for the catalog's forwarder.
+    name_servers: NameServerConfigGroup,
+    // whether lookups are minimized; see minimize.
+    qname_minimization: bool,
+}
+
+impl Upstreams {
+    // new parses the upstreams and resolves the names of their hosts. The answers they give are
+    // validated with DNSSEC if validate is set.
+    pub async fn new(upstreams: &[String], validate: bool) -> Result<Self, anyhow::Error> {
+        let options = system_options();
+        let mut members = Vec::new();
+        let mut name_servers = NameServerConfigGroup::new();
+
+        for upstream in upstreams {
+            let parsed = Upstream::from_str(upstream)
+                .map_err(|e| anyhow!("Invalid upstream {}: {}", upstream, e))?;
+            let group = parsed.name_servers().await?;
+
+            let config = ResolverConfig::from_parts(None, Vec::new(), group.clone());
+            members.push(Member::new(upstream, config, options, validate)?);
+            name_servers.merge(group);
+        }
+
+        Ok(Self {
+            members: Arc::new(members),
+            name_servers,
+            qname_minimization: false,
+        })
+    }
+
+    // system resolves with the system's resolvers, as a single upstream which is not probed.
+    pub fn system(validate: bool) -> Result<Self, anyhow::Error> {
+        let (config, options) = resolver_config(None)?;
+        let name_servers = NameServerConfigGroup::from(config.name_servers().to_vec());
+
+        Ok(Self {
+            members: Arc::new(vec![Member::new("system", config, options, validate)?]),
+            name_servers,
+            qname_minimization: false,
+        })
+    }
+
+    // with_qname_minimization resolves queries a label at a time; see minimize.
+    pub fn with_qname_minimization(mut self, enabled: bool) -> Self {
+        self.qname_minimization = enabled;
+        self
+    }
+
+    pub fn name_servers(&self) -> &NameServerConfigGroup {
+        &self.name_servers
+    }
+
+    // lookup resolves the query, minimized if the upstreams are set to.
+    pub async fn lookup(
+        &self,
+        name: &LowerName,
+        record_type: RecordType,
+    ) -> Result<Lookup, ResolveError> {
+        let name = Name::from(name);
+
+        if self.qname_minimization {
+            minimize(&name, record_type, |name, record_type| {
+                self.query(name, record_type)
+            })
+            .await
+        } else {
+            self.query(name, record_type).await
+        }
+    }
+
+    // query asks the healthy upstream with the lowest latency, and then the others in turn as
+    // long as they fail to answer. Answers that there are no such records are answers too.
+    async fn query(&self, name: Name, record_type: RecordType) -> Result<Lookup, ResolveError> {
+        let mut result = Err(ResolveError::from("no upstreams"));
+
+        for member in self.ranked() {
+            result = member
+                .resolver
+                .lookup(name.clone(), record_type, DnsRequestOptions::default())
+                .await;
+
+            let mut health = member.health.lock().unwrap();
+            health.stats.queries += 1;
+
+            match &result {
+                Ok(_) => health.success(),
+                Err(e) if matches!(e.kind(), ResolveErrorKind::NoRecordsFound { .. }) => {
+                    health.success()
+                }
+                Err(e) => {
+                    tracing::debug!("Upstream {} failed: {}", health.stats.upstream, e);
+                    health.stats.failures += 1;
+                    health.failure();
+                    continue;
+                }
+            }
+
+            break;
+        }
+
+        result
+    }
+
+    // ranked returns the upstreams in the order they are tried in: the healthy ones first, each
+    // by latency, those which were not probed yet last.
+    fn ranked(&self) -> Vec<&Member> {
+        let mut members: Vec<(&Member, bool, Option<f64>)> = self
+            .members
+            .iter()
+            .map(|member| {
+                let health = member.health.lock().unwrap();
+                (member, health.stats.healthy, health.stats.latency_ms)
+            })
+            .collect();
+
+        members.sort_by(|a, b| {
+            b.1.cmp(&a.1).then_with(|| {
+                a.2.unwrap_or(f64::MAX)
+                    .partial_cmp(&b.2.unwrap_or(f64::MAX))
+                    .unwrap_or(std::cmp::Ordering::Equal)
+            })
+        });
+
+        members.into_iter().map(|(member, _, _)| member).collect()
+    }
+
+    // probe asks every upstream for the root's name servers every PROBE_INTERVAL, measuring their
+    // latency and bringing those which were down back once they answer, and logs their statistics
+    // every REPORT_INTERVAL. It never returns.
+    pub async fn probe(self) {
+        let mut timer = tokio::time::interval(PROBE_INTERVAL);
+        let mut reported = Instant::now();
+
+        loop {
+            timer.tick().await;
+
+            for member in self.members.iter() {
+                let start = Instant::now();
+                let result = tokio::time::timeout(
+                    PROBE_TIMEOUT,
+                    member.prober.lookup(
+                        Name::root(),
+                        RecordType::NS,
+                        DnsRequestOptions::default(),
+                    ),
+                )
+                .await;
+
+                let mut health = member.health.lock().unwrap();
+
+                match result {
+                    Ok(Ok(_)) => {
+                        let latency = start.elapsed().as_secs_f64() * 1000.0;
+                        health.stats.latency_ms = Some(match health.stats.latency_ms {
+                            Some(srtt) => srtt * (1.0 - LATENCY_WEIGHT) + latency * LATENCY_WEIGHT,
+                            None => latency,
+                        });
+                        health.success();
+                    }
+                    Ok(Err(e)) => {
+                        tracing::debug!(
+                            "Probe of upstream {} failed: {}",
+                            health.stats.upstream,
+                            e
+                        );
+                        health.failure();
+                    }
+                    Err(_) => {
+                        tracing::debug!("Probe of upstream {} timed out", health.stats.upstream);
+                        health.failure();
+                    }
+                }
+            }
+
+            if reported.elapsed() >= REPORT_INTERVAL {
+                reported = Instant::now();
+
+                for stats in self.stats() {
+                    tracing::info!(
+                        "Upstream {}: {}, {} latency, {} queries, {} failures",
+                        stats.upstream,
+                        if stats.healthy { "healthy" } else { "down" },
+                        stats
+                            .latency_ms
+                            .map_or("unknown".to_string(), |ms| format!("{:.1}ms", ms)),
+                        stats.queries,
+                        stats.failures
+                    );
+                }
+            }
+        }
+    }
+
+    pub fn stats(&self) -> Vec<UpstreamStats> {
+        self.members
+            .iter()
+            .map(|member| member.health.lock().unwrap().stats.clone())
+            .collect()
+    }
+}
+
+impl Member {
+    fn new(
+        upstream: &str,
+        config: ResolverConfig,
+        options: ResolverOpts,
+        validate: bool,
+    ) -> Result<Self, anyhow::Error> {
+        let mut resolver_options = options;
+        resolver_options.validate = validate;
+
+        let mut probe_options = options;
+        probe_options.cache_size = 0;
+        probe_options.attempts = 1;
+        probe_options.timeout = PROBE_TIMEOUT;
+
+        Ok(Self {
+            resolver: TokioAsyncResolver::tokio(config.clone(), resolver_options)?,
+            prober: TokioAsyncResolver::tokio(config, probe_options)?,
+            health: Mutex::new(Health {
+                stats: UpstreamStats::new(upstream),
+                failures: 0,
+            }),
+        })
+    }
+}
+
+// minimize resolves a query with QNAME minimization (RFC 7816): the ancestors of the name are asked
+// for their NS records from the top-level domain down, one label added at a time, and the name
+// itself for the type of the query last. An ancestor answered with NXDOMAIN has nothing under it
+// (RFC 8020), so the name is answered with NXDOMAIN without being asked for; ancestors not
+// answered at all, as from broken servers, end the minimized queries, and the whole name is asked
+// for at once. The upstreams are recursive resolvers, which see the whole name of every query
+// which gets that far: minimizing hides nothing from them, only names under ancestors which do
+// not exist, and it is up to them to minimize what they ask the authoritative servers.
+async fn minimize<F, Fut>(
+    name: &Name,
+    record_type: RecordType,
+    query: F,
+) -> Result<Lookup, ResolveError>
+where
+    F: Fn(Name, RecordType) -> Fut,
+    Fut: Future<Output = Result<Lookup, ResolveError>>,
+{
+    let labels = name.num_labels() as usize;
+
+    for count in (1..labels).take(MAX_MINIMIZE_COUNT) {
+        let ancestor = name.trim_to(count);
+
+        match query(ancestor.clone(), RecordType::NS).await {
+            Ok(_) => {}
+            Err(e)
+                if matches!(
+                    e.kind(),
+                    ResolveErrorKind::NoRecordsFound {
+                        response_code: ResponseCode::NoError,
+                        ..
+                    }
+                ) => {}
+            Err(e)
+                if matches!(
+                    e.kind(),
+                    ResolveErrorKind::NoRecordsFound {
+                        response_code: ResponseCode::NXDomain,
+                        ..
+                    }
+                ) =>
+            {
+                return Err(e)
+            }
+            Err(e) => {
+                tracing::debug!(
+                    "Minimized query for {} failed ({}); asking for {} instead",
+                    ancestor,
+                    e,
+                    name
+                );
+                break;
+            }
+        }
+    }
+
+    query(name.clone(), record_type).await
+}
+
+// system_options are the resolver options of the system, or the defaults if it has none.
+fn system_options() -> ResolverOpts {
+    trust_dns_resolver::system_conf::read_system_conf()
+        .map(|(_, options)| options)
+        .unwrap_or_default()
+}
+
+// resolver_config returns the resolvers forwarded queries are sent to: the upstreams, if any are
+// configured, or else those of the system, with the system's resolver options.
+pub fn resolver_config(
+    upstreams: Option<&NameServerConfigGroup>,
+) -> Result<(ResolverConfig, ResolverOpts), anyhow::Error> {
+    match upstreams {
+        Some(upstreams) => Ok((
+            ResolverConfig::from_parts(None, Vec::new(), upstreams.clone()),
+            system_options(),
+        )),
+        None => Ok(trust_dns_resolver::system_conf::read_system_conf()?),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{minimize, Upstream, Upstreams, MAX_FAILURES, MAX_MINIMIZE_COUNT};
+    use std::{str::FromStr, sync::Mutex};
+    use trust_dns_resolver::{
+        config::Protocol,
+        error::{ResolveError, ResolveErrorKind},
+        lookup::Lookup,
+        proto::{
+            op::{Query, ResponseCode},
+            rr::{RData, RecordType},
+        },
+        Name,
+    };
+
+    #[test]
+    fn test_parse_upstream() {
+        let upstream = |protocol, plain, host: &str, port, tls_name: Option<&str>| Upstream {
+            protocol,
+            plain,
+            host: host.to_string(),
+            port,
+            tls_name: tls_name.map(ToString::to_string),
+        };
+
+        for (s, expected) in [
+            (
+                "10.0.0.1",
+                upstream(Protocol::Udp, true, "10.0.0.1", 53, None),
+            ),
+            (
+                "10.0.0.1:5353",
+                upstream(Protocol::Udp, true, "10.0.0.1", 5353, None),
+            ),
+            (
+                "2606:4700::1111",
+                upstream(Protocol::Udp, true, "2606:4700::1111", 53, None),
+            ),
+            (
+                "tcp://10.0.0.1",
+                upstream(Protocol::Tcp, false, "10.0.0.1", 53, None),
+            ),
+            (
+                "tls://[2606:4700::1111]:8853#cloudflare-dns.com",
+                upstream(
+                    Protocol::Tls,
+                    false,
+                    "2606:4700::1111",
+                    8853,
+                    Some("cloudflare-dns.com"),
+                ),
+            ),
+            (
+                "tls://1.1.1.1#cloudflare-dns.com",
+                upstream(
+                    Protocol::Tls,
+                    false,
+                    "1.1.1.1",
+                    853,
+                    Some("cloudflare-dns.com"),
+                ),
+            ),
+            (
+                "tls://dns.quad9.net",
+                upstream(
+                    Protocol::Tls,
+                    false,
+                    "dns.quad9.net",
+                    853,
+                    Some("dns.quad9.net"),
+                ),
+            ),
+            (
+                "https://dns.google/dns-query",
+                upstream(
+                    Protocol::Https,
+                    false,
+                    "dns.google",
+                    443,
+                    Some("dns.google"),
+                ),
+            ),
+            (
+                "https://dns.google:8443",
+                upstream(
+                    Protocol::Https,
+                    false,
+                    "dns.google",
+                    8443,
+                    Some("dns.google"),
+                ),
+            ),
+        ] {
+            assert_eq!(Upstream::from_str(s).unwrap(), expected, "{}", s);
+        }
+
+        for s in [
+            "quic://dns.example.com",
+            "tls://1.1.1.1",
+            "https://dns.google/resolve",
+            "tcp://10.0.0.1/dns-query",
+            "10.0.0.1#dns.example.com",
+            "tls://:853",
+            "dns.example.com:domain",
+        ] {
+            assert!(Upstream::from_str(s).is_err(), "{}", s);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_upstream_failover() {
+        let upstreams = Upstreams::new(
+            &[
+                "10.0.0.1".to_string(),
+                "tcp://10.0.0.2".to_string(),
+                "tls://10.0.0.3#dns.example.com".to_string(),
+            ],
+            false,
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(upstreams.name_servers().len(), 4);
+
+        let ranked = |upstreams: &Upstreams| -> Vec<String> {
+            upstreams
+                .ranked()
+                .iter()
+                .map(|member| member.health.lock().unwrap().stats.upstream.clone())
+                .collect()
+        };
+
+        // upstreams which were not probed yet come last, in the order they were given in.
+        upstreams.members[1].health.lock().unwrap().stats.latency_ms = Some(20.0);
+        upstreams.members[2].health.lock().unwrap().stats.latency_ms = Some(10.0);
+        assert_eq!(
+            ranked(&upstreams),
+            vec![
+                "tls://10.0.0.3#dns.example.com",
+                "tcp://10.0.0.2",
+                "10.0.0.1"
+            ]
+        );
+
+        // the fastest upstream is only failed over from once it fails a few times in a row.
+        for _ in 1..MAX_FAILURES {
+            upstreams.members[2].health.lock().unwrap().failure();
+        }
+        assert!(upstreams.stats()[2].healthy);
+
+        upstreams.members[2].health.lock().unwrap().failure();
+        assert!(!upstreams.stats()[2].healthy);
+        assert_eq!(
+            ranked(&upstreams),
+            vec![
+                "tcp://10.0.0.2",
+                "10.0.0.1",
+                "tls://10.0.0.3#dns.example.com"
+            ]
+        );
+
+        // and recovers once it answers again.
+        upstreams.members[2].health.lock().unwrap().success();
+        assert!(upstreams.stats()[2].healthy);
+        assert_eq!(ranked(&upstreams)[0], "tls://10.0.0.3#dns.example.com");
+    }
+
+    #[tokio::test]
+    async fn test_minimize() {
+        let name = Name::from_str("db.corp.example.com.").unwrap();
+
+        // asks for the names, answering NXDOMAIN for those given, and failing for those under
+        // broken servers.
+        let resolve = |nxdomain: &'static [&'static str], broken: &'static [&'static str]| {
+            let (name, asked) = (&name, Mutex::new(Vec::new()));
+
+            async move {
+                let result = minimize(name, RecordType::A, |name, record_type| {
+                    asked
+                        .lock()
+                        .unwrap()
+                        .push(format!("{} {}", name, record_type));
+                    let query = Query::query(name.clone(), record_type);
+
+                    let result: Result<Lookup, ResolveError> =
+                        if nxdomain.contains(&name.to_string().as_str()) {
+                            Err(ResolveErrorKind::NoRecordsFound {
+                                query: Box::new(query),
+                                soa: None,
+                                negative_ttl: None,
+                                response_code: ResponseCode::NXDomain,
+                                trusted: true,
+                            }
+                            .into())
+                        } else if broken.contains(&name.to_string().as_str()) {
+                            Err(ResolveErrorKind::Timeout.into())
+                        } else if record_type == RecordType::NS && name.num_labels() == 2 {
+                            // the only zone cut below the top-level domain.
+                            Ok(Lookup::from_rdata(query, RData::NS(name)))
+                        } else if record_type == RecordType::NS {
+                            Err(ResolveErrorKind::NoRecordsFound {
+                                query: Box::new(query),
+                                soa: None,
+                                negative_ttl: None,
+                                response_code: ResponseCode::NoError,
+                                trusted: true,
+                            }
+                            .into())
+                        } else {
+                            Ok(Lookup::from_rdata(
+                                query,
+                                RData::A("10.0.0.1".parse().unwrap()),
+                            ))
+                        };
+
+                    async move { result }
+                })
+                .await;
+
+                (result.is_ok(), asked.into_inner().unwrap())
+            }
+        };
+
+        // a label at a time, through zone cuts and names without records of their own.
+        assert_eq!(
+            resolve(&[], &[]).await,
+            (
+                true,
+                vec![
+                    "com. NS".to_string(),
+                    "example.com. NS".to_string(),
+                    "corp.example.com. NS".to_string(),
+                    "db.corp.example.com. A".to_string(),
+                ]
+            )
+        );
+
+        // NXDOMAIN for an ancestor is the answer, and the whole name is never asked for.
+        assert_eq!(
+            resolve(&["corp.example.com."], &[]).await,
+            (
+                false,
+                vec![
+                    "com. NS".to_string(),
+                    "example.com. NS".to_string(),
+                    "corp.example.com. NS".to_string(),
+                ]
+            )
+        );
+
+        // servers failing get the whole name instead.
+        assert_eq!(
+            resolve(&[], &["com."]).await,
+            (
+                true,
+                vec!["com. NS".to_string(), "db.corp.example.com. A".to_string()]
+            )
+        );
+        assert!(!resolve(&["db.corp.example.com."], &[]).await.0);
+
+        // names with many labels only have the first few added one at a time.
+        let long = Name::from_str("a.b.c.d.e.f.g.h.i.j.k.l.example.com.").unwrap();
+        let asked = Mutex::new(0);
+        minimize(&long, RecordType::A, |name, _| {
+            *asked.lock().unwrap() += 1;
+            async move {
+                Ok(Lookup::from_rdata(
+                    Query::query(name.clone(), RecordType::NS),
+                    RData::NS(name),
+                ))
+            }
+        })
+        .await
+        .unwrap();
+        assert_eq!(asked.into_inner().unwrap(), MAX_MINIMIZE_COUNT + 1);
+    }
+}
@@ -1,13 +1,25 @@
-use std::{net::IpAddr, path::Path, str::FromStr, sync::Once};
+use std::{
+    collections::HashMap,
+    net::{IpAddr, SocketAddr},
+    path::{Path, PathBuf},
+    str::FromStr,
+    sync::{Mutex, Once},
+    time::Duration,
+};
 
 use ipnetwork::IpNetwork;
+use lazy_static::lazy_static;
 use reqwest::header::{HeaderMap, HeaderValue};
-use tracing::warn;
+use serde::{Deserialize, Serialize};
+use tracing::{debug, warn};
 use trust_dns_server::client::rr::{LowerName, Name};
 
 use anyhow::anyhow;
 
-use crate::traits::ToHostname;
+use crate::{
+    init::{ConfigFormat, Launcher},
+    traits::ToHostname,
+};
 
 // collections of test hosts files
 pub const TEST_HOSTS_DIR: &str = "testdata/hosts-files";
@@ -17,6 +29,29 @@ pub const DOMAIN_NAME: &str = "home.arpa.";
 pub const VERSION_STRING: &str = env!("CARGO_PKG_VERSION");
 // address of Central
 pub const CENTRAL_BASEURL: &str = "https://my.zerotier.com/api/v1";
+// default number of seconds a request to Central may take, including reading the response
+pub const DEFAULT_CENTRAL_TIMEOUT: u64 = 30;
+// default number of seconds to wait for a connection to Central
+pub const DEFAULT_CENTRAL_CONNECT_TIMEOUT: u64 = 10;
+// default number of seconds between polls of Central
+pub const DEFAULT_POLL_INTERVAL: u64 = 30;
+// default TTL of the records served, in seconds
+pub const DEFAULT_TTL: u32 = 60;
+// default SOA timers, in seconds; the SOA minimum defaults to the TTL
+pub const DEFAULT_SOA_REFRESH: i32 = 30;
+pub const DEFAULT_SOA_RETRY: i32 = 10;
+pub const DEFAULT_SOA_EXPIRE: i32 = 604800;
+// number of seconds queries already being answered are given to finish when shutting down
+pub const SHUTDOWN_GRACE_PERIOD: u64 = 2;
+// longest wait between checks for an address with --wait-for-network, in seconds
+pub const WAIT_FOR_NETWORK_MAX_BACKOFF: u64 = 30;
+// default number of milliseconds a TCP connection is kept open waiting for a request
+pub const DEFAULT_TCP_TIMEOUT: u64 = 1000;
+// port DNS is served on unless --listen-port is given
+pub const DNS_PORT: u16 = 53;
+// service and account the Central token is kept under in the OS credential store
+pub const KEYCHAIN_SERVICE: &str = "zeronsd";
+pub const KEYCHAIN_ACCOUNT: &str = "central-token";
 // address of local zerotier instance
 pub const ZEROTIER_LOCAL_URL: &str = "http://127.0.0.1:9993";
 
@@ -29,6 +64,12 @@ static LOGGER: Once = Once::new();
 
 // initializes a logger
 pub fn init_logger(level: Option<tracing::Level>) {
+    init_logger_with_format(level, crate::log::LogFormat::Text)
+}
+
+// initializes a logger with the provided output format. JSON output includes the fields of any
+// spans, such as the network_id of the network being served.
+pub fn init_logger_with_format(level: Option<tracing::Level>, format: crate::log::LogFormat) {
     LOGGER.call_once(|| {
         let loglevel = std::env::var("ZERONSD_LOG").or_else(|_| std::env::var("RUST_LOG"));
 
@@ -43,34 +84,116 @@ pub fn init_logger(level: Option<tracing::Level>) {
         tracing_log::log_tracer::LogTracer::init().expect("initializing logger failed");
 
         if let Some(level) = level {
-            let subscriber = tracing_subscriber::FmtSubscriber::builder()
+            let builder = tracing_subscriber::FmtSubscriber::builder()
                 // all spans/events with a level higher than TRACE (e.g, debug, info, warn, etc.)
                 // will be written to stdout.
-                .with_max_level(level)
-                // completes the builder.
-                .finish();
+                .with_max_level(level);
+
+            let res = match format {
+                crate::log::LogFormat::Text => {
+                    tracing::subscriber::set_global_default(builder.finish())
+                }
+                crate::log::LogFormat::JSON => {
+                    tracing::subscriber::set_global_default(builder.json().finish())
+                }
+            };
 
-            tracing::subscriber::set_global_default(subscriber)
-                .expect("setting default subscriber failed");
+            res.expect("setting default subscriber failed");
+        }
+    })
+}
+
+// init_event_logger sends logs to the Windows event log, under the given source, instead of
+// stdout. It must be called before init_logger, which then has no effect.
+#[cfg(target_os = "windows")]
+pub fn init_event_logger(source: &str, level: crate::log::LevelFilter) {
+    LOGGER.call_once(|| {
+        // without a tracing subscriber, tracing hands its events to the `log` crate.
+        if let Some(level) = level.to_event_log() {
+            if let Err(e) = eventlog::init(source, level) {
+                eprintln!("Could not initialize the event log: {}", e);
+            }
         }
     })
 }
 
 // this provides the production configuration for talking to central through the openapi libraries.
 pub fn central_client(token: String) -> Result<zerotier_central_api::Client, anyhow::Error> {
+    central_client_with_url(token, None)
+}
+
+// central_client_with_url talks to the given instance of Central, such as a self-hosted
+// controller, instead of my.zerotier.com or ZEROTIER_CENTRAL_INSTANCE. Plain HTTP is only allowed
+// when the URL asks for it.
+pub fn central_client_with_url(
+    token: String,
+    url: Option<&str>,
+) -> Result<zerotier_central_api::Client, anyhow::Error> {
+    central_client_with_options(
+        token,
+        &CentralOptions {
+            url: url.map(ToString::to_string),
+            ..Default::default()
+        },
+    )
+}
+
+// CentralOptions are the settings of the HTTP client every request to Central goes through.
+#[derive(Debug, Clone, Default)]
+pub struct CentralOptions {
+    pub url: Option<String>,
+    pub timeout: Option<Duration>,
+    pub connect_timeout: Option<Duration>,
+    // proxy for both http and https URLs; without it, HTTP_PROXY, HTTPS_PROXY and NO_PROXY from
+    // the environment are used.
+    pub proxy: Option<String>,
+}
+
+// central_client_with_options builds a Central client which gives up on requests after the
+// configured timeouts, so that a proxy or firewall silently dropping them cannot stall zeronsd.
+pub fn central_client_with_options(
+    token: String,
+    options: &CentralOptions,
+) -> Result<zerotier_central_api::Client, anyhow::Error> {
     let mut headers = HeaderMap::new();
     headers.insert(
         "Authorization",
         HeaderValue::from_str(&format!("bearer {}", token))?,
     );
 
+    let url = match &options.url {
+        Some(url) => url.to_string(),
+        None => std::env::var("ZEROTIER_CENTRAL_INSTANCE").unwrap_or(CENTRAL_BASEURL.to_string()),
+    };
+
+    let parsed =
+        reqwest::Url::parse(&url).map_err(|e| anyhow!("Invalid Central URL {}: {}", url, e))?;
+
+    let mut builder = reqwest::Client::builder()
+        .user_agent(version())
+        .https_only(parsed.scheme() != "http")
+        .default_headers(headers)
+        .timeout(
+            options
+                .timeout
+                .unwrap_or(Duration::new(DEFAULT_CENTRAL_TIMEOUT, 0)),
+        )
+        .connect_timeout(
+            options
+                .connect_timeout
+                .unwrap_or(Duration::new(DEFAULT_CENTRAL_CONNECT_TIMEOUT, 0)),
+        );
+
+    if let Some(proxy) = &options.proxy {
+        builder = builder.proxy(
+            reqwest::Proxy::all(proxy.as_str())
+                .map_err(|e| anyhow!("Invalid Central proxy {}: {}", proxy, e))?,
+        );
+    }
+
     Ok(zerotier_central_api::Client::new_with_client(
-        &std::env::var("ZEROTIER_CENTRAL_INSTANCE").unwrap_or(CENTRAL_BASEURL.to_string()),
-        reqwest::Client::builder()
-            .user_agent(version())
-            .https_only(true)
-            .default_headers(headers)
-            .build()?,
+        url.trim_end_matches('/'),
+        builder.build()?,
     ))
 }
 
@@ -81,11 +204,54 @@ pub fn parse_ip_from_cidr(ip_with_cidr: String) -> IpAddr {
         .ip()
 }
 
+// strips the host portion of a CIDR so that all addresses in the same network compare equal.
+// 10.0.0.1/24 becomes 10.0.0.0/24
+pub fn network_from_cidr(cidr: IpNetwork) -> Result<IpNetwork, anyhow::Error> {
+    Ok(IpNetwork::new(cidr.network(), cidr.prefix())?)
+}
+
+// parse_dns_server parses a nameserver given as `ip` or `ip:port`; port 53 is assumed when
+// omitted.
+pub fn parse_dns_server(server: &str) -> Result<SocketAddr, anyhow::Error> {
+    match SocketAddr::from_str(server) {
+        Ok(addr) => Ok(addr),
+        Err(_) => Ok(SocketAddr::new(IpAddr::from_str(server)?, 53)),
+    }
+}
+
+// parses the stub zones from the configuration. Each zone maps to a list of upstreams given as
+// `ip` or `ip:port`; port 53 is assumed when omitted.
+pub fn parse_forward_zones(
+    zones: &HashMap<String, Vec<String>>,
+) -> Result<HashMap<Name, Vec<SocketAddr>>, anyhow::Error> {
+    let mut res = HashMap::new();
+
+    for (zone, servers) in zones {
+        let name = domain_or_default(Some(zone.trim_end_matches('.')))?;
+        let mut addrs = Vec::new();
+
+        for server in servers {
+            addrs
+                .push(parse_dns_server(server).map_err(|e| {
+                    anyhow!("Invalid upstream {} for zone {}: {}", server, zone, e)
+                })?);
+        }
+
+        if addrs.is_empty() {
+            return Err(anyhow!("Zone {} has no upstreams to forward to", zone));
+        }
+
+        res.insert(name, addrs);
+    }
+
+    Ok(res)
+}
+
 // load and prepare the central API token
 pub fn central_token(arg: Option<&Path>) -> Result<String, anyhow::Error> {
     if let Some(path) = arg {
         return Ok(std::fs::read_to_string(path)
-            .expect("Could not load token file")
+            .map_err(|e| anyhow!("Could not load token file {}: {}", path.display(), e))?
             .trim()
             .to_string());
     }
@@ -99,6 +265,98 @@ pub fn central_token(arg: Option<&Path>) -> Result<String, anyhow::Error> {
     return Err(anyhow!("missing zerotier central token: set ZEROTIER_CENTRAL_TOKEN in environment, or pass a file containing it with -t"));
 }
 
+// load_config reads the configuration file every subcommand taking one is given.
+pub fn load_config(path: &Path, format: ConfigFormat) -> Result<Launcher, anyhow::Error> {
+    std::fs::read_to_string(path)
+        .map_err(anyhow::Error::from)
+        .and_then(|s| Launcher::parse_format(&s, format))
+        .map_err(|e| {
+            anyhow!(
+                "Could not load configuration file {}: {}",
+                path.display(),
+                e
+            )
+        })
+}
+
+// check_token_permissions refuses a token file which other users than its owner may access, as
+// anyone who can read the token can manage the network in Central. Windows relies on the ACLs of
+// the directory holding the file instead.
+pub fn check_token_permissions(path: &Path) -> Result<(), anyhow::Error> {
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+
+        let mode = std::fs::metadata(path)
+            .map_err(|e| anyhow!("Could not stat token file {}: {}", path.display(), e))?
+            .permissions()
+            .mode();
+
+        if mode & 0o077 != 0 {
+            return Err(anyhow!(
+                "Token file {} is accessible by other users (mode {:o}); run `chmod 600 {}`, or pass --insecure-token-permissions",
+                path.display(),
+                mode & 0o777,
+                path.display()
+            ));
+        }
+    }
+
+    Ok(())
+}
+
+lazy_static! {
+    // standard input can only be read once, so the token read from it is kept for later clients.
+    static ref STDIN_TOKEN: Mutex<Option<String>> = Mutex::new(None);
+}
+
+// TokenSource is where the Central token is read from; see `Launcher::token_source`.
+#[derive(Debug, Clone, PartialEq)]
+pub enum TokenSource {
+    // a file, given with -t; read again whenever the token is reloaded.
+    File(PathBuf),
+    // the first line of standard input, read once.
+    Stdin,
+    // the OS credential store: Secret Service, macOS Keychain or Windows Credential Manager.
+    Keychain,
+    // ZEROTIER_CENTRAL_TOKEN.
+    Environment,
+}
+
+impl TokenSource {
+    pub fn read(&self) -> Result<String, anyhow::Error> {
+        match self {
+            Self::File(path) => central_token(Some(path)),
+            Self::Stdin => {
+                let mut token = STDIN_TOKEN.lock().unwrap();
+
+                if token.is_none() {
+                    let mut line = String::new();
+                    std::io::stdin().read_line(&mut line)?;
+                    *token = Some(line.trim().to_string());
+                }
+
+                match token.as_deref() {
+                    Some("") | None => Err(anyhow!("No Central token was given on standard input")),
+                    Some(token) => Ok(token.to_string()),
+                }
+            }
+            Self::Keychain => keyring::Entry::new(KEYCHAIN_SERVICE, KEYCHAIN_ACCOUNT)
+                .get_password()
+                .map(|token| token.trim().to_string())
+                .map_err(|e| {
+                    anyhow!(
+                        "Could not read the Central token from the credential store (service {}, account {}): {}",
+                        KEYCHAIN_SERVICE,
+                        KEYCHAIN_ACCOUNT,
+                        e
+                    )
+                }),
+            Self::Environment => central_token(None),
+        }
+    }
+}
+
 // determine the path of the authtoken.secret
 pub fn authtoken_path(arg: Option<&Path>) -> &Path {
     if let Some(arg) = arg {
@@ -129,13 +387,122 @@ pub fn domain_or_default(tld: Option<&str>) -> Result<Name, anyhow::Error> {
     Ok(Name::from_str(DOMAIN_NAME)?)
 }
 
-// parse_member_name ensures member names are DNS compliant
-pub fn parse_member_name(name: Option<String>, domain_name: Name) -> Option<Name> {
+// domain_from_network_name turns the name of a network into a domain, the way member names are
+// turned into hostnames. "Home Lab" becomes home-lab.
+pub fn domain_from_network_name(name: &str) -> Result<Name, anyhow::Error> {
+    let hostname = name.to_hostname()?.to_string().to_lowercase();
+    domain_or_default(Some(hostname.trim_end_matches('.')))
+}
+
+// NamePolicy is how the names of members, and their aliases, which are not valid hostnames are
+// turned into ones.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum NamePolicy {
+    // whitespace becomes `-` and other punctuation is dropped, while letters outside ASCII are
+    // kept, encoded as punycode: "Erik's laptop" becomes Eriks-laptop, "Büro" xn--bro-hoa.
+    // Names with `_` are not valid.
+    #[serde(alias = "punycode")]
+    Idna,
+    // every run of characters other than ASCII letters, digits and `-` becomes `-`: "Erik's
+    // laptop" becomes Erik-s-laptop, "Büro" B-ro.
+    Dash,
+    // characters other than ASCII letters, digits and `-` are dropped: "Erik's laptop" becomes
+    // Erikslaptop, "Büro" Bro.
+    Strip,
+    // names with characters other than ASCII letters, digits and `-` get no record.
+    Reject,
+}
+
+impl Default for NamePolicy {
+    fn default() -> Self {
+        NamePolicy::Idna
+    }
+}
+
+impl ToString for NamePolicy {
+    fn to_string(&self) -> String {
+        match self {
+            NamePolicy::Idna => "idna",
+            NamePolicy::Dash => "dash",
+            NamePolicy::Strip => "strip",
+            NamePolicy::Reject => "reject",
+        }
+        .to_string()
+    }
+}
+
+impl FromStr for NamePolicy {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "idna" | "punycode" => Ok(Self::Idna),
+            "dash" => Ok(Self::Dash),
+            "strip" => Ok(Self::Strip),
+            "reject" => Ok(Self::Reject),
+            _ => Err(anyhow!(
+                "invalid policy: allowed values: [idna, punycode, dash, strip, reject]"
+            )),
+        }
+    }
+}
+
+impl NamePolicy {
+    // to_hostname turns the name into a hostname under the policy. Labels are kept as they are
+    // given, dots included.
+    pub fn to_hostname(&self, name: &str) -> Result<Name, anyhow::Error> {
+        let valid = |c: char| c.is_ascii_alphanumeric() || c == '-';
+
+        let name = match self {
+            NamePolicy::Idna => name.to_string(),
+            NamePolicy::Dash => name
+                .split('.')
+                .map(|label| {
+                    label
+                        .split(|c| !valid(c))
+                        .filter(|part| !part.is_empty())
+                        .collect::<Vec<_>>()
+                        .join("-")
+                })
+                .collect::<Vec<_>>()
+                .join("."),
+            NamePolicy::Strip => name.chars().filter(|&c| valid(c) || c == '.').collect(),
+            NamePolicy::Reject => {
+                if let Some(c) = name.chars().find(|&c| !valid(c) && c != '.') {
+                    return Err(anyhow!("{:?} is not allowed in names", c));
+                }
+
+                name.to_string()
+            }
+        };
+
+        name.to_hostname()
+    }
+}
+
+// parse_member_name ensures member names are DNS compliant, turning them into hostnames under the
+// policy. Names which are changed on the way are logged.
+pub fn parse_member_name(
+    name: Option<String>,
+    domain_name: Name,
+    policy: NamePolicy,
+) -> Option<Name> {
     if let Some(name) = name {
         let name = name.trim();
         if !name.is_empty() {
-            match name.to_fqdn(domain_name) {
-                Ok(record) => return Some(record),
+            match policy.to_hostname(name) {
+                Ok(hostname) => {
+                    if !hostname.to_ascii().eq_ignore_ascii_case(name) {
+                        debug!(
+                            "Member name {:?} is served as {}",
+                            name,
+                            hostname.to_ascii()
+                        );
+                    }
+
+                    return Some(hostname.append_domain(&domain_name).unwrap());
+                }
                 Err(e) => {
                     warn!("Record {} not entered into catalog: {:?}", name, e);
                     return None;
@@ -147,6 +514,23 @@ pub fn parse_member_name(name: Option<String>, domain_name: Name) -> Option<Name
     None
 }
 
+// parse_member_aliases finds the `aliases=` entry in a member description, e.g.
+// `aliases=db,primary-db`, and returns the aliases as names under the domain. The rest of the
+// description is left alone, so it can still be used for other notes.
+pub fn parse_member_aliases(
+    description: Option<String>,
+    domain_name: Name,
+    policy: NamePolicy,
+) -> Vec<Name> {
+    description
+        .unwrap_or_default()
+        .split_whitespace()
+        .filter_map(|word| word.strip_prefix("aliases="))
+        .flat_map(|aliases| aliases.split(','))
+        .filter_map(|alias| parse_member_name(Some(alias.to_string()), domain_name.clone(), policy))
+        .collect()
+}
+
 pub async fn get_member_name(
     authtoken_path: &Path,
     domain_name: Name,
@@ -164,7 +548,17 @@ pub async fn get_member_name(
     ))
 }
 
-fn local_client_from_file(
+// get_node_id is the ZeroTier address of the member zeronsd runs on.
+pub async fn get_node_id(client: &zerotier_one_api::Client) -> Result<String, anyhow::Error> {
+    client
+        .get_status()
+        .await?
+        .address
+        .clone()
+        .ok_or_else(|| anyhow!("zerotier-one did not report the address of this member"))
+}
+
+pub fn local_client_from_file(
     authtoken_path: &Path,
     local_url: String,
 ) -> Result<zerotier_one_api::Client, anyhow::Error> {
@@ -195,8 +589,19 @@ pub async fn get_listen_ips(
     network_id: &str,
     local_url: String,
 ) -> Result<Vec<String>, anyhow::Error> {
-    let client = local_client_from_file(authtoken_path, local_url)?;
+    assigned_ips(
+        &local_client_from_file(authtoken_path, local_url)?,
+        network_id,
+    )
+    .await
+}
 
+// assigned_ips is get_listen_ips with an existing client, for callers which poll it and may no
+// longer be able to read authtoken.secret.
+pub async fn assigned_ips(
+    client: &zerotier_one_api::Client,
+    network_id: &str,
+) -> Result<Vec<String>, anyhow::Error> {
     match client.get_network(network_id).await {
         Err(error) => Err(anyhow!(
             "Error: {}. Are you joined to {}?",
@@ -214,6 +619,66 @@ pub async fn get_listen_ips(
     }
 }
 
+// wait_for_assigned_ips is assigned_ips, retried with exponential backoff until it succeeds or
+// the timeout has passed. At boot, zeronsd is often started before zerotier-one is up or has been
+// assigned an address on the network.
+pub async fn wait_for_assigned_ips(
+    client: &zerotier_one_api::Client,
+    network_id: &str,
+    timeout: std::time::Duration,
+) -> Result<Vec<String>, anyhow::Error> {
+    let deadline = tokio::time::Instant::now() + timeout;
+    let mut backoff = std::time::Duration::new(1, 0);
+
+    loop {
+        match assigned_ips(client, network_id).await {
+            Ok(ips) => return Ok(ips),
+            Err(e) => {
+                let now = tokio::time::Instant::now();
+                if now >= deadline {
+                    return Err(anyhow!(
+                        "Gave up waiting for an address on network {}: {}",
+                        network_id,
+                        e
+                    ));
+                }
+
+                let wait = backoff.min(deadline - now);
+                warn!("{}; checking again in {}s", e, wait.as_secs_f32());
+                tokio::time::sleep(wait).await;
+
+                backoff =
+                    (backoff * 2).min(std::time::Duration::new(WAIT_FOR_NETWORK_MAX_BACKOFF, 0));
+            }
+        }
+    }
+}
+
+// ZeroTier members use at most this many of the DNS servers of their network.
+const MAX_DNS_SERVERS: usize = 4;
+
+// dns_servers are the addresses of this member pushed to Central as the network's DNS servers:
+// its first IPv4 and first IPv6 address, so that members with only one of the two can reach it,
+// then the others, up to as many as members use.
+pub fn dns_servers(ips: &[IpAddr]) -> Vec<String> {
+    let mut servers: Vec<IpAddr> = ips
+        .iter()
+        .find(|ip| ip.is_ipv4())
+        .into_iter()
+        .chain(ips.iter().find(|ip| ip.is_ipv6()))
+        .cloned()
+        .collect();
+
+    for ip in ips {
+        if !servers.contains(ip) {
+            servers.push(*ip);
+        }
+    }
+
+    servers.truncate(MAX_DNS_SERVERS);
+    servers.iter().map(ToString::to_string).collect()
+}
+
 // update_central_dns pushes the search records
 pub async fn update_central_dns(
     domain_name: Name,
@@ -239,3 +704,187 @@ pub async fn update_central_dns(
 
     Ok(())
 }
+
+// clear_central_dns removes the DNS settings pushed by update_central_dns, so members stop
+// sending queries to a nameserver that is no longer running.
+pub async fn clear_central_dns(
+    client: zerotier_central_api::Client,
+    network: String,
+) -> Result<(), anyhow::Error> {
+    let mut zt_network = client.get_network_by_id(&network).await?;
+
+    if let Some(mut zt_network_config) = zt_network.config.to_owned() {
+        zt_network_config.dns = Some(zerotier_central_api::types::Dns {
+            domain: Some(String::new()),
+            servers: Some(Vec::new()),
+        });
+        zt_network.config = Some(zt_network_config);
+        client.update_network(&network, &zt_network).await?;
+    }
+
+    Ok(())
+}
+
+// wait_for_shutdown resolves when the process is asked to stop, either through SIGINT (Ctrl+C)
+// or, on unix platforms, SIGTERM.
+pub async fn wait_for_shutdown() -> Result<(), anyhow::Error> {
+    #[cfg(unix)]
+    {
+        use tokio::signal::unix::{signal, SignalKind};
+
+        let mut sigterm = signal(SignalKind::terminate())?;
+
+        tokio::select! {
+            res = tokio::signal::ctrl_c() => res?,
+            _ = sigterm.recv() => {}
+        }
+    }
+
+    #[cfg(not(unix))]
+    tokio::signal::ctrl_c().await?;
+
+    Ok(())
+}
+
+// init_runtime builds the runtime zeronsd runs on: one thread per core unless worker_threads says
+// otherwise, or a single thread running everything for a worker_threads of 1. max_blocking_threads
+// caps the threads blocking work, such as file reads, is moved to.
+pub fn init_runtime(
+    worker_threads: Option<usize>,
+    max_blocking_threads: Option<usize>,
+) -> Result<tokio::runtime::Runtime, anyhow::Error> {
+    if worker_threads == Some(0) || max_blocking_threads == Some(0) {
+        return Err(anyhow!(
+            "worker_threads and max_blocking_threads must be at least 1"
+        ));
+    }
+
+    let mut builder = match worker_threads {
+        Some(1) => tokio::runtime::Builder::new_current_thread(),
+        Some(threads) => {
+            let mut builder = tokio::runtime::Builder::new_multi_thread();
+            builder.worker_threads(threads);
+            builder
+        }
+        None => tokio::runtime::Builder::new_multi_thread(),
+    };
+
+    if let Some(threads) = max_blocking_threads {
+        builder.max_blocking_threads(threads);
+    }
+
+    Ok(builder.enable_all().build()?)
+}
+
+// notify_ready tells systemd, when zeronsd is run as a Type=notify service, that the listeners are
+// up. Outside of systemd this does nothing.
+pub fn notify_ready() {
+    #[cfg(target_os = "linux")]
+    if let Err(e) = sd_notify::notify(false, &[sd_notify::NotifyState::Ready]) {
+        warn!("Could not notify systemd of startup: {}", e);
+    }
+}
+
+// watchdog_interval returns how often systemd expects to be pinged, if its watchdog is enabled for
+// this service. Pings are sent at twice the rate systemd requires.
+pub fn watchdog_interval() -> Option<std::time::Duration> {
+    #[cfg(target_os = "linux")]
+    {
+        let mut usec = 0;
+        if sd_notify::watchdog_enabled(false, &mut usec) {
+            return Some(std::time::Duration::from_micros(usec / 2));
+        }
+    }
+
+    None
+}
+
+// notify_watchdog pings the systemd watchdog; see watchdog_interval.
+pub fn notify_watchdog() {
+    #[cfg(target_os = "linux")]
+    if let Err(e) = sd_notify::notify(false, &[sd_notify::NotifyState::Watchdog]) {
+        warn!("Could not ping the systemd watchdog: {}", e);
+    }
+}
+
+// wait_for_reload_signal resolves when SIGHUP or SIGUSR1 is received. These signals do not exist
+// on other platforms, where it never resolves.
+pub async fn wait_for_reload_signal() -> Result<(), anyhow::Error> {
+    #[cfg(unix)]
+    {
+        use tokio::signal::unix::{signal, SignalKind};
+
+        let mut sighup = signal(SignalKind::hangup())?;
+        let mut sigusr1 = signal(SignalKind::user_defined1())?;
+
+        tokio::select! {
+            _ = sighup.recv() => {}
+            _ = sigusr1.recv() => {}
+        }
+    }
+
+    #[cfg(not(unix))]
+    std::future::pending::<()>().await;
+
+    Ok(())
+}
+
+// drop_privileges switches the process to the given user and group once the listeners are bound,
+// so that only binding port 53 needs root. The group defaults to the user's primary group. Already
+// running as them, e.g. when systemd started zeronsd with `User=`, is not an error.
+pub fn drop_privileges(user: Option<&str>, group: Option<&str>) -> Result<(), anyhow::Error> {
+    if user.is_none() && group.is_none() {
+        return Ok(());
+    }
+
+    #[cfg(unix)]
+    {
+        use nix::unistd::{setgid, setuid, Gid, Group, Uid, User};
+
+        let user = match user {
+            Some(name) => Some(
+                User::from_name(name)?.ok_or_else(|| anyhow!("Could not find user {}", name))?,
+            ),
+            None => None,
+        };
+
+        let gid = match group {
+            Some(name) => Some(
+                Group::from_name(name)?
+                    .ok_or_else(|| anyhow!("Could not find group {}", name))?
+                    .gid,
+            ),
+            None => user.as_ref().map(|user| user.gid),
+        };
+
+        // the group has to be changed first; once the user is, we no longer may.
+        if let Some(gid) = gid {
+            if Gid::effective() != gid {
+                #[cfg(not(any(target_os = "macos", target_os = "ios")))]
+                nix::unistd::setgroups(&[gid])
+                    .map_err(|e| anyhow!("Could not drop supplementary groups: {}", e))?;
+                setgid(gid).map_err(|e| anyhow!("Could not switch to group {}: {}", gid, e))?;
+            }
+        }
+
+        if let Some(user) = user {
+            if Uid::effective() != user.uid {
+                setuid(user.uid)
+                    .map_err(|e| anyhow!("Could not switch to user {}: {}", user.name, e))?;
+            }
+        }
+
+        tracing::info!(
+            "Running as uid {}, gid {}",
+            Uid::effective(),
+            Gid::effective()
+        );
+
+        Ok(())
+    }
+
+    #[cfg(not(unix))]
+    Err(anyhow!(
+        "--user and --group are only supported on unix platforms"
+    ))
+}
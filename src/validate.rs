@@ -0,0 +1,398 @@
+/// code for `zeronsd validate`, checking a configuration without starting anything.
+use std::{
+    collections::HashMap,
+    fmt,
+    net::IpAddr,
+    path::{Path, PathBuf},
+    str::FromStr,
+};
+
+use trust_dns_server::client::rr::Name;
+
+use crate::{
+    hosts::expand_hosts_paths,
+    init::{ConfigFormat, Launcher},
+    records::parse_records,
+    traits::ToHostname,
+    utils::{domain_or_default, parse_forward_zones},
+};
+
+// Problem is something wrong with a file, at a line of it if it is known.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Problem {
+    pub path: PathBuf,
+    pub line: Option<usize>,
+    pub message: String,
+}
+
+impl Problem {
+    fn new(path: &Path, line: Option<usize>, message: impl fmt::Display) -> Self {
+        Self {
+            path: path.to_path_buf(),
+            line,
+            message: message.to_string(),
+        }
+    }
+}
+
+impl fmt::Display for Problem {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.line {
+            Some(line) => write!(f, "{}:{}: {}", self.path.display(), line, self.message),
+            None => write!(f, "{}: {}", self.path.display(), self.message),
+        }
+    }
+}
+
+// validate checks the configuration file, if any, and the hosts files given along with those it
+// names, under the domain given or else the one it configures.
+pub fn validate(
+    config: Option<(&Path, ConfigFormat)>,
+    hosts: &[PathBuf],
+    domain: Option<&str>,
+) -> Vec<Problem> {
+    let mut problems = Vec::new();
+    let mut hosts = hosts.to_vec();
+    let mut domain = domain.map(str::to_string);
+
+    if let Some((path, format)) = config {
+        let launcher = match std::fs::read_to_string(path)
+            .map_err(anyhow::Error::from)
+            .and_then(|s| Launcher::parse_format(&s, format))
+        {
+            Ok(launcher) => launcher,
+            Err(e) => {
+                problems.push(Problem::new(path, None, e));
+                return problems;
+            }
+        };
+
+        problems.extend(check_config(path, &launcher, domain.as_deref()));
+        hosts.extend(launcher.hosts.iter().cloned());
+        domain = domain.or(launcher.domain);
+    }
+
+    let domain = match domain_or_default(domain.as_deref()) {
+        Ok(domain) => domain,
+        // reported with the configuration, or by clap for --domain.
+        Err(_) => return problems,
+    };
+
+    problems.extend(check_hosts_files(&hosts, &domain));
+    problems
+}
+
+// check_config reports the settings of the configuration file which would stop zeronsd from
+// starting.
+fn check_config(path: &Path, launcher: &Launcher, domain: Option<&str>) -> Vec<Problem> {
+    let mut problems = Vec::new();
+
+    let domain = match domain_or_default(domain.or(launcher.domain.as_deref())) {
+        Ok(domain) => Some(domain),
+        Err(e) => {
+            problems.push(Problem::new(path, None, format!("domain: {}", e)));
+            None
+        }
+    };
+
+    for alias in &launcher.domain_aliases {
+        if let Err(e) = domain_or_default(Some(alias)) {
+            problems.push(Problem::new(
+                path,
+                None,
+                format!("domain alias {}: {}", alias, e),
+            ));
+        }
+    }
+
+    for (network_id, network_domain) in &launcher.network_domains {
+        if let Err(e) = domain_or_default(Some(network_domain)) {
+            problems.push(Problem::new(
+                path,
+                None,
+                format!("domain of network {}: {}", network_id, e),
+            ));
+        }
+    }
+
+    if let Err(e) = parse_forward_zones(&launcher.forward_zones) {
+        problems.push(Problem::new(path, None, format!("forward_zones: {}", e)));
+    }
+
+    if let Some(domain) = domain {
+        if let Err(e) = parse_records(&launcher.records, &domain) {
+            problems.push(Problem::new(path, None, format!("records: {}", e)));
+        }
+    }
+
+    if launcher.dynamic_zone.is_some() && launcher.tsig_keys.is_empty() {
+        problems.push(Problem::new(
+            path,
+            None,
+            "dynamic_zone requires tsig_keys to authenticate the updates",
+        ));
+    }
+
+    if launcher.workers == Some(0) {
+        problems.push(Problem::new(path, None, "workers must be at least 1"));
+    }
+
+    if launcher.worker_threads == Some(0) || launcher.max_blocking_threads == Some(0) {
+        problems.push(Problem::new(
+            path,
+            None,
+            "worker_threads and max_blocking_threads must be at least 1",
+        ));
+    }
+
+    if launcher.max_records == Some(0) {
+        problems.push(Problem::new(path, None, "max_records must be at least 1"));
+    }
+
+    if launcher.query_timeout == Some(0) {
+        problems.push(Problem::new(path, None, "query_timeout must be at least 1"));
+    }
+
+    if launcher.tcp_timeout == Some(0) {
+        problems.push(Problem::new(path, None, "tcp_timeout must be at least 1"));
+    }
+
+    if launcher.webhook_addr.is_some() && launcher.webhook_secret.is_none() {
+        problems.push(Problem::new(
+            path,
+            None,
+            "webhook_addr requires webhook_secret",
+        ));
+    }
+
+    if (launcher.replication_addr.is_some() || launcher.primary.is_some())
+        && launcher.replication_secret.is_none()
+    {
+        problems.push(Problem::new(
+            path,
+            None,
+            "primary and replication_addr require replication_secret",
+        ));
+    }
+
+    if !launcher.cluster.is_empty() && launcher.replication_addr.is_none() {
+        problems.push(Problem::new(
+            path,
+            None,
+            "cluster requires replication_addr, for the other members to read from",
+        ));
+    }
+
+    if !launcher.cluster.is_empty() && launcher.primary.is_some() {
+        problems.push(Problem::new(
+            path,
+            None,
+            "cluster and primary cannot be used together",
+        ));
+    }
+
+    problems
+}
+
+// an entry of a hosts file, where it was found.
+struct Seen {
+    ip: IpAddr,
+    path: PathBuf,
+    line: usize,
+}
+
+// check_hosts_files reports the lines of the hosts files which do not parse, and the names given
+// twice, either to the same address or to two of the same family.
+fn check_hosts_files(paths: &[PathBuf], domain: &Name) -> Vec<Problem> {
+    let mut problems = Vec::new();
+    let mut seen: HashMap<Name, Vec<Seen>> = HashMap::new();
+
+    for path in paths {
+        if let Err(e) = std::fs::metadata(path) {
+            problems.push(Problem::new(path, None, e));
+        }
+    }
+
+    let files = match expand_hosts_paths(paths) {
+        Ok(files) => files,
+        Err(_) => return problems,
+    };
+
+    for file in files {
+        let content = match std::fs::read_to_string(&file) {
+            Ok(content) => content,
+            Err(e) => {
+                // missing files are reported above.
+                if e.kind() != std::io::ErrorKind::NotFound {
+                    problems.push(Problem::new(&file, None, e));
+                }
+                continue;
+            }
+        };
+
+        for (i, line) in content.lines().enumerate() {
+            let line_number = i + 1;
+            let line = line.split('#').next().unwrap_or_default();
+            let mut fields = line.split_whitespace();
+
+            let ip = match fields.next() {
+                Some(ip) => ip,
+                None => continue,
+            };
+
+            let ip = match IpAddr::from_str(ip) {
+                Ok(ip) => ip,
+                Err(e) => {
+                    problems.push(Problem::new(
+                        &file,
+                        Some(line_number),
+                        format!("invalid address {}: {}", ip, e),
+                    ));
+                    continue;
+                }
+            };
+
+            for host in fields {
+                let name = match host.to_fqdn(domain.clone()) {
+                    Ok(name) => name,
+                    Err(e) => {
+                        problems.push(Problem::new(
+                            &file,
+                            Some(line_number),
+                            format!("invalid host {}: {}", host, e),
+                        ));
+                        continue;
+                    }
+                };
+
+                let entries = seen.entry(name.clone()).or_default();
+
+                for entry in entries.iter() {
+                    let message = if entry.ip == ip {
+                        format!("{} is already given {}", name, ip)
+                    } else if entry.ip.is_ipv4() == ip.is_ipv4() {
+                        format!("{} is given {}, but also {}", name, ip, entry.ip)
+                    } else {
+                        continue;
+                    };
+
+                    problems.push(Problem::new(
+                        &file,
+                        Some(line_number),
+                        format!("{} at {}:{}", message, entry.path.display(), entry.line),
+                    ));
+                }
+
+                entries.push(Seen {
+                    ip,
+                    path: file.clone(),
+                    line: line_number,
+                });
+            }
+        }
+    }
+
+    problems
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{validate, Problem};
+    use crate::init::ConfigFormat;
+    use std::path::PathBuf;
+
+    #[test]
+    fn test_validate() {
+        let dir = std::env::temp_dir().join("zeronsd-test-validate");
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+
+        let hosts = dir.join("hosts");
+        std::fs::write(
+            &hosts,
+            "# a comment\n\
+             10.147.20.5 db # the database\n\
+             10.147.20.6 web\n\
+             not-an-ip broken\n\
+             10.147.20.5 db\n\
+             10.147.20.7 web\n\
+             fd00::6 web\n",
+        )
+        .unwrap();
+
+        let config = dir.join("zeronsd.yaml");
+        std::fs::write(
+            &config,
+            format!(
+                "domain: example.com\nhosts: {}\nrecords:\n  build: [\"A nonsense\"]\n",
+                hosts.display()
+            ),
+        )
+        .unwrap();
+
+        let problems = validate(Some((&config, ConfigFormat::YAML)), &[], None);
+        assert_eq!(problems.len(), 4, "{:?}", problems);
+        assert_eq!(problems[0].path, config);
+        assert!(problems[0].message.starts_with("records: "));
+        assert_eq!(
+            problems[1..]
+                .iter()
+                .map(|problem| (problem.line, problem.message.clone()))
+                .collect::<Vec<_>>(),
+            vec![
+                (
+                    Some(4),
+                    "invalid address not-an-ip: invalid IP address syntax".to_string()
+                ),
+                (
+                    Some(5),
+                    format!(
+                        "db.example.com. is already given 10.147.20.5 at {}:2",
+                        hosts.display()
+                    )
+                ),
+                (
+                    Some(6),
+                    format!(
+                        "web.example.com. is given 10.147.20.7, but also 10.147.20.6 at {}:3",
+                        hosts.display()
+                    )
+                ),
+            ]
+        );
+        assert_eq!(
+            problems[1].to_string(),
+            format!(
+                "{}:4: invalid address not-an-ip: invalid IP address syntax",
+                hosts.display()
+            )
+        );
+
+        // syntax errors stop the checks, and tell where they are.
+        std::fs::write(&config, "domain: [example.com\n").unwrap();
+        let problems = validate(Some((&config, ConfigFormat::YAML)), &[], None);
+        assert_eq!(problems.len(), 1);
+        assert!(problems[0].message.contains("line"), "{}", problems[0]);
+
+        // hosts files alone, under the default domain.
+        let missing = dir.join("missing");
+        assert_eq!(
+            validate(None, &[missing.clone()], None),
+            vec![Problem {
+                path: missing,
+                line: None,
+                message: std::fs::metadata(dir.join("missing"))
+                    .unwrap_err()
+                    .to_string(),
+            }]
+        );
+        assert!(validate(
+            None,
+            &[PathBuf::from(crate::utils::TEST_HOSTS_DIR).join("basic")],
+            None
+        )
+        .is_empty());
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,220 @@
+/// views, answering some clients differently from the rest of the network.
+use std::{net::IpAddr, sync::Arc};
+
+use arc_swap::ArcSwap;
+use ipnetwork::IpNetwork;
+use serde::{Deserialize, Serialize};
+use trust_dns_server::proto::rr::Record;
+use zerotier_central_api::types::{Member, Network};
+
+use crate::{
+    acl::member_addresses,
+    authority::RecordAuthority,
+    filter::{matches, MemberFilter},
+    records::{deserialize_records, Records},
+};
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct View {
+    // the name the view is logged under.
+    pub name: Option<String>,
+    // the networks of the clients the view is for.
+    pub clients: Vec<IpNetwork>,
+    // the members the view is for, as patterns of member filters; see crate::filter.
+    pub members: Vec<String>,
+    // the ranges of the addresses its clients are answered with.
+    pub addresses: Vec<IpNetwork>,
+    // the members served to its clients instead of those of the network.
+    pub include_members: Vec<String>,
+    pub exclude_members: Vec<String>,
+    // static records served to its clients besides those of the network.
+    #[serde(deserialize_with = "deserialize_records")]
+    pub records: Records,
+}
+
+impl View {
+    // has_zones tells whether the view is served zones of its own, with names of its own.
+    pub fn has_zones(&self) -> bool {
+        !self.include_members.is_empty()
+            || !self.exclude_members.is_empty()
+            || !self.records.is_empty()
+    }
+
+    // member_filter is the filter of the members served to the view's clients.
+    pub fn member_filter(&self, authorized_only: bool) -> MemberFilter {
+        MemberFilter {
+            include: self.include_members.clone(),
+            exclude: self.exclude_members.clone(),
+            authorized_only,
+        }
+    }
+
+    pub fn label(&self, index: usize) -> String {
+        self.name
+            .clone()
+            .unwrap_or_else(|| format!("view {}", index + 1))
+    }
+}
+
+// ViewZones are the zones of a view with names of its own: copies of the domain and its aliases.
+#[derive(Clone)]
+pub struct ViewZones {
+    // the index of the view among those configured.
+    pub view: usize,
+    pub forward_authority: RecordAuthority,
+    pub alias_authorities: Vec<RecordAuthority>,
+}
+
+impl ViewZones {
+    pub fn zones(&self) -> impl Iterator<Item = &RecordAuthority> {
+        std::iter::once(&self.forward_authority).chain(self.alias_authorities.iter())
+    }
+}
+
+// Views are the views configured. Copies share the addresses of the members each view is for,
+// which are updated as members are fetched.
+#[derive(Clone, Default)]
+pub struct Views {
+    views: Arc<Vec<View>>,
+    members: Arc<ArcSwap<Vec<Vec<IpNetwork>>>>,
+}
+
+impl Views {
+    pub fn new(views: Vec<View>) -> Self {
+        Self {
+            views: Arc::new(views),
+            members: Arc::default(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.views.is_empty()
+    }
+
+    pub fn views(&self) -> &[View] {
+        &self.views
+    }
+
+    // sync_members replaces the addresses of the members each view is for with those of the
+    // members fetched.
+    pub fn sync_members(&self, network: &Network, members: &[Member]) {
+        if self.views.iter().all(|view| view.members.is_empty()) {
+            return;
+        }
+
+        let addresses = self
+            .views
+            .iter()
+            .map(|view| {
+                members
+                    .iter()
+                    .filter(|member| {
+                        view.members
+                            .iter()
+                            .any(|pattern| matches(pattern, network, member))
+                    })
+                    .flat_map(member_addresses)
+                    .collect()
+            })
+            .collect();
+
+        self.members.store(Arc::new(addresses));
+    }
+
+    // view_of is the index of the view the client is in, if any.
+    pub fn view_of(&self, client: IpAddr) -> Option<usize> {
+        let members = self.members.load();
+
+        let in_view = |(i, view): &(usize, &View)| {
+            view.clients.iter().any(|network| network.contains(client))
+                || members.get(*i).map_or(false, |addresses| {
+                    addresses.iter().any(|network| network.contains(client))
+                })
+        };
+
+        self.views.iter().enumerate().find(in_view).map(|(i, _)| i)
+    }
+
+    // select keeps the records of an answer to the client which have an address the client's view
+    // prefers, if any of them do.
+    pub fn select(&self, records: &mut Vec<Record>, client: IpAddr) {
+        let view = match self.view_of(client) {
+            Some(view) => &self.views[view],
+            None => return,
+        };
+
+        let preferred = |record: &Record| {
+            record
+                .data()
+                .and_then(|rdata| rdata.to_ip_addr())
+                .map_or(false, |ip| {
+                    view.addresses.iter().any(|network| network.contains(ip))
+                })
+        };
+
+        if records.iter().any(preferred) {
+            records.retain(preferred);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{View, Views};
+    use ipnetwork::IpNetwork;
+    use std::{net::IpAddr, str::FromStr};
+    use trust_dns_server::proto::rr::{Name, RData, Record};
+
+    #[test]
+    fn test_views() {
+        let name = Name::from_str("nas.home.arpa.").unwrap();
+        let records: Vec<Record> = ["10.147.17.5", "192.168.1.5"]
+            .iter()
+            .map(|ip| Record::from_rdata(name.clone(), 60, RData::A(ip.parse().unwrap())))
+            .collect();
+
+        let networks = |networks: &[&str]| {
+            networks
+                .iter()
+                .map(|network| IpNetwork::from_str(network).unwrap())
+                .collect()
+        };
+
+        let views = Views::new(vec![
+            View {
+                clients: networks(&["192.168.1.0/24"]),
+                addresses: networks(&["192.168.1.0/24"]),
+                ..Default::default()
+            },
+            View {
+                clients: networks(&["10.147.17.0/24", "10.0.0.0/8"]),
+                addresses: networks(&["10.147.17.0/24"]),
+                ..Default::default()
+            },
+            View {
+                clients: networks(&["172.16.0.0/12"]),
+                addresses: networks(&["172.16.0.0/12"]),
+                ..Default::default()
+            },
+        ]);
+
+        let answer = |client: &str| {
+            let mut records = records.clone();
+            views.select(&mut records, IpAddr::from_str(client).unwrap());
+            records
+                .iter()
+                .map(|record| record.data().unwrap().to_ip_addr().unwrap().to_string())
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(answer("192.168.1.20"), ["192.168.1.5"]);
+        assert_eq!(answer("10.147.17.20"), ["10.147.17.5"]);
+
+        // clients in no view, or whose view prefers none of the addresses, get them all.
+        assert_eq!(answer("203.0.113.1"), ["10.147.17.5", "192.168.1.5"]);
+        assert_eq!(answer("172.16.0.1"), ["10.147.17.5", "192.168.1.5"]);
+
+        assert!(Views::default().is_empty());
+    }
+}
@@ -0,0 +1,263 @@
+/// the webhook receiver of `--webhook-addr`, refreshing networks when members change.
+use std::{
+    net::SocketAddr,
+    path::Path,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use anyhow::anyhow;
+use openssl::{hash::MessageDigest, memcmp, pkey::PKey, sign::Signer};
+use serde_json::Value;
+use tokio::{net::TcpListener, task::JoinHandle};
+
+use crate::{
+    admin::Networks,
+    http::{self, Request, Response},
+};
+
+// how far the time of a signature given with X-ZTC-Signature may be from ours, so that captured
+// requests cannot be replayed later on.
+const MAX_SIGNATURE_AGE: Duration = Duration::from_secs(300);
+
+// read_secret reads the shared secret from its file, without the trailing newline.
+pub fn read_secret(path: &Path) -> Result<Vec<u8>, anyhow::Error> {
+    let secret = std::fs::read_to_string(path)
+        .map_err(|e| anyhow!("Could not read secret {}: {}", path.display(), e))?;
+    let secret = secret.trim();
+
+    if secret.is_empty() {
+        return Err(anyhow!("Secret {} is empty", path.display()));
+    }
+
+    Ok(secret.as_bytes().to_vec())
+}
+
+// listen binds the address and receives webhooks on it in the background.
+pub async fn listen(
+    addr: SocketAddr,
+    secret: Vec<u8>,
+    networks: Networks,
+) -> Result<JoinHandle<()>, anyhow::Error> {
+    let listener = TcpListener::bind(addr)
+        .await
+        .map_err(|e| anyhow!("could not listen for webhooks on {}: {}", addr, e))?;
+
+    tracing::info!("Receiving webhooks on http://{}", addr);
+
+    Ok(tokio::spawn(async move {
+        loop {
+            match listener.accept().await {
+                Ok((stream, peer)) => {
+                    let secret = secret.clone();
+                    let networks = networks.clone();
+                    tokio::spawn(async move {
+                        let answer =
+                            move |request| async move { respond(&request, &secret, &networks) };
+
+                        if let Err(e) = http::handle(stream, answer).await {
+                            tracing::debug!("Webhook from {} failed: {}", peer, e);
+                        }
+                    });
+                }
+                Err(e) => tracing::warn!("Could not accept webhook: {}", e),
+            }
+        }
+    }))
+}
+
+// respond refreshes the networks the webhook is about, once its signature is verified.
+fn respond(request: &Request, secret: &[u8], networks: &Networks) -> Response {
+    if request.method != "POST" {
+        return Response::error(405, "method not allowed");
+    }
+
+    if let Err(e) = verify(request, secret, SystemTime::now()) {
+        tracing::warn!("Rejected webhook: {}", e);
+        return Response::error(401, e);
+    }
+
+    let network_id = serde_json::from_slice::<Value>(&request.body)
+        .ok()
+        .and_then(|body| {
+            body.get("network_id")
+                .or_else(|| body.get("networkId"))
+                .and_then(Value::as_str)
+                .map(ToString::to_string)
+        });
+
+    let refreshed: Vec<String> = match &network_id {
+        Some(network_id) => networks.get(network_id).into_iter().collect(),
+        None => networks.all(),
+    }
+    .into_iter()
+    .map(|zt| {
+        zt.control.refresh();
+        zt.network_id
+    })
+    .collect();
+
+    tracing::info!("Webhook received; refreshing {:?}", refreshed);
+    Response::json(202, &serde_json::json!({ "refreshing": refreshed }))
+}
+
+// verify checks the signature of the request, in either of the headers it may be given in.
+fn verify(request: &Request, secret: &[u8], now: SystemTime) -> Result<(), anyhow::Error> {
+    if let Some(header) = request.header("x-ztc-signature") {
+        let mut time = None;
+        let mut signatures = Vec::new();
+
+        for part in header.split(',') {
+            match part.trim().split_once('=') {
+                Some(("t", value)) => time = value.parse::<u64>().ok(),
+                Some(("v1", value)) => signatures.push(value),
+                _ => {}
+            }
+        }
+
+        let time = time.ok_or_else(|| anyhow!("the signature has no valid time"))?;
+        let now = now.duration_since(UNIX_EPOCH)?.as_secs();
+        if now.abs_diff(time) > MAX_SIGNATURE_AGE.as_secs() {
+            return Err(anyhow!("the signature is too old"));
+        }
+
+        let mut payload = format!("{}.", time).into_bytes();
+        payload.extend(&request.body);
+        let expected = sign(secret, &payload)?;
+
+        if signatures
+            .iter()
+            .any(|signature| matches(signature, &expected))
+        {
+            return Ok(());
+        }
+
+        return Err(anyhow!("invalid signature"));
+    }
+
+    if let Some(header) = request.header("x-hub-signature-256") {
+        let signature = header
+            .strip_prefix("sha256=")
+            .ok_or_else(|| anyhow!("the signature is not sha256"))?;
+
+        if matches(signature, &sign(secret, &request.body)?) {
+            return Ok(());
+        }
+
+        return Err(anyhow!("invalid signature"));
+    }
+
+    Err(anyhow!("the request is not signed"))
+}
+
+pub(crate) fn sign(secret: &[u8], payload: &[u8]) -> Result<Vec<u8>, anyhow::Error> {
+    let key = PKey::hmac(secret)?;
+    Ok(Signer::new(MessageDigest::sha256(), &key)?.sign_oneshot_to_vec(payload)?)
+}
+
+// matches compares the hex signature given with the expected one, in constant time.
+fn matches(signature: &str, expected: &[u8]) -> bool {
+    match hex::decode(signature) {
+        Ok(signature) => signature.len() == expected.len() && memcmp::eq(&signature, expected),
+        Err(_) => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{read_secret, respond, sign, verify};
+    use crate::{admin::Networks, http::Request};
+    use std::time::{Duration, UNIX_EPOCH};
+
+    fn request(header: (&str, String), body: &str) -> Request {
+        Request {
+            method: "POST".to_string(),
+            path: "/".to_string(),
+            headers: vec![(header.0.to_string(), header.1)],
+            body: body.as_bytes().to_vec(),
+        }
+    }
+
+    #[test]
+    fn test_verify() {
+        let secret = b"s3cret";
+        let body = r#"{"network_id": "8056c2e21c000001", "hook_type": "NETWORK_JOIN"}"#;
+        let now = UNIX_EPOCH + Duration::from_secs(1700000000);
+
+        // as Central signs them.
+        let signature =
+            hex::encode(sign(secret, format!("1700000000.{}", body).as_bytes()).unwrap());
+        let signed = request(
+            ("x-ztc-signature", format!("t=1700000000,v1={}", signature)),
+            body,
+        );
+        assert!(verify(&signed, secret, now).is_ok());
+        assert!(verify(&signed, b"other", now).is_err());
+        assert!(verify(&signed, secret, now + Duration::from_secs(3600)).is_err());
+
+        let tampered = Request {
+            body: b"{}".to_vec(),
+            ..signed
+        };
+        assert!(verify(&tampered, secret, now).is_err());
+
+        // as automation signs them.
+        let signature = hex::encode(sign(secret, body.as_bytes()).unwrap());
+        let signed = request(
+            ("x-hub-signature-256", format!("sha256={}", signature)),
+            body,
+        );
+        assert!(verify(&signed, secret, now).is_ok());
+
+        let truncated = request(
+            (
+                "x-hub-signature-256",
+                format!("sha256={}", &signature[..32]),
+            ),
+            body,
+        );
+        assert!(verify(&truncated, secret, now).is_err());
+
+        let unsigned = request(("content-type", "application/json".to_string()), body);
+        assert_eq!(
+            verify(&unsigned, secret, now).unwrap_err().to_string(),
+            "the request is not signed"
+        );
+    }
+
+    #[test]
+    fn test_respond() {
+        let networks = Networks::default();
+        let body = r#"{"network_id": "8056c2e21c000001"}"#;
+        let signature = hex::encode(sign(b"s3cret", body.as_bytes()).unwrap());
+        let signed = request(
+            ("x-hub-signature-256", format!("sha256={}", signature)),
+            body,
+        );
+
+        // networks not served are left alone.
+        let response = respond(&signed, b"s3cret", &networks);
+        assert_eq!(response.status, 202);
+        assert_eq!(response.body, r#"{"refreshing":[]}"#);
+
+        assert_eq!(respond(&signed, b"other", &networks).status, 401);
+
+        let get = Request {
+            method: "GET".to_string(),
+            ..signed
+        };
+        assert_eq!(respond(&get, b"s3cret", &networks).status, 405);
+    }
+
+    #[test]
+    fn test_read_secret() {
+        let path = std::env::temp_dir().join("zeronsd-test-webhook-secret");
+
+        std::fs::write(&path, "s3cret\n").unwrap();
+        assert_eq!(read_secret(&path).unwrap(), b"s3cret".to_vec());
+
+        std::fs::write(&path, "\n").unwrap();
+        assert!(read_secret(&path).is_err());
+
+        std::fs::remove_file(&path).unwrap();
+    }
+}
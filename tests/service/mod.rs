@@ -22,10 +22,12 @@ use trust_dns_resolver::config::{NameServerConfig, ResolverConfig, ResolverOpts}
 use zeronsd::{
     addresses::Calculator,
     authority::{find_members, RecordAuthority, ZTAuthority},
+    controller::Central,
     server::Server,
     traits::{ToHostname, ToPointerSOA},
     utils::{
-        authtoken_path, domain_or_default, get_listen_ips, parse_ip_from_cidr, ZEROTIER_LOCAL_URL,
+        authtoken_path, domain_or_default, get_listen_ips, parse_ip_from_cidr, DEFAULT_TTL,
+        ZEROTIER_LOCAL_URL,
     },
 };
 
@@ -191,6 +193,7 @@ impl Service {
                 let ptr_authority = RecordAuthority::new(
                     cidr.to_ptr_soa_name().unwrap(),
                     cidr.to_ptr_soa_name().unwrap(),
+                    DEFAULT_TTL,
                 )
                 .await
                 .unwrap();
@@ -205,6 +208,7 @@ impl Service {
                     let ptr_authority = RecordAuthority::new(
                         cidr.to_ptr_soa_name().unwrap(),
                         cidr.to_ptr_soa_name().unwrap(),
+                        DEFAULT_TTL,
                     )
                     .await
                     .unwrap();
@@ -219,19 +223,53 @@ impl Service {
                 .to_fqdn(domain_or_default(None).unwrap().into())
                 .unwrap()
                 .into(),
+            DEFAULT_TTL,
         )
         .await
         .unwrap();
 
         let ztauthority = ZTAuthority {
             network_id: tn.network.clone().id.unwrap(),
-            client: tn.central(),
-            hosts_file: format_hosts_file(hosts),
+            controller: Arc::new(Central::new(tn.central(), None)),
+            hosts_files: format_hosts_file(hosts).into_iter().collect(),
             reverse_authority_map: authority_map,
             update_interval,
             forward_authority: authority.clone(),
+            alias_authorities: Vec::new(),
+            forward_zones: HashMap::new(),
+            upstreams: None,
             wildcard: wildcard_everything,
+            wildcard_members: Vec::new(),
+            name_policy: Default::default(),
+            name_collisions: Default::default(),
+            member_filter: Default::default(),
+            node_id_names: false,
+            all_ptr_names: false,
+            member_txt: false,
+            max_records: None,
+            record_eviction: Default::default(),
             hosts: None,
+            reload: None,
+            snapshot: None,
+            store: None,
+            records: Default::default(),
+            dynamic_zone: None,
+            dnssec_validation: false,
+            qname_minimization: false,
+            ip_names: false,
+            edns: Default::default(),
+            query_timeout: None,
+            query_acl: None,
+            query_log: None,
+            dnstap: None,
+            health: Default::default(),
+            control: Default::default(),
+            replica: Default::default(),
+            cache: None,
+            blocklist: Default::default(),
+            rpz: Default::default(),
+            views: Default::default(),
+            view_zones: Vec::new(),
         };
 
         tokio::spawn(find_members(ztauthority.clone()));
@@ -240,7 +278,7 @@ impl Service {
         for ip in listen_ips.clone() {
             let server = Server::new(ztauthority.to_owned());
             info!("Serving {}", ip.clone());
-            tokio::spawn(server.listen(ip.ip(), Duration::new(1, 0), None, None, None));
+            tokio::spawn(server.listen(ip, Duration::new(1, 0), None, None, None, None));
         }
 
         listen_ips